@@ -0,0 +1,42 @@
+use reactive_service_domain::aggregate_root::AggregateRoot;
+use reactive_service_domain::customer::CustomerId;
+use reactive_service_domain::order_entity::OrderEntity;
+use reactive_service_domain::order_state::OrderState;
+use crate::command_errors::{NOT_THE_CUSTOMER, NOT_THE_OWNER};
+
+/// Who issues a command: a customer, on the orders they created, or an admin, on any order.
+/// The runtimes take it from the caller with each command, only the trusted callers (the scheduler) act as `Admin`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Principal {
+    Customer(CustomerId),
+    Admin,
+}
+
+impl Principal {
+    /// The customer recorded on the orders this principal creates.
+    pub fn customer_id(&self) -> Option<CustomerId> {
+        match self {
+            Principal::Customer(customer_id) => Some(*customer_id),
+            Principal::Admin => None,
+        }
+    }
+
+    /// A customer may create an order, then only change the orders they own.
+    /// The orders created before the customers have no owner: only an admin may change them.
+    pub fn authorize_order(&self, order_entity: &OrderEntity) -> Result<(), &'static str> {
+        match (self, order_entity.get_customer_id()) {
+            (Principal::Admin, _) => Ok(()),
+            (Principal::Customer(customer_id), Some(owner)) if *customer_id == owner => Ok(()),
+            (Principal::Customer(_), None) if matches!(order_entity.get_state(), OrderState::Empty(_)) => Ok(()),
+            (Principal::Customer(_), _) => Err(NOT_THE_OWNER),
+        }
+    }
+
+    /// A customer may only change their own profile.
+    pub fn authorize_customer(&self, customer_id: CustomerId) -> Result<(), &'static str> {
+        match self {
+            Principal::Customer(id) if *id != customer_id => Err(NOT_THE_CUSTOMER),
+            _ => Ok(()),
+        }
+    }
+}
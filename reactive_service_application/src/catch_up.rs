@@ -0,0 +1,63 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+use crate::subscriptions::Positioned;
+
+/// Settings of the `CatchUpSubscription` of the runtimes.
+#[derive(Debug, Clone)]
+pub struct CatchUpConfig {
+    /// Events read from the journal at once.
+    pub batch_size: usize,
+    /// Longest wait for new events before reading again, in case a notification was missed.
+    pub max_wait: Duration,
+}
+
+impl Default for CatchUpConfig {
+    fn default() -> Self {
+        Self {
+            batch_size: 500,
+            max_wait: Duration::from_secs(1),
+        }
+    }
+}
+
+/// Where a catch-up subscription is in the global order of the journal, with the events read ahead of its consumer.
+/// The runtimes read the journal, with their own calls, from its `next_position`.
+pub struct CatchUpCursor<E> {
+    buffered: VecDeque<Positioned<E>>,
+    /// Next position to read from the journal
+    next_position: i64,
+    /// Last position received
+    position: i64,
+}
+
+impl<E> CatchUpCursor<E> {
+    /// A cursor after `position`, 0 for the start of the journal.
+    pub fn new(position: i64) -> Self {
+        Self { buffered: VecDeque::new(), next_position: position + 1, position }
+    }
+
+    /// Position of the last event received, to checkpoint once it is processed.
+    pub fn position(&self) -> i64 {
+        self.position
+    }
+
+    /// Position to read the journal from, once the events read ahead are received.
+    pub fn next_position(&self) -> i64 {
+        self.next_position
+    }
+
+    /// Receive the next event read ahead, if any.
+    pub fn pop(&mut self) -> Option<Positioned<E>> {
+        let event = self.buffered.pop_front()?;
+        self.position = event.position;
+        Some(event)
+    }
+
+    /// Keep the events read from the `next_position`. Returns `false` when there are none: the journal is caught up.
+    pub fn push(&mut self, batch: Vec<Positioned<E>>) -> bool {
+        let Some(last) = batch.last() else { return false };
+        self.next_position = last.position + 1;
+        self.buffered.extend(batch);
+        true
+    }
+}
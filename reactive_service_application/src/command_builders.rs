@@ -0,0 +1,95 @@
+use reactive_service_domain::aggregate_root::AggregateRoot;
+use reactive_service_domain::customer::CustomerId;
+use reactive_service_domain::non_empty_cart::NonEmptyCart;
+use reactive_service_domain::order_entity::{OrderEntity, OrderEntityCommand};
+use reactive_service_domain::order_state::{DeliveryAddress, Money, OrderState};
+use crate::payment_processor::{PaymentProcessor, PaymentToken};
+use crate::shipping_calculator::ShippingCalculator;
+use crate::tax_calculator::TaxCalculator;
+
+// The command builders are shared by every runtime (locks, actors, shards, event loop),
+// they only read the entity state to decide which entity command to issue.
+
+/// The customer is recorded on the order when the cart creates it.
+pub fn update_cart_command<S: ShippingCalculator, T: TaxCalculator>(
+    order_entity: &OrderEntity, cart: NonEmptyCart, customer_id: Option<CustomerId>, shipping_calculator: &S, tax_calculator: &T
+) -> Result<OrderEntityCommand, &'static str> {
+
+    match order_entity.get_state() {
+
+        OrderState::Empty(_) | OrderState::WithCart(_) =>
+            Ok(OrderEntityCommand::AddCart{cart, customer_id}),
+
+        OrderState::WithAddress(with_addr) => {
+            let shipping_cost = shipping_calculator.shipping_cost(&cart, with_addr.get_delivery_address());
+            let tax: Money = tax_calculator.tax_cost(&cart, &shipping_cost);
+            Ok(OrderEntityCommand::UpdateCart {cart, shipping_cost, tax})
+        },
+
+        OrderState::Completed(_) => Err("Can't update the cart on a completed order."),
+
+        OrderState::Expired(_) => Err("Can't update the cart on an expired order."),
+    }
+}
+
+pub fn update_delivery_address_command<S: ShippingCalculator, T: TaxCalculator>(
+    order_entity: &OrderEntity, delivery_address: DeliveryAddress, shipping_calculator: &S, tax_calculator: &T
+) -> Result<OrderEntityCommand, &'static str> {
+
+    match order_entity.get_state() {
+
+        OrderState::Empty(_) => Err("Can't add address to an empty cart."),
+
+        OrderState::WithCart(with_cart) => {
+            let cart = with_cart.get_cart();
+            let shipping_cost = shipping_calculator.shipping_cost(cart, &delivery_address);
+            let tax: Money = tax_calculator.tax_cost(cart, &shipping_cost);
+            Ok(OrderEntityCommand::UpdateDeliveryAddress { delivery_address, shipping_cost, tax })
+        },
+
+        OrderState::WithAddress(with_addr) => {
+            let cart = with_addr.get_cart();
+            let shipping_cost = shipping_calculator.shipping_cost(cart, &delivery_address);
+            let tax: Money = tax_calculator.tax_cost(cart, &shipping_cost);
+            Ok(OrderEntityCommand::UpdateDeliveryAddress { delivery_address, shipping_cost, tax })
+        },
+
+        OrderState::Completed(_) => Err("Can't update address on a completed order."),
+
+        OrderState::Expired(_) => Err("Can't update address on an expired order."),
+    }
+}
+
+pub fn pay_order_command<P: PaymentProcessor>(
+    order_entity: &OrderEntity, payment_token: PaymentToken, payment_processor: &P
+) -> Result<OrderEntityCommand, &'static str> {
+
+    match order_entity.get_state() {
+
+        OrderState::Empty(_) | OrderState::WithCart(_) =>
+            Err("Order not ready to be paid."),
+
+        OrderState::WithAddress(_) => {
+            let invoice = payment_processor.pay_with_token(payment_token);
+            Ok(OrderEntityCommand::Complete{invoice})
+        },
+
+        OrderState::Completed(_) => Err("Order is already paid."),
+
+        OrderState::Expired(_) => Err("Order is expired."),
+    }
+}
+
+/// The expiry is scheduled after a change of the order: a later change, or the payment, makes it stale.
+pub fn expire_order_command(order_entity: &OrderEntity, sequence_number: i64) -> Result<OrderEntityCommand, &'static str> {
+
+    if order_entity.get_sequence_number() != sequence_number {
+        return Err("Order changed since its expiry was scheduled.");
+    }
+    match order_entity.get_state() {
+
+        OrderState::WithCart(_) | OrderState::WithAddress(_) => Ok(OrderEntityCommand::Expire),
+
+        OrderState::Empty(_) | OrderState::Completed(_) | OrderState::Expired(_) => Err("Order can't expire."),
+    }
+}
@@ -0,0 +1,97 @@
+use reactive_service_domain::canada_postal_code::INVALID_POSTAL_CODE;
+use reactive_service_domain::inventory_item::NON_POSITIVE_QUANTITY;
+use reactive_service_domain::non_empty_cart::EMPTY_CART;
+
+/// What went wrong with a command, for a caller to react to it: the network APIs map it onto their status codes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommandErrorKind {
+    /// The command is malformed, e.g. an empty cart or an invalid postal code: it would never succeed.
+    Invalid,
+    /// The principal may not issue the command.
+    Forbidden,
+    /// The command doesn't apply to the current state of the order, or it changed meanwhile.
+    Conflict,
+    /// The service is overloaded or stopping: the command may succeed later.
+    Unavailable,
+    /// The journal, or another port, failed.
+    Internal,
+}
+
+pub const NOT_THE_OWNER: &str = "Not the owner of the order";
+pub const NOT_THE_CUSTOMER: &str = "Not the customer";
+
+pub const COMMAND_TIMED_OUT: &str = "Command timed out";
+pub const TOO_MANY_COMMANDS: &str = "Too many commands in progress";
+pub const VERSION_TIMED_OUT: &str = "Timed out waiting for the version";
+pub const NO_DB_CONNECTION: &str = "Failed to get a DB connection";
+pub const MAILBOX_UNAVAILABLE: &str = "Order actor mailbox is unavailable";
+pub const MAILBOX_FULL: &str = "Order actor mailbox is full";
+pub const ACTOR_STOPPED: &str = "Order actor stopped before replying";
+pub const SUPERVISOR_STOPPED: &str = "Order supervisor is stopped";
+pub const SHARD_STOPPED: &str = "Order shard stopped before replying";
+pub const EVENT_LOOP_STOPPED: &str = "Event loop is stopped";
+pub const EVENT_LOOP_STOPPED_BEFORE_REPLYING: &str = "Event loop stopped before replying";
+
+pub const ENTITY_NOT_RETRIEVED: &str = "Can't retrieve the entity";
+pub const ENTITY_POISONED: &str = "Entity poisoned by a panic";
+pub const ENTITIES_POISONED: &str = "Entities poisoned by a panic";
+pub const JOURNAL_POISONED: &str = "Journal poisoned by a panic";
+pub const RETRIEVE_EVENTS_FAILED: &str = "Failed to retrieve events";
+pub const PERSIST_EVENT_FAILED: &str = "Failed to persist event";
+pub const APPLY_EVENTS_FAILED: &str = "Failed to apply events";
+pub const SERIALIZE_EVENT_FAILED: &str = "Failed to serialize event";
+pub const DESERIALIZE_EVENT_FAILED: &str = "Failed to deserialize event";
+pub const SERIALIZE_COMMAND_FAILED: &str = "Failed to serialize command";
+pub const DESERIALIZE_COMMAND_FAILED: &str = "Failed to deserialize command";
+pub const SCHEDULE_COMMAND_FAILED: &str = "Failed to schedule command";
+pub const RETRIEVE_SCHEDULED_COMMANDS_FAILED: &str = "Failed to retrieve scheduled commands";
+pub const REMOVE_SCHEDULED_COMMAND_FAILED: &str = "Failed to remove scheduled command";
+pub const LOAD_CHECKPOINT_FAILED: &str = "Failed to load checkpoint";
+pub const SAVE_CHECKPOINT_FAILED: &str = "Failed to save checkpoint";
+pub const LIMIT_COMMANDS_FAILED: &str = "Failed to limit the commands";
+
+/// The kind of each error a caller may react to, the constants returned by their producers.
+pub const ERROR_KINDS: &[(&str, CommandErrorKind)] = &[
+    (EMPTY_CART, CommandErrorKind::Invalid),
+    (INVALID_POSTAL_CODE, CommandErrorKind::Invalid),
+    (NON_POSITIVE_QUANTITY, CommandErrorKind::Invalid),
+    (NOT_THE_OWNER, CommandErrorKind::Forbidden),
+    (NOT_THE_CUSTOMER, CommandErrorKind::Forbidden),
+    (COMMAND_TIMED_OUT, CommandErrorKind::Unavailable),
+    (TOO_MANY_COMMANDS, CommandErrorKind::Unavailable),
+    (VERSION_TIMED_OUT, CommandErrorKind::Unavailable),
+    (NO_DB_CONNECTION, CommandErrorKind::Unavailable),
+    (MAILBOX_UNAVAILABLE, CommandErrorKind::Unavailable),
+    (MAILBOX_FULL, CommandErrorKind::Unavailable),
+    (ACTOR_STOPPED, CommandErrorKind::Unavailable),
+    (SUPERVISOR_STOPPED, CommandErrorKind::Unavailable),
+    (SHARD_STOPPED, CommandErrorKind::Unavailable),
+    (EVENT_LOOP_STOPPED, CommandErrorKind::Unavailable),
+    (EVENT_LOOP_STOPPED_BEFORE_REPLYING, CommandErrorKind::Unavailable),
+    (ENTITY_NOT_RETRIEVED, CommandErrorKind::Internal),
+    (ENTITY_POISONED, CommandErrorKind::Internal),
+    (ENTITIES_POISONED, CommandErrorKind::Internal),
+    (JOURNAL_POISONED, CommandErrorKind::Internal),
+    (RETRIEVE_EVENTS_FAILED, CommandErrorKind::Internal),
+    (PERSIST_EVENT_FAILED, CommandErrorKind::Internal),
+    (APPLY_EVENTS_FAILED, CommandErrorKind::Internal),
+    (SERIALIZE_EVENT_FAILED, CommandErrorKind::Internal),
+    (DESERIALIZE_EVENT_FAILED, CommandErrorKind::Internal),
+    (SERIALIZE_COMMAND_FAILED, CommandErrorKind::Internal),
+    (DESERIALIZE_COMMAND_FAILED, CommandErrorKind::Internal),
+    (SCHEDULE_COMMAND_FAILED, CommandErrorKind::Internal),
+    (RETRIEVE_SCHEDULED_COMMANDS_FAILED, CommandErrorKind::Internal),
+    (REMOVE_SCHEDULED_COMMAND_FAILED, CommandErrorKind::Internal),
+    (LOAD_CHECKPOINT_FAILED, CommandErrorKind::Internal),
+    (SAVE_CHECKPOINT_FAILED, CommandErrorKind::Internal),
+    (LIMIT_COMMANDS_FAILED, CommandErrorKind::Internal),
+];
+
+impl CommandErrorKind {
+    /// The kind of an error of the domain, or of a runtime, by the `ERROR_KINDS`. The other rejections of the domain are conflicts.
+    pub fn of(error: &str) -> Self {
+        ERROR_KINDS.iter()
+            .find(|(known, _)| *known == error)
+            .map_or(CommandErrorKind::Conflict, |(_, kind)| *kind)
+    }
+}
@@ -0,0 +1,161 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+use reactive_service_domain::aggregate_root::SequencedEvent;
+use crate::order_commands::OrderId;
+use crate::subscriptions::{Committed, SubscriptionError};
+
+/// Publishes the committed events to the subscribers, of all the entities or of a single one.
+///
+/// Each subscription has its own bounded queue: a slow subscriber never blocks the commands.
+/// When its queue is full, the oldest event is dropped and the subscriber is told how many it missed.
+///
+/// Cheap to clone, the clones publish to the same subscribers. Once every clone is dropped, the subscriptions are closed.
+/// The subscribers wait for the events on a thread: the async runtime has a bus of its own.
+pub struct EventBus<E> {
+    inner: Arc<Inner<E>>,
+}
+
+impl<E> Clone for EventBus<E> {
+    fn clone(&self) -> Self {
+        Self { inner: self.inner.clone() }
+    }
+}
+
+impl<E> Default for EventBus<E> {
+    fn default() -> Self {
+        Self::new(1024)
+    }
+}
+
+impl<E> EventBus<E> {
+    /// `capacity`: events queued per subscription before the oldest ones are dropped.
+    pub fn new(capacity: usize) -> Self {
+        Self { inner: Arc::new(Inner { subscribers: Mutex::new(Vec::new()), capacity: capacity.max(1) }) }
+    }
+}
+
+impl<E: Clone> EventBus<E> {
+    /// Subscribe to the events of every entity, from now on.
+    pub fn subscribe_all(&self) -> Subscription<E> {
+        self.add_subscriber(None)
+    }
+
+    /// Subscribe to the events of a single entity, from now on.
+    pub fn subscribe(&self, entity_id: OrderId) -> Subscription<E> {
+        self.add_subscriber(Some(entity_id))
+    }
+
+    /// Publish events, only once they are persisted.
+    pub fn publish(&self, entity_id: OrderId, events: &[SequencedEvent<E>]) {
+        let Ok(mut subscribers) = self.inner.subscribers.lock() else { return };
+        // The subscriptions dropped since the last publication only have the bus holding their queue
+        subscribers.retain(|queue| Arc::strong_count(queue) > 1);
+
+        for queue in subscribers.iter().filter(|queue| queue.entity_id.map_or(true, |id| id == entity_id)) {
+            queue.push(entity_id, events, self.inner.capacity);
+        }
+    }
+
+    fn add_subscriber(&self, entity_id: Option<OrderId>) -> Subscription<E> {
+        let queue = Arc::new(Queue {
+            entity_id,
+            state: Mutex::new(QueueState { events: VecDeque::new(), lagged: 0, closed: false }),
+            available: Condvar::new(),
+        });
+        if let Ok(mut subscribers) = self.inner.subscribers.lock() {
+            subscribers.push(queue.clone());
+        }
+        Subscription { queue }
+    }
+}
+
+/// The receiving end of a subscription, it can be moved to another thread.
+pub struct Subscription<E> {
+    queue: Arc<Queue<E>>,
+}
+
+impl<E> Subscription<E> {
+    /// Wait for the next event.
+    pub fn recv(&self) -> Result<Committed<E>, SubscriptionError> {
+        let state = self.queue.state.lock().map_err(|_| SubscriptionError::Closed)?;
+        let mut state = self.queue.available
+            .wait_while(state, |state| state.nothing_to_receive())
+            .map_err(|_| SubscriptionError::Closed)?;
+        state.next()
+    }
+
+    /// Wait for the next event, `None` if there is none within `timeout`.
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<Option<Committed<E>>, SubscriptionError> {
+        let deadline = Instant::now() + timeout;
+        let state = self.queue.state.lock().map_err(|_| SubscriptionError::Closed)?;
+        let (mut state, _) = self.queue.available
+            .wait_timeout_while(state, deadline.saturating_duration_since(Instant::now()), |state| state.nothing_to_receive())
+            .map_err(|_| SubscriptionError::Closed)?;
+        if state.nothing_to_receive() {
+            return Ok(None);
+        }
+        state.next().map(Some)
+    }
+}
+
+struct Inner<E> {
+    subscribers: Mutex<Vec<Arc<Queue<E>>>>,
+    capacity: usize,
+}
+
+impl<E> Drop for Inner<E> {
+    fn drop(&mut self) {
+        // The last clone of the bus is gone: wake up the subscribers, to close their subscription
+        if let Ok(subscribers) = self.subscribers.get_mut() {
+            for queue in subscribers.iter() {
+                if let Ok(mut state) = queue.state.lock() {
+                    state.closed = true;
+                }
+                queue.available.notify_all();
+            }
+        }
+    }
+}
+
+struct Queue<E> {
+    /// `None` to receive the events of every entity
+    entity_id: Option<OrderId>,
+    state: Mutex<QueueState<E>>,
+    available: Condvar,
+}
+
+impl<E: Clone> Queue<E> {
+    fn push(&self, entity_id: OrderId, events: &[SequencedEvent<E>], capacity: usize) {
+        let Ok(mut state) = self.state.lock() else { return };
+        for event in events {
+            if state.events.len() == capacity {
+                state.events.pop_front();
+                state.lagged += 1;
+            }
+            state.events.push_back(Committed { entity_id, event: event.clone() });
+        }
+        self.available.notify_one();
+    }
+}
+
+struct QueueState<E> {
+    events: VecDeque<Committed<E>>,
+    /// Events dropped since the subscriber was last told
+    lagged: u64,
+    closed: bool,
+}
+
+impl<E> QueueState<E> {
+    fn nothing_to_receive(&self) -> bool {
+        self.events.is_empty() && self.lagged == 0 && !self.closed
+    }
+
+    /// The lag is reported before the events following it, the queued events before the closing.
+    fn next(&mut self) -> Result<Committed<E>, SubscriptionError> {
+        if self.lagged > 0 {
+            return Err(SubscriptionError::Lagged(std::mem::take(&mut self.lagged)));
+        }
+        self.events.pop_front().ok_or(SubscriptionError::Closed)
+    }
+}
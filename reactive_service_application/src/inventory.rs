@@ -0,0 +1,81 @@
+use std::time::Duration;
+use reactive_service_domain::non_empty_cart::{NonEmptyCart, Sku};
+use crate::order_commands::OrderId;
+
+/// The stock of the SKUs, reserved for the orders until they are shipped.
+pub trait Inventory {
+    /// Reserve the items of the cart for the order, all or none. Reserving them again is a no-op.
+    fn reserve_stock(&self, order_id: OrderId, cart: &NonEmptyCart) -> Result<(), &'static str>;
+
+    /// Release the stock reserved for the order. Releasing it again, or a stock never reserved, is a no-op.
+    fn release_stock(&self, order_id: OrderId, cart: &NonEmptyCart) -> Result<(), &'static str>;
+
+    /// Take the stock reserved for the order out of the inventory. Shipping it again is a no-op.
+    fn ship_stock(&self, order_id: OrderId, cart: &NonEmptyCart) -> Result<(), &'static str>;
+}
+
+/// A port shared by reference, e.g. the inventory of the order service used by the fulfillment too.
+impl<I: Inventory> Inventory for &I {
+    fn reserve_stock(&self, order_id: OrderId, cart: &NonEmptyCart) -> Result<(), &'static str> {
+        (**self).reserve_stock(order_id, cart)
+    }
+
+    fn release_stock(&self, order_id: OrderId, cart: &NonEmptyCart) -> Result<(), &'static str> {
+        (**self).release_stock(order_id, cart)
+    }
+
+    fn ship_stock(&self, order_id: OrderId, cart: &NonEmptyCart) -> Result<(), &'static str> {
+        (**self).ship_stock(order_id, cart)
+    }
+}
+
+pub struct LocalInventory {}
+
+impl Inventory for LocalInventory {
+    fn reserve_stock(&self, order_id: OrderId, cart: &NonEmptyCart) -> Result<(), &'static str> {
+        let _ = order_id;
+        let _ = cart;
+        Ok(())
+    }
+
+    fn release_stock(&self, order_id: OrderId, cart: &NonEmptyCart) -> Result<(), &'static str> {
+        let _ = order_id;
+        let _ = cart;
+        Ok(())
+    }
+
+    fn ship_stock(&self, order_id: OrderId, cart: &NonEmptyCart) -> Result<(), &'static str> {
+        let _ = order_id;
+        let _ = cart;
+        Ok(())
+    }
+}
+
+/// Settings of the `InventoryService` of the runtimes.
+pub struct InventoryConfig {
+    /// How long a reservation holds its stock, unless it is renewed: an order checked out but never fulfilled
+    /// gives its stock back once it expires
+    pub reservation_ttl: Duration,
+}
+
+impl Default for InventoryConfig {
+    fn default() -> Self {
+        Self { reservation_ttl: Duration::from_secs(15 * 60) }
+    }
+}
+
+/// The id of the `InventoryItem` entity of a SKU: a stable hash (FNV-1a) of the SKU, the same in every process.
+/// The items are kept in a journal of their own, their ids don't collide with the ones of the orders.
+pub fn item_id(sku: &Sku) -> i64 {
+    let hash = sku.0.bytes().fold(0xcbf29ce484222325u64, |hash, byte| (hash ^ byte as u64).wrapping_mul(0x100000001b3));
+    (hash >> 1) as i64
+}
+
+/// The items of the cart, by item id: the order the runtimes reserve them in.
+pub fn cart_items(cart: &NonEmptyCart) -> Vec<(i64, u32)> {
+    let mut items: Vec<(i64, u32)> = cart.get_items().iter()
+        .map(|(sku, quantity)| (item_id(sku), quantity.0 as u32))
+        .collect();
+    items.sort_unstable();
+    items
+}
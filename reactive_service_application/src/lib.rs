@@ -0,0 +1,21 @@
+pub mod order_commands;
+pub mod order_queries;
+pub mod subscriptions;
+pub mod event_bus;
+pub mod catch_up;
+pub mod projections;
+pub mod outbox;
+pub mod order_fulfillment;
+pub mod scheduler;
+pub mod clock;
+pub mod command_builders;
+pub mod authorization;
+pub mod command_errors;
+pub mod middleware;
+pub mod metrics;
+pub mod shipping_calculator;
+pub mod tax_calculator;
+pub mod payment_processor;
+pub mod inventory;
+pub mod shipment_service;
+pub mod segmented_log;
@@ -0,0 +1,141 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use crate::middleware::LatencyHistogram;
+
+/// Where the runtimes report how the commands and the journal perform, e.g. a `PrometheusRegistry`.
+/// Called on the thread or task of the command: an implementation only records, it doesn't export.
+pub trait MetricsRegistry {
+    /// A command handled, see `OrderCommand::name`, with the error it failed with.
+    fn observe_command(&self, command: &'static str, elapsed: Duration, error: Option<&'static str>);
+    /// A call to the events journal: `load` the events of an entity, or `persist` the events of a command.
+    fn observe_journal(&self, operation: &'static str, elapsed: Duration, failed: bool);
+    /// The number of entities kept in memory.
+    fn set_cached_entities(&self, count: usize);
+}
+
+impl<R: MetricsRegistry + ?Sized> MetricsRegistry for &R {
+    fn observe_command(&self, command: &'static str, elapsed: Duration, error: Option<&'static str>) {
+        (**self).observe_command(command, elapsed, error)
+    }
+
+    fn observe_journal(&self, operation: &'static str, elapsed: Duration, failed: bool) {
+        (**self).observe_journal(operation, elapsed, failed)
+    }
+
+    fn set_cached_entities(&self, count: usize) {
+        (**self).set_cached_entities(count)
+    }
+}
+
+impl<R: MetricsRegistry + ?Sized> MetricsRegistry for Arc<R> {
+    fn observe_command(&self, command: &'static str, elapsed: Duration, error: Option<&'static str>) {
+        (**self).observe_command(command, elapsed, error)
+    }
+
+    fn observe_journal(&self, operation: &'static str, elapsed: Duration, failed: bool) {
+        (**self).observe_journal(operation, elapsed, failed)
+    }
+
+    fn set_cached_entities(&self, count: usize) {
+        (**self).set_cached_entities(count)
+    }
+}
+
+/// Records nothing, the default of the runtimes.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoMetrics;
+
+impl MetricsRegistry for NoMetrics {
+    fn observe_command(&self, _: &'static str, _: Duration, _: Option<&'static str>) {}
+    fn observe_journal(&self, _: &'static str, _: Duration, _: bool) {}
+    fn set_cached_entities(&self, _: usize) {}
+}
+
+#[derive(Debug, Default)]
+struct Recorded {
+    commands: HashMap<&'static str, LatencyHistogram>,
+    errors: HashMap<(&'static str, &'static str), u64>,
+    journal: HashMap<&'static str, LatencyHistogram>,
+    cached_entities: usize,
+}
+
+/// Keeps the metrics in memory, and renders them in the text format of Prometheus, e.g. for a `/metrics` endpoint:
+/// - `order_command_duration_seconds`, a histogram by `command`,
+/// - `order_command_errors_total`, a counter by `command` and `error`, the message of the error,
+/// - `events_journal_duration_seconds`, a histogram by `operation`,
+/// - `cached_entities`, a gauge.
+#[derive(Debug, Default)]
+pub struct PrometheusRegistry {
+    recorded: Mutex<Recorded>,
+}
+
+impl MetricsRegistry for PrometheusRegistry {
+    fn observe_command(&self, command: &'static str, elapsed: Duration, error: Option<&'static str>) {
+        if let Ok(mut recorded) = self.recorded.lock() {
+            recorded.commands.entry(command).or_default().record(elapsed, error.is_some());
+            if let Some(error) = error {
+                *recorded.errors.entry((command, error)).or_default() += 1;
+            }
+        }
+    }
+
+    fn observe_journal(&self, operation: &'static str, elapsed: Duration, failed: bool) {
+        if let Ok(mut recorded) = self.recorded.lock() {
+            recorded.journal.entry(operation).or_default().record(elapsed, failed);
+        }
+    }
+
+    fn set_cached_entities(&self, count: usize) {
+        if let Ok(mut recorded) = self.recorded.lock() {
+            recorded.cached_entities = count;
+        }
+    }
+}
+
+impl PrometheusRegistry {
+    /// The metrics recorded so far, in the text exposition format, sorted by labels.
+    pub fn render(&self) -> String {
+        let Ok(recorded) = self.recorded.lock() else { return String::new() };
+        let mut text = String::new();
+
+        render_histograms(&mut text, "order_command_duration_seconds", "Latency of the order commands.", "command", &recorded.commands);
+
+        let _ = writeln!(text, "# HELP order_command_errors_total Order commands failed, by error.");
+        let _ = writeln!(text, "# TYPE order_command_errors_total counter");
+        let mut errors: Vec<_> = recorded.errors.iter().collect();
+        errors.sort();
+        for ((command, error), count) in errors {
+            let _ = writeln!(text, "order_command_errors_total{{command=\"{}\",error=\"{}\"}} {}", escape(command), escape(error), count);
+        }
+
+        render_histograms(&mut text, "events_journal_duration_seconds", "Latency of the calls to the events journal.", "operation", &recorded.journal);
+
+        let _ = writeln!(text, "# HELP cached_entities Entities kept in memory.");
+        let _ = writeln!(text, "# TYPE cached_entities gauge");
+        let _ = writeln!(text, "cached_entities {}", recorded.cached_entities);
+        text
+    }
+}
+
+fn render_histograms(text: &mut String, name: &str, help: &str, label: &str, histograms: &HashMap<&'static str, LatencyHistogram>) {
+    let _ = writeln!(text, "# HELP {} {}", name, help);
+    let _ = writeln!(text, "# TYPE {} histogram", name);
+    let mut histograms: Vec<_> = histograms.iter().collect();
+    histograms.sort_by_key(|(value, _)| **value);
+    for (value, histogram) in histograms {
+        let value = escape(value);
+        for (bound, cumulated) in histogram.cumulative_buckets() {
+            let _ = writeln!(text, "{}_bucket{{{}=\"{}\",le=\"{}\"}} {}", name, label, value, bound.as_secs_f64(), cumulated);
+        }
+        let _ = writeln!(text, "{}_bucket{{{}=\"{}\",le=\"+Inf\"}} {}", name, label, value, histogram.count());
+        let _ = writeln!(text, "{}_sum{{{}=\"{}\"}} {}", name, label, value, histogram.total().as_secs_f64());
+        let _ = writeln!(text, "{}_count{{{}=\"{}\"}} {}", name, label, value, histogram.count());
+    }
+}
+
+/// A label value, with its backslashes, double quotes and line feeds escaped.
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
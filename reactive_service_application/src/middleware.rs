@@ -0,0 +1,164 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+use reactive_service_domain::customer::CustomerId;
+use tracing::{info, warn};
+use crate::authorization::Principal;
+use crate::order_commands::{CommandResult, OrderId};
+
+/// A command handled through the middlewares of a runtime, and how it went.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommandTrace {
+    pub order_id: OrderId,
+    /// The kind of the command, see `OrderCommand::name`.
+    pub command: &'static str,
+    /// The customer who issued the command, `None` for an admin.
+    pub customer_id: Option<CustomerId>,
+    pub elapsed: Duration,
+    /// The number of events of the command, or why it failed.
+    pub outcome: Result<usize, &'static str>,
+}
+
+impl CommandTrace {
+    pub fn new(order_id: OrderId, command: &'static str, principal: &Principal, elapsed: Duration, result: &CommandResult) -> Self {
+        let outcome = result.as_ref().map(|(_, events)| events.len()).map_err(|err| *err);
+        Self { order_id, command, customer_id: principal.customer_id(), elapsed, outcome }
+    }
+}
+
+/// Where the traces of the commands go, e.g. a log. Called once a command is over, on the thread or task which handled it.
+pub trait CommandTracer {
+    fn on_command(&self, trace: &CommandTrace);
+}
+
+impl<F: Fn(&CommandTrace)> CommandTracer for F {
+    fn on_command(&self, trace: &CommandTrace) {
+        self(trace)
+    }
+}
+
+/// Emits a `tracing` event per command: `info` when it succeeds, `warn` when it fails.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LogTracer;
+
+impl CommandTracer for LogTracer {
+    fn on_command(&self, trace: &CommandTrace) {
+        let elapsed_us = trace.elapsed.as_micros() as u64;
+        match trace.outcome {
+            Ok(events) => info!(order_id = trace.order_id, command = trace.command, customer_id = trace.customer_id,
+                                events, elapsed_us, "command handled"),
+            Err(error) => warn!(order_id = trace.order_id, command = trace.command, customer_id = trace.customer_id,
+                                error, elapsed_us, "command failed"),
+        }
+    }
+}
+
+/// Settings of the `ConcurrencyLimitLayer` of the runtimes.
+#[derive(Debug, Clone)]
+pub struct ConcurrencyLimitConfig {
+    /// Commands handled at once, across the threads or tasks.
+    pub max_in_flight: usize,
+    /// Longest wait of a command for its turn, before it fails.
+    pub max_wait: Duration,
+}
+
+impl Default for ConcurrencyLimitConfig {
+    fn default() -> Self {
+        Self { max_in_flight: 64, max_wait: Duration::from_secs(1) }
+    }
+}
+
+/// Upper bounds of the buckets of a `LatencyHistogram`, the last bucket holding the longer latencies.
+pub const LATENCY_BUCKETS: [Duration; 15] = [
+    Duration::from_micros(50), Duration::from_micros(100), Duration::from_micros(250), Duration::from_micros(500),
+    Duration::from_millis(1), Duration::from_micros(2_500), Duration::from_millis(5), Duration::from_millis(10),
+    Duration::from_millis(25), Duration::from_millis(50), Duration::from_millis(100), Duration::from_millis(250),
+    Duration::from_millis(500), Duration::from_secs(1), Duration::from_micros(2_500_000),
+];
+
+/// The latencies of a kind of command, by bucket: cheap to record, good enough for the percentiles.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LatencyHistogram {
+    buckets: [u64; LATENCY_BUCKETS.len() + 1],
+    count: u64,
+    errors: u64,
+    total: Duration,
+    max: Duration,
+}
+
+impl LatencyHistogram {
+    pub fn record(&mut self, elapsed: Duration, failed: bool) {
+        let bucket = LATENCY_BUCKETS.iter().position(|bound| elapsed <= *bound).unwrap_or(LATENCY_BUCKETS.len());
+        self.buckets[bucket] += 1;
+        self.count += 1;
+        self.errors += u64::from(failed);
+        self.total += elapsed;
+        self.max = self.max.max(elapsed);
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// The commands which failed, whatever the reason.
+    pub fn errors(&self) -> u64 {
+        self.errors
+    }
+
+    /// The sum of the latencies.
+    pub fn total(&self) -> Duration {
+        self.total
+    }
+
+    pub fn max(&self) -> Duration {
+        self.max
+    }
+
+    /// The number of latencies up to each bound of `LATENCY_BUCKETS`, cumulated.
+    pub fn cumulative_buckets(&self) -> impl Iterator<Item = (Duration, u64)> + '_ {
+        LATENCY_BUCKETS.iter().zip(self.buckets.iter().scan(0, |cumulated, count| {
+            *cumulated += count;
+            Some(*cumulated)
+        })).map(|(bound, cumulated)| (*bound, cumulated))
+    }
+
+    /// An upper bound of the latency under which are the `quantile` (from 0 to 1) of the commands: the bound of its bucket,
+    /// or the max latency past the last one. `None` until a latency is recorded.
+    pub fn quantile(&self, quantile: f64) -> Option<Duration> {
+        if self.count == 0 {
+            return None;
+        }
+        let rank = ((quantile.clamp(0.0, 1.0) * self.count as f64).ceil() as u64).max(1);
+        let bound = self.cumulative_buckets().find(|(_, cumulated)| *cumulated >= rank).map(|(bound, _)| bound);
+        Some(bound.map_or(self.max, |bound| bound.min(self.max)))
+    }
+}
+
+/// The latencies of the commands by kind, shared by the middlewares recording them and their readers.
+/// The lock is only held to record a latency, or to copy a histogram.
+#[derive(Debug, Default)]
+pub struct CommandLatencies {
+    histograms: Mutex<HashMap<&'static str, LatencyHistogram>>,
+}
+
+impl CommandLatencies {
+    pub fn record(&self, command: &'static str, elapsed: Duration, failed: bool) {
+        if let Ok(mut histograms) = self.histograms.lock() {
+            histograms.entry(command).or_default().record(elapsed, failed);
+        }
+    }
+
+    /// The latencies of a kind of command, see `OrderCommand::name`.
+    pub fn get(&self, command: &str) -> Option<LatencyHistogram> {
+        self.histograms.lock().ok()?.get(command).cloned()
+    }
+
+    /// The latencies of every kind of command recorded so far, by name.
+    pub fn snapshot(&self) -> Vec<(&'static str, LatencyHistogram)> {
+        let mut snapshot: Vec<_> = self.histograms.lock()
+            .map(|histograms| histograms.iter().map(|(command, histogram)| (*command, histogram.clone())).collect())
+            .unwrap_or_default();
+        snapshot.sort_by_key(|(command, _)| *command);
+        snapshot
+    }
+}
@@ -0,0 +1,99 @@
+use serde_derive::{Deserialize, Serialize};
+use reactive_service_domain::aggregate_root::SequencedEvent;
+use reactive_service_domain::non_empty_cart::NonEmptyCart;
+use reactive_service_domain::order_entity::{OrderEntity, OrderEntityCommand, OrderEvent};
+use reactive_service_domain::order_state::{DeliveryAddress, OrderState};
+use crate::authorization::Principal;
+use crate::command_builders::{expire_order_command, pay_order_command, update_cart_command, update_delivery_address_command};
+use crate::payment_processor::{PaymentProcessor, PaymentToken};
+use crate::shipping_calculator::ShippingCalculator;
+use crate::tax_calculator::TaxCalculator;
+
+pub type OrderId = i64;
+
+/// Owned result of a command, when it can't borrow the state from the service.
+pub type CommandResult = Result<(OrderState, Vec<SequencedEvent<OrderEvent>>), &'static str>;
+
+#[derive(Debug, Clone)]
+pub struct UpdateCart{pub order_id: OrderId, pub cart: NonEmptyCart}
+#[derive(Debug)]
+pub struct UpdateDeliveryAddress{pub order_id: OrderId, pub delivery_address: DeliveryAddress}
+#[derive(Debug)]
+pub struct PayOrder{pub order_id: OrderId, pub payment_token: PaymentToken}
+/// Expire the order, if it wasn't changed since the version `sequence_number`. Scheduled, rather than issued by a client.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExpireOrder{pub order_id: OrderId, pub sequence_number: i64}
+
+/// Any of the order commands, to queue them, send them to the entity owner or handle them in batch.
+#[derive(Debug)]
+pub enum OrderCommand {
+    UpdateCart(UpdateCart),
+    UpdateDeliveryAddress(UpdateDeliveryAddress),
+    PayOrder(PayOrder),
+    ExpireOrder(ExpireOrder),
+}
+
+impl OrderCommand {
+    pub fn order_id(&self) -> OrderId {
+        match self {
+            OrderCommand::UpdateCart(cmd) => cmd.order_id,
+            OrderCommand::UpdateDeliveryAddress(cmd) => cmd.order_id,
+            OrderCommand::PayOrder(cmd) => cmd.order_id,
+            OrderCommand::ExpireOrder(cmd) => cmd.order_id,
+        }
+    }
+
+    /// The kind of the command, e.g. to trace or measure it.
+    pub fn name(&self) -> &'static str {
+        match self {
+            OrderCommand::UpdateCart(_) => "update_cart",
+            OrderCommand::UpdateDeliveryAddress(_) => "update_delivery_address",
+            OrderCommand::PayOrder(_) => "pay_order",
+            OrderCommand::ExpireOrder(_) => "expire_order",
+        }
+    }
+
+    /// The cart to reserve the stock of, when the command checks the order out.
+    /// The runtimes reserve it before issuing the entity command: an order is only completed with its stock reserved.
+    pub fn checkout_cart<'a>(&self, order_state: &'a OrderState) -> Option<&'a NonEmptyCart> {
+        match (self, order_state) {
+            (OrderCommand::PayOrder(_), OrderState::WithAddress(with_addr)) => Some(with_addr.get_cart()),
+            _ => None,
+        }
+    }
+
+    /// The entity command to issue, given the current state of the order, once the principal is authorized on it.
+    /// A new command only needs a variant and a builder here, every runtime handles it the same way.
+    pub fn entity_command<S: ShippingCalculator, T: TaxCalculator, P: PaymentProcessor>(
+        self, principal: &Principal, order_entity: &OrderEntity, shipping_calculator: &S, tax_calculator: &T, payment_processor: &P
+    ) -> Result<OrderEntityCommand, &'static str> {
+
+        principal.authorize_order(order_entity)?;
+        match self {
+            OrderCommand::UpdateCart(cmd) =>
+                update_cart_command(order_entity, cmd.cart, principal.customer_id(), shipping_calculator, tax_calculator),
+            OrderCommand::UpdateDeliveryAddress(cmd) =>
+                update_delivery_address_command(order_entity, cmd.delivery_address, shipping_calculator, tax_calculator),
+            OrderCommand::PayOrder(cmd) =>
+                pay_order_command(order_entity, cmd.payment_token, payment_processor),
+            OrderCommand::ExpireOrder(cmd) =>
+                expire_order_command(order_entity, cmd.sequence_number),
+        }
+    }
+}
+
+impl From<UpdateCart> for OrderCommand {
+    fn from(cmd: UpdateCart) -> Self { OrderCommand::UpdateCart(cmd) }
+}
+
+impl From<UpdateDeliveryAddress> for OrderCommand {
+    fn from(cmd: UpdateDeliveryAddress) -> Self { OrderCommand::UpdateDeliveryAddress(cmd) }
+}
+
+impl From<PayOrder> for OrderCommand {
+    fn from(cmd: PayOrder) -> Self { OrderCommand::PayOrder(cmd) }
+}
+
+impl From<ExpireOrder> for OrderCommand {
+    fn from(cmd: ExpireOrder) -> Self { OrderCommand::ExpireOrder(cmd) }
+}
@@ -0,0 +1,379 @@
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant, SystemTime};
+use serde_derive::{Deserialize, Serialize};
+use reactive_service_domain::aggregate_root::{EventSourced, EventSourcedState};
+use reactive_service_domain::non_empty_cart::NonEmptyCart;
+use reactive_service_domain::order_state::{DeliveryAddress, OrderState};
+use crate::catch_up::CatchUpConfig;
+use crate::inventory::Inventory;
+use crate::order_commands::OrderId;
+use crate::payment_processor::PaymentProcessor;
+use crate::shipment_service::ShipmentService;
+
+/// The steps of the fulfillment of an order, in their order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FulfillmentStep {
+    ReserveStock,
+    CapturePayment,
+    CreateShipment,
+}
+
+impl FulfillmentStep {
+    pub fn next(&self) -> Option<FulfillmentStep> {
+        match self {
+            FulfillmentStep::ReserveStock => Some(FulfillmentStep::CapturePayment),
+            FulfillmentStep::CapturePayment => Some(FulfillmentStep::CreateShipment),
+            FulfillmentStep::CreateShipment => None,
+        }
+    }
+}
+
+/// How long each step may take from its start, recorded when the fulfillment starts.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StepTimeouts {
+    pub reserve_stock: Duration,
+    pub capture_payment: Duration,
+    pub create_shipment: Duration,
+}
+
+impl Default for StepTimeouts {
+    fn default() -> Self {
+        Self {
+            reserve_stock: Duration::from_secs(5),
+            capture_payment: Duration::from_secs(10),
+            create_shipment: Duration::from_secs(10),
+        }
+    }
+}
+
+impl StepTimeouts {
+    pub fn of(&self, step: FulfillmentStep) -> Duration {
+        match step {
+            FulfillmentStep::ReserveStock => self.reserve_stock,
+            FulfillmentStep::CapturePayment => self.capture_payment,
+            FulfillmentStep::CreateShipment => self.create_shipment,
+        }
+    }
+}
+
+/// What the steps need of the completed order.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FulfillmentOrder {
+    pub cart: NonEmptyCart,
+    pub delivery_address: DeliveryAddress,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FulfillmentStatus {
+    #[default]
+    NotStarted,
+    InProgress,
+    /// A step failed: the steps done are undone, the last one first
+    Compensating,
+    Fulfilled,
+    Cancelled,
+}
+
+/// A step in flight, failed if it doesn't complete by its deadline.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PendingStep {
+    pub step: FulfillmentStep,
+    pub deadline: SystemTime,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct FulfillmentState {
+    pub status: FulfillmentStatus,
+    pub order: Option<FulfillmentOrder>,
+    pub timeouts: StepTimeouts,
+    pub pending: Option<PendingStep>,
+    /// The steps done, to undo if a later one fails. A step timed out may be done: it is undone too.
+    pub to_compensate: Vec<FulfillmentStep>,
+    /// Why the fulfillment is compensated, or cancelled
+    pub failure: Option<String>,
+}
+
+/// What moves a fulfillment on, performed with the ports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FulfillmentAction {
+    Perform(FulfillmentStep),
+    /// Give up on a step past its deadline, e.g. left in flight when the process stopped
+    Expire(FulfillmentStep),
+    Compensate(FulfillmentStep),
+}
+
+impl FulfillmentState {
+    /// The next action at `now`, `None` once the fulfillment is over, or not started.
+    pub fn next_action(&self, now: SystemTime) -> Option<FulfillmentAction> {
+        match self.status {
+            FulfillmentStatus::InProgress => self.pending.map(|pending| {
+                if now > pending.deadline { FulfillmentAction::Expire(pending.step) } else { FulfillmentAction::Perform(pending.step) }
+            }),
+            FulfillmentStatus::Compensating => self.to_compensate.last().map(|step| FulfillmentAction::Compensate(*step)),
+            FulfillmentStatus::NotStarted | FulfillmentStatus::Fulfilled | FulfillmentStatus::Cancelled => None,
+        }
+    }
+
+    fn in_flight(&self, step: FulfillmentStep) -> Result<PendingStep, &'static str> {
+        match self.pending {
+            Some(pending) if self.status == FulfillmentStatus::InProgress && pending.step == step => Ok(pending),
+            _ => Err("Step not in progress"),
+        }
+    }
+}
+
+impl FulfillmentAction {
+    /// Perform the action with the ports, then the command recording its outcome.
+    /// A compensation failing is an error: it is performed again later, the fulfillment waits for it.
+    pub fn perform<I: Inventory, P: PaymentProcessor, S: ShipmentService>(
+        self, order_id: OrderId, state: &FulfillmentState, inventory: &I, payment_processor: &P, shipment_service: &S
+    ) -> Result<FulfillmentCommand, &'static str> {
+
+        let order = state.order.as_ref().ok_or("Fulfillment not started")?;
+        match self {
+            FulfillmentAction::Perform(step) => {
+                let outcome = match step {
+                    FulfillmentStep::ReserveStock => inventory.reserve_stock(order_id, &order.cart),
+                    FulfillmentStep::CapturePayment => payment_processor.capture_payment(order_id),
+                    FulfillmentStep::CreateShipment =>
+                        shipment_service.create_shipment(order_id, &order.cart, &order.delivery_address)
+                            .and_then(|_| inventory.ship_stock(order_id, &order.cart).inspect_err(|_| {
+                                // Not compensated as a failed step: the shipment is cancelled right away, at best
+                                let _ = shipment_service.cancel_shipment(order_id);
+                            })),
+                };
+                Ok(FulfillmentCommand::RecordStep { step, outcome, at: SystemTime::now() })
+            },
+            FulfillmentAction::Expire(step) => Ok(FulfillmentCommand::ExpireStep { step, at: SystemTime::now() }),
+            FulfillmentAction::Compensate(step) => {
+                match step {
+                    FulfillmentStep::ReserveStock => inventory.release_stock(order_id, &order.cart)?,
+                    FulfillmentStep::CapturePayment => payment_processor.refund_payment(order_id)?,
+                    FulfillmentStep::CreateShipment => shipment_service.cancel_shipment(order_id)?,
+                }
+                Ok(FulfillmentCommand::RecordCompensation { step })
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum FulfillmentCommand {
+    Start { order: FulfillmentOrder, timeouts: StepTimeouts, at: SystemTime },
+    /// The outcome of the step in flight, at the time it was known
+    RecordStep { step: FulfillmentStep, outcome: Result<(), &'static str>, at: SystemTime },
+    ExpireStep { step: FulfillmentStep, at: SystemTime },
+    RecordCompensation { step: FulfillmentStep },
+}
+
+impl FulfillmentCommand {
+    /// Start the fulfillment of the order, once it is completed.
+    pub fn start(order_state: &OrderState, timeouts: StepTimeouts, at: SystemTime) -> Result<Self, &'static str> {
+        match order_state {
+            OrderState::Completed(completed) => {
+                let order = FulfillmentOrder {
+                    cart: completed.get_cart().clone(),
+                    delivery_address: completed.get_delivery_address().clone(),
+                };
+                Ok(FulfillmentCommand::Start { order, timeouts, at })
+            },
+            _ => Err("Order is not completed"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum FulfillmentEvent {
+    Started { order: FulfillmentOrder, timeouts: StepTimeouts },
+    StepStarted { step: FulfillmentStep, deadline: SystemTime },
+    StepSucceeded { step: FulfillmentStep },
+    StepFailed { step: FulfillmentStep, reason: String },
+    /// The step didn't complete by its deadline: it may be done, it is compensated
+    StepTimedOut { step: FulfillmentStep },
+    Compensated { step: FulfillmentStep },
+    Fulfilled,
+    Cancelled { reason: String },
+}
+
+/// The process manager of the fulfillment of a completed order: reserves its stock, renewing the reservation made at checkout,
+/// captures its payment, then creates its shipment, taking its stock out of the inventory, one step at a time. When a step fails, or doesn't complete by its deadline,
+/// the steps done are compensated in the reverse order (the stock released, the payment refunded...),
+/// then the fulfillment is cancelled.
+///
+/// Its state is persisted as events, in a journal of its own, with the id of its order:
+/// the runtimes host it as any other aggregate, and resume it after a restart where it stopped.
+/// The steps and compensations are performed again when their outcome wasn't recorded: the ports are idempotent.
+pub type OrderFulfillment = EventSourced<FulfillmentState>;
+
+impl EventSourcedState for FulfillmentState {
+    type Command = FulfillmentCommand;
+    type Error = &'static str;
+    type Event = FulfillmentEvent;
+
+    fn decide(&self, command: FulfillmentCommand) -> Result<Vec<FulfillmentEvent>, &'static str> {
+        match command {
+            FulfillmentCommand::Start { order, timeouts, at } => {
+                if self.status != FulfillmentStatus::NotStarted {
+                    // The completion of the order delivered again
+                    return Ok(vec![]);
+                }
+                let deadline = at + timeouts.of(FulfillmentStep::ReserveStock);
+                Ok(vec![
+                    FulfillmentEvent::Started { order, timeouts },
+                    FulfillmentEvent::StepStarted { step: FulfillmentStep::ReserveStock, deadline },
+                ])
+            },
+            FulfillmentCommand::RecordStep { step, outcome, at } => {
+                let pending = self.in_flight(step)?;
+                match outcome {
+                    Ok(()) if at <= pending.deadline => {
+                        let next = match step.next() {
+                            Some(next) => FulfillmentEvent::StepStarted { step: next, deadline: at + self.timeouts.of(next) },
+                            None => FulfillmentEvent::Fulfilled,
+                        };
+                        Ok(vec![FulfillmentEvent::StepSucceeded { step }, next])
+                    },
+                    Ok(()) => Ok(vec![FulfillmentEvent::StepTimedOut { step }]),
+                    Err(reason) => {
+                        let mut events = vec![FulfillmentEvent::StepFailed { step, reason: reason.to_owned() }];
+                        if self.to_compensate.is_empty() {
+                            events.push(FulfillmentEvent::Cancelled { reason: reason.to_owned() });
+                        }
+                        Ok(events)
+                    },
+                }
+            },
+            FulfillmentCommand::ExpireStep { step, at } => {
+                if at <= self.in_flight(step)?.deadline {
+                    return Err("Step not timed out yet");
+                }
+                Ok(vec![FulfillmentEvent::StepTimedOut { step }])
+            },
+            FulfillmentCommand::RecordCompensation { step } => {
+                if self.status != FulfillmentStatus::Compensating || self.to_compensate.last() != Some(&step) {
+                    return Err("Step not being compensated");
+                }
+                let mut events = vec![FulfillmentEvent::Compensated { step }];
+                if self.to_compensate.len() == 1 {
+                    events.push(FulfillmentEvent::Cancelled { reason: self.failure.clone().unwrap_or_default() });
+                }
+                Ok(events)
+            },
+        }
+    }
+
+    fn apply(&mut self, event: FulfillmentEvent) -> Result<(), &'static str> {
+        if self.status == FulfillmentStatus::NotStarted && !matches!(event, FulfillmentEvent::Started { .. }) {
+            return Err("Fulfillment not started");
+        }
+        match event {
+            FulfillmentEvent::Started { order, timeouts } => {
+                if self.status != FulfillmentStatus::NotStarted {
+                    return Err("Fulfillment already started");
+                }
+                *self = FulfillmentState { status: FulfillmentStatus::InProgress, order: Some(order), timeouts, ..FulfillmentState::default() };
+            },
+            FulfillmentEvent::StepStarted { step, deadline } => self.pending = Some(PendingStep { step, deadline }),
+            FulfillmentEvent::StepSucceeded { step } => {
+                self.pending = None;
+                self.to_compensate.push(step);
+            },
+            FulfillmentEvent::StepFailed { reason, .. } => {
+                self.pending = None;
+                self.status = FulfillmentStatus::Compensating;
+                self.failure = Some(reason);
+            },
+            FulfillmentEvent::StepTimedOut { step } => {
+                self.pending = None;
+                self.to_compensate.push(step);
+                self.status = FulfillmentStatus::Compensating;
+                self.failure = Some("Step timed out".to_owned());
+            },
+            FulfillmentEvent::Compensated { .. } => {
+                self.to_compensate.pop();
+            },
+            FulfillmentEvent::Fulfilled => self.status = FulfillmentStatus::Fulfilled,
+            FulfillmentEvent::Cancelled { reason } => {
+                self.status = FulfillmentStatus::Cancelled;
+                self.failure = Some(reason);
+            },
+        }
+        Ok(())
+    }
+}
+
+/// The checkpoint of the `FulfillmentManager`, in its `CheckpointStore`.
+pub const FULFILLMENT_CONSUMER: &str = "order_fulfillment";
+
+/// Settings of the `FulfillmentManager` of the runtimes.
+#[derive(Debug, Clone)]
+pub struct FulfillmentConfig {
+    /// Timeouts of the steps of the fulfillments started from now on.
+    pub step_timeouts: StepTimeouts,
+    pub catch_up: CatchUpConfig,
+    /// Wait before retrying a failed fulfillment, doubled after each failure.
+    pub retry_delay: Duration,
+    pub max_retry_delay: Duration,
+}
+
+impl Default for FulfillmentConfig {
+    fn default() -> Self {
+        Self {
+            step_timeouts: StepTimeouts::default(),
+            catch_up: CatchUpConfig::default(),
+            retry_delay: Duration::from_secs(1),
+            max_retry_delay: Duration::from_secs(60),
+        }
+    }
+}
+
+/// The failed fulfillments of a `FulfillmentManager`, by position of the completion of their order, until they are over.
+#[derive(Debug, Default)]
+pub struct FulfillmentRetries {
+    retries: BTreeMap<i64, Retry>,
+}
+
+#[derive(Debug)]
+struct Retry {
+    order_id: OrderId,
+    failures: u32,
+    at: Instant,
+}
+
+impl FulfillmentRetries {
+    pub fn len(&self) -> usize {
+        self.retries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.retries.is_empty()
+    }
+
+    /// Retry the fulfillment of the order completed at `position` after a backoff. Returns its number of failures.
+    pub fn failed(&mut self, position: i64, order_id: OrderId, now: Instant, config: &FulfillmentConfig) -> u32 {
+        let retry = self.retries.entry(position).or_insert(Retry { order_id, failures: 0, at: now });
+        retry.failures += 1;
+        let delay = config.retry_delay.saturating_mul(1 << (retry.failures - 1).min(16));
+        retry.at = now + delay.min(config.max_retry_delay);
+        retry.failures
+    }
+
+    /// The fulfillments to retry at `now`: the position of the completion of their order, and the order.
+    pub fn due(&self, now: Instant) -> Vec<(i64, OrderId)> {
+        self.retries.iter()
+            .filter(|(_, retry)| retry.at <= now)
+            .map(|(position, retry)| (*position, retry.order_id))
+            .collect()
+    }
+
+    /// The fulfillment of the order completed at `position` is over.
+    pub fn over(&mut self, position: i64) {
+        self.retries.remove(&position);
+    }
+
+    /// The checkpoint once the events up to `position` are processed: before the first failed fulfillment,
+    /// to resume it after a restart.
+    pub fn checkpoint(&self, position: i64) -> i64 {
+        self.retries.keys().next().map_or(position, |first| first - 1)
+    }
+}
@@ -0,0 +1,15 @@
+use reactive_service_domain::order_state::OrderState;
+
+/// A state as of the sequence number of the last event applied to it.
+///
+/// The runtimes only expose persisted states: a version returned by a command
+/// is visible to the queries once the command has replied.
+#[derive(Debug, Clone)]
+pub struct Versioned<S> {
+    pub state: S,
+    pub sequence_number: i64,
+}
+
+pub type OrderView = Versioned<OrderState>;
+
+pub type QueryResult = Result<OrderView, &'static str>;
@@ -0,0 +1,63 @@
+use std::time::Duration;
+use crate::order_commands::OrderId;
+
+/// An event to publish to other services, as persisted in the journal: its payload is the serialized event.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutboxMessage {
+    pub position: i64,
+    pub entity_id: OrderId,
+    pub sequence_number: i64,
+    pub payload: String,
+}
+
+impl OutboxMessage {
+    /// The message as a line of JSON, without the line feed. The payload is JSON already, it is embedded as is.
+    pub fn to_json_line(&self) -> String {
+        format!(
+            r#"{{"position":{},"entity_id":{},"sequence_number":{},"event":{}}}"#,
+            self.position, self.entity_id, self.sequence_number, self.payload
+        )
+    }
+}
+
+/// A message the relay gave up on, kept aside until it is unparked.
+#[derive(Debug, Clone)]
+pub struct ParkedMessage {
+    pub message: OutboxMessage,
+    pub attempts: u32,
+    pub last_error: String,
+}
+
+/// Settings of the outbox relays.
+#[derive(Debug, Clone)]
+pub struct OutboxRelayConfig {
+    /// Messages read from the outbox at once.
+    pub batch_size: usize,
+    /// Longest wait for new messages before reading the outbox again, e.g. for a retry.
+    pub poll_interval: Duration,
+    /// Wait before the first retry of a message, doubled on each retry.
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    /// Attempts to publish a message before it is parked.
+    pub max_attempts: u32,
+}
+
+impl Default for OutboxRelayConfig {
+    fn default() -> Self {
+        Self {
+            batch_size: 100,
+            poll_interval: Duration::from_secs(1),
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(60),
+            max_attempts: 10,
+        }
+    }
+}
+
+impl OutboxRelayConfig {
+    /// Wait before the next attempt, once a message failed `attempts` times.
+    pub fn backoff(&self, attempts: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempts.saturating_sub(1));
+        self.initial_backoff.saturating_mul(factor).min(self.max_backoff)
+    }
+}
@@ -0,0 +1,42 @@
+use reactive_service_domain::order_state::Invoice;
+use crate::order_commands::OrderId;
+
+#[derive(Debug, Clone)]
+pub struct PaymentToken(String);
+
+impl PaymentToken {
+    pub fn new(token: impl Into<String>) -> Self {
+        Self(token.into())
+    }
+}
+
+/// The payment of an order is authorized when it is paid with its token, then captured once its stock is reserved.
+pub trait PaymentProcessor {
+    fn pay_with_token(&self, payment_token: PaymentToken) -> Invoice;
+
+    /// Capture the authorized payment of the order. Capturing it again is a no-op.
+    fn capture_payment(&self, order_id: OrderId) -> Result<(), &'static str>;
+
+    /// Refund the captured payment of the order, or release it if only authorized.
+    /// Refunding it again, or an order without payment, is a no-op.
+    fn refund_payment(&self, order_id: OrderId) -> Result<(), &'static str>;
+}
+
+pub struct LocalPaymentProcessor {}
+
+impl PaymentProcessor for LocalPaymentProcessor {
+    fn pay_with_token(&self, payment_token: PaymentToken) -> Invoice {
+        let _ = payment_token.0;
+        Invoice{}
+    }
+
+    fn capture_payment(&self, order_id: OrderId) -> Result<(), &'static str> {
+        let _ = order_id;
+        Ok(())
+    }
+
+    fn refund_payment(&self, order_id: OrderId) -> Result<(), &'static str> {
+        let _ = order_id;
+        Ok(())
+    }
+}
@@ -0,0 +1,177 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+use reactive_service_domain::non_empty_cart::{NonEmptyCart, Sku};
+use reactive_service_domain::order_entity::OrderEvent;
+use reactive_service_domain::order_state::Money;
+use crate::order_commands::OrderId;
+use crate::subscriptions::Positioned;
+
+/// A view of the orders, folded from their events in the global order of the journal.
+/// The views are shared by every runtime: kept in memory as is, or as the model of their database tables.
+pub trait ReadModel: Default {
+    fn apply(&mut self, positioned: &Positioned<OrderEvent>);
+}
+
+/// A `ReadModel` with its checkpoint, the position of the last event applied to it: the view of the in-memory projections.
+#[derive(Debug, Default)]
+pub struct CheckpointedModel<M> {
+    model: M,
+    checkpoint: i64,
+}
+
+impl<M: ReadModel> CheckpointedModel<M> {
+    pub fn model(&self) -> &M {
+        &self.model
+    }
+
+    pub fn checkpoint(&self) -> i64 {
+        self.checkpoint
+    }
+
+    /// Apply the events after the checkpoint, in their order: an event delivered again is skipped.
+    pub fn apply(&mut self, events: &[Positioned<OrderEvent>]) {
+        for positioned in events {
+            if positioned.position > self.checkpoint {
+                self.model.apply(positioned);
+                self.checkpoint = positioned.position;
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OrderStatus {
+    WithCart,
+    WithAddress,
+    Completed,
+    Expired,
+}
+
+impl OrderStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OrderStatus::WithCart => "WithCart",
+            OrderStatus::WithAddress => "WithAddress",
+            OrderStatus::Completed => "Completed",
+            OrderStatus::Expired => "Expired",
+        }
+    }
+}
+
+impl FromStr for OrderStatus {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "WithCart" => Ok(OrderStatus::WithCart),
+            "WithAddress" => Ok(OrderStatus::WithAddress),
+            "Completed" => Ok(OrderStatus::Completed),
+            "Expired" => Ok(OrderStatus::Expired),
+            _ => Err("Invalid order status"),
+        }
+    }
+}
+
+/// A row of the order summary view.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OrderSummary {
+    pub order_id: OrderId,
+    pub status: OrderStatus,
+    /// The cart has no prices: the total is the shipping cost and the tax, 0 until there is a delivery address.
+    pub total_cents: u32,
+    pub postal_code: Option<String>,
+    /// The events have no time: the position of the last event of the order, which orders the updates across the orders.
+    pub last_updated: i64,
+}
+
+impl OrderSummary {
+    /// The summary of the order once the event is applied to its previous summary, if any.
+    pub fn evolve(previous: Option<&OrderSummary>, positioned: &Positioned<OrderEvent>) -> OrderSummary {
+        let total_cents = previous.map_or(0, |summary| summary.total_cents);
+        let postal_code = previous.and_then(|summary| summary.postal_code.clone());
+        let (status, total_cents, postal_code) = match &positioned.event.event {
+            OrderEvent::UpdatedCart { .. } => (OrderStatus::WithCart, total_cents, postal_code),
+            OrderEvent::UpdatedDeliveryAddress { delivery_address, shipping_cost, tax } =>
+                (OrderStatus::WithAddress, total(shipping_cost, tax), Some(delivery_address.postal_code.to_string())),
+            OrderEvent::UpdatedCartOnExistingDeliveryAddress { shipping_cost, tax, .. } =>
+                (OrderStatus::WithAddress, total(shipping_cost, tax), postal_code),
+            OrderEvent::Completed { .. } => (OrderStatus::Completed, total_cents, postal_code),
+            OrderEvent::Expired => (OrderStatus::Expired, total_cents, postal_code),
+        };
+        OrderSummary { order_id: positioned.entity_id, status, total_cents, postal_code, last_updated: positioned.position }
+    }
+}
+
+fn total(shipping_cost: &Money, tax: &Money) -> u32 {
+    shipping_cost.amount_cents.saturating_add(tax.amount_cents)
+}
+
+/// The order summary view, by order id.
+#[derive(Debug, Default)]
+pub struct OrderSummaries {
+    by_order: HashMap<OrderId, OrderSummary>,
+}
+
+impl OrderSummaries {
+    pub fn get(&self, order_id: OrderId) -> Option<&OrderSummary> {
+        self.by_order.get(&order_id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.by_order.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_order.is_empty()
+    }
+}
+
+impl ReadModel for OrderSummaries {
+    fn apply(&mut self, positioned: &Positioned<OrderEvent>) {
+        let summary = OrderSummary::evolve(self.by_order.get(&positioned.entity_id), positioned);
+        self.by_order.insert(positioned.entity_id, summary);
+    }
+}
+
+/// A row of the sales view: the quantity of a SKU sold, and the number of orders it was sold in.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SkuSales {
+    pub quantity: u64,
+    pub orders: u64,
+}
+
+/// The sales of the completed orders, by SKU.
+/// A completed order doesn't carry its cart: the view keeps the last cart of each open order, to count it once completed.
+#[derive(Debug, Default)]
+pub struct SalesBySku {
+    sales: HashMap<Sku, SkuSales>,
+    open_carts: HashMap<OrderId, NonEmptyCart>,
+}
+
+impl SalesBySku {
+    pub fn get(&self, sku: &Sku) -> Option<&SkuSales> {
+        self.sales.get(sku)
+    }
+}
+
+impl ReadModel for SalesBySku {
+    fn apply(&mut self, positioned: &Positioned<OrderEvent>) {
+        match &positioned.event.event {
+            OrderEvent::UpdatedCart { cart, .. } | OrderEvent::UpdatedCartOnExistingDeliveryAddress { cart, .. } => {
+                self.open_carts.insert(positioned.entity_id, cart.clone());
+            },
+            OrderEvent::UpdatedDeliveryAddress { .. } => {},
+            OrderEvent::Expired => {
+                self.open_carts.remove(&positioned.entity_id);
+            },
+            OrderEvent::Completed { .. } => {
+                let Some(cart) = self.open_carts.remove(&positioned.entity_id) else { return };
+                for (sku, quantity) in cart.get_items() {
+                    let sales = self.sales.entry(sku.clone()).or_default();
+                    sales.quantity += u64::from(quantity.0);
+                    sales.orders += 1;
+                }
+            },
+        }
+    }
+}
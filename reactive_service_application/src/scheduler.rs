@@ -0,0 +1,115 @@
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use serde_derive::{Deserialize, Serialize};
+use reactive_service_domain::aggregate_root::SequencedEvent;
+use reactive_service_domain::order_entity::OrderEvent;
+use crate::catch_up::CatchUpConfig;
+use crate::order_commands::{ExpireOrder, OrderCommand, OrderId};
+
+/// The checkpoint of the `CommandScheduler`, in its `CheckpointStore`.
+pub const SCHEDULER_CONSUMER: &str = "command_scheduler";
+
+/// Settings of the `CommandScheduler`.
+#[derive(Debug, Clone)]
+pub struct SchedulerConfig {
+    /// How long an order may stay open, untouched, before it expires.
+    pub order_ttl: Duration,
+    /// Due commands delivered at once.
+    pub batch_size: usize,
+    pub catch_up: CatchUpConfig,
+}
+
+impl Default for SchedulerConfig {
+    fn default() -> Self {
+        Self { order_ttl: Duration::from_secs(24 * 60 * 60), batch_size: 100, catch_up: CatchUpConfig::default() }
+    }
+}
+
+/// A command the domain asks to deliver at a future time, kept by the schedule stores of the runtimes.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ScheduledCommand {
+    ExpireOrder(ExpireOrder),
+}
+
+impl ScheduledCommand {
+    /// Scheduling a command under the key of a pending one replaces it: an order has at most one expiry pending.
+    pub fn key(&self) -> String {
+        match self {
+            ScheduledCommand::ExpireOrder(cmd) => format!("expire_order/{}", cmd.order_id),
+        }
+    }
+}
+
+impl From<ScheduledCommand> for OrderCommand {
+    fn from(command: ScheduledCommand) -> Self {
+        match command {
+            ScheduledCommand::ExpireOrder(cmd) => OrderCommand::ExpireOrder(cmd),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Scheduled {
+    pub due_at: SystemTime,
+    pub command: ScheduledCommand,
+}
+
+impl Scheduled {
+    /// The expiry of the order `ttl` after `now`, if the event leaves the order open.
+    /// The expiry is stale once the order changes again: it is only scheduled for the version of the event.
+    pub fn order_expiry(order_id: OrderId, seq_event: &SequencedEvent<OrderEvent>, now: SystemTime, ttl: Duration) -> Option<Scheduled> {
+        match seq_event.event {
+            OrderEvent::UpdatedCart { .. } | OrderEvent::UpdatedDeliveryAddress { .. } | OrderEvent::UpdatedCartOnExistingDeliveryAddress { .. } => {
+                let command = ScheduledCommand::ExpireOrder(ExpireOrder { order_id, sequence_number: seq_event.sequence_number });
+                Some(Scheduled { due_at: now + ttl, command })
+            },
+            OrderEvent::Completed { .. } | OrderEvent::Expired => None,
+        }
+    }
+
+    pub fn key(&self) -> String {
+        self.command.key()
+    }
+
+    /// The due time in milliseconds since the epoch, as the databases store it.
+    pub fn due_at_millis(&self) -> i64 {
+        to_millis(self.due_at)
+    }
+
+    pub fn from_millis(due_at_millis: i64, command: ScheduledCommand) -> Self {
+        Self { due_at: UNIX_EPOCH + Duration::from_millis(due_at_millis.max(0) as u64), command }
+    }
+}
+
+/// The commands pending in memory, by key: the in-memory schedule stores of the runtimes.
+#[derive(Debug, Default)]
+pub struct PendingCommands {
+    scheduled: HashMap<String, Scheduled>,
+}
+
+impl PendingCommands {
+    /// Schedule the command, replacing the one pending under the same key.
+    pub fn schedule(&mut self, scheduled: &Scheduled) {
+        self.scheduled.insert(scheduled.key(), scheduled.clone());
+    }
+
+    /// Up to `limit` commands due at `now`, the earliest first.
+    pub fn due(&self, now: SystemTime, limit: usize) -> Vec<Scheduled> {
+        let mut due: Vec<Scheduled> = self.scheduled.values().filter(|scheduled| scheduled.due_at <= now).cloned().collect();
+        due.sort_by_key(|scheduled| scheduled.due_at);
+        due.truncate(limit);
+        due
+    }
+
+    /// Remove the command once delivered, unless it was scheduled again meanwhile.
+    pub fn remove(&mut self, delivered: &Scheduled) {
+        if self.scheduled.get(&delivered.key()) == Some(delivered) {
+            self.scheduled.remove(&delivered.key());
+        }
+    }
+}
+
+/// Milliseconds since the epoch, 0 before it.
+pub fn to_millis(time: SystemTime) -> i64 {
+    time.duration_since(UNIX_EPOCH).map_or(0, |since_epoch| since_epoch.as_millis() as i64)
+}
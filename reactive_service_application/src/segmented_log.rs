@@ -0,0 +1,319 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use crate::command_errors::{PERSIST_EVENT_FAILED, RETRIEVE_EVENTS_FAILED};
+
+/// When the appended events are flushed to the disk.
+#[derive(Debug, Clone)]
+pub enum FsyncPolicy {
+    /// Each append returns once its events are on the disk.
+    EveryWrite,
+    /// Flush once `max_writes` appends are pending, or once the oldest pending one is `max_delay` old,
+    /// checked on each append. A machine crash loses the pending appends.
+    Batched { max_writes: usize, max_delay: Duration },
+    /// Left to the operating system. A process crash loses nothing, a machine crash may.
+    Never,
+}
+
+/// Settings of the file journals.
+#[derive(Debug, Clone)]
+pub struct FileJournalConfig {
+    pub directory: PathBuf,
+    /// A new segment file is started once the active one would grow past this size.
+    pub segment_size: u64,
+    pub fsync: FsyncPolicy,
+}
+
+impl Default for FileJournalConfig {
+    fn default() -> Self {
+        Self {
+            directory: PathBuf::from("journal"),
+            segment_size: 64 * 1024 * 1024,
+            fsync: FsyncPolicy::EveryWrite,
+        }
+    }
+}
+
+/// Entity id, sequence number and serialized event.
+pub type Record = (i64, i64, Vec<u8>);
+
+/// Length and CRC32
+const HEADER_LEN: u64 = 8;
+/// Entity id and sequence number, at the start of the payload
+const KEY_LEN: u32 = 16;
+
+/// Position of a record payload.
+#[derive(Clone, Copy)]
+struct Location { segment: usize, offset: u64, len: u32 }
+
+struct Segment { file: File, size: u64 }
+
+/// Log of serialized events in segment files, behind the file journal of each runtime.
+///
+/// Each record is `length (u32) | CRC32 of the payload (u32) | payload`, where the payload is
+/// `entity id (i64) | sequence number (i64) | serialized event`, all integers little endian.
+/// The offsets of the events of each entity are indexed in memory, as the offsets of all the events in the log order,
+/// their positions: the indexes are rebuilt by scanning the segments when the log is opened.
+///
+/// An invalid record at the end of the last segment, with no valid record after it, was torn by a crash
+/// in the middle of a write: it is truncated. Any other invalid record is a corruption, the log refuses to open.
+///
+/// A sequence number already persisted for an entity is rejected, as the primary key of the SQL stores does.
+pub struct SegmentedLog {
+    config: FileJournalConfig,
+    segments: Vec<Segment>,
+    next_segment_id: u64,
+    index: HashMap<i64, BTreeMap<i64, Location>>,
+    /// The location of each event, in the log order: the position of an event is its rank, starting at 1
+    positions: Vec<Location>,
+    pending_writes: usize,
+    oldest_pending_write: Option<Instant>,
+}
+
+impl SegmentedLog {
+    pub fn open(config: FileJournalConfig) -> Result<Self, Box<dyn std::error::Error>> {
+        fs::create_dir_all(&config.directory)?;
+
+        let mut segment_ids: Vec<u64> = fs::read_dir(&config.directory)?
+            .filter_map(|entry| entry.ok()?.file_name().to_str()?.strip_suffix(".log")?.parse().ok())
+            .collect();
+        segment_ids.sort();
+        if segment_ids.is_empty() {
+            segment_ids.push(0);
+        }
+
+        let last_segment = segment_ids.len() - 1;
+        let mut segments = Vec::with_capacity(segment_ids.len());
+        let mut index = HashMap::new();
+        let mut positions = Vec::new();
+        for (position, segment_id) in segment_ids.iter().enumerate() {
+            let path = segment_path(&config.directory, *segment_id);
+            let mut file = OpenOptions::new().read(true).write(true).create(true).truncate(false).open(&path)?;
+            let size = file.metadata()?.len();
+            let valid_size = scan_segment(&mut file, size, position, &mut index, &mut positions)?;
+            if valid_size < size {
+                if position != last_segment || valid_record_after(&mut file, valid_size, size)? {
+                    return Err(format!("Corrupted journal segment {} at offset {}", path.display(), valid_size).into());
+                }
+                // Torn write of a crash, none of its events were acknowledged
+                file.set_len(valid_size)?;
+                file.sync_all()?;
+            }
+            segments.push(Segment { file, size: valid_size });
+        }
+
+        Ok(Self {
+            config,
+            segments,
+            next_segment_id: segment_ids[last_segment] + 1,
+            index,
+            positions,
+            pending_writes: 0,
+            oldest_pending_write: None,
+        })
+    }
+
+    /// All or nothing: the records are indexed, hence readable, only once written.
+    pub fn append(&mut self, records: Vec<Record>) -> Result<(), &'static str> {
+        let mut keys = HashSet::with_capacity(records.len());
+        for (entity_id, sequence_number, _) in &records {
+            let persisted = self.index.get(entity_id).is_some_and(|events| events.contains_key(sequence_number));
+            if persisted || !keys.insert((*entity_id, *sequence_number)) {
+                return Err(PERSIST_EVENT_FAILED);
+            }
+        }
+
+        let mut buffer = Vec::new();
+        let mut offsets = Vec::with_capacity(records.len());
+        for (entity_id, sequence_number, event_payload) in &records {
+            let mut payload = Vec::with_capacity(KEY_LEN as usize + event_payload.len());
+            payload.extend_from_slice(&entity_id.to_le_bytes());
+            payload.extend_from_slice(&sequence_number.to_le_bytes());
+            payload.extend_from_slice(event_payload);
+            let len = u32::try_from(payload.len()).map_err(|_| PERSIST_EVENT_FAILED)?;
+
+            offsets.push((buffer.len() as u64 + HEADER_LEN, len));
+            buffer.extend_from_slice(&len.to_le_bytes());
+            buffer.extend_from_slice(&crc32fast::hash(&payload).to_le_bytes());
+            buffer.extend_from_slice(&payload);
+        }
+
+        let active = self.active_segment_for(buffer.len() as u64).map_err(|_| PERSIST_EVENT_FAILED)?;
+        let segment = &mut self.segments[active];
+        let start = segment.size;
+        let written = segment.file.seek(SeekFrom::Start(start))
+            .and_then(|_| segment.file.write_all(&buffer))
+            .and_then(|_| self.flush_after_write(active));
+        if written.is_err() {
+            let _ = self.segments[active].file.set_len(start);
+            return Err(PERSIST_EVENT_FAILED);
+        }
+
+        self.segments[active].size += buffer.len() as u64;
+        for ((entity_id, sequence_number, _), (offset, len)) in records.iter().zip(offsets) {
+            let location = Location { segment: active, offset: start + offset, len };
+            self.index.entry(*entity_id).or_default().insert(*sequence_number, location);
+            self.positions.push(location);
+        }
+        Ok(())
+    }
+
+    pub fn last_position(&self) -> i64 {
+        self.positions.len() as i64
+    }
+
+    /// Sequence numbers and serialized events of an entity, in order.
+    pub fn read(&mut self, entity_id: i64) -> Result<Vec<(i64, Vec<u8>)>, &'static str> {
+        let Some(locations) = self.index.get(&entity_id) else {
+            return Ok(Vec::new());
+        };
+
+        locations.iter()
+            .map(|(sequence_number, location)| {
+                let file = &mut self.segments[location.segment].file;
+                let mut event_payload = vec![0; (location.len - KEY_LEN) as usize];
+                file.seek(SeekFrom::Start(location.offset + KEY_LEN as u64))
+                    .and_then(|_| file.read_exact(&mut event_payload))
+                    .map_err(|_| RETRIEVE_EVENTS_FAILED)?;
+                Ok((*sequence_number, event_payload))
+            })
+            .collect()
+    }
+
+    /// Positions and records of up to `batch_size` events, from `from_position` included.
+    pub fn read_all(&mut self, from_position: i64, batch_size: usize) -> Result<Vec<(i64, Record)>, &'static str> {
+        let start = usize::try_from(from_position - 1).unwrap_or(0).min(self.positions.len());
+        let end = start + batch_size.min(self.positions.len() - start);
+
+        (start..end)
+            .map(|rank| {
+                let location = self.positions[rank];
+                let file = &mut self.segments[location.segment].file;
+                let mut payload = vec![0; location.len as usize];
+                file.seek(SeekFrom::Start(location.offset))
+                    .and_then(|_| file.read_exact(&mut payload))
+                    .map_err(|_| RETRIEVE_EVENTS_FAILED)?;
+                let entity_id = i64::from_le_bytes(payload[0..8].try_into().unwrap());
+                let sequence_number = i64::from_le_bytes(payload[8..16].try_into().unwrap());
+                let event_payload = payload.split_off(KEY_LEN as usize);
+                Ok((rank as i64 + 1, (entity_id, sequence_number, event_payload)))
+            })
+            .collect()
+    }
+
+    /// Position of the segment to append `len` bytes to, starting a new one when the active one is full.
+    fn active_segment_for(&mut self, len: u64) -> io::Result<usize> {
+        let active = self.segments.len() - 1;
+        let segment = &self.segments[active];
+        if segment.size == 0 || segment.size + len <= self.config.segment_size {
+            return Ok(active);
+        }
+
+        // The pending writes of the previous segment are not forgotten
+        if self.pending_writes > 0 {
+            self.segments[active].file.sync_data()?;
+            self.pending_writes = 0;
+            self.oldest_pending_write = None;
+        }
+        let path = segment_path(&self.config.directory, self.next_segment_id);
+        let file = OpenOptions::new().read(true).write(true).create_new(true).open(path)?;
+        File::open(&self.config.directory)?.sync_all()?;
+        self.next_segment_id += 1;
+        self.segments.push(Segment { file, size: 0 });
+        Ok(active + 1)
+    }
+
+    fn flush_after_write(&mut self, active: usize) -> io::Result<()> {
+        match self.config.fsync {
+            FsyncPolicy::EveryWrite => self.segments[active].file.sync_data(),
+            FsyncPolicy::Batched { max_writes, max_delay } => {
+                self.pending_writes += 1;
+                let oldest = *self.oldest_pending_write.get_or_insert_with(Instant::now);
+                if self.pending_writes >= max_writes || oldest.elapsed() >= max_delay {
+                    self.segments[active].file.sync_data()?;
+                    self.pending_writes = 0;
+                    self.oldest_pending_write = None;
+                }
+                Ok(())
+            }
+            FsyncPolicy::Never => Ok(()),
+        }
+    }
+}
+
+impl Drop for SegmentedLog {
+    fn drop(&mut self) {
+        if self.pending_writes > 0 {
+            if let Some(active) = self.segments.last() {
+                let _ = active.file.sync_data();
+            }
+        }
+    }
+}
+
+fn segment_path(directory: &Path, segment_id: u64) -> PathBuf {
+    directory.join(format!("{:020}.log", segment_id))
+}
+
+/// Index the valid records of a segment, returning the size they span.
+fn scan_segment(file: &mut File, size: u64, segment: usize, index: &mut HashMap<i64, BTreeMap<i64, Location>>,
+                positions: &mut Vec<Location>) -> io::Result<u64> {
+    let mut reader = BufReader::new(file);
+    reader.seek(SeekFrom::Start(0))?;
+
+    let mut offset = 0;
+    let mut header = [0u8; HEADER_LEN as usize];
+    loop {
+        if !read_record_part(&mut reader, &mut header)? {
+            break;
+        }
+        let len = u32::from_le_bytes(header[0..4].try_into().unwrap());
+        let crc = u32::from_le_bytes(header[4..8].try_into().unwrap());
+        if len < KEY_LEN || offset + HEADER_LEN + len as u64 > size {
+            break;
+        }
+
+        let mut payload = vec![0; len as usize];
+        if !read_record_part(&mut reader, &mut payload)? || crc32fast::hash(&payload) != crc {
+            break;
+        }
+        let entity_id = i64::from_le_bytes(payload[0..8].try_into().unwrap());
+        let sequence_number = i64::from_le_bytes(payload[8..16].try_into().unwrap());
+        let location = Location { segment, offset: offset + HEADER_LEN, len };
+        index.entry(entity_id).or_default().insert(sequence_number, location);
+        positions.push(location);
+
+        offset += HEADER_LEN + len as u64;
+    }
+    Ok(offset)
+}
+
+/// Whether a valid record starts anywhere after the invalid one at `invalid_offset`:
+/// the length of the invalid record can't be trusted to find the next one.
+fn valid_record_after(file: &mut File, invalid_offset: u64, size: u64) -> io::Result<bool> {
+    let mut rest = Vec::with_capacity((size - invalid_offset) as usize);
+    file.seek(SeekFrom::Start(invalid_offset))?;
+    file.read_to_end(&mut rest)?;
+
+    Ok((1..rest.len()).any(|start| {
+        let Some(header) = rest.get(start..start + HEADER_LEN as usize) else {
+            return false;
+        };
+        let len = u32::from_le_bytes(header[0..4].try_into().unwrap());
+        let crc = u32::from_le_bytes(header[4..8].try_into().unwrap());
+        let payload_start = start + HEADER_LEN as usize;
+        len >= KEY_LEN && rest.get(payload_start..payload_start + len as usize)
+            .is_some_and(|payload| crc32fast::hash(payload) == crc)
+    }))
+}
+
+/// False at the end of the segment.
+fn read_record_part(reader: &mut impl Read, buffer: &mut [u8]) -> io::Result<bool> {
+    match reader.read_exact(buffer) {
+        Ok(()) => Ok(true),
+        Err(e) if e.kind() == ErrorKind::UnexpectedEof => Ok(false),
+        Err(e) => Err(e),
+    }
+}
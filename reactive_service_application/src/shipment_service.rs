@@ -0,0 +1,28 @@
+use reactive_service_domain::non_empty_cart::NonEmptyCart;
+use reactive_service_domain::order_state::DeliveryAddress;
+use crate::order_commands::OrderId;
+
+/// Ships the orders, once they are paid for.
+pub trait ShipmentService {
+    /// Create the shipment of the order. Creating it again is a no-op.
+    fn create_shipment(&self, order_id: OrderId, cart: &NonEmptyCart, delivery_address: &DeliveryAddress) -> Result<(), &'static str>;
+
+    /// Cancel the shipment of the order. Cancelling it again, or a shipment never created, is a no-op.
+    fn cancel_shipment(&self, order_id: OrderId) -> Result<(), &'static str>;
+}
+
+pub struct LocalShipmentService {}
+
+impl ShipmentService for LocalShipmentService {
+    fn create_shipment(&self, order_id: OrderId, cart: &NonEmptyCart, delivery_address: &DeliveryAddress) -> Result<(), &'static str> {
+        let _ = order_id;
+        let _ = cart;
+        let _ = delivery_address;
+        Ok(())
+    }
+
+    fn cancel_shipment(&self, order_id: OrderId) -> Result<(), &'static str> {
+        let _ = order_id;
+        Ok(())
+    }
+}
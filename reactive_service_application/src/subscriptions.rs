@@ -0,0 +1,26 @@
+use reactive_service_domain::aggregate_root::SequencedEvent;
+use crate::order_commands::OrderId;
+
+/// An event persisted in the journal, with the entity it belongs to.
+#[derive(Debug, Clone)]
+pub struct Committed<E> {
+    pub entity_id: OrderId,
+    pub event: SequencedEvent<E>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SubscriptionError {
+    /// The subscriber didn't keep up: this many events were dropped, the following ones are still received.
+    Lagged(u64),
+    /// The service is gone, no more events will be published.
+    Closed,
+}
+
+/// An event at its position in the global order of a journal, across the entities.
+/// The positions increase with each persisted event, starting at 1.
+#[derive(Debug, Clone)]
+pub struct Positioned<E> {
+    pub position: i64,
+    pub entity_id: OrderId,
+    pub event: SequencedEvent<E>,
+}
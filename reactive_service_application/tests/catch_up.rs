@@ -0,0 +1,30 @@
+#[cfg(test)]
+mod tests {
+    use reactive_service_application::catch_up::CatchUpCursor;
+    use reactive_service_application::subscriptions::Positioned;
+    use reactive_service_domain::aggregate_root::SequencedEvent;
+
+    fn positioned(positions: &[i64]) -> Vec<Positioned<String>> {
+        positions.iter()
+            .map(|position| Positioned { position: *position, entity_id: 1, event: SequencedEvent { sequence_number: *position, event: format!("event {}", position) } })
+            .collect()
+    }
+
+    #[test]
+    fn receives_the_events_read_in_order_then_reads_after_the_last_one() {
+        let mut cursor = CatchUpCursor::new(2);
+        assert_eq!((cursor.position(), cursor.next_position()), (2, 3));
+        assert!(cursor.pop().is_none());
+
+        assert!(cursor.push(positioned(&[3, 5])));
+        assert_eq!(cursor.next_position(), 6);
+        assert_eq!(cursor.pop().map(|event| event.position), Some(3));
+        assert_eq!(cursor.position(), 3);
+        assert_eq!(cursor.pop().map(|event| event.event.event), Some("event 5".to_owned()));
+        assert_eq!(cursor.position(), 5);
+
+        assert!(!cursor.push(vec![]));
+        assert!(cursor.pop().is_none());
+        assert_eq!((cursor.position(), cursor.next_position()), (5, 6));
+    }
+}
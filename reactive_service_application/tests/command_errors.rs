@@ -0,0 +1,72 @@
+#[cfg(test)]
+mod tests {
+    use std::collections::{HashMap, HashSet};
+
+    use reactive_service_application::authorization::Principal;
+    use reactive_service_application::command_errors::*;
+    use reactive_service_application::order_commands::{OrderCommand, UpdateCart};
+    use reactive_service_application::payment_processor::LocalPaymentProcessor;
+    use reactive_service_application::shipping_calculator::LocalShippingCalculator;
+    use reactive_service_application::tax_calculator::LocalTaxCalculator;
+    use reactive_service_domain::aggregate_root::AggregateRoot;
+    use reactive_service_domain::canada_postal_code::CanadaPostalCode;
+    use reactive_service_domain::inventory_item::{InventoryItem, InventoryItemCommand};
+    use reactive_service_domain::non_empty_cart::{NonEmptyCart, Quantity, Sku};
+    use reactive_service_domain::order_entity::OrderEntity;
+
+    fn kind(result: Result<impl Sized, &'static str>) -> CommandErrorKind {
+        CommandErrorKind::of(result.err().unwrap())
+    }
+
+    fn order_of(customer_id: i64) -> OrderEntity {
+        let cart = NonEmptyCart::new(HashMap::from([(Sku("apple".to_owned()), Quantity(1))])).unwrap();
+        let command: OrderCommand = UpdateCart { order_id: 1, cart }.into();
+        let principal = Principal::Customer(customer_id);
+        let mut order_entity = OrderEntity::default();
+        let entity_command = command
+            .entity_command(&principal, &order_entity, &LocalShippingCalculator{}, &LocalTaxCalculator{}, &LocalPaymentProcessor{})
+            .unwrap();
+        order_entity.handle_command(entity_command).unwrap();
+        order_entity
+    }
+
+    #[test]
+    fn classifies_the_errors_of_the_domain() {
+        assert_eq!(kind(NonEmptyCart::new(HashMap::new())), CommandErrorKind::Invalid);
+        assert_eq!(kind("A1A".parse::<CanadaPostalCode>()), CommandErrorKind::Invalid);
+        let mut item = InventoryItem::default();
+        assert_eq!(kind(item.handle_command(InventoryItemCommand::Receive { quantity: 0 }).map(|_| ())), CommandErrorKind::Invalid);
+        assert_eq!(CommandErrorKind::of("Order not ready to be paid."), CommandErrorKind::Conflict);
+        assert_eq!(CommandErrorKind::of("Insufficient stock"), CommandErrorKind::Conflict);
+    }
+
+    #[test]
+    fn classifies_the_errors_of_the_authorization() {
+        assert_eq!(kind(Principal::Customer(2).authorize_order(&order_of(1))), CommandErrorKind::Forbidden);
+        assert_eq!(kind(Principal::Customer(2).authorize_customer(1)), CommandErrorKind::Forbidden);
+    }
+
+    #[test]
+    fn classifies_the_errors_of_the_runtimes() {
+        for error in [
+            COMMAND_TIMED_OUT, TOO_MANY_COMMANDS, VERSION_TIMED_OUT, NO_DB_CONNECTION, MAILBOX_UNAVAILABLE, MAILBOX_FULL,
+            ACTOR_STOPPED, SUPERVISOR_STOPPED, SHARD_STOPPED, EVENT_LOOP_STOPPED, EVENT_LOOP_STOPPED_BEFORE_REPLYING,
+        ] {
+            assert_eq!(CommandErrorKind::of(error), CommandErrorKind::Unavailable, "{}", error);
+        }
+        for error in [
+            ENTITY_NOT_RETRIEVED, ENTITY_POISONED, ENTITIES_POISONED, JOURNAL_POISONED, RETRIEVE_EVENTS_FAILED,
+            PERSIST_EVENT_FAILED, APPLY_EVENTS_FAILED, SERIALIZE_EVENT_FAILED, DESERIALIZE_EVENT_FAILED,
+            SERIALIZE_COMMAND_FAILED, DESERIALIZE_COMMAND_FAILED, SCHEDULE_COMMAND_FAILED, RETRIEVE_SCHEDULED_COMMANDS_FAILED,
+            REMOVE_SCHEDULED_COMMAND_FAILED, LOAD_CHECKPOINT_FAILED, SAVE_CHECKPOINT_FAILED, LIMIT_COMMANDS_FAILED,
+        ] {
+            assert_eq!(CommandErrorKind::of(error), CommandErrorKind::Internal, "{}", error);
+        }
+    }
+
+    #[test]
+    fn knows_each_error_once() {
+        let errors: HashSet<&str> = ERROR_KINDS.iter().map(|(error, _)| *error).collect();
+        assert_eq!(errors.len(), ERROR_KINDS.len());
+    }
+}
@@ -0,0 +1,68 @@
+#[cfg(test)]
+mod tests {
+    use reactive_service_application::authorization::Principal;
+    use reactive_service_domain::aggregate_root::AggregateRoot;
+    use reactive_service_domain::customer::{Customer, CustomerCommand, CustomerEvent, PaymentMethodRef, Profile};
+    use reactive_service_domain::order_state::{DeliveryAddress, Street};
+
+    fn profile(name: &str) -> Profile {
+        Profile { name: name.to_owned(), email: "jane@example.com".to_owned() }
+    }
+
+    fn address(street: &str) -> DeliveryAddress {
+        DeliveryAddress { street: Street(street.to_owned()), postal_code: "H0H 0H0".parse().unwrap() }
+    }
+
+    fn registered() -> Customer {
+        let mut customer = Customer::default();
+        customer.handle_command(CustomerCommand::Register { profile: profile("Jane") }).unwrap();
+        customer
+    }
+
+    #[test]
+    fn registers_the_customer_once() {
+        let mut customer = Customer::default();
+        assert_eq!(customer.handle_command(CustomerCommand::UpdateProfile { profile: profile("Jane") }).err(), Some("Customer not registered"));
+
+        customer.handle_command(CustomerCommand::Register { profile: profile("Jane") }).unwrap();
+        assert!(customer.handle_command(CustomerCommand::Register { profile: profile("Jane") }).unwrap().1.is_empty());
+        assert_eq!(customer.handle_command(CustomerCommand::Register { profile: profile("John") }).err(), Some("Customer already registered"));
+
+        customer.handle_command(CustomerCommand::UpdateProfile { profile: profile("John") }).unwrap();
+        assert_eq!(customer.get_state().profile, Some(profile("John")));
+    }
+
+    #[test]
+    fn saves_each_address_once() {
+        let mut customer = registered();
+        customer.handle_command(CustomerCommand::SaveAddress { address: address("1 Main Street") }).unwrap();
+        customer.handle_command(CustomerCommand::SaveAddress { address: address("2 Main Street") }).unwrap();
+        assert!(customer.handle_command(CustomerCommand::SaveAddress { address: address("1 Main Street") }).unwrap().1.is_empty());
+
+        customer.handle_command(CustomerCommand::RemoveAddress { address: address("1 Main Street") }).unwrap();
+        assert!(customer.handle_command(CustomerCommand::RemoveAddress { address: address("1 Main Street") }).unwrap().1.is_empty());
+        assert_eq!(customer.get_state().saved_addresses, vec![address("2 Main Street")]);
+        assert_eq!(customer.get_sequence_number(), 4);
+    }
+
+    #[test]
+    fn restores_the_customer_from_its_events() {
+        let mut customer = Customer::default();
+        let payment_method = PaymentMethodRef("pm_123".to_owned());
+        let mut events = customer.handle_command(CustomerCommand::Register { profile: profile("Jane") }).unwrap().1;
+        events.extend(customer.handle_command(CustomerCommand::SetDefaultPaymentMethod { payment_method: payment_method.clone() }).unwrap().1);
+        assert!(matches!(events[1].event, CustomerEvent::DefaultPaymentMethodSet { .. }));
+
+        let mut restored = Customer::default();
+        restored.restore_from_events(events).unwrap();
+        assert_eq!((restored.get_state().profile.clone(), restored.get_state().default_payment_method.clone()), (Some(profile("Jane")), Some(payment_method)));
+        assert_eq!(restored.get_sequence_number(), 2);
+    }
+
+    #[test]
+    fn a_customer_only_changes_their_own_profile() {
+        assert!(Principal::Customer(7).authorize_customer(7).is_ok());
+        assert_eq!(Principal::Customer(8).authorize_customer(7).err(), Some("Not the customer"));
+        assert!(Principal::Admin.authorize_customer(7).is_ok());
+    }
+}
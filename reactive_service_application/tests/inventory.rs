@@ -0,0 +1,115 @@
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::time::{Duration, SystemTime};
+
+    use reactive_service_application::inventory::{cart_items, item_id};
+    use reactive_service_domain::aggregate_root::{AggregateRoot, SequencedEvent};
+    use reactive_service_domain::inventory_item::{InventoryItem, InventoryItemCommand, InventoryItemEvent};
+    use reactive_service_domain::non_empty_cart::{NonEmptyCart, Quantity, Sku};
+
+    fn received(quantity: u32) -> InventoryItem {
+        let mut item = InventoryItem::default();
+        item.handle_command(InventoryItemCommand::Receive { quantity }).unwrap();
+        item
+    }
+
+    fn reserve(order_id: i64, quantity: u32, expires_at: SystemTime, at: SystemTime) -> InventoryItemCommand {
+        InventoryItemCommand::Reserve { order_id, quantity, expires_at, at }
+    }
+
+    #[test]
+    fn reserves_up_to_the_stock_available() {
+        let now = SystemTime::now();
+        let expires_at = now + Duration::from_secs(60);
+        let mut item = received(5);
+
+        item.handle_command(reserve(1, 3, expires_at, now)).unwrap();
+        assert_eq!(item.handle_command(reserve(2, 3, expires_at, now)).err(), Some("Insufficient stock"));
+        item.handle_command(reserve(2, 2, expires_at, now)).unwrap();
+        assert_eq!(item.get_state().available(now), 0);
+        assert_eq!(item.handle_command(InventoryItemCommand::Receive { quantity: 0 }).err(), Some("Quantity must be positive"));
+    }
+
+    #[test]
+    fn reserving_again_is_a_no_op_or_replaces_the_reservation() {
+        let now = SystemTime::now();
+        let expires_at = now + Duration::from_secs(60);
+        let mut item = received(5);
+        item.handle_command(reserve(1, 3, expires_at, now)).unwrap();
+
+        assert!(item.handle_command(reserve(1, 3, expires_at, now)).unwrap().1.is_empty());
+        // The units held by the order count for its new reservation
+        item.handle_command(reserve(1, 5, expires_at, now)).unwrap();
+        assert_eq!(item.get_state().reservations[&1].quantity, 5);
+        let later = expires_at + Duration::from_secs(60);
+        item.handle_command(reserve(1, 5, later, now)).unwrap();
+        assert_eq!(item.get_state().reservations[&1].expires_at, later);
+    }
+
+    #[test]
+    fn an_expired_reservation_releases_its_stock_to_the_next_reservation() {
+        let now = SystemTime::now();
+        let mut item = received(5);
+        item.handle_command(reserve(1, 5, now + Duration::from_secs(1), now)).unwrap();
+
+        let later = now + Duration::from_secs(2);
+        assert_eq!(item.get_state().available(later), 5);
+        let (state, events) = item.handle_command(reserve(2, 4, later + Duration::from_secs(60), later)).unwrap();
+        assert!(matches!(events[0].event, InventoryItemEvent::ReservationReleased { order_id: 1 }));
+        assert!(matches!(events[1].event, InventoryItemEvent::Reserved { order_id: 2, quantity: 4, .. }));
+        assert!(!state.reservations.contains_key(&1));
+
+        // Its order can't ship it anymore
+        assert_eq!(item.handle_command(InventoryItemCommand::Ship { order_id: 1, at: later }).err(), Some("No reservation for the order"));
+    }
+
+    #[test]
+    fn ships_the_reserved_stock_once() {
+        let now = SystemTime::now();
+        let mut item = received(5);
+        item.handle_command(reserve(1, 3, now + Duration::from_secs(60), now)).unwrap();
+
+        item.handle_command(InventoryItemCommand::Ship { order_id: 1, at: now }).unwrap();
+        assert_eq!((item.get_state().on_hand, item.get_state().available(now)), (2, 2));
+        assert!(item.handle_command(InventoryItemCommand::Ship { order_id: 1, at: now }).unwrap().1.is_empty());
+        assert!(item.handle_command(InventoryItemCommand::Release { order_id: 1 }).unwrap().1.is_empty());
+        assert!(item.handle_command(reserve(1, 3, now + Duration::from_secs(60), now)).unwrap().1.is_empty());
+        assert_eq!(item.get_sequence_number(), 3);
+    }
+
+    #[test]
+    fn resumes_from_its_events() {
+        let now = SystemTime::now();
+        let mut item = received(5);
+        let mut events = vec![SequencedEvent { sequence_number: 1, event: InventoryItemEvent::Received { quantity: 5 } }];
+        for command in [
+            reserve(1, 2, now + Duration::from_secs(60), now),
+            reserve(2, 1, now + Duration::from_secs(60), now),
+            InventoryItemCommand::Release { order_id: 2 },
+            InventoryItemCommand::Ship { order_id: 1, at: now },
+        ] {
+            events.extend(item.handle_command(command).unwrap().1);
+        }
+
+        let mut restored = InventoryItem::default();
+        let state = restored.restore_from_events(events).unwrap();
+        assert_eq!((state.on_hand, state.available(now), state.reservations.len()), (3, 3, 0));
+        assert!(state.shipped.contains(&1));
+        assert_eq!(restored.get_sequence_number(), item.get_sequence_number());
+    }
+
+    #[test]
+    fn items_are_identified_by_their_sku() {
+        let apple = Sku("apple".to_owned());
+        assert_eq!(item_id(&apple), item_id(&Sku("apple".to_owned())));
+        assert_ne!(item_id(&apple), item_id(&Sku("chocolate".to_owned())));
+        assert!(item_id(&apple) >= 0);
+
+        let cart = NonEmptyCart::new(HashMap::from([(apple.clone(), Quantity(2)), (Sku("chocolate".to_owned()), Quantity(1))])).unwrap();
+        let items = cart_items(&cart);
+        assert_eq!(items.len(), 2);
+        assert!(items.windows(2).all(|pair| pair[0].0 < pair[1].0));
+        assert!(items.contains(&(item_id(&apple), 2)));
+    }
+}
@@ -0,0 +1,39 @@
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use reactive_service_application::metrics::{MetricsRegistry, PrometheusRegistry};
+
+    #[test]
+    fn renders_the_metrics_in_the_prometheus_text_format() {
+        let registry = PrometheusRegistry::default();
+        registry.observe_command("update_cart", Duration::from_micros(80), None);
+        registry.observe_command("pay_order", Duration::from_millis(2), Some("Order not ready to be paid."));
+        registry.observe_journal("persist", Duration::from_micros(300), false);
+        registry.set_cached_entities(2);
+
+        let text = registry.render();
+        for line in [
+            "# TYPE order_command_duration_seconds histogram",
+            "order_command_duration_seconds_bucket{command=\"update_cart\",le=\"0.00005\"} 0",
+            "order_command_duration_seconds_bucket{command=\"update_cart\",le=\"0.0001\"} 1",
+            "order_command_duration_seconds_bucket{command=\"update_cart\",le=\"+Inf\"} 1",
+            "order_command_duration_seconds_sum{command=\"pay_order\"} 0.002",
+            "order_command_duration_seconds_count{command=\"pay_order\"} 1",
+            "order_command_errors_total{command=\"pay_order\",error=\"Order not ready to be paid.\"} 1",
+            "events_journal_duration_seconds_count{operation=\"persist\"} 1",
+            "# TYPE cached_entities gauge",
+            "cached_entities 2",
+        ] {
+            assert!(text.lines().any(|rendered| rendered == line), "missing {}", line);
+        }
+        assert!(text.find("command=\"pay_order\"").unwrap() < text.find("command=\"update_cart\"").unwrap());
+    }
+
+    #[test]
+    fn escapes_the_label_values() {
+        let registry = PrometheusRegistry::default();
+        registry.observe_command("update_cart", Duration::ZERO, Some("A \"quoted\"\nerror"));
+        assert!(registry.render().contains("error=\"A \\\"quoted\\\"\\nerror\"} 1"));
+    }
+}
@@ -0,0 +1,56 @@
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use reactive_service_application::authorization::Principal;
+    use reactive_service_application::middleware::{CommandLatencies, CommandTrace, LatencyHistogram};
+    use reactive_service_application::order_commands::{CommandResult, ExpireOrder, OrderCommand};
+    use reactive_service_domain::order_state::{Empty, OrderState};
+
+    #[test]
+    fn the_quantiles_are_bounded_by_their_bucket() {
+        let mut histogram = LatencyHistogram::default();
+        assert_eq!(histogram.quantile(0.5), None);
+
+        for micros in [40, 60, 70, 80, 3_000] {
+            histogram.record(Duration::from_micros(micros), micros == 3_000);
+        }
+        assert_eq!((histogram.count(), histogram.errors()), (5, 1));
+        assert_eq!(histogram.quantile(0.2), Some(Duration::from_micros(50)));
+        assert_eq!(histogram.quantile(0.5), Some(Duration::from_micros(100)));
+        assert_eq!(histogram.quantile(1.0), Some(Duration::from_millis(3)));
+        assert_eq!(histogram.cumulative_buckets().last(), Some((Duration::from_micros(2_500_000), 5)));
+    }
+
+    #[test]
+    fn a_latency_past_the_last_bucket_is_bounded_by_the_max() {
+        let mut histogram = LatencyHistogram::default();
+        histogram.record(Duration::from_secs(4), false);
+        assert_eq!(histogram.quantile(0.99), Some(Duration::from_secs(4)));
+        assert_eq!(histogram.cumulative_buckets().last(), Some((Duration::from_micros(2_500_000), 0)));
+    }
+
+    #[test]
+    fn records_the_latencies_by_command() {
+        let latencies = CommandLatencies::default();
+        latencies.record("update_cart", Duration::from_micros(10), false);
+        latencies.record("pay_order", Duration::from_micros(10), true);
+        latencies.record("update_cart", Duration::from_micros(10), false);
+
+        assert_eq!(latencies.get("update_cart").map(|histogram| histogram.count()), Some(2));
+        assert_eq!(latencies.get("expire_order"), None);
+        let names: Vec<_> = latencies.snapshot().into_iter().map(|(command, _)| command).collect();
+        assert_eq!(names, vec!["pay_order", "update_cart"]);
+    }
+
+    #[test]
+    fn traces_the_outcome_of_a_command() {
+        let command = OrderCommand::ExpireOrder(ExpireOrder { order_id: 7, sequence_number: 1 });
+        let result: CommandResult = Ok((OrderState::Empty(Empty {}), vec![]));
+        let trace = CommandTrace::new(command.order_id(), command.name(), &Principal::Customer(3), Duration::ZERO, &result);
+        assert_eq!((trace.order_id, trace.command, trace.customer_id, trace.outcome), (7, "expire_order", Some(3), Ok(0)));
+
+        let trace = CommandTrace::new(7, "expire_order", &Principal::Admin, Duration::ZERO, &Err("Order can't expire."));
+        assert_eq!((trace.customer_id, trace.outcome), (None, Err("Order can't expire.")));
+    }
+}
@@ -0,0 +1,148 @@
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use reactive_service_application::authorization::Principal;
+    use reactive_service_application::order_commands::{OrderCommand, PayOrder, UpdateCart, UpdateDeliveryAddress};
+    use reactive_service_application::payment_processor::{LocalPaymentProcessor, PaymentToken};
+    use reactive_service_application::shipping_calculator::LocalShippingCalculator;
+    use reactive_service_application::tax_calculator::LocalTaxCalculator;
+    use reactive_service_domain::aggregate_root::AggregateRoot;
+    use reactive_service_domain::non_empty_cart::{NonEmptyCart, Quantity, Sku};
+    use reactive_service_domain::order_entity::{OrderEntity, OrderEntityCommand};
+    use reactive_service_domain::order_state::{DeliveryAddress, OrderState, Street};
+
+    const ORDER_ID: i64 = 1;
+
+    fn cart() -> NonEmptyCart {
+        NonEmptyCart::new(HashMap::from([(Sku("apple".to_owned()), Quantity(1))])).unwrap()
+    }
+
+    fn delivery_address() -> DeliveryAddress {
+        DeliveryAddress { street: Street("1 Main Street".to_owned()), postal_code: "H0H 0H0".parse().unwrap() }
+    }
+
+    fn update_cart() -> OrderCommand {
+        UpdateCart { order_id: ORDER_ID, cart: cart() }.into()
+    }
+
+    fn update_delivery_address() -> OrderCommand {
+        UpdateDeliveryAddress { order_id: ORDER_ID, delivery_address: delivery_address() }.into()
+    }
+
+    fn pay_order() -> OrderCommand {
+        PayOrder { order_id: ORDER_ID, payment_token: PaymentToken::new("token") }.into()
+    }
+
+    fn entity_command(order_entity: &OrderEntity, command: OrderCommand) -> Result<OrderEntityCommand, &'static str> {
+        entity_command_as(&Principal::Admin, order_entity, command)
+    }
+
+    fn entity_command_as(principal: &Principal, order_entity: &OrderEntity, command: OrderCommand) -> Result<OrderEntityCommand, &'static str> {
+        command.entity_command(principal, order_entity, &LocalShippingCalculator{}, &LocalTaxCalculator{}, &LocalPaymentProcessor{})
+    }
+
+    /// An order after the given commands, each one handled by the entity.
+    fn order_after(commands: Vec<OrderCommand>) -> OrderEntity {
+        order_after_as(&Principal::Admin, commands)
+    }
+
+    fn order_after_as(principal: &Principal, commands: Vec<OrderCommand>) -> OrderEntity {
+        let mut order_entity = OrderEntity::default();
+        for command in commands {
+            let entity_command = entity_command_as(principal, &order_entity, command).unwrap();
+            order_entity.handle_command(entity_command).unwrap();
+        }
+        order_entity
+    }
+
+    #[test]
+    fn commands_know_their_order() {
+        assert_eq!(update_cart().order_id(), ORDER_ID);
+        assert_eq!(update_delivery_address().order_id(), ORDER_ID);
+        assert_eq!(pay_order().order_id(), ORDER_ID);
+    }
+
+    #[test]
+    fn empty_order() {
+        let order_entity = order_after(vec![]);
+
+        assert!(matches!(entity_command(&order_entity, update_cart()), Ok(OrderEntityCommand::AddCart { .. })));
+        assert!(entity_command(&order_entity, update_delivery_address()).is_err());
+        assert!(entity_command(&order_entity, pay_order()).is_err());
+    }
+
+    #[test]
+    fn order_with_cart() {
+        let order_entity = order_after(vec![update_cart()]);
+        assert!(matches!(order_entity.get_state(), OrderState::WithCart(_)));
+
+        assert!(matches!(entity_command(&order_entity, update_cart()), Ok(OrderEntityCommand::AddCart { .. })));
+        assert!(matches!(entity_command(&order_entity, update_delivery_address()), Ok(OrderEntityCommand::UpdateDeliveryAddress { .. })));
+        assert!(entity_command(&order_entity, pay_order()).is_err());
+    }
+
+    #[test]
+    fn order_with_address() {
+        let order_entity = order_after(vec![update_cart(), update_delivery_address()]);
+        assert!(matches!(order_entity.get_state(), OrderState::WithAddress(_)));
+
+        assert!(matches!(entity_command(&order_entity, update_cart()), Ok(OrderEntityCommand::UpdateCart { .. })));
+        assert!(matches!(entity_command(&order_entity, update_delivery_address()), Ok(OrderEntityCommand::UpdateDeliveryAddress { .. })));
+        assert!(matches!(entity_command(&order_entity, pay_order()), Ok(OrderEntityCommand::Complete { .. })));
+    }
+
+    #[test]
+    fn completed_order() {
+        let order_entity = order_after(vec![update_cart(), update_delivery_address(), pay_order()]);
+        assert!(matches!(order_entity.get_state(), OrderState::Completed(_)));
+
+        assert!(entity_command(&order_entity, update_cart()).is_err());
+        assert!(entity_command(&order_entity, update_delivery_address()).is_err());
+        assert!(entity_command(&order_entity, pay_order()).is_err());
+    }
+
+    #[test]
+    fn only_the_checkout_of_an_order_with_address_reserves_its_cart() {
+        let with_cart = order_after(vec![update_cart()]);
+        let with_address = order_after(vec![update_cart(), update_delivery_address()]);
+        let completed = order_after(vec![update_cart(), update_delivery_address(), pay_order()]);
+
+        let cart = pay_order().checkout_cart(with_address.get_state()).map(|cart| cart.get_items().clone());
+        assert_eq!(cart, Some(self::cart().get_items().clone()));
+        assert!(update_cart().checkout_cart(with_address.get_state()).is_none());
+        assert!(pay_order().checkout_cart(with_cart.get_state()).is_none());
+        assert!(pay_order().checkout_cart(completed.get_state()).is_none());
+    }
+
+    #[test]
+    fn the_customer_creating_the_order_owns_it() {
+        let owner = Principal::Customer(7);
+        let order_entity = order_after_as(&owner, vec![update_cart(), update_delivery_address()]);
+        assert_eq!(order_entity.get_customer_id(), Some(7));
+
+        assert!(entity_command_as(&owner, &order_entity, pay_order()).is_ok());
+        assert!(entity_command_as(&Principal::Admin, &order_entity, pay_order()).is_ok());
+        assert_eq!(entity_command_as(&Principal::Customer(8), &order_entity, pay_order()).err(), Some("Not the owner of the order"));
+    }
+
+    #[test]
+    fn only_an_admin_changes_an_order_without_owner() {
+        let order_entity = order_after(vec![update_cart()]);
+        assert_eq!(order_entity.get_customer_id(), None);
+
+        assert_eq!(entity_command_as(&Principal::Customer(7), &order_entity, update_cart()).err(), Some("Not the owner of the order"));
+        assert!(entity_command_as(&Principal::Admin, &order_entity, update_cart()).is_ok());
+    }
+
+    #[test]
+    fn the_owner_is_restored_with_the_order() {
+        let mut order_entity = OrderEntity::default();
+        let entity_command = entity_command_as(&Principal::Customer(7), &order_entity, update_cart()).unwrap();
+        let (_, events) = order_entity.handle_command(entity_command).unwrap();
+
+        let mut restored = OrderEntity::default();
+        restored.restore_from_events(events).unwrap();
+        assert_eq!(restored.get_customer_id(), Some(7));
+    }
+}
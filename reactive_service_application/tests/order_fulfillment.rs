@@ -0,0 +1,197 @@
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::collections::HashMap;
+    use std::time::{Duration, Instant, SystemTime};
+
+    use reactive_service_application::inventory::Inventory;
+    use reactive_service_application::order_fulfillment::{
+        FulfillmentAction, FulfillmentCommand, FulfillmentConfig, FulfillmentEvent, FulfillmentOrder, FulfillmentRetries,
+        FulfillmentStatus, FulfillmentStep, OrderFulfillment, StepTimeouts
+    };
+    use reactive_service_application::payment_processor::{PaymentProcessor, PaymentToken};
+    use reactive_service_application::shipment_service::ShipmentService;
+    use reactive_service_domain::aggregate_root::{AggregateRoot, SequencedEvent};
+    use reactive_service_domain::non_empty_cart::{NonEmptyCart, Quantity, Sku};
+    use reactive_service_domain::order_state::{DeliveryAddress, Invoice, Street};
+
+    /// Records the calls of the steps, and fails the one given.
+    #[derive(Default)]
+    struct Ports {
+        failing: Option<&'static str>,
+        calls: RefCell<Vec<&'static str>>,
+    }
+
+    impl Ports {
+        fn call(&self, name: &'static str) -> Result<(), &'static str> {
+            self.calls.borrow_mut().push(name);
+            if self.failing == Some(name) { Err("Port unavailable") } else { Ok(()) }
+        }
+    }
+
+    impl Inventory for Ports {
+        fn reserve_stock(&self, _: i64, _: &NonEmptyCart) -> Result<(), &'static str> { self.call("reserve_stock") }
+        fn release_stock(&self, _: i64, _: &NonEmptyCart) -> Result<(), &'static str> { self.call("release_stock") }
+        fn ship_stock(&self, _: i64, _: &NonEmptyCart) -> Result<(), &'static str> { self.call("ship_stock") }
+    }
+
+    impl PaymentProcessor for Ports {
+        fn pay_with_token(&self, _: PaymentToken) -> Invoice { Invoice{} }
+        fn capture_payment(&self, _: i64) -> Result<(), &'static str> { self.call("capture_payment") }
+        fn refund_payment(&self, _: i64) -> Result<(), &'static str> { self.call("refund_payment") }
+    }
+
+    impl ShipmentService for Ports {
+        fn create_shipment(&self, _: i64, _: &NonEmptyCart, _: &DeliveryAddress) -> Result<(), &'static str> { self.call("create_shipment") }
+        fn cancel_shipment(&self, _: i64) -> Result<(), &'static str> { self.call("cancel_shipment") }
+    }
+
+    fn order() -> FulfillmentOrder {
+        FulfillmentOrder {
+            cart: NonEmptyCart::new(HashMap::from([(Sku("apple".to_owned()), Quantity(2))])).unwrap(),
+            delivery_address: DeliveryAddress { street: Street("1 Main Street".to_owned()), postal_code: "H0H 0H0".parse().unwrap() },
+        }
+    }
+
+    fn started(timeouts: StepTimeouts) -> OrderFulfillment {
+        let mut fulfillment = OrderFulfillment::default();
+        fulfillment.handle_command(FulfillmentCommand::Start { order: order(), timeouts, at: SystemTime::now() }).unwrap();
+        fulfillment
+    }
+
+    /// Perform the actions until the fulfillment is over, or a compensation fails.
+    fn drive(fulfillment: &mut OrderFulfillment, ports: &Ports) -> Result<(), &'static str> {
+        while let Some(action) = fulfillment.get_state().next_action(SystemTime::now()) {
+            let command = action.perform(1, fulfillment.get_state(), ports, ports, ports)?;
+            fulfillment.handle_command(command)?;
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn fulfills_the_order_step_by_step() {
+        let mut fulfillment = started(StepTimeouts::default());
+        let ports = Ports::default();
+        drive(&mut fulfillment, &ports).unwrap();
+
+        assert_eq!(fulfillment.get_state().status, FulfillmentStatus::Fulfilled);
+        assert_eq!(*ports.calls.borrow(), vec!["reserve_stock", "capture_payment", "create_shipment", "ship_stock"]);
+        assert_eq!(fulfillment.get_sequence_number(), 8);
+    }
+
+    #[test]
+    fn compensates_the_steps_done_in_reverse_order() {
+        let mut fulfillment = started(StepTimeouts::default());
+        let ports = Ports { failing: Some("create_shipment"), ..Ports::default() };
+        drive(&mut fulfillment, &ports).unwrap();
+
+        let state = fulfillment.get_state();
+        assert_eq!((state.status, state.failure.as_deref()), (FulfillmentStatus::Cancelled, Some("Port unavailable")));
+        assert_eq!(*ports.calls.borrow(), vec!["reserve_stock", "capture_payment", "create_shipment", "refund_payment", "release_stock"]);
+    }
+
+    #[test]
+    fn cancels_without_compensation_when_the_first_step_fails() {
+        let mut fulfillment = started(StepTimeouts::default());
+        let ports = Ports { failing: Some("reserve_stock"), ..Ports::default() };
+        drive(&mut fulfillment, &ports).unwrap();
+
+        assert_eq!(fulfillment.get_state().status, FulfillmentStatus::Cancelled);
+        assert_eq!(*ports.calls.borrow(), vec!["reserve_stock"]);
+    }
+
+    #[test]
+    fn compensates_a_step_completed_past_its_deadline() {
+        let mut fulfillment = started(StepTimeouts { capture_payment: Duration::ZERO, ..StepTimeouts::default() });
+        let ports = Ports::default();
+        let reserve = fulfillment.get_state().next_action(SystemTime::now()).unwrap();
+        let command = reserve.perform(1, fulfillment.get_state(), &ports, &ports, &ports).unwrap();
+        fulfillment.handle_command(command).unwrap();
+
+        // Replies after its deadline
+        let late = SystemTime::now() + Duration::from_secs(1);
+        fulfillment.handle_command(FulfillmentCommand::RecordStep { step: FulfillmentStep::CapturePayment, outcome: Ok(()), at: late }).unwrap();
+        assert_eq!(fulfillment.get_state().next_action(late), Some(FulfillmentAction::Compensate(FulfillmentStep::CapturePayment)));
+        drive(&mut fulfillment, &ports).unwrap();
+
+        let state = fulfillment.get_state();
+        assert_eq!((state.status, state.failure.as_deref()), (FulfillmentStatus::Cancelled, Some("Step timed out")));
+        assert_eq!(*ports.calls.borrow(), vec!["reserve_stock", "refund_payment", "release_stock"]);
+    }
+
+    #[test]
+    fn expires_a_step_left_in_flight() {
+        let mut fulfillment = started(StepTimeouts::default());
+        let now = SystemTime::now();
+        assert_eq!(
+            fulfillment.handle_command(FulfillmentCommand::ExpireStep { step: FulfillmentStep::ReserveStock, at: now }).err(),
+            Some("Step not timed out yet")
+        );
+
+        let later = now + Duration::from_secs(60);
+        assert_eq!(fulfillment.get_state().next_action(later), Some(FulfillmentAction::Expire(FulfillmentStep::ReserveStock)));
+        fulfillment.handle_command(FulfillmentCommand::ExpireStep { step: FulfillmentStep::ReserveStock, at: later }).unwrap();
+        assert_eq!(fulfillment.get_state().next_action(later), Some(FulfillmentAction::Compensate(FulfillmentStep::ReserveStock)));
+    }
+
+    #[test]
+    fn ignores_a_step_not_in_progress_and_a_start_delivered_again() {
+        let mut fulfillment = started(StepTimeouts::default());
+        let record = FulfillmentCommand::RecordStep { step: FulfillmentStep::CreateShipment, outcome: Ok(()), at: SystemTime::now() };
+        assert_eq!(fulfillment.handle_command(record).err(), Some("Step not in progress"));
+
+        let start = FulfillmentCommand::Start { order: order(), timeouts: StepTimeouts::default(), at: SystemTime::now() };
+        assert!(fulfillment.handle_command(start).unwrap().1.is_empty());
+        assert_eq!(fulfillment.get_sequence_number(), 2);
+    }
+
+    #[test]
+    fn resumes_from_its_events() {
+        let mut fulfillment = started(StepTimeouts::default());
+        let ports = Ports { failing: Some("capture_payment"), ..Ports::default() };
+        let mut events = vec![];
+        while let Some(action @ FulfillmentAction::Perform(_)) = fulfillment.get_state().next_action(SystemTime::now()) {
+            let command = action.perform(1, fulfillment.get_state(), &ports, &ports, &ports).unwrap();
+            events.extend(fulfillment.handle_command(command).unwrap().1);
+        }
+
+        let history: Vec<SequencedEvent<FulfillmentEvent>> = vec![
+            SequencedEvent { sequence_number: 1, event: FulfillmentEvent::Started { order: order(), timeouts: StepTimeouts::default() } },
+            SequencedEvent { sequence_number: 2, event: FulfillmentEvent::StepStarted { step: FulfillmentStep::ReserveStock, deadline: SystemTime::now() + Duration::from_secs(5) } },
+        ].into_iter().chain(events).collect();
+        let mut restored = OrderFulfillment::default();
+        let state = restored.restore_from_events(history).unwrap();
+        assert_eq!(state.next_action(SystemTime::now()), Some(FulfillmentAction::Compensate(FulfillmentStep::ReserveStock)));
+        assert_eq!(restored.get_sequence_number(), fulfillment.get_sequence_number());
+
+        drive(&mut restored, &ports).unwrap();
+        assert_eq!(restored.get_state().status, FulfillmentStatus::Cancelled);
+    }
+
+    #[test]
+    fn retries_a_failed_fulfillment_with_a_backoff_and_checkpoints_before_it() {
+        let config = FulfillmentConfig { retry_delay: Duration::from_secs(1), max_retry_delay: Duration::from_secs(3), ..FulfillmentConfig::default() };
+        let mut retries = FulfillmentRetries::default();
+        let now = Instant::now();
+        assert_eq!(retries.checkpoint(10), 10);
+
+        assert_eq!(retries.failed(4, 1, now, &config), 1);
+        assert_eq!(retries.failed(7, 2, now, &config), 1);
+        assert_eq!(retries.checkpoint(10), 3);
+        assert!(retries.due(now).is_empty());
+        assert_eq!(retries.due(now + Duration::from_secs(1)), vec![(4, 1), (7, 2)]);
+
+        // 2 then 3 seconds, at most
+        assert_eq!(retries.failed(4, 1, now, &config), 2);
+        assert_eq!(retries.due(now + Duration::from_secs(1)), vec![(7, 2)]);
+        assert_eq!(retries.failed(4, 1, now, &config), 3);
+        assert_eq!(retries.failed(4, 1, now, &config), 4);
+        assert_eq!(retries.due(now + Duration::from_secs(3)).len(), 2);
+
+        retries.over(4);
+        assert_eq!(retries.checkpoint(10), 6);
+        retries.over(7);
+        assert!(retries.is_empty());
+        assert_eq!(retries.checkpoint(10), 10);
+    }
+}
@@ -0,0 +1,24 @@
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use reactive_service_application::outbox::{OutboxMessage, OutboxRelayConfig};
+
+    #[test]
+    fn backoff_doubles_up_to_the_maximum() {
+        let config = OutboxRelayConfig {
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(1),
+            ..OutboxRelayConfig::default()
+        };
+        let backoffs: Vec<u128> = (1..=6).map(|attempts| config.backoff(attempts).as_millis()).collect();
+        assert_eq!(backoffs, vec![100, 200, 400, 800, 1000, 1000]);
+        assert_eq!(config.backoff(u32::MAX), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn message_as_a_json_line() {
+        let message = OutboxMessage { position: 7, entity_id: 3, sequence_number: 2, payload: r#"{"Completed":{"invoice":{}}}"#.to_owned() };
+        assert_eq!(message.to_json_line(), r#"{"position":7,"entity_id":3,"sequence_number":2,"event":{"Completed":{"invoice":{}}}}"#);
+    }
+}
@@ -0,0 +1,112 @@
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use reactive_service_application::projections::{
+        CheckpointedModel, OrderStatus, OrderSummaries, ReadModel, SalesBySku, SkuSales
+    };
+    use reactive_service_application::subscriptions::Positioned;
+    use reactive_service_domain::aggregate_root::SequencedEvent;
+    use reactive_service_domain::non_empty_cart::{NonEmptyCart, Quantity, Sku};
+    use reactive_service_domain::order_entity::OrderEvent;
+    use reactive_service_domain::order_state::{Currency, DeliveryAddress, Invoice, Money, Street};
+
+    fn cart(items: &[(&str, u16)]) -> NonEmptyCart {
+        NonEmptyCart::new(items.iter().map(|(sku, quantity)| (Sku(sku.to_string()), Quantity(*quantity))).collect::<HashMap<_, _>>()).unwrap()
+    }
+
+    fn cad(amount_cents: u32) -> Money {
+        Money { amount_cents, currency: Currency::Cad }
+    }
+
+    fn delivery_address() -> DeliveryAddress {
+        DeliveryAddress { street: Street("1 Main Street".to_owned()), postal_code: "H0H 0H0".parse().unwrap() }
+    }
+
+    /// The events of the orders, at their positions in the given order.
+    fn positioned(events: Vec<(i64, i64, OrderEvent)>) -> Vec<Positioned<OrderEvent>> {
+        events.into_iter()
+            .enumerate()
+            .map(|(index, (entity_id, sequence_number, event))|
+                Positioned { position: index as i64 + 1, entity_id, event: SequencedEvent { sequence_number, event } })
+            .collect()
+    }
+
+    fn fold<M: ReadModel>(events: &[Positioned<OrderEvent>]) -> M {
+        let mut model = M::default();
+        events.iter().for_each(|event| model.apply(event));
+        model
+    }
+
+    #[test]
+    fn summarizes_the_orders() {
+        let events = positioned(vec![
+            (1, 1, OrderEvent::UpdatedCart { cart: cart(&[("apple", 1)]), customer_id: None }),
+            (2, 1, OrderEvent::UpdatedCart { cart: cart(&[("pear", 1)]), customer_id: None }),
+            (1, 2, OrderEvent::UpdatedDeliveryAddress { delivery_address: delivery_address(), shipping_cost: cad(200), tax: cad(130) }),
+            (1, 3, OrderEvent::UpdatedCartOnExistingDeliveryAddress { cart: cart(&[("apple", 2)]), shipping_cost: cad(300), tax: cad(140) }),
+            (1, 4, OrderEvent::Completed { invoice: Invoice{} }),
+        ]);
+
+        let summaries: OrderSummaries = fold(&events);
+        assert_eq!(summaries.len(), 2);
+        let completed = summaries.get(1).unwrap();
+        assert_eq!((completed.status, completed.total_cents, completed.last_updated), (OrderStatus::Completed, 440, 5));
+        assert_eq!(completed.postal_code.as_deref(), Some("H0H 0H0"));
+        let with_cart = summaries.get(2).unwrap();
+        assert_eq!((with_cart.status, with_cart.total_cents, with_cart.postal_code.clone()), (OrderStatus::WithCart, 0, None));
+        assert!(summaries.get(3).is_none());
+    }
+
+    #[test]
+    fn counts_the_sales_of_the_completed_orders_only() {
+        let events = positioned(vec![
+            (1, 1, OrderEvent::UpdatedCart { cart: cart(&[("apple", 1)]), customer_id: None }),
+            (2, 1, OrderEvent::UpdatedCart { cart: cart(&[("apple", 5)]), customer_id: None }),
+            (1, 2, OrderEvent::UpdatedDeliveryAddress { delivery_address: delivery_address(), shipping_cost: cad(200), tax: cad(130) }),
+            (1, 3, OrderEvent::UpdatedCartOnExistingDeliveryAddress { cart: cart(&[("apple", 2), ("pear", 3)]), shipping_cost: cad(200), tax: cad(130) }),
+            (1, 4, OrderEvent::Completed { invoice: Invoice{} }),
+        ]);
+
+        let sales: SalesBySku = fold(&events);
+        assert_eq!(sales.get(&Sku("apple".to_owned())), Some(&SkuSales { quantity: 2, orders: 1 }));
+        assert_eq!(sales.get(&Sku("pear".to_owned())), Some(&SkuSales { quantity: 3, orders: 1 }));
+        assert_eq!(sales.get(&Sku("plum".to_owned())), None);
+    }
+
+    #[test]
+    fn an_expired_order_is_never_sold() {
+        let events = positioned(vec![
+            (1, 1, OrderEvent::UpdatedCart { cart: cart(&[("apple", 1)]), customer_id: None }),
+            (1, 2, OrderEvent::Expired),
+        ]);
+
+        let summaries: OrderSummaries = fold(&events);
+        assert_eq!(summaries.get(1).map(|summary| summary.status), Some(OrderStatus::Expired));
+        let sales: SalesBySku = fold(&events);
+        assert_eq!(sales.get(&Sku("apple".to_owned())), None);
+    }
+
+    #[test]
+    fn order_status_round_trips() {
+        for status in [OrderStatus::WithCart, OrderStatus::WithAddress, OrderStatus::Completed, OrderStatus::Expired] {
+            assert_eq!(status.as_str().parse::<OrderStatus>(), Ok(status));
+        }
+        assert!("Unknown".parse::<OrderStatus>().is_err());
+    }
+
+    #[test]
+    fn applies_the_events_after_its_checkpoint_once() {
+        let events = positioned(vec![
+            (1, 1, OrderEvent::UpdatedCart { cart: cart(&[("apple", 1)]), customer_id: None }),
+            (1, 2, OrderEvent::Completed { invoice: Invoice{} }),
+        ]);
+        let mut view: CheckpointedModel<SalesBySku> = CheckpointedModel::default();
+        view.apply(&events[..1]);
+        // Delivered again with the next one
+        view.apply(&events);
+
+        assert_eq!(view.checkpoint(), 2);
+        assert_eq!(view.model().get(&Sku("apple".to_owned())), Some(&SkuSales { quantity: 1, orders: 1 }));
+    }
+}
@@ -0,0 +1,98 @@
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::time::Duration;
+
+    use reactive_service_application::authorization::Principal;
+    use reactive_service_application::clock::{Clock, ManualClock};
+    use reactive_service_application::order_commands::{ExpireOrder, OrderCommand, PayOrder, UpdateCart};
+    use reactive_service_application::payment_processor::{LocalPaymentProcessor, PaymentToken};
+    use reactive_service_application::scheduler::{PendingCommands, Scheduled, ScheduledCommand};
+    use reactive_service_application::shipping_calculator::LocalShippingCalculator;
+    use reactive_service_application::tax_calculator::LocalTaxCalculator;
+    use reactive_service_domain::aggregate_root::{AggregateRoot, SequencedEvent};
+    use reactive_service_domain::non_empty_cart::{NonEmptyCart, Quantity, Sku};
+    use reactive_service_domain::order_entity::{OrderEntity, OrderEvent};
+    use reactive_service_domain::order_state::{Invoice, OrderState};
+
+    const TTL: Duration = Duration::from_secs(60);
+
+    fn cart() -> NonEmptyCart {
+        NonEmptyCart::new(HashMap::from([(Sku("apple".to_owned()), Quantity(1))])).unwrap()
+    }
+
+    fn handle(order_entity: &mut OrderEntity, command: OrderCommand) -> Result<Vec<SequencedEvent<OrderEvent>>, &'static str> {
+        let entity_command = command.entity_command(
+            &Principal::Admin, order_entity, &LocalShippingCalculator{}, &LocalTaxCalculator{}, &LocalPaymentProcessor{}
+        )?;
+        Ok(order_entity.handle_command(entity_command)?.1)
+    }
+
+    fn expire_order(sequence_number: i64) -> OrderCommand {
+        ExpireOrder { order_id: 1, sequence_number }.into()
+    }
+
+    #[test]
+    fn the_clock_only_moves_when_told_to() {
+        let clock = ManualClock::default();
+        let start = clock.now();
+        clock.advance(TTL);
+        assert_eq!(clock.now(), start + TTL);
+    }
+
+    #[test]
+    fn schedules_an_expiry_after_each_change_of_an_open_order() {
+        let clock = ManualClock::default();
+        let updated = SequencedEvent { sequence_number: 3, event: OrderEvent::UpdatedCart { cart: cart(), customer_id: None } };
+        let completed = SequencedEvent { sequence_number: 4, event: OrderEvent::Completed { invoice: Invoice{} } };
+
+        let expiry = Scheduled::order_expiry(1, &updated, clock.now(), TTL).unwrap();
+        assert_eq!(expiry.due_at, clock.now() + TTL);
+        assert_eq!(expiry.command, ScheduledCommand::ExpireOrder(ExpireOrder { order_id: 1, sequence_number: 3 }));
+        assert_eq!(expiry.key(), "expire_order/1");
+        assert_eq!(Scheduled::from_millis(expiry.due_at_millis(), expiry.command.clone()), expiry);
+        assert!(Scheduled::order_expiry(1, &completed, clock.now(), TTL).is_none());
+    }
+
+    #[test]
+    fn expires_an_order_untouched_since_its_expiry_was_scheduled() {
+        let mut order_entity = OrderEntity::default();
+        let mut events = handle(&mut order_entity, UpdateCart { order_id: 1, cart: cart() }.into()).unwrap();
+        events.extend(handle(&mut order_entity, UpdateCart { order_id: 1, cart: cart() }.into()).unwrap());
+
+        assert_eq!(handle(&mut order_entity, expire_order(1)).err(), Some("Order changed since its expiry was scheduled."));
+        events.extend(handle(&mut order_entity, expire_order(2)).unwrap());
+        assert!(matches!(events[2].event, OrderEvent::Expired));
+
+        assert!(handle(&mut order_entity, UpdateCart { order_id: 1, cart: cart() }.into()).is_err());
+        assert!(handle(&mut order_entity, PayOrder { order_id: 1, payment_token: PaymentToken::new("token") }.into()).is_err());
+        assert!(handle(&mut order_entity, expire_order(3)).is_err());
+
+        let mut restored = OrderEntity::default();
+        restored.restore_from_events(events).unwrap();
+        assert!(matches!(restored.get_state(), OrderState::Expired(_)));
+    }
+
+    #[test]
+    fn delivers_the_pending_commands_due_the_earliest_first() {
+        let clock = ManualClock::default();
+        let expiry = |order_id, sequence_number, ttl| Scheduled {
+            due_at: clock.now() + ttl,
+            command: ScheduledCommand::ExpireOrder(ExpireOrder { order_id, sequence_number }),
+        };
+        let mut pending = PendingCommands::default();
+        pending.schedule(&expiry(1, 1, TTL));
+        pending.schedule(&expiry(2, 1, TTL / 2));
+        pending.schedule(&expiry(3, 1, TTL * 2));
+        // Replaces the expiry of the order 1
+        pending.schedule(&expiry(1, 2, TTL));
+
+        assert_eq!(pending.due(clock.now() + TTL, 10), vec![expiry(2, 1, TTL / 2), expiry(1, 2, TTL)]);
+        assert_eq!(pending.due(clock.now() + TTL, 1), vec![expiry(2, 1, TTL / 2)]);
+
+        // Scheduled again meanwhile: kept
+        pending.remove(&expiry(1, 1, TTL));
+        pending.remove(&expiry(2, 1, TTL / 2));
+        assert_eq!(pending.due(clock.now() + TTL, 10), vec![expiry(1, 2, TTL)]);
+    }
+}
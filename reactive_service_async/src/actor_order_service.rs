@@ -0,0 +1,337 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use reactive_service_domain::aggregate_root::AggregateRoot;
+use reactive_service_domain::order_entity::{OrderEntity, OrderEvent};
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::sync::{mpsc, oneshot};
+use tracing::error;
+use reactive_service_application::command_errors::{ACTOR_STOPPED, MAILBOX_FULL, MAILBOX_UNAVAILABLE, SUPERVISOR_STOPPED};
+use crate::order_service::{
+    CommandResult, EventsJournal, OrderCommand, OrderId, PayOrder, Principal, ShippingCalculator, TaxCalculator, UpdateCart,
+    UpdateDeliveryAddress
+};
+use crate::payment_processor::PaymentProcessor;
+
+/// Tuning of the actor runtime.
+#[derive(Debug, Clone)]
+pub struct ActorOrderServiceConfig {
+    /// Capacity of the bounded mailbox of each order actor (and of the supervisor).
+    /// A command for an order whose mailbox is full is rejected.
+    pub mailbox_capacity: usize,
+    /// An order actor without any command for this long is stopped, its memory released.
+    /// It is restored from the journal on its next command.
+    pub passivate_after: Duration,
+}
+
+impl Default for ActorOrderServiceConfig {
+    fn default() -> Self {
+        Self {
+            mailbox_capacity: 64,
+            passivate_after: Duration::from_secs(60),
+        }
+    }
+}
+
+/// Alternative runtime to the `OrderService`: each live order is a tokio task owning its `OrderEntity`.
+///
+/// Commands are routed by a single supervisor task to the mailbox of the order actor,
+/// the actor handles them one at a time, in the order they were sent, and replies through a oneshot channel.
+/// No lock is involved: the entity is only ever touched by the task owning it.
+///
+/// The supervisor also owns the lifecycle of the actors:
+/// - an actor is spawned (and restored from the journal) on the first command for its order,
+/// - an idle actor is passivated after `passivate_after`,
+/// - a crashed actor is dropped, the next command restores a fresh one from the journal.
+///
+/// The handle is cheap to clone, and must be created from within a tokio runtime.
+#[derive(Clone)]
+pub struct ActorOrderService {
+    supervisor: mpsc::Sender<SupervisorMessage>,
+}
+
+impl ActorOrderService {
+
+    pub fn new<E, S, T, P>(events_journal: E, shipping_calculator: S, tax_calculator: T, payment_processor: P) -> Self
+    where
+        E: EventsJournal<OrderEvent> + Send + Sync + 'static,
+        S: ShippingCalculator + Send + Sync + 'static,
+        T: TaxCalculator + Send + Sync + 'static,
+        P: PaymentProcessor + Send + Sync + 'static,
+    {
+        Self::with_config(
+            events_journal, shipping_calculator, tax_calculator, payment_processor, ActorOrderServiceConfig::default()
+        )
+    }
+
+    pub fn with_config<E, S, T, P>(events_journal: E, shipping_calculator: S, tax_calculator: T, payment_processor: P,
+                                   config: ActorOrderServiceConfig) -> Self
+    where
+        E: EventsJournal<OrderEvent> + Send + Sync + 'static,
+        S: ShippingCalculator + Send + Sync + 'static,
+        T: TaxCalculator + Send + Sync + 'static,
+        P: PaymentProcessor + Send + Sync + 'static,
+    {
+        let (supervisor_sender, supervisor_mailbox) = mpsc::channel(config.mailbox_capacity);
+
+        let supervisor = Supervisor {
+            dependencies: Arc::new(Dependencies { events_journal, shipping_calculator, tax_calculator, payment_processor }),
+            actors: HashMap::default(),
+            next_generation: 0,
+            myself: supervisor_sender.downgrade(),
+            config,
+        };
+        tokio::spawn(supervisor.run(supervisor_mailbox));
+
+        Self { supervisor: supervisor_sender }
+    }
+
+    pub async fn update_cart(&self, principal: &Principal, cmd: UpdateCart) -> CommandResult {
+        self.handle_as(principal, OrderCommand::UpdateCart(cmd)).await
+    }
+
+    pub async fn update_delivery_address(&self, principal: &Principal, cmd: UpdateDeliveryAddress) -> CommandResult {
+        self.handle_as(principal, OrderCommand::UpdateDeliveryAddress(cmd)).await
+    }
+
+    pub async fn pay_order(&self, principal: &Principal, cmd: PayOrder) -> CommandResult {
+        self.handle_as(principal, OrderCommand::PayOrder(cmd)).await
+    }
+
+    /// Send any order command to the actor of its order, on behalf of the principal, and wait for the reply.
+    pub async fn handle_as(&self, principal: &Principal, command: OrderCommand) -> CommandResult {
+        let order_id = command.order_id();
+        let (reply, response) = oneshot::channel();
+        let envelope = Envelope { principal: *principal, command, reply };
+        self.supervisor.send(SupervisorMessage::Deliver { order_id, envelope })
+            .await
+            .map_err(|_| SUPERVISOR_STOPPED)?;
+
+        // The reply sender is dropped without answer if the actor crashed while handling the command
+        response.await.map_err(|_| ACTOR_STOPPED)?
+    }
+}
+
+struct Envelope {
+    principal: Principal,
+    command: OrderCommand,
+    reply: oneshot::Sender<CommandResult>,
+}
+
+enum SupervisorMessage {
+    Deliver { order_id: OrderId, envelope: Envelope },
+    /// The actor did not receive anything during `passivate_after`, after having received `received` envelopes.
+    Idle { order_id: OrderId, generation: u64, received: u64 },
+    /// The actor task is over, `crashed` when it panicked.
+    Stopped { order_id: OrderId, generation: u64, crashed: bool },
+}
+
+struct Dependencies<E, S, T, P> {
+    events_journal: E,
+    shipping_calculator: S,
+    tax_calculator: T,
+    payment_processor: P,
+}
+
+/// What the supervisor knows about a running actor
+struct ActorRef {
+    mailbox: mpsc::Sender<Envelope>,
+    // Distinguish successive incarnations of the same order actor
+    generation: u64,
+    forwarded: u64,
+}
+
+struct Supervisor<E, S, T, P> {
+    dependencies: Arc<Dependencies<E, S, T, P>>,
+    actors: HashMap<OrderId, ActorRef>,
+    next_generation: u64,
+    // Weak, so the supervisor stops once every ActorOrderService handle is dropped
+    myself: mpsc::WeakSender<SupervisorMessage>,
+    config: ActorOrderServiceConfig,
+}
+
+impl<E, S, T, P> Supervisor<E, S, T, P>
+where
+    E: EventsJournal<OrderEvent> + Send + Sync + 'static,
+    S: ShippingCalculator + Send + Sync + 'static,
+    T: TaxCalculator + Send + Sync + 'static,
+    P: PaymentProcessor + Send + Sync + 'static,
+{
+    async fn run(mut self, mut mailbox: mpsc::Receiver<SupervisorMessage>) {
+        while let Some(message) = mailbox.recv().await {
+            match message {
+                SupervisorMessage::Deliver { order_id, envelope } =>
+                    self.deliver(order_id, envelope),
+
+                SupervisorMessage::Idle { order_id, generation, received } => {
+                    // Only passivate if nothing was forwarded since the actor went idle,
+                    // otherwise the actor is about to receive a new envelope.
+                    let is_idle = self.actors.get(&order_id)
+                        .is_some_and(|actor| actor.generation == generation && actor.forwarded == received);
+                    if is_idle {
+                        // Dropping the only sender ends the actor loop
+                        self.actors.remove(&order_id);
+                    }
+                },
+
+                SupervisorMessage::Stopped { order_id, generation, crashed } => {
+                    if crashed {
+                        error!(order_id, "order actor crashed, it will be restored from the journal");
+                    }
+                    if self.actors.get(&order_id).is_some_and(|actor| actor.generation == generation) {
+                        self.actors.remove(&order_id);
+                    }
+                }
+            }
+        }
+        // All the handles are dropped: dropping the actors mailboxes stops them
+    }
+
+    fn deliver(&mut self, order_id: OrderId, envelope: Envelope) {
+        if !self.actors.contains_key(&order_id) {
+            let actor = self.spawn_actor(order_id);
+            self.actors.insert(order_id, actor);
+        }
+        let actor = self.actors.get_mut(&order_id).expect("Actor was just spawned");
+
+        match actor.mailbox.try_send(envelope) {
+            Ok(()) => actor.forwarded += 1,
+
+            // Don't block every other order on a busy one, nor queue without bound: the caller may retry later
+            Err(TrySendError::Full(envelope)) => {
+                let _ = envelope.reply.send(Err(MAILBOX_FULL));
+            },
+
+            // The actor stopped, we didn't process its Stopped message yet. Start a new incarnation.
+            Err(TrySendError::Closed(envelope)) => {
+                let mut actor = self.spawn_actor(order_id);
+                match actor.mailbox.try_send(envelope) {
+                    Ok(()) => actor.forwarded += 1,
+                    Err(err) => { let _ = err.into_inner().reply.send(Err(MAILBOX_UNAVAILABLE)); }
+                }
+                self.actors.insert(order_id, actor);
+            }
+        }
+    }
+
+    fn spawn_actor(&mut self, order_id: OrderId) -> ActorRef {
+        let generation = self.next_generation;
+        self.next_generation += 1;
+
+        let (mailbox_sender, mailbox) = mpsc::channel(self.config.mailbox_capacity);
+        let actor = OrderActor {
+            order_id,
+            generation,
+            dependencies: self.dependencies.clone(),
+            supervisor: self.myself.clone(),
+            passivate_after: self.config.passivate_after,
+        };
+
+        // Watch the actor task, so the supervisor learns about a panic
+        let actor_task = tokio::spawn(actor.run(mailbox));
+        let supervisor = self.myself.clone();
+        tokio::spawn(async move {
+            let crashed = actor_task.await.is_err();
+            if let Some(supervisor) = supervisor.upgrade() {
+                let _ = supervisor.send(SupervisorMessage::Stopped { order_id, generation, crashed }).await;
+            }
+        });
+
+        ActorRef { mailbox: mailbox_sender, generation, forwarded: 0 }
+    }
+}
+
+struct OrderActor<E, S, T, P> {
+    order_id: OrderId,
+    generation: u64,
+    dependencies: Arc<Dependencies<E, S, T, P>>,
+    supervisor: mpsc::WeakSender<SupervisorMessage>,
+    passivate_after: Duration,
+}
+
+impl<E, S, T, P> OrderActor<E, S, T, P>
+where
+    E: EventsJournal<OrderEvent> + Send + Sync + 'static,
+    S: ShippingCalculator + Send + Sync + 'static,
+    T: TaxCalculator + Send + Sync + 'static,
+    P: PaymentProcessor + Send + Sync + 'static,
+{
+    async fn run(self, mut mailbox: mpsc::Receiver<Envelope>) {
+        let mut order = match self.restore().await {
+            Ok(order) => order,
+            Err(err) => return Self::reject_pending(mailbox, err),
+        };
+
+        let mut received: u64 = 0;
+        let mut reported_idle = false;
+
+        loop {
+            match tokio::time::timeout(self.passivate_after, mailbox.recv()).await {
+                Ok(Some(envelope)) => {
+                    received += 1;
+                    reported_idle = false;
+
+                    match self.handle(&mut order, &envelope.principal, envelope.command).await {
+                        Ok(result) => { let _ = envelope.reply.send(result); },
+                        // The in-memory entity is ahead of the journal, stop so the next incarnation restores it
+                        Err(err) => {
+                            let _ = envelope.reply.send(Err(err));
+                            return Self::reject_pending(mailbox, err);
+                        }
+                    }
+                },
+                // The supervisor dropped our mailbox: passivated
+                Ok(None) => return,
+
+                Err(_elapsed) => {
+                    if !reported_idle {
+                        reported_idle = true;
+                        let Some(supervisor) = self.supervisor.upgrade() else { return };
+                        let idle = SupervisorMessage::Idle { order_id: self.order_id, generation: self.generation, received };
+                        if supervisor.send(idle).await.is_err() {
+                            return;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    async fn restore(&self) -> Result<OrderEntity, &'static str> {
+        let events = self.dependencies.events_journal.retrieve_events(self.order_id).await?;
+        let mut entity = OrderEntity::default();
+        let _ = entity.restore_from_events(events)?;
+        Ok(entity)
+    }
+
+    /// Handle a command, the outer error means the entity can't be trusted anymore.
+    async fn handle(&self, order: &mut OrderEntity, principal: &Principal, command: OrderCommand) -> Result<CommandResult, &'static str> {
+        let dependencies = &self.dependencies;
+
+        let entity_command = command.entity_command(
+            principal, order, &dependencies.shipping_calculator, &dependencies.tax_calculator, &dependencies.payment_processor
+        );
+
+        let entity_command = match entity_command {
+            Ok(entity_command) => entity_command,
+            Err(err) => return Ok(Err(err)),
+        };
+
+        let (state, events) = match order.handle_command(entity_command) {
+            Ok((state, events)) => (state.clone(), events),
+            Err(err) => return Ok(Err(err)),
+        };
+
+        for evt in &events {
+            dependencies.events_journal.persist_event(self.order_id, evt).await?;
+        }
+        Ok(Ok((state, events)))
+    }
+
+    fn reject_pending(mut mailbox: mpsc::Receiver<Envelope>, err: &'static str) {
+        mailbox.close();
+        while let Ok(envelope) = mailbox.try_recv() {
+            let _ = envelope.reply.send(Err(err));
+        }
+    }
+}
@@ -0,0 +1,84 @@
+use std::future::Future;
+use reactive_service_domain::aggregate_root::SequencedEvent;
+use reactive_service_domain::order_entity::OrderEvent;
+use crate::event_bus::EventBus;
+use crate::inventory::AsyncInventory;
+use crate::order_service::{CommandResult, EventsJournal, OrderCommand, OrderId, OrderService, Principal, QueryResult};
+use crate::payment_processor::PaymentProcessor;
+use crate::shipping_calculator::ShippingCalculator;
+use crate::tax_calculator::TaxCalculator;
+
+pub use reactive_service_application::command_errors::CommandErrorKind;
+
+/// The customer issuing a request, in the HTTP headers or the gRPC metadata.
+/// The servers are meant to run behind a gateway authenticating the customers.
+pub const CUSTOMER_ID_HEADER: &str = "x-customer-id";
+
+/// The admin credential, in the HTTP headers or the gRPC metadata: a request carrying the configured token acts as an admin.
+pub const ADMIN_TOKEN_HEADER: &str = "x-admin-token";
+
+/// How the network APIs authenticate their callers.
+#[derive(Debug, Clone, Default)]
+pub struct ApiAuthConfig {
+    /// The token granting the admin rights. None by default: no request acts as an admin.
+    pub admin_token: Option<String>,
+}
+
+impl ApiAuthConfig {
+    /// The principal of a request, from the values of its `CUSTOMER_ID_HEADER` and `ADMIN_TOKEN_HEADER`.
+    /// A request without any of them, or with a wrong admin token, is rejected.
+    pub fn principal(&self, customer_id: Option<&[u8]>, admin_token: Option<&[u8]>) -> Result<Principal, &'static str> {
+        match (admin_token, customer_id) {
+            (Some(token), _) => match self.admin_token.as_deref() {
+                Some(admin_token) if !admin_token.is_empty() && same_token(admin_token.as_bytes(), token) => Ok(Principal::Admin),
+                _ => Err("Invalid admin token"),
+            },
+            (None, Some(customer_id)) => std::str::from_utf8(customer_id).ok()
+                .and_then(|customer_id| customer_id.trim().parse().ok())
+                .map(Principal::Customer)
+                .ok_or("Invalid customer id"),
+            (None, None) => Err("Missing customer id"),
+        }
+    }
+}
+
+// Compare every byte, so the time taken doesn't tell how much of the token matched
+fn same_token(expected: &[u8], actual: &[u8]) -> bool {
+    expected.len() == actual.len() && expected.iter().zip(actual).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+/// The order service behind the network APIs, shared by their connections for the lifetime of the server.
+pub trait OrderApi: Send + Sync + 'static {
+    fn handle_as(&self, principal: &Principal, command: OrderCommand) -> impl Future<Output = CommandResult> + Send;
+    /// The order, if the principal may read it: a customer only reads the orders they own.
+    fn get_order_as(&self, principal: &Principal, order_id: OrderId) -> impl Future<Output = QueryResult> + Send;
+
+    /// The persisted events of the order, to catch up before following the `event_bus`.
+    fn retrieve_events(&self, order_id: OrderId) -> impl Future<Output = Result<Vec<SequencedEvent<OrderEvent>>, &'static str>> + Send;
+    fn event_bus(&self) -> &EventBus<OrderEvent>;
+}
+
+impl<E, S, T, P, I> OrderApi for OrderService<E, S, T, P, I>
+where
+    E: EventsJournal<OrderEvent> + Send + Sync + 'static,
+    S: ShippingCalculator + Send + Sync + 'static,
+    T: TaxCalculator + Send + Sync + 'static,
+    P: PaymentProcessor + Send + Sync + 'static,
+    I: AsyncInventory + Send + Sync + 'static,
+{
+    fn handle_as(&self, principal: &Principal, command: OrderCommand) -> impl Future<Output = CommandResult> + Send {
+        OrderService::handle_as(self, principal, command)
+    }
+
+    fn get_order_as(&self, principal: &Principal, order_id: OrderId) -> impl Future<Output = QueryResult> + Send {
+        OrderService::get_order_as(self, principal, order_id)
+    }
+
+    fn retrieve_events(&self, order_id: OrderId) -> impl Future<Output = Result<Vec<SequencedEvent<OrderEvent>>, &'static str>> + Send {
+        self.events_journal().retrieve_events(order_id)
+    }
+
+    fn event_bus(&self) -> &EventBus<OrderEvent> {
+        OrderService::event_bus(self)
+    }
+}
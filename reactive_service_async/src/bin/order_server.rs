@@ -0,0 +1,135 @@
+//! The order service, over HTTP/JSON, and gRPC. Configured by the environment:
+//! - `ORDER_SERVER_ADDRESS`: the address to listen on, `127.0.0.1:8080` by default.
+//! - `ORDER_GRPC_ADDRESS`: the address to serve gRPC on, none by default.
+//! - `ORDER_JOURNAL`: where the events are kept, `memory` by default, or `sqlite:<path>`, `file:<directory>`,
+//!   `postgres` for a local database, `postgres:<dsn>`.
+//! - `ORDER_ADMIN_TOKEN`: the token of the admins, in the `x-admin-token` header. None by default: only the customers,
+//!   by their `x-customer-id` header, are served.
+//! - `RUST_LOG`: the logs written to the standard error, `info` by default.
+//!
+//! The metrics of the service are served on `GET /metrics`, next to the HTTP API.
+//!
+//! On Ctrl-C, or SIGTERM, the servers stop accepting connections, then exit once the requests in progress are served.
+
+use std::error::Error;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+use reactive_service_domain::order_entity::OrderEvent;
+use reactive_service_async::{grpc_api, http_api};
+use reactive_service_async::api::ApiAuthConfig;
+use reactive_service_async::infra::file_journal::FileJournal;
+use reactive_service_async::infra::inmem_journal::InMemoryJournal;
+use reactive_service_async::infra::postgres_events_store::{PostgresEventStore, PostgresEventStoreConfig};
+use reactive_service_async::infra::sqlite_event_store::SqliteEventStore;
+use reactive_service_async::order_service::{EventsJournal, OrderService, PrometheusRegistry};
+use reactive_service_async::payment_processor::LocalPaymentProcessor;
+use reactive_service_async::shipping_calculator::LocalShippingCalculator;
+use reactive_service_async::tax_calculator::LocalTaxCalculator;
+use tokio::net::TcpListener;
+use tokio::sync::watch;
+use tracing::info;
+use tracing_subscriber::EnvFilter;
+
+enum JournalBackend {
+    InMemory,
+    Sqlite(PathBuf),
+    File(PathBuf),
+    Postgres(PostgresEventStoreConfig),
+}
+
+impl FromStr for JournalBackend {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            None if s == "memory" => Ok(JournalBackend::InMemory),
+            None if s == "postgres" => Ok(JournalBackend::Postgres(PostgresEventStoreConfig::default())),
+            Some(("sqlite", path)) if !path.is_empty() => Ok(JournalBackend::Sqlite(path.into())),
+            Some(("file", directory)) if !directory.is_empty() => Ok(JournalBackend::File(directory.into())),
+            Some(("postgres", dsn)) if !dsn.is_empty() =>
+                Ok(JournalBackend::Postgres(PostgresEventStoreConfig { dsn: dsn.to_owned(), ..Default::default() })),
+            _ => Err("Invalid journal, expected memory, sqlite:<path>, file:<directory>, postgres or postgres:<dsn>"),
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn Error>> {
+    tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")))
+        .init();
+
+    let address: SocketAddr = std::env::var("ORDER_SERVER_ADDRESS").as_deref().unwrap_or("127.0.0.1:8080").parse()?;
+    let grpc_address: Option<SocketAddr> = std::env::var("ORDER_GRPC_ADDRESS").ok().map(|address| address.parse()).transpose()?;
+    let journal: JournalBackend = std::env::var("ORDER_JOURNAL").as_deref().unwrap_or("memory").parse()?;
+    let auth = ApiAuthConfig { admin_token: std::env::var("ORDER_ADMIN_TOKEN").ok().filter(|token| !token.is_empty()) };
+
+    match journal {
+        JournalBackend::InMemory => serve(InMemoryJournal::<OrderEvent>::new()?, address, grpc_address, auth).await,
+        JournalBackend::Sqlite(path) => serve(SqliteEventStore::new(path).await?, address, grpc_address, auth).await,
+        JournalBackend::File(directory) => serve(FileJournal::new(directory).await?, address, grpc_address, auth).await,
+        JournalBackend::Postgres(config) => serve(PostgresEventStore::with_config(config).await?, address, grpc_address, auth).await,
+    }
+}
+
+async fn serve<E: EventsJournal<OrderEvent> + Send + Sync + 'static>(
+    journal: E, address: SocketAddr, grpc_address: Option<SocketAddr>, auth: ApiAuthConfig
+) -> Result<(), Box<dyn Error>> {
+
+    let registry = Arc::new(PrometheusRegistry::default());
+    let service = Arc::new(
+        OrderService::new(journal, LocalShippingCalculator{}, LocalTaxCalculator{}, LocalPaymentProcessor{})
+            .with_metrics(registry.clone())
+    );
+    let (stop, stopping) = watch::channel(false);
+    tokio::spawn(async move {
+        shutdown_signal().await;
+        let _ = stop.send(true);
+    });
+
+    let listener = TcpListener::bind(address).await?;
+    info!(address = %listener.local_addr()?, "Listening");
+    let http = async {
+        let router = http_api::router(service.clone(), auth.clone()).merge(http_api::metrics_router(registry));
+        axum::serve(listener, router).with_graceful_shutdown(stopped(stopping.clone())).await?;
+        Ok::<_, Box<dyn Error>>(())
+    };
+    let grpc = async {
+        if let Some(grpc_address) = grpc_address {
+            let listener = TcpListener::bind(grpc_address).await?;
+            info!(address = %listener.local_addr()?, "Serving gRPC");
+            grpc_api::serve(listener, service.clone(), auth.clone(), stopped(stopping.clone())).await?;
+        }
+        Ok::<_, Box<dyn Error>>(())
+    };
+    tokio::try_join!(http, grpc)?;
+    info!("Stopped");
+    Ok(())
+}
+
+async fn stopped(mut stopping: watch::Receiver<bool>) {
+    let _ = stopping.wait_for(|stopping| *stopping).await;
+}
+
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        let _ = tokio::signal::ctrl_c().await;
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => { signal.recv().await; },
+            Err(_) => std::future::pending().await,
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+}
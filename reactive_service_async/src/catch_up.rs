@@ -0,0 +1,136 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio::time::Instant;
+use reactive_service_application::catch_up::CatchUpCursor;
+use reactive_service_application::command_errors::{LOAD_CHECKPOINT_FAILED, SAVE_CHECKPOINT_FAILED};
+use crate::order_service::{GlobalEventsJournal, Positioned};
+
+pub use reactive_service_application::catch_up::CatchUpConfig;
+
+/// Where the consumers of the journal, e.g. the projections, are: the position of the last event each one processed.
+pub trait CheckpointStore {
+    /// Position of the last event processed by the consumer, 0 when it never saved one.
+    fn load_checkpoint(&self, consumer: &str) -> impl std::future::Future<Output = Result<i64, &'static str>> + Send;
+    fn save_checkpoint(&self, consumer: &str, position: i64) -> impl std::future::Future<Output = Result<(), &'static str>> + Send;
+}
+
+/// Checkpoints kept in memory, for tests and benchmarks.
+/// The lock is never held across an await point, so a blocking lock is fine.
+#[derive(Default)]
+pub struct InMemoryCheckpointStore {
+    positions: Mutex<HashMap<String, i64>>,
+}
+
+impl CheckpointStore for InMemoryCheckpointStore {
+    async fn load_checkpoint(&self, consumer: &str) -> Result<i64, &'static str> {
+        let positions = self.positions.lock().map_err(|_| LOAD_CHECKPOINT_FAILED)?;
+        Ok(positions.get(consumer).copied().unwrap_or(0))
+    }
+
+    async fn save_checkpoint(&self, consumer: &str, position: i64) -> Result<(), &'static str> {
+        let mut positions = self.positions.lock().map_err(|_| SAVE_CHECKPOINT_FAILED)?;
+        positions.insert(consumer.to_owned(), position);
+        Ok(())
+    }
+}
+
+/// Reads the events of every entity in the global order of the journal: the history first, by batches,
+/// then the new events as they are persisted. There is no switch to miss an event at:
+/// the live events are read from the journal too, once it notifies they are there.
+///
+/// Unlike the `EventBus`, a subscriber never lags: it reads at its own pace, and can resume from a checkpoint.
+pub struct CatchUpSubscription<'a, E, J: GlobalEventsJournal<E>> {
+    journal: &'a J,
+    config: CatchUpConfig,
+    cursor: CatchUpCursor<E>,
+}
+
+impl<'a, E, J: GlobalEventsJournal<E>> CatchUpSubscription<'a, E, J> {
+    /// Subscribe to the events after `position`, 0 for all of them, with the default settings.
+    pub fn new(journal: &'a J, position: i64) -> Self {
+        Self::with_config(journal, position, CatchUpConfig::default())
+    }
+
+    pub fn with_config(journal: &'a J, position: i64, config: CatchUpConfig) -> Self {
+        Self {
+            journal,
+            config,
+            cursor: CatchUpCursor::new(position),
+        }
+    }
+
+    /// Resume the subscription of a consumer after its last saved checkpoint.
+    pub async fn from_checkpoint<C: CheckpointStore>(journal: &'a J, checkpoints: &C, consumer: &str, config: CatchUpConfig)
+        -> Result<Self, &'static str> {
+
+        Ok(Self::with_config(journal, checkpoints.load_checkpoint(consumer).await?, config))
+    }
+
+    /// Position of the last event received, to checkpoint once it is processed.
+    pub fn position(&self) -> i64 {
+        self.cursor.position()
+    }
+
+    /// Save the position of the last event received as the checkpoint of the consumer.
+    pub async fn save_checkpoint<C: CheckpointStore>(&self, checkpoints: &C, consumer: &str) -> Result<(), &'static str> {
+        checkpoints.save_checkpoint(consumer, self.cursor.position()).await
+    }
+
+    /// Wait for the next event.
+    pub async fn recv(&mut self) -> Result<Positioned<E>, &'static str> {
+        loop {
+            if let Some(event) = self.recv_timeout(self.config.max_wait).await? {
+                return Ok(event);
+            }
+        }
+    }
+
+    /// Wait for the next event, `None` if there is none within `timeout`.
+    pub async fn recv_timeout(&mut self, timeout: Duration) -> Result<Option<Positioned<E>>, &'static str> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if let Some(event) = self.cursor.pop() {
+                return Ok(Some(event));
+            }
+
+            let batch = self.journal.read_all(self.cursor.next_position(), self.config.batch_size.max(1)).await?;
+            if self.cursor.push(batch) {
+                continue;
+            }
+
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Ok(None);
+            }
+            self.journal.wait_for_position(self.cursor.next_position(), remaining.min(self.config.max_wait)).await?;
+        }
+    }
+}
+
+/// The last position appended by the journals of this process, to wake up the subscriptions waiting for it.
+pub(crate) struct AppendedPosition {
+    position: watch::Sender<i64>,
+}
+
+impl AppendedPosition {
+    pub(crate) fn new(position: i64) -> Self {
+        Self { position: watch::Sender::new(position) }
+    }
+
+    pub(crate) fn advance(&self, position: i64) {
+        self.position.send_if_modified(|last| {
+            let is_newer = position > *last;
+            if is_newer {
+                *last = position;
+            }
+            is_newer
+        });
+    }
+
+    pub(crate) async fn wait_for(&self, position: i64, timeout: Duration) {
+        let mut appended = self.position.subscribe();
+        let _ = tokio::time::timeout(timeout, appended.wait_for(|last| *last >= position)).await;
+    }
+}
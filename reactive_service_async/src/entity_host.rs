@@ -0,0 +1,209 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use reactive_service_domain::aggregate_root::{AggregateRoot, SequencedEvent};
+use reactive_service_application::metrics::{MetricsRegistry, NoMetrics};
+use reactive_service_application::order_queries::Versioned;
+use tokio::sync::{watch, Mutex, MutexGuard, RwLock};
+use tokio::time::Instant;
+use tracing::{info_span, Instrument};
+use reactive_service_application::command_errors::{ENTITY_NOT_RETRIEVED, VERSION_TIMED_OUT};
+use crate::event_bus::EventBus;
+use crate::order_service::EventsJournal;
+
+pub type EntityId = i64;
+
+/// The events applied by a command on an entity of the aggregate `A`.
+pub type AppliedEvents<A> = Vec<SequencedEvent<<A as AggregateRoot>::Event>>;
+
+/// Owned result of a command on an entity of the aggregate `A`.
+pub type HostResult<A> = Result<(<A as AggregateRoot>::State, AppliedEvents<A>), &'static str>;
+
+struct Slot<A: AggregateRoot> {
+    /// `None` until restored from the journal, or after its events failed to be persisted
+    entity: Mutex<Option<A>>,
+    /// Last persisted state of the entity, read by the queries without locking the entity
+    published: watch::Sender<Option<Versioned<A::State>>>,
+}
+
+/// Hosts the entities of any aggregate, shared by many tasks: restores them from the journal on their first command,
+/// keeps them in memory, and persists the events of their commands.
+///
+/// Each entity has its own lock, held for the time of a command and of the persistence of its events:
+/// the commands of an entity are handled one at a time, the commands of different entities concurrently.
+/// Once its events are persisted, the state is published for the queries: they don't wait for the commands,
+/// and the events are published to the subscribers, in sequence for each entity.
+///
+/// The `OrderService` is the host of the `OrderEntity`, with the ports it needs to build the entity commands.
+/// The durable journals serialize the events, the aggregate events must then be `Serialize` and `DeserializeOwned`.
+///
+/// The restores, commands and persistence of the entities are traced in `load`, `handle` and `persist` spans,
+/// and the latency of the journal and the number of entities in memory reported to its `MetricsRegistry`.
+pub struct EntityHost<A: AggregateRoot, J: EventsJournal<A::Event>> {
+    entities: RwLock<HashMap<EntityId, Arc<Slot<A>>>>,
+    events_journal: J,
+    event_bus: EventBus<A::Event>,
+    metrics: Arc<dyn MetricsRegistry + Send + Sync>,
+}
+
+impl<A, J> EntityHost<A, J>
+where
+    A: AggregateRoot<Error = &'static str> + Default,
+    A::State: Clone,
+    A::Event: Clone,
+    J: EventsJournal<A::Event>,
+{
+    pub fn new(events_journal: J) -> Self {
+        Self { entities: RwLock::new(HashMap::default()), events_journal, event_bus: EventBus::default(), metrics: Arc::new(NoMetrics) }
+    }
+
+    /// Report the metrics of the entities and of their journal to the registry.
+    pub fn with_metrics(self, metrics: Arc<dyn MetricsRegistry + Send + Sync>) -> Self {
+        Self { metrics, ..self }
+    }
+
+    /// The committed events of the entities, to subscribe to them.
+    pub fn event_bus(&self) -> &EventBus<A::Event> {
+        &self.event_bus
+    }
+
+    /// The journal of the entities, e.g. to read their events in the global order.
+    pub fn events_journal(&self) -> &J {
+        &self.events_journal
+    }
+
+    /// Handle a command, built from the current entity, then persist its events.
+    /// If persisting fails, the entity is dropped, to be restored from the journal on its next command.
+    ///
+    /// The entity is out of its slot while its events are persisted: a command cancelled meanwhile, e.g. by a timeout,
+    /// leaves it to be restored from the journal too, rather than ahead of it.
+    pub async fn handle<F>(&self, entity_id: EntityId, to_command: F) -> HostResult<A>
+    where
+        F: FnOnce(&A) -> Result<A::Command, &'static str>,
+    {
+        let slot = self.slot(entity_id).await;
+        // Now, we'll lock the entity for the time needed to handle the command and persist its events.
+        let mut guard = self.lock_entity(entity_id, &slot).await?;
+        let mut entity = guard.take().ok_or(ENTITY_NOT_RETRIEVED)?;
+
+        let handled = info_span!("handle", entity_id).in_scope(|| {
+            to_command(&entity).and_then(|command| {
+                entity.handle_command(command).map(|(state, events)| (state.clone(), events))
+            })
+        });
+        let (state, events) = match handled {
+            Ok(handled) => handled,
+            Err(err) => {
+                *guard = Some(entity);
+                return Err(err);
+            }
+        };
+
+        // On failure, the entity is ahead of the journal: it stays out of its slot
+        self.persist(entity_id, &events).await?;
+        // Still under the entity lock: the events of an entity are published in sequence
+        self.event_bus.publish(entity_id, &events);
+        publish(&slot, Versioned { state: state.clone(), sequence_number: entity.get_sequence_number() });
+        *guard = Some(entity);
+        Ok((state, events)) // We return the result and the entity lock is released
+    }
+
+    /// The last persisted state of the entity, with its sequence number.
+    /// Only locks the entity to restore it from the journal, if it is not in memory yet.
+    pub async fn query(&self, entity_id: EntityId) -> Result<Versioned<A::State>, &'static str> {
+        let slot = self.slot(entity_id).await;
+        if slot.published.borrow().is_none() {
+            drop(self.lock_entity(entity_id, &slot).await?);
+        }
+        let published = slot.published.borrow().clone();
+        published.ok_or(ENTITY_NOT_RETRIEVED)
+    }
+
+    /// Read the entity under its lock, restored from the journal if needed, e.g. to check a command before its side effects.
+    pub async fn inspect<R, F>(&self, entity_id: EntityId, read: F) -> Result<R, &'static str>
+    where
+        F: FnOnce(&A) -> R,
+    {
+        let slot = self.slot(entity_id).await;
+        let guard = self.lock_entity(entity_id, &slot).await?;
+        let entity = guard.as_ref().ok_or(ENTITY_NOT_RETRIEVED)?;
+        Ok(read(entity))
+    }
+
+    /// The state of the entity, once its sequence number is at least `sequence_number`.
+    /// Fails if the entity doesn't reach this version within `timeout`.
+    pub async fn query_at_least(&self, entity_id: EntityId, sequence_number: i64, timeout: Duration)
+        -> Result<Versioned<A::State>, &'static str> {
+
+        let slot = self.slot(entity_id).await;
+        if slot.published.borrow().is_none() {
+            drop(self.lock_entity(entity_id, &slot).await?);
+        }
+        let mut published = slot.published.subscribe();
+        let reached = published.wait_for(|published| {
+            published.as_ref().is_some_and(|published| published.sequence_number >= sequence_number)
+        });
+        let result = tokio::time::timeout(timeout, reached).await
+            .map_err(|_| VERSION_TIMED_OUT)?
+            .map_err(|_| ENTITY_NOT_RETRIEVED)?
+            .clone();
+        result.ok_or(ENTITY_NOT_RETRIEVED)
+    }
+
+    /// The slot of the entity, created empty if the entity is not in memory yet.
+    async fn slot(&self, entity_id: EntityId) -> Arc<Slot<A>> {
+        // Check if the entity is already in the map without locking (read-only access)
+        if let Some(slot) = self.entities.read().await.get(&entity_id) {
+            return slot.clone();
+        }
+        // This is the only place we lock the map, just the time of the insert,
+        // minimal contention only when we access an entity not in memory yet.
+        // The entity itself is restored out of the map lock, under its own lock.
+        let mut write_lock = self.entities.write().await;
+        let slot = write_lock.entry(entity_id)
+            .or_insert_with(|| Arc::new(Slot { entity: Mutex::new(None), published: watch::Sender::new(None) }))
+            .clone();
+        self.metrics.set_cached_entities(write_lock.len());
+        slot
+    }
+
+    /// Lock the entity, restoring it from the journal if needed.
+    async fn lock_entity<'a>(&self, entity_id: EntityId, slot: &'a Slot<A>) -> Result<MutexGuard<'a, Option<A>>, &'static str> {
+        let mut guard = slot.entity.lock().await;
+        if guard.is_none() {
+            let span = info_span!("load", entity_id);
+            let start_time = Instant::now();
+            let events = self.events_journal.retrieve_events(entity_id).instrument(span.clone()).await;
+            self.metrics.observe_journal("load", start_time.elapsed(), events.is_err());
+            let mut entity = A::default();
+            let _ = span.in_scope(|| entity.restore_from_events(events?))?;
+            publish(slot, Versioned { state: entity.get_state().clone(), sequence_number: entity.get_sequence_number() });
+            *guard = Some(entity);
+        }
+        Ok(guard)
+    }
+
+    async fn persist(&self, entity_id: EntityId, events: &[SequencedEvent<A::Event>]) -> Result<(), &'static str> {
+        let start_time = Instant::now();
+        let persisted = async {
+            for evt in events {
+                self.events_journal.persist_event(entity_id, evt).await?;
+            }
+            Ok(())
+        }.instrument(info_span!("persist", entity_id, events = events.len())).await;
+        self.metrics.observe_journal("persist", start_time.elapsed(), persisted.is_err());
+        persisted
+    }
+}
+
+/// Make a persisted state visible to the queries, and wake up the ones waiting for a version.
+fn publish<A: AggregateRoot>(slot: &Slot<A>, versioned: Versioned<A::State>) {
+    slot.published.send_if_modified(|published| {
+        // A restored entity is never ahead of what was published
+        let is_newer = published.as_ref().map_or(true, |published| published.sequence_number <= versioned.sequence_number);
+        if is_newer {
+            *published = Some(versioned);
+        }
+        is_newer
+    });
+}
@@ -0,0 +1,72 @@
+use reactive_service_domain::aggregate_root::SequencedEvent;
+use reactive_service_application::order_commands::OrderId;
+use reactive_service_application::subscriptions::{Committed, SubscriptionError};
+use tokio::sync::broadcast;
+
+/// Publishes the committed events to the subscribers, of all the entities or of a single one.
+///
+/// The subscribers share a bounded broadcast buffer: a slow subscriber never blocks the commands.
+/// When it falls behind by more than the capacity, it misses the oldest events and is told how many.
+/// A subscription to a single entity filters the events of every entity: its lag counts them all.
+///
+/// Cheap to clone, the clones publish to the same subscribers. Once every clone is dropped, the subscriptions are closed.
+#[derive(Clone)]
+pub struct EventBus<E> {
+    sender: broadcast::Sender<Committed<E>>,
+}
+
+impl<E: Clone> Default for EventBus<E> {
+    fn default() -> Self {
+        Self::new(1024)
+    }
+}
+
+impl<E: Clone> EventBus<E> {
+    /// `capacity`: events buffered for the slowest subscriber before the oldest ones are dropped.
+    pub fn new(capacity: usize) -> Self {
+        let (sender, _) = broadcast::channel(capacity.max(1));
+        Self { sender }
+    }
+
+    /// Subscribe to the events of every entity, from now on.
+    pub fn subscribe_all(&self) -> Subscription<E> {
+        Subscription { receiver: self.sender.subscribe(), entity_id: None }
+    }
+
+    /// Subscribe to the events of a single entity, from now on.
+    pub fn subscribe(&self, entity_id: OrderId) -> Subscription<E> {
+        Subscription { receiver: self.sender.subscribe(), entity_id: Some(entity_id) }
+    }
+
+    /// Publish events, only once they are persisted.
+    pub fn publish(&self, entity_id: OrderId, events: &[SequencedEvent<E>]) {
+        // Don't clone the events for nobody
+        if self.sender.receiver_count() == 0 {
+            return;
+        }
+        for event in events {
+            let _ = self.sender.send(Committed { entity_id, event: event.clone() });
+        }
+    }
+}
+
+/// The receiving end of a subscription, it can be moved to another task.
+pub struct Subscription<E> {
+    receiver: broadcast::Receiver<Committed<E>>,
+    /// `None` to receive the events of every entity
+    entity_id: Option<OrderId>,
+}
+
+impl<E: Clone> Subscription<E> {
+    /// Wait for the next event.
+    pub async fn recv(&mut self) -> Result<Committed<E>, SubscriptionError> {
+        loop {
+            match self.receiver.recv().await {
+                Ok(committed) if self.entity_id.map_or(true, |id| id == committed.entity_id) => return Ok(committed),
+                Ok(_) => continue,
+                Err(broadcast::error::RecvError::Lagged(missed)) => return Err(SubscriptionError::Lagged(missed)),
+                Err(broadcast::error::RecvError::Closed) => return Err(SubscriptionError::Closed),
+            }
+        }
+    }
+}
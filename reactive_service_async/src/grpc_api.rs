@@ -0,0 +1,393 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Arc;
+use reactive_service_domain::aggregate_root::SequencedEvent;
+use reactive_service_domain::non_empty_cart::{NonEmptyCart, Quantity, Sku};
+use reactive_service_domain::order_entity::OrderEvent;
+use reactive_service_domain::order_state::{Currency, DeliveryAddress, Empty, Invoice, Money, OrderState, Street};
+use tokio::net::TcpListener;
+use tokio::sync::{mpsc, watch};
+use tokio_stream::wrappers::{ReceiverStream, TcpListenerStream};
+use tonic::metadata::MetadataMap;
+use tonic::transport::Server;
+use tonic::{Request, Response, Status};
+use reactive_service_application::command_errors::NOT_THE_OWNER;
+use crate::api::{ApiAuthConfig, CommandErrorKind, OrderApi, ADMIN_TOKEN_HEADER, CUSTOMER_ID_HEADER};
+use crate::event_bus::Subscription;
+use crate::order_service::{
+    OrderCommand, OrderId, PayOrder, Principal, SubscriptionError, UpdateCart, UpdateDeliveryAddress
+};
+use crate::payment_processor::PaymentToken;
+use proto::orders_server::{Orders, OrdersServer};
+
+/// The types and the client generated from `proto/orders.proto`.
+pub mod proto {
+    tonic::include_proto!("orders");
+}
+
+/// Events buffered for a watcher, before waiting for it to receive them.
+const WATCH_BUFFER: usize = 64;
+
+/// The `Orders` gRPC service, over the order service. Every call requires a customer id, or the admin token, in its metadata.
+pub struct OrderGrpcService<A: OrderApi> {
+    api: Arc<A>,
+    auth: Arc<ApiAuthConfig>,
+    stopping: Arc<watch::Sender<bool>>,
+}
+
+impl<A: OrderApi> Clone for OrderGrpcService<A> {
+    fn clone(&self) -> Self {
+        Self { api: self.api.clone(), auth: self.auth.clone(), stopping: self.stopping.clone() }
+    }
+}
+
+impl<A: OrderApi> OrderGrpcService<A> {
+    pub fn new(api: Arc<A>, auth: ApiAuthConfig) -> Self {
+        Self { api, auth: Arc::new(auth), stopping: Arc::new(watch::Sender::new(false)) }
+    }
+
+    /// End the watch streams with an `Unavailable` status, e.g. on shutdown: a server waits for its streams before stopping.
+    pub fn stop_watches(&self) {
+        self.stopping.send_replace(true);
+    }
+
+    async fn handle(&self, metadata: &MetadataMap, command: OrderCommand) -> Result<Response<proto::OrderReply>, Status> {
+        let order_id = command.order_id();
+        let principal = self.principal(metadata).map_err(Status::unauthenticated)?;
+        let (state, events) = self.api.handle_as(&principal, command).await.map_err(status)?;
+        let sequence_number = match events.last() {
+            Some(event) => event.sequence_number,
+            // The order didn't change: its current version
+            None => return self.order(&principal, order_id).await,
+        };
+        Ok(Response::new(proto::OrderReply { order_id, sequence_number, state: Some((&state).into()) }))
+    }
+
+    async fn order(&self, principal: &Principal, order_id: OrderId) -> Result<Response<proto::OrderReply>, Status> {
+        let order = self.api.get_order_as(principal, order_id).await.map_err(status)?;
+        if let OrderState::Empty(_) = order.state {
+            return Err(Status::not_found("Order not found"));
+        }
+        Ok(Response::new(proto::OrderReply { order_id, sequence_number: order.sequence_number, state: Some((&order.state).into()) }))
+    }
+
+    /// The principal of the call: a customer by the `CUSTOMER_ID_HEADER`, or an admin by the `ADMIN_TOKEN_HEADER`.
+    fn principal(&self, metadata: &MetadataMap) -> Result<Principal, &'static str> {
+        let value = |name| metadata.get(name).map(|value| value.as_encoded_bytes());
+        self.auth.principal(value(CUSTOMER_ID_HEADER), value(ADMIN_TOKEN_HEADER))
+    }
+}
+
+/// Serve the service on the listener until `shutdown` completes, then end the watch streams and wait for the calls in progress.
+pub async fn serve<A: OrderApi>(
+    listener: TcpListener, api: Arc<A>, auth: ApiAuthConfig, shutdown: impl Future<Output = ()> + Send + 'static
+) -> Result<(), tonic::transport::Error> {
+
+    let service = OrderGrpcService::new(api, auth);
+    let stopping = service.clone();
+    Server::builder()
+        .add_service(OrdersServer::new(service))
+        .serve_with_incoming_shutdown(TcpListenerStream::new(listener), async move {
+            shutdown.await;
+            stopping.stop_watches();
+        })
+        .await
+}
+
+#[tonic::async_trait]
+impl<A: OrderApi> Orders for OrderGrpcService<A> {
+    async fn update_cart(&self, request: Request<proto::UpdateCartRequest>) -> Result<Response<proto::OrderReply>, Status> {
+        let (metadata, _, request) = request.into_parts();
+        let cart = request.cart.ok_or("Missing cart").and_then(NonEmptyCart::try_from).map_err(Status::invalid_argument)?;
+        self.handle(&metadata, OrderCommand::UpdateCart(UpdateCart { order_id: request.order_id, cart })).await
+    }
+
+    async fn update_delivery_address(&self, request: Request<proto::UpdateDeliveryAddressRequest>)
+        -> Result<Response<proto::OrderReply>, Status> {
+
+        let (metadata, _, request) = request.into_parts();
+        let delivery_address = request.delivery_address.ok_or("Missing delivery address")
+            .and_then(DeliveryAddress::try_from)
+            .map_err(Status::invalid_argument)?;
+        let command = UpdateDeliveryAddress { order_id: request.order_id, delivery_address };
+        self.handle(&metadata, OrderCommand::UpdateDeliveryAddress(command)).await
+    }
+
+    async fn pay_order(&self, request: Request<proto::PayOrderRequest>) -> Result<Response<proto::OrderReply>, Status> {
+        let (metadata, _, request) = request.into_parts();
+        let command = PayOrder { order_id: request.order_id, payment_token: PaymentToken::new(request.payment_token) };
+        self.handle(&metadata, OrderCommand::PayOrder(command)).await
+    }
+
+    async fn get_order(&self, request: Request<proto::GetOrderRequest>) -> Result<Response<proto::OrderReply>, Status> {
+        let principal = self.principal(request.metadata()).map_err(Status::unauthenticated)?;
+        self.order(&principal, request.into_inner().order_id).await
+    }
+
+    type WatchOrderStream = ReceiverStream<Result<proto::CommittedEvent, Status>>;
+
+    /// A customer may only watch the orders they own.
+    async fn watch_order(&self, request: Request<proto::WatchOrderRequest>) -> Result<Response<Self::WatchOrderStream>, Status> {
+        let principal = self.principal(request.metadata()).map_err(Status::unauthenticated)?;
+        let request = request.into_inner();
+
+        // Subscribed before reading the journal: an event committed meanwhile is received twice, rather than missed
+        let subscription = self.api.event_bus().subscribe(request.order_id);
+        let history = self.api.retrieve_events(request.order_id).await.map_err(status)?;
+        if let Principal::Customer(customer_id) = principal {
+            match history.first().map(|first| &first.event) {
+                Some(OrderEvent::UpdatedCart { customer_id: Some(owner), .. }) if *owner == customer_id => {},
+                Some(_) => return Err(status(NOT_THE_OWNER)),
+                None => return Err(Status::not_found("Order not found")),
+            }
+        }
+
+        let (sender, receiver) = mpsc::channel(WATCH_BUFFER);
+        let watch = Watch {
+            api: self.api.clone(),
+            order_id: request.order_id,
+            last_sequence_number: request.after_sequence_number,
+            subscription,
+            stopping: self.stopping.subscribe(),
+            sender,
+        };
+        tokio::spawn(watch.run(history));
+        Ok(Response::new(ReceiverStream::new(receiver)))
+    }
+}
+
+/// Sends the events of an order to a watcher, in order and once each, until it goes away.
+struct Watch<A: OrderApi> {
+    api: Arc<A>,
+    order_id: OrderId,
+    last_sequence_number: i64,
+    subscription: Subscription<OrderEvent>,
+    stopping: watch::Receiver<bool>,
+    sender: mpsc::Sender<Result<proto::CommittedEvent, Status>>,
+}
+
+impl<A: OrderApi> Watch<A> {
+    /// Send the persisted events first, then follow the committed ones.
+    /// When the watcher lags behind the event bus, it catches up from the journal again.
+    async fn run(mut self, mut history: Vec<SequencedEvent<OrderEvent>>) {
+        loop {
+            for event in history {
+                if !self.send(event).await {
+                    return;
+                }
+            }
+            loop {
+                let received = tokio::select! {
+                    _ = self.sender.closed() => return,
+                    _ = self.stopping.wait_for(|stopping| *stopping) => None,
+                    received = self.subscription.recv() => Some(received),
+                };
+                match received {
+                    Some(Ok(committed)) => if !self.send(committed.event).await {
+                        return;
+                    },
+                    Some(Err(SubscriptionError::Lagged(_))) => break,
+                    Some(Err(SubscriptionError::Closed)) => return,
+                    None => {
+                        let _ = self.sender.send(Err(Status::unavailable("Server is stopping"))).await;
+                        return;
+                    },
+                }
+            }
+            history = match self.api.retrieve_events(self.order_id).await {
+                Ok(events) => events,
+                Err(error) => {
+                    let _ = self.sender.send(Err(status(error))).await;
+                    return;
+                }
+            };
+        }
+    }
+
+    /// Send the event, unless it was already sent. `false` once the watcher is gone.
+    async fn send(&mut self, event: SequencedEvent<OrderEvent>) -> bool {
+        if event.sequence_number <= self.last_sequence_number {
+            return true;
+        }
+        self.last_sequence_number = event.sequence_number;
+        let committed = proto::CommittedEvent {
+            order_id: self.order_id,
+            sequence_number: event.sequence_number,
+            event: Some((&event.event).into()),
+        };
+        self.sender.send(Ok(committed)).await.is_ok()
+    }
+}
+
+/// The status of an error of the service, by its `CommandErrorKind`.
+pub fn status(error: &'static str) -> Status {
+    match CommandErrorKind::of(error) {
+        CommandErrorKind::Invalid => Status::invalid_argument(error),
+        CommandErrorKind::Forbidden => Status::permission_denied(error),
+        CommandErrorKind::Conflict => Status::failed_precondition(error),
+        CommandErrorKind::Unavailable => Status::unavailable(error),
+        CommandErrorKind::Internal => Status::internal(error),
+    }
+}
+
+impl From<&NonEmptyCart> for proto::Cart {
+    fn from(cart: &NonEmptyCart) -> Self {
+        Self { items: cart.get_items().iter().map(|(sku, quantity)| (sku.0.clone(), u32::from(quantity.0))).collect() }
+    }
+}
+
+impl TryFrom<proto::Cart> for NonEmptyCart {
+    type Error = &'static str;
+
+    fn try_from(cart: proto::Cart) -> Result<Self, Self::Error> {
+        let items = cart.items.into_iter()
+            .map(|(sku, quantity)| Ok((Sku(sku), Quantity(u16::try_from(quantity).map_err(|_| "Quantity too large")?))))
+            .collect::<Result<HashMap<_, _>, &'static str>>()?;
+        NonEmptyCart::new(items)
+    }
+}
+
+impl From<&DeliveryAddress> for proto::DeliveryAddress {
+    fn from(delivery_address: &DeliveryAddress) -> Self {
+        Self { street: delivery_address.street.0.clone(), postal_code: delivery_address.postal_code.to_string() }
+    }
+}
+
+impl TryFrom<proto::DeliveryAddress> for DeliveryAddress {
+    type Error = &'static str;
+
+    fn try_from(delivery_address: proto::DeliveryAddress) -> Result<Self, Self::Error> {
+        Ok(DeliveryAddress { street: Street(delivery_address.street), postal_code: delivery_address.postal_code.parse()? })
+    }
+}
+
+impl From<&Money> for proto::Money {
+    fn from(money: &Money) -> Self {
+        let currency = match money.currency {
+            Currency::Cad => proto::Currency::Cad,
+        };
+        Self { amount_cents: money.amount_cents, currency: currency.into() }
+    }
+}
+
+impl TryFrom<proto::Money> for Money {
+    type Error = &'static str;
+
+    fn try_from(money: proto::Money) -> Result<Self, Self::Error> {
+        let currency = match proto::Currency::try_from(money.currency) {
+            Ok(proto::Currency::Cad) => Currency::Cad,
+            Ok(proto::Currency::Unspecified) | Err(_) => return Err("Invalid currency"),
+        };
+        Ok(Money { amount_cents: money.amount_cents, currency })
+    }
+}
+
+impl From<&OrderState> for proto::OrderState {
+    fn from(state: &OrderState) -> Self {
+        use proto::order_state::State;
+        let state = match state {
+            OrderState::Empty(_) => State::Empty(proto::Empty {}),
+            OrderState::WithCart(order) => State::WithCart(proto::WithCart { cart: Some(order.get_cart().into()) }),
+            OrderState::WithAddress(order) => State::WithAddress(proto::WithAddress {
+                cart: Some(order.get_cart().into()),
+                delivery_address: Some(order.get_delivery_address().into()),
+                shipping_cost: Some(order.get_shipping_cost().into()),
+                tax: Some(order.get_tax().into()),
+            }),
+            OrderState::Completed(order) => State::Completed(proto::Completed {
+                cart: Some(order.get_cart().into()),
+                delivery_address: Some(order.get_delivery_address().into()),
+                shipping_cost: Some(order.get_shipping_cost().into()),
+                tax: Some(order.get_tax().into()),
+            }),
+            OrderState::Expired(order) => State::Expired(proto::Expired { cart: Some(order.get_cart().into()) }),
+        };
+        Self { state: Some(state) }
+    }
+}
+
+/// The state is rebuilt through the transitions of the order: only a reachable state is valid.
+impl TryFrom<proto::OrderState> for OrderState {
+    type Error = &'static str;
+
+    fn try_from(state: proto::OrderState) -> Result<Self, Self::Error> {
+        use proto::order_state::State;
+        match state.state.ok_or("Missing order state")? {
+            State::Empty(_) => Ok(OrderState::Empty(Empty {})),
+            State::WithCart(order) => Ok(OrderState::WithCart(Empty {}.add_cart(cart(order.cart)?))),
+            State::WithAddress(order) => {
+                let with_cart = Empty {}.add_cart(cart(order.cart)?);
+                Ok(OrderState::WithAddress(
+                    with_cart.add_delivery_address(delivery_address(order.delivery_address)?, money(order.shipping_cost)?, money(order.tax)?)
+                ))
+            },
+            State::Completed(order) => {
+                let with_cart = Empty {}.add_cart(cart(order.cart)?);
+                let with_address =
+                    with_cart.add_delivery_address(delivery_address(order.delivery_address)?, money(order.shipping_cost)?, money(order.tax)?);
+                Ok(OrderState::Completed(with_address.complete_order(Invoice {})))
+            },
+            State::Expired(order) => Ok(OrderState::Expired(Empty {}.add_cart(cart(order.cart)?).expire())),
+        }
+    }
+}
+
+impl From<&OrderEvent> for proto::OrderEvent {
+    fn from(event: &OrderEvent) -> Self {
+        use proto::order_event::Event;
+        let event = match event {
+            OrderEvent::UpdatedCart { cart, customer_id } =>
+                Event::UpdatedCart(proto::UpdatedCart { cart: Some(cart.into()), customer_id: *customer_id }),
+            OrderEvent::UpdatedDeliveryAddress { delivery_address, shipping_cost, tax } =>
+                Event::UpdatedDeliveryAddress(proto::UpdatedDeliveryAddress {
+                    delivery_address: Some(delivery_address.into()),
+                    shipping_cost: Some(shipping_cost.into()),
+                    tax: Some(tax.into()),
+                }),
+            OrderEvent::UpdatedCartOnExistingDeliveryAddress { cart, shipping_cost, tax } =>
+                Event::UpdatedCartOnExistingDeliveryAddress(proto::UpdatedCartOnExistingDeliveryAddress {
+                    cart: Some(cart.into()),
+                    shipping_cost: Some(shipping_cost.into()),
+                    tax: Some(tax.into()),
+                }),
+            OrderEvent::Completed { .. } => Event::Completed(proto::OrderCompleted {}),
+            OrderEvent::Expired => Event::Expired(proto::OrderExpired {}),
+        };
+        Self { event: Some(event) }
+    }
+}
+
+impl TryFrom<proto::OrderEvent> for OrderEvent {
+    type Error = &'static str;
+
+    fn try_from(event: proto::OrderEvent) -> Result<Self, Self::Error> {
+        use proto::order_event::Event;
+        match event.event.ok_or("Missing order event")? {
+            Event::UpdatedCart(event) => Ok(OrderEvent::UpdatedCart { cart: cart(event.cart)?, customer_id: event.customer_id }),
+            Event::UpdatedDeliveryAddress(event) => Ok(OrderEvent::UpdatedDeliveryAddress {
+                delivery_address: delivery_address(event.delivery_address)?,
+                shipping_cost: money(event.shipping_cost)?,
+                tax: money(event.tax)?,
+            }),
+            Event::UpdatedCartOnExistingDeliveryAddress(event) => Ok(OrderEvent::UpdatedCartOnExistingDeliveryAddress {
+                cart: cart(event.cart)?,
+                shipping_cost: money(event.shipping_cost)?,
+                tax: money(event.tax)?,
+            }),
+            Event::Completed(_) => Ok(OrderEvent::Completed { invoice: Invoice {} }),
+            Event::Expired(_) => Ok(OrderEvent::Expired),
+        }
+    }
+}
+
+fn cart(cart: Option<proto::Cart>) -> Result<NonEmptyCart, &'static str> {
+    cart.ok_or("Missing cart")?.try_into()
+}
+
+fn delivery_address(delivery_address: Option<proto::DeliveryAddress>) -> Result<DeliveryAddress, &'static str> {
+    delivery_address.ok_or("Missing delivery address")?.try_into()
+}
+
+fn money(money: Option<proto::Money>) -> Result<Money, &'static str> {
+    money.ok_or("Missing amount")?.try_into()
+}
@@ -0,0 +1,282 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Arc;
+use axum::extract::rejection::{JsonRejection, PathRejection};
+use axum::extract::{Path, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post, put};
+use axum::{Json, Router};
+use reactive_service_domain::non_empty_cart::{NonEmptyCart, Quantity, Sku};
+use reactive_service_domain::order_state::{DeliveryAddress, Money, OrderState, Street};
+use serde_derive::{Deserialize, Serialize};
+use tokio::net::TcpListener;
+use crate::api::{ApiAuthConfig, CommandErrorKind, OrderApi};
+pub use crate::api::{ADMIN_TOKEN_HEADER, CUSTOMER_ID_HEADER};
+use crate::order_service::{
+    OrderCommand, OrderId, PayOrder, Principal, PrometheusRegistry, QueryResult, UpdateCart, UpdateDeliveryAddress
+};
+use crate::payment_processor::PaymentToken;
+use crate::projections::OrderStatus;
+
+/// The body of `PUT /orders/:order_id/cart`: the quantity of each SKU.
+#[derive(Debug, Deserialize)]
+pub struct CartRequest {
+    pub items: HashMap<String, u16>,
+}
+
+impl CartRequest {
+    pub fn into_command(self, order_id: OrderId) -> Result<UpdateCart, &'static str> {
+        let items = self.items.into_iter().map(|(sku, quantity)| (Sku(sku), Quantity(quantity))).collect();
+        Ok(UpdateCart { order_id, cart: NonEmptyCart::new(items)? })
+    }
+}
+
+/// The body of `PUT /orders/:order_id/delivery-address`.
+#[derive(Debug, Deserialize)]
+pub struct DeliveryAddressRequest {
+    pub street: String,
+    pub postal_code: String,
+}
+
+impl DeliveryAddressRequest {
+    pub fn into_command(self, order_id: OrderId) -> Result<UpdateDeliveryAddress, &'static str> {
+        let delivery_address = DeliveryAddress { street: Street(self.street), postal_code: self.postal_code.parse()? };
+        Ok(UpdateDeliveryAddress { order_id, delivery_address })
+    }
+}
+
+/// The body of `POST /orders/:order_id/payment`.
+#[derive(Debug, Deserialize)]
+pub struct PaymentRequest {
+    pub payment_token: String,
+}
+
+impl PaymentRequest {
+    pub fn into_command(self, order_id: OrderId) -> PayOrder {
+        PayOrder { order_id, payment_token: PaymentToken::new(self.payment_token) }
+    }
+}
+
+/// An order, as of its `sequence_number`. The delivery address, the shipping cost and the tax are only known once
+/// the address is set; an expired order only keeps its cart.
+#[derive(Debug, Serialize)]
+pub struct OrderResponse {
+    pub order_id: OrderId,
+    pub sequence_number: i64,
+    pub status: &'static str,
+    pub cart: HashMap<String, u16>,
+    pub delivery_address: Option<DeliveryAddressResponse>,
+    pub shipping_cost: Option<Money>,
+    pub tax: Option<Money>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DeliveryAddressResponse {
+    pub street: String,
+    pub postal_code: String,
+}
+
+impl OrderResponse {
+    /// `None` for an empty order: nothing was ever done with it.
+    pub fn new(order_id: OrderId, sequence_number: i64, state: &OrderState) -> Option<Self> {
+        let (status, cart, with_address) = match state {
+            OrderState::Empty(_) => return None,
+            OrderState::WithCart(order) => (OrderStatus::WithCart, order.get_cart(), None),
+            OrderState::WithAddress(order) =>
+                (OrderStatus::WithAddress, order.get_cart(), Some((order.get_delivery_address(), order.get_shipping_cost(), order.get_tax()))),
+            OrderState::Completed(order) =>
+                (OrderStatus::Completed, order.get_cart(), Some((order.get_delivery_address(), order.get_shipping_cost(), order.get_tax()))),
+            OrderState::Expired(order) => (OrderStatus::Expired, order.get_cart(), None),
+        };
+        Some(Self {
+            order_id,
+            sequence_number,
+            status: status.as_str(),
+            cart: cart.get_items().iter().map(|(sku, quantity)| (sku.0.clone(), quantity.0)).collect(),
+            delivery_address: with_address.map(|(address, _, _)| DeliveryAddressResponse {
+                street: address.street.0.clone(),
+                postal_code: address.postal_code.to_string(),
+            }),
+            shipping_cost: with_address.map(|(_, shipping_cost, _)| shipping_cost.clone()),
+            tax: with_address.map(|(_, _, tax)| tax.clone()),
+        })
+    }
+}
+
+/// The body of every error response.
+#[derive(Debug, Serialize)]
+pub struct ErrorResponse {
+    pub error: String,
+}
+
+/// A failed request: a malformed one, or a command rejected by the service, with the status of its `CommandErrorKind`.
+#[derive(Debug)]
+pub struct ApiError {
+    pub status: StatusCode,
+    pub error: String,
+}
+
+impl ApiError {
+    fn new(status: StatusCode, error: impl Into<String>) -> Self {
+        Self { status, error: error.into() }
+    }
+
+    fn invalid(error: &'static str) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, error)
+    }
+
+    fn unauthorized(error: &'static str) -> Self {
+        Self::new(StatusCode::UNAUTHORIZED, error)
+    }
+}
+
+impl From<&'static str> for ApiError {
+    fn from(error: &'static str) -> Self {
+        let status = match CommandErrorKind::of(error) {
+            CommandErrorKind::Invalid => StatusCode::BAD_REQUEST,
+            CommandErrorKind::Forbidden => StatusCode::FORBIDDEN,
+            CommandErrorKind::Conflict => StatusCode::CONFLICT,
+            CommandErrorKind::Unavailable => StatusCode::SERVICE_UNAVAILABLE,
+            CommandErrorKind::Internal => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        Self::new(status, error)
+    }
+}
+
+impl From<JsonRejection> for ApiError {
+    fn from(rejection: JsonRejection) -> Self {
+        Self::new(rejection.status(), rejection.body_text())
+    }
+}
+
+impl From<PathRejection> for ApiError {
+    fn from(rejection: PathRejection) -> Self {
+        Self::new(rejection.status(), rejection.body_text())
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.status, Json(ErrorResponse { error: self.error })).into_response()
+    }
+}
+
+type ApiResult = Result<Json<OrderResponse>, ApiError>;
+
+/// The state of the routes: the service, and how its callers are authenticated.
+struct ApiState<A> {
+    api: Arc<A>,
+    auth: Arc<ApiAuthConfig>,
+}
+
+impl<A> Clone for ApiState<A> {
+    fn clone(&self) -> Self {
+        Self { api: self.api.clone(), auth: self.auth.clone() }
+    }
+}
+
+impl<A> ApiState<A> {
+    /// The principal of the request: a customer by the `CUSTOMER_ID_HEADER`, or an admin by the `ADMIN_TOKEN_HEADER`.
+    fn principal(&self, headers: &HeaderMap) -> Result<Principal, ApiError> {
+        let header = |name| headers.get(name).map(|value| value.as_bytes());
+        self.auth.principal(header(CUSTOMER_ID_HEADER), header(ADMIN_TOKEN_HEADER)).map_err(ApiError::unauthorized)
+    }
+}
+
+/// The routes of the API, over the service. Every order route requires a customer id, or the admin token:
+/// - `GET /health`
+/// - `GET /orders/:order_id`
+/// - `PUT /orders/:order_id/cart`
+/// - `PUT /orders/:order_id/delivery-address`
+/// - `POST /orders/:order_id/payment`
+pub fn router<A: OrderApi>(api: Arc<A>, auth: ApiAuthConfig) -> Router {
+    Router::new()
+        .route("/health", get(health))
+        .route("/orders/:order_id", get(get_order::<A>))
+        .route("/orders/:order_id/cart", put(update_cart::<A>))
+        .route("/orders/:order_id/delivery-address", put(update_delivery_address::<A>))
+        .route("/orders/:order_id/payment", post(pay_order::<A>))
+        .with_state(ApiState { api, auth: Arc::new(auth) })
+}
+
+/// `GET /metrics`: the metrics of the registry, in the Prometheus text format. Like `/health`, it requires no credential.
+pub fn metrics_router(registry: Arc<PrometheusRegistry>) -> Router {
+    Router::new()
+        .route("/metrics", get(metrics))
+        .with_state(registry)
+}
+
+/// Serve the API on the listener until `shutdown` completes, then wait for the requests in progress.
+pub async fn serve<A: OrderApi>(
+    listener: TcpListener, api: Arc<A>, auth: ApiAuthConfig, shutdown: impl Future<Output = ()> + Send + 'static
+) -> std::io::Result<()> {
+
+    axum::serve(listener, router(api, auth)).with_graceful_shutdown(shutdown).await
+}
+
+async fn health() -> Json<HashMap<&'static str, &'static str>> {
+    Json(HashMap::from([("status", "ok")]))
+}
+
+async fn metrics(State(registry): State<Arc<PrometheusRegistry>>) -> impl IntoResponse {
+    ([(header::CONTENT_TYPE, "text/plain; version=0.0.4")], registry.render())
+}
+
+async fn get_order<A: OrderApi>(
+    State(state): State<ApiState<A>>, headers: HeaderMap, order_id: Result<Path<OrderId>, PathRejection>
+) -> ApiResult {
+    let Path(order_id) = order_id?;
+    let principal = state.principal(&headers)?;
+    order_response(order_id, state.api.get_order_as(&principal, order_id).await)
+}
+
+async fn update_cart<A: OrderApi>(
+    State(state): State<ApiState<A>>, headers: HeaderMap, order_id: Result<Path<OrderId>, PathRejection>,
+    request: Result<Json<CartRequest>, JsonRejection>
+) -> ApiResult {
+    let (Path(order_id), Json(request)) = (order_id?, request?);
+    let command = request.into_command(order_id).map_err(ApiError::invalid)?;
+    handle(&state, &headers, OrderCommand::UpdateCart(command)).await
+}
+
+async fn update_delivery_address<A: OrderApi>(
+    State(state): State<ApiState<A>>, headers: HeaderMap, order_id: Result<Path<OrderId>, PathRejection>,
+    request: Result<Json<DeliveryAddressRequest>, JsonRejection>
+) -> ApiResult {
+    let (Path(order_id), Json(request)) = (order_id?, request?);
+    let command = request.into_command(order_id).map_err(ApiError::invalid)?;
+    handle(&state, &headers, OrderCommand::UpdateDeliveryAddress(command)).await
+}
+
+async fn pay_order<A: OrderApi>(
+    State(state): State<ApiState<A>>, headers: HeaderMap, order_id: Result<Path<OrderId>, PathRejection>,
+    request: Result<Json<PaymentRequest>, JsonRejection>
+) -> ApiResult {
+    let (Path(order_id), Json(request)) = (order_id?, request?);
+    handle(&state, &headers, OrderCommand::PayOrder(request.into_command(order_id))).await
+}
+
+async fn handle<A: OrderApi>(state: &ApiState<A>, headers: &HeaderMap, command: OrderCommand) -> ApiResult {
+    let order_id = command.order_id();
+    let principal = state.principal(headers)?;
+    let (order, events) = state.api.handle_as(&principal, command).await?;
+    match events.last() {
+        Some(event) => command_response(order_id, event.sequence_number, &order),
+        // The order didn't change: its current version
+        None => order_response(order_id, state.api.get_order_as(&principal, order_id).await),
+    }
+}
+
+fn command_response(order_id: OrderId, sequence_number: i64, state: &OrderState) -> ApiResult {
+    OrderResponse::new(order_id, sequence_number, state)
+        .map(Json)
+        .ok_or_else(|| ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, "Order still empty"))
+}
+
+fn order_response(order_id: OrderId, result: QueryResult) -> ApiResult {
+    let order = result?;
+    OrderResponse::new(order_id, order.sequence_number, &order.state)
+        .map(Json)
+        .ok_or_else(|| ApiError::new(StatusCode::NOT_FOUND, "Order not found"))
+}
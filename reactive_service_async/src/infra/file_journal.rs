@@ -0,0 +1,106 @@
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use reactive_service_domain::aggregate_root::SequencedEvent;
+use reactive_service_application::command_errors::{DESERIALIZE_EVENT_FAILED, JOURNAL_POISONED, PERSIST_EVENT_FAILED, RETRIEVE_EVENTS_FAILED, SERIALIZE_EVENT_FAILED};
+use crate::catch_up::AppendedPosition;
+use crate::order_service::{EventsJournal, GlobalEventsJournal, OrderId, Positioned};
+pub use reactive_service_application::segmented_log::{FileJournalConfig, FsyncPolicy};
+use reactive_service_application::segmented_log::{Record, SegmentedLog};
+
+/// Embedded journal, appending the events serialized in JSON to the segment files of a `SegmentedLog`, in a directory.
+/// A record torn by a crash in the middle of a write is truncated when the journal is opened, any other corruption fails the opening.
+///
+/// The file operations are blocking: they run on the blocking thread pool of tokio, serialized by a mutex.
+pub struct FileJournal {
+    log: Arc<Mutex<SegmentedLog>>,
+    appended: AppendedPosition,
+}
+
+impl FileJournal {
+    /// Open, or create, the journal in `directory`, with the default settings.
+    pub async fn new(directory: impl Into<PathBuf>) -> Result<Self, Box<dyn std::error::Error>> {
+        Self::with_config(FileJournalConfig { directory: directory.into(), ..FileJournalConfig::default() }).await
+    }
+
+    pub async fn with_config(config: FileJournalConfig) -> Result<Self, Box<dyn std::error::Error>> {
+        // Scanning the segments reads the whole journal
+        let log = tokio::task::spawn_blocking(move || SegmentedLog::open(config).map_err(|e| e.to_string()))
+            .await??;
+        let appended = AppendedPosition::new(log.last_position());
+        Ok(Self { log: Arc::new(Mutex::new(log)), appended })
+    }
+
+    async fn append(&self, records: Vec<Record>) -> Result<(), &'static str> {
+        let log = self.log.clone();
+        let last_position = tokio::task::spawn_blocking(move || {
+                let mut log = log.lock().map_err(|_| JOURNAL_POISONED)?;
+                log.append(records).map(|_| log.last_position())
+            })
+            .await
+            .map_err(|_| PERSIST_EVENT_FAILED)??;
+        self.appended.advance(last_position);
+        Ok(())
+    }
+}
+
+impl<E: Serialize + DeserializeOwned + Send + Sync> EventsJournal<E> for FileJournal {
+    async fn persist_event(&self, entity_id: OrderId, seq_event: &SequencedEvent<E>) -> Result<(), &'static str> {
+        let serialized_event = serde_json::to_vec(&seq_event.event).map_err(|_| SERIALIZE_EVENT_FAILED)?;
+        self.append(vec![(entity_id, seq_event.sequence_number, serialized_event)]).await
+    }
+
+    async fn persist_events(&self, events: &[(OrderId, SequencedEvent<E>)]) -> Result<(), &'static str> {
+        let records = events.iter()
+            .map(|(entity_id, seq_event)| {
+                let serialized_event = serde_json::to_vec(&seq_event.event).map_err(|_| SERIALIZE_EVENT_FAILED)?;
+                Ok((*entity_id, seq_event.sequence_number, serialized_event))
+            })
+            .collect::<Result<Vec<Record>, &'static str>>()?;
+        self.append(records).await
+    }
+
+    async fn retrieve_events(&self, entity_id: OrderId) -> Result<Vec<SequencedEvent<E>>, &'static str> {
+        let log = self.log.clone();
+        let events = tokio::task::spawn_blocking(move || log.lock().map_err(|_| JOURNAL_POISONED)?.read(entity_id))
+            .await
+            .map_err(|_| RETRIEVE_EVENTS_FAILED)??;
+
+        events.into_iter()
+            .map(|(sequence_number, event_payload)| {
+                let event: E = serde_json::from_slice(&event_payload).map_err(|_| DESERIALIZE_EVENT_FAILED)?;
+                Ok(SequencedEvent { sequence_number, event })
+            })
+            .collect()
+    }
+}
+
+impl<E: Serialize + DeserializeOwned + Send + Sync> GlobalEventsJournal<E> for FileJournal {
+    async fn read_all(&self, from_position: i64, batch_size: usize) -> Result<Vec<Positioned<E>>, &'static str> {
+        let log = self.log.clone();
+        let records = tokio::task::spawn_blocking(move || {
+                log.lock().map_err(|_| JOURNAL_POISONED)?.read_all(from_position, batch_size)
+            })
+            .await
+            .map_err(|_| RETRIEVE_EVENTS_FAILED)??;
+
+        records.into_iter()
+            .map(|(position, (entity_id, sequence_number, event_payload))| {
+                let event: E = serde_json::from_slice(&event_payload).map_err(|_| DESERIALIZE_EVENT_FAILED)?;
+                Ok(Positioned { position, entity_id, event: SequencedEvent { sequence_number, event } })
+            })
+            .collect()
+    }
+
+    /// Only reads the index, no file operation.
+    async fn last_position(&self) -> Result<i64, &'static str> {
+        Ok(self.log.lock().map_err(|_| JOURNAL_POISONED)?.last_position())
+    }
+
+    async fn wait_for_position(&self, position: i64, timeout: Duration) -> Result<(), &'static str> {
+        self.appended.wait_for(position, timeout).await;
+        Ok(())
+    }
+}
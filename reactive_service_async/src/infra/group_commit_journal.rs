@@ -0,0 +1,138 @@
+use std::sync::Arc;
+use std::time::Duration;
+use reactive_service_domain::aggregate_root::SequencedEvent;
+use tokio::sync::{mpsc, oneshot};
+use tokio::time::Instant;
+use reactive_service_application::command_errors::PERSIST_EVENT_FAILED;
+use crate::order_service::{EventsJournal, GlobalEventsJournal, OrderId, Positioned};
+
+/// When to write a group of events.
+#[derive(Debug, Clone)]
+pub struct GroupCommitConfig {
+    /// Write as soon as this many events are waiting.
+    pub max_batch_size: usize,
+    /// Maximum time the first event of a group waits for others to join.
+    pub linger: Duration,
+}
+
+impl Default for GroupCommitConfig {
+    fn default() -> Self {
+        Self {
+            max_batch_size: 256,
+            linger: Duration::from_millis(2),
+        }
+    }
+}
+
+/// Journal decorator gathering the events persisted by concurrent commands, across orders,
+/// to write them with a single `persist_events` call of the underlying journal.
+/// Each `persist_event` completes once its group is written.
+///
+/// If the group write fails, none of its events is written, `persist_events` being all or nothing:
+/// they are retried one by one, so only the commands whose events can't be written (e.g. duplicated sequence number) fail.
+///
+/// Must be created from within a tokio runtime.
+pub struct GroupCommitJournal<J, Event> {
+    journal: Arc<J>,
+    writes: mpsc::Sender<PendingWrite<Event>>,
+}
+
+struct PendingWrite<Event> {
+    entity_id: OrderId,
+    event: SequencedEvent<Event>,
+    done: oneshot::Sender<Result<(), &'static str>>,
+}
+
+impl<J, Event> GroupCommitJournal<J, Event>
+where
+    J: EventsJournal<Event> + Send + Sync + 'static,
+    Event: Send + Sync + 'static,
+{
+    pub fn new(journal: J, config: GroupCommitConfig) -> Self {
+        let journal = Arc::new(journal);
+        let max_batch_size = config.max_batch_size.max(1);
+        let (writes, pending_writes) = mpsc::channel(max_batch_size * 2);
+        tokio::spawn(Self::run(journal.clone(), pending_writes, max_batch_size, config.linger));
+        Self { journal, writes }
+    }
+
+    async fn run(journal: Arc<J>, mut pending_writes: mpsc::Receiver<PendingWrite<Event>>,
+                 max_batch_size: usize, linger: Duration) {
+
+        let mut group = Vec::with_capacity(max_batch_size);
+
+        // Ends when the GroupCommitJournal is dropped
+        while let Some(first) = pending_writes.recv().await {
+            group.push(first);
+
+            let deadline = Instant::now() + linger;
+            while group.len() < max_batch_size {
+                match tokio::time::timeout_at(deadline, pending_writes.recv()).await {
+                    Ok(Some(write)) => group.push(write),
+                    _ => break,
+                }
+            }
+
+            Self::commit(&journal, group.drain(..)).await;
+        }
+    }
+
+    async fn commit(journal: &J, group: impl Iterator<Item = PendingWrite<Event>>) {
+        let (events, dones): (Vec<(OrderId, SequencedEvent<Event>)>, Vec<_>) = group
+            .map(|write| ((write.entity_id, write.event), write.done))
+            .unzip();
+
+        match journal.persist_events(&events).await {
+            Ok(()) => {
+                for done in dones {
+                    let _ = done.send(Ok(()));
+                }
+            },
+            Err(_) => {
+                for ((entity_id, event), done) in events.iter().zip(dones) {
+                    let _ = done.send(journal.persist_event(*entity_id, event).await);
+                }
+            }
+        }
+    }
+}
+
+impl<J, Event> EventsJournal<Event> for GroupCommitJournal<J, Event>
+where
+    J: EventsJournal<Event> + Send + Sync + 'static,
+    Event: Clone + Send + Sync + 'static,
+{
+    async fn persist_event(&self, entity_id: OrderId, evt_w_seq: &SequencedEvent<Event>) -> Result<(), &'static str> {
+        let (done, written) = oneshot::channel();
+        let write = PendingWrite { entity_id, event: evt_w_seq.clone(), done };
+        self.writes.send(write).await.map_err(|_| PERSIST_EVENT_FAILED)?;
+        written.await.map_err(|_| PERSIST_EVENT_FAILED)?
+    }
+
+    async fn retrieve_events(&self, entity_id: OrderId) -> Result<Vec<SequencedEvent<Event>>, &'static str> {
+        self.journal.retrieve_events(entity_id).await
+    }
+
+    async fn persist_events(&self, events: &[(OrderId, SequencedEvent<Event>)]) -> Result<(), &'static str> {
+        // Already a group
+        self.journal.persist_events(events).await
+    }
+}
+
+impl<J, Event> GlobalEventsJournal<Event> for GroupCommitJournal<J, Event>
+where
+    J: GlobalEventsJournal<Event> + Send + Sync + 'static,
+    Event: Clone + Send + Sync + 'static,
+{
+    async fn read_all(&self, from_position: i64, batch_size: usize) -> Result<Vec<Positioned<Event>>, &'static str> {
+        self.journal.read_all(from_position, batch_size).await
+    }
+
+    async fn last_position(&self) -> Result<i64, &'static str> {
+        self.journal.last_position().await
+    }
+
+    async fn wait_for_position(&self, position: i64, timeout: Duration) -> Result<(), &'static str> {
+        self.journal.wait_for_position(position, timeout).await
+    }
+}
@@ -1,2 +1,8 @@
 pub mod postgres_events_store;
+pub mod postgres_projections;
+pub mod postgres_outbox;
 pub mod scylla_event_store;
+pub mod group_commit_journal;
+pub mod file_journal;
+pub mod sqlite_event_store;
+pub mod inmem_journal;
@@ -0,0 +1,235 @@
+use deadpool_postgres::{Object, Pool, Transaction};
+use reactive_service_domain::non_empty_cart::Sku;
+use reactive_service_domain::order_entity::OrderEvent;
+use tokio_postgres::Row;
+use reactive_service_application::command_errors::{APPLY_EVENTS_FAILED, LOAD_CHECKPOINT_FAILED, NO_DB_CONNECTION};
+use crate::infra::postgres_events_store::{create_pool, PostgresEventStoreConfig};
+use crate::order_service::{OrderId, Positioned};
+use crate::projections::{OrderSummary, Projection, SkuSales};
+
+/// The views, and their checkpoints in the `checkpoints` table of the `PostgresEventStore`.
+/// The carts of the open orders are kept for the sales: a completed order doesn't carry its cart.
+const SCHEMA: &str = "
+    BEGIN;
+    SELECT pg_advisory_xact_lock(7300);
+    CREATE TABLE IF NOT EXISTS checkpoints (
+        consumer TEXT PRIMARY KEY,
+        position BIGINT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS order_summaries (
+        order_id BIGINT PRIMARY KEY,
+        status TEXT NOT NULL,
+        total_cents BIGINT NOT NULL,
+        postal_code TEXT,
+        last_updated BIGINT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS sku_sales (
+        sku TEXT PRIMARY KEY,
+        quantity BIGINT NOT NULL,
+        orders BIGINT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS sku_sales_open_carts (
+        order_id BIGINT NOT NULL,
+        sku TEXT NOT NULL,
+        quantity BIGINT NOT NULL,
+        PRIMARY KEY(order_id, sku)
+    );
+    COMMIT;
+";
+
+const ORDER_SUMMARIES: &str = "order_summaries";
+const SKU_SALES: &str = "sku_sales";
+
+/// The order summary view, in the `order_summaries` table.
+pub struct PostgresOrderSummaries { pool: Pool }
+
+impl PostgresOrderSummaries {
+    /// Connect with the default settings, to a local database.
+    pub async fn new() -> Result<Self, Box<dyn std::error::Error>> {
+        Self::with_config(PostgresEventStoreConfig::default()).await
+    }
+
+    pub async fn with_config(config: PostgresEventStoreConfig) -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(Self { pool: connect(&config).await? })
+    }
+
+    pub async fn get(&self, order_id: OrderId) -> Result<Option<OrderSummary>, &'static str> {
+        let client = client(&self.pool).await?;
+        let row = client.query_opt(
+            "SELECT order_id, status, total_cents, postal_code, last_updated FROM order_summaries WHERE order_id = $1",
+            &[&order_id],
+        ).await.map_err(|_| "Failed to query projection")?;
+        row.map(|row| summary_from_row(&row)).transpose()
+    }
+}
+
+impl Projection<OrderEvent> for PostgresOrderSummaries {
+    async fn checkpoint(&self) -> Result<i64, &'static str> {
+        load_checkpoint(&self.pool, ORDER_SUMMARIES).await
+    }
+
+    async fn apply(&self, events: &[Positioned<OrderEvent>]) -> Result<(), &'static str> {
+        let mut client = client(&self.pool).await?;
+        let transaction = client.transaction().await.map_err(|_| APPLY_EVENTS_FAILED)?;
+        let checkpoint = lock_checkpoint(&transaction, ORDER_SUMMARIES).await?;
+
+        for positioned in events.iter().filter(|positioned| positioned.position > checkpoint) {
+            let previous = transaction.query_opt(
+                "SELECT order_id, status, total_cents, postal_code, last_updated FROM order_summaries WHERE order_id = $1",
+                &[&positioned.entity_id],
+            ).await.map_err(|_| APPLY_EVENTS_FAILED)?;
+            let previous = previous.map(|row| summary_from_row(&row)).transpose()?;
+
+            let summary = OrderSummary::evolve(previous.as_ref(), positioned);
+            transaction.execute(
+                "INSERT INTO order_summaries (order_id, status, total_cents, postal_code, last_updated) VALUES ($1, $2, $3, $4, $5)
+                 ON CONFLICT (order_id) DO UPDATE SET status = EXCLUDED.status, total_cents = EXCLUDED.total_cents,
+                    postal_code = EXCLUDED.postal_code, last_updated = EXCLUDED.last_updated",
+                &[&summary.order_id, &summary.status.as_str(), &i64::from(summary.total_cents), &summary.postal_code, &summary.last_updated],
+            ).await.map_err(|_| APPLY_EVENTS_FAILED)?;
+        }
+
+        commit_checkpoint(transaction, ORDER_SUMMARIES, checkpoint, events).await
+    }
+
+    async fn reset(&self) -> Result<(), &'static str> {
+        reset(&self.pool, ORDER_SUMMARIES, "DELETE FROM order_summaries").await
+    }
+}
+
+/// The sales by SKU view, in the `sku_sales` table.
+pub struct PostgresSalesBySku { pool: Pool }
+
+impl PostgresSalesBySku {
+    /// Connect with the default settings, to a local database.
+    pub async fn new() -> Result<Self, Box<dyn std::error::Error>> {
+        Self::with_config(PostgresEventStoreConfig::default()).await
+    }
+
+    pub async fn with_config(config: PostgresEventStoreConfig) -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(Self { pool: connect(&config).await? })
+    }
+
+    pub async fn get(&self, sku: &Sku) -> Result<Option<SkuSales>, &'static str> {
+        let client = client(&self.pool).await?;
+        let row = client.query_opt("SELECT quantity, orders FROM sku_sales WHERE sku = $1", &[&sku.0])
+            .await
+            .map_err(|_| "Failed to query projection")?;
+        Ok(row.map(|row| SkuSales { quantity: row.get::<_, i64>(0) as u64, orders: row.get::<_, i64>(1) as u64 }))
+    }
+}
+
+impl Projection<OrderEvent> for PostgresSalesBySku {
+    async fn checkpoint(&self) -> Result<i64, &'static str> {
+        load_checkpoint(&self.pool, SKU_SALES).await
+    }
+
+    async fn apply(&self, events: &[Positioned<OrderEvent>]) -> Result<(), &'static str> {
+        let mut client = client(&self.pool).await?;
+        let transaction = client.transaction().await.map_err(|_| APPLY_EVENTS_FAILED)?;
+        let checkpoint = lock_checkpoint(&transaction, SKU_SALES).await?;
+
+        for positioned in events.iter().filter(|positioned| positioned.position > checkpoint) {
+            let order_id = positioned.entity_id;
+            match &positioned.event.event {
+                OrderEvent::UpdatedCart { cart, .. } | OrderEvent::UpdatedCartOnExistingDeliveryAddress { cart, .. } => {
+                    let (skus, quantities): (Vec<&str>, Vec<i64>) = cart.get_items().iter()
+                        .map(|(sku, quantity)| (sku.0.as_str(), i64::from(quantity.0)))
+                        .unzip();
+                    transaction.execute("DELETE FROM sku_sales_open_carts WHERE order_id = $1", &[&order_id])
+                        .await
+                        .map_err(|_| APPLY_EVENTS_FAILED)?;
+                    transaction.execute(
+                        "INSERT INTO sku_sales_open_carts (order_id, sku, quantity)
+                         SELECT $1, * FROM UNNEST($2::TEXT[], $3::BIGINT[])",
+                        &[&order_id, &skus, &quantities],
+                    ).await.map_err(|_| APPLY_EVENTS_FAILED)?;
+                },
+                OrderEvent::UpdatedDeliveryAddress { .. } => {},
+                OrderEvent::Expired => {
+                    transaction.execute("DELETE FROM sku_sales_open_carts WHERE order_id = $1", &[&order_id])
+                        .await
+                        .map_err(|_| APPLY_EVENTS_FAILED)?;
+                },
+                OrderEvent::Completed { .. } => {
+                    transaction.execute(
+                        "INSERT INTO sku_sales (sku, quantity, orders)
+                         SELECT sku, quantity, 1 FROM sku_sales_open_carts WHERE order_id = $1
+                         ON CONFLICT (sku) DO UPDATE SET quantity = sku_sales.quantity + EXCLUDED.quantity, orders = sku_sales.orders + 1",
+                        &[&order_id],
+                    ).await.map_err(|_| APPLY_EVENTS_FAILED)?;
+                    transaction.execute("DELETE FROM sku_sales_open_carts WHERE order_id = $1", &[&order_id])
+                        .await
+                        .map_err(|_| APPLY_EVENTS_FAILED)?;
+                },
+            }
+        }
+
+        commit_checkpoint(transaction, SKU_SALES, checkpoint, events).await
+    }
+
+    async fn reset(&self) -> Result<(), &'static str> {
+        reset(&self.pool, SKU_SALES, "DELETE FROM sku_sales; DELETE FROM sku_sales_open_carts").await
+    }
+}
+
+async fn connect(config: &PostgresEventStoreConfig) -> Result<Pool, Box<dyn std::error::Error>> {
+    let (pool, _) = create_pool(config)?;
+    pool.get().await?.batch_execute(SCHEMA).await?;
+    Ok(pool)
+}
+
+async fn client(pool: &Pool) -> Result<Object, &'static str> {
+    pool.get().await.map_err(|_| NO_DB_CONNECTION)
+}
+
+async fn load_checkpoint(pool: &Pool, consumer: &str) -> Result<i64, &'static str> {
+    let client = client(pool).await?;
+    let row = client.query_opt("SELECT position FROM checkpoints WHERE consumer = $1", &[&consumer])
+        .await
+        .map_err(|_| LOAD_CHECKPOINT_FAILED)?;
+    Ok(row.map_or(0, |row| row.get(0)))
+}
+
+/// The checkpoint of the view, locked until the end of the transaction:
+/// a concurrent projector waits, then skips the events applied meanwhile.
+async fn lock_checkpoint(transaction: &Transaction<'_>, consumer: &str) -> Result<i64, &'static str> {
+    transaction.execute("INSERT INTO checkpoints (consumer, position) VALUES ($1, 0) ON CONFLICT (consumer) DO NOTHING", &[&consumer])
+        .await
+        .map_err(|_| APPLY_EVENTS_FAILED)?;
+    let row = transaction.query_one("SELECT position FROM checkpoints WHERE consumer = $1 FOR UPDATE", &[&consumer])
+        .await
+        .map_err(|_| APPLY_EVENTS_FAILED)?;
+    Ok(row.get(0))
+}
+
+/// Move the checkpoint to the last event applied, with the view.
+async fn commit_checkpoint(transaction: Transaction<'_>, consumer: &str, checkpoint: i64, events: &[Positioned<OrderEvent>])
+    -> Result<(), &'static str> {
+
+    let position = events.last().map_or(checkpoint, |last| last.position.max(checkpoint));
+    transaction.execute("UPDATE checkpoints SET position = $2 WHERE consumer = $1", &[&consumer, &position])
+        .await
+        .map_err(|_| APPLY_EVENTS_FAILED)?;
+    transaction.commit().await.map_err(|_| APPLY_EVENTS_FAILED)
+}
+
+async fn reset(pool: &Pool, consumer: &str, delete_view: &str) -> Result<(), &'static str> {
+    let mut client = client(pool).await?;
+    let transaction = client.transaction().await.map_err(|_| "Failed to reset projection")?;
+    transaction.batch_execute(delete_view).await.map_err(|_| "Failed to reset projection")?;
+    transaction.execute("DELETE FROM checkpoints WHERE consumer = $1", &[&consumer])
+        .await
+        .map_err(|_| "Failed to reset projection")?;
+    transaction.commit().await.map_err(|_| "Failed to reset projection")
+}
+
+fn summary_from_row(row: &Row) -> Result<OrderSummary, &'static str> {
+    Ok(OrderSummary {
+        order_id: row.get(0),
+        status: row.get::<_, &str>(1).parse()?,
+        total_cents: u32::try_from(row.get::<_, i64>(2)).map_err(|_| "Invalid total")?,
+        postal_code: row.get(3),
+        last_updated: row.get(4),
+    })
+}
@@ -1,28 +1,107 @@
+use std::collections::{HashMap, HashSet};
+use futures::StreamExt;
+use futures::future::join_all;
 use reactive_service_domain::aggregate_root::SequencedEvent;
-use scylla::{IntoTypedRows, Session, SessionBuilder};
+use scylla::batch::{Batch, BatchType};
+use scylla::prepared_statement::PreparedStatement;
+use scylla::statement::Consistency;
+use scylla::{QueryResult, Session, SessionBuilder};
 use serde::de::DeserializeOwned;
 use serde::Serialize;
 use serde_json;
+use reactive_service_application::command_errors::{DESERIALIZE_EVENT_FAILED, PERSIST_EVENT_FAILED, RETRIEVE_EVENTS_FAILED, SERIALIZE_EVENT_FAILED};
 use crate::order_service::EventsJournal;
 
-pub struct ScyllaEventStore { session: Session }
+/// Replication of the keyspace, only used when the keyspace is created.
+#[derive(Debug, Clone)]
+pub enum Replication {
+    SimpleStrategy { replication_factor: u32 },
+    /// Replication factor per datacenter name
+    NetworkTopologyStrategy { datacenters: Vec<(String, u32)> },
+}
+
+impl Replication {
+    fn to_cql(&self) -> String {
+        match self {
+            Replication::SimpleStrategy { replication_factor } =>
+                format!("{{'class': 'SimpleStrategy', 'replication_factor': {}}}", replication_factor),
+            Replication::NetworkTopologyStrategy { datacenters } => {
+                let factors: String = datacenters.iter()
+                    .map(|(datacenter, factor)| format!(", '{}': {}", datacenter.replace('\'', "''"), factor))
+                    .collect();
+                format!("{{'class': 'NetworkTopologyStrategy'{}}}", factors)
+            }
+        }
+    }
+}
+
+/// Connection settings of the `ScyllaEventStore`.
+#[derive(Debug, Clone)]
+pub struct ScyllaEventStoreConfig {
+    pub known_nodes: Vec<String>,
+    pub keyspace: String,
+    pub replication: Replication,
+    /// Consistency of the reads and writes
+    pub consistency: Consistency,
+    /// Number of rows fetched per round-trip when reading a stream of events
+    pub page_size: i32,
+}
+
+impl Default for ScyllaEventStoreConfig {
+    fn default() -> Self {
+        Self {
+            known_nodes: vec!["127.0.0.1:9042".to_owned()],
+            keyspace: "ddd".to_owned(),
+            replication: Replication::SimpleStrategy { replication_factor: 1 },
+            consistency: Consistency::LocalQuorum,
+            page_size: 1000,
+        }
+    }
+}
+
+/// Events journal in a Scylla (or Cassandra) cluster.
+///
+/// The events are inserted with lightweight transactions, so a sequence number already persisted
+/// for an entity is rejected instead of being overwritten. A conditional batch can only span a partition:
+/// the events of a batch are written in a batch per entity and, if one of them is rejected, the events of the others
+/// are deleted, for the batch to be all or nothing. Unless the cluster fails in the middle of the deletion.
+///
+/// The events are partitioned by entity, with no order across the partitions:
+/// it is not a `GlobalEventsJournal`, the catch-up subscriptions need one of the other journals.
+pub struct ScyllaEventStore {
+    session: Session,
+    insert_event: PreparedStatement,
+    delete_event: PreparedStatement,
+    select_events: PreparedStatement,
+    consistency: Consistency,
+}
 
 impl ScyllaEventStore {
+    /// Connect to a single node, with the default settings.
     pub async fn new(contact_point: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        Self::with_config(ScyllaEventStoreConfig {
+            known_nodes: vec![contact_point.to_owned()],
+            ..ScyllaEventStoreConfig::default()
+        }).await
+    }
+
+    pub async fn with_config(config: ScyllaEventStoreConfig) -> Result<Self, Box<dyn std::error::Error>> {
+        // The keyspace name can't be a bind marker, make sure it can't inject CQL
+        if config.keyspace.is_empty() || !config.keyspace.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            return Err("Invalid keyspace name".into());
+        }
+
         let session = SessionBuilder::new()
-            .known_node(contact_point)
+            .known_nodes(&config.known_nodes)
             .build()
             .await?;
 
-        // Create the keyspace "ddd" if it doesn't exist
-        session.query(r#"
-                CREATE KEYSPACE IF NOT EXISTS ddd
-                WITH REPLICATION = {
-                    'class': 'SimpleStrategy',
-                    'replication_factor': 1
-                };"#, (), ).await?;
+        session.query(format!(
+            "CREATE KEYSPACE IF NOT EXISTS {} WITH REPLICATION = {};",
+            config.keyspace, config.replication.to_cql()
+        ), ()).await?;
 
-        session.use_keyspace("ddd", false).await?;
+        session.use_keyspace(&config.keyspace, false).await?;
 
         // Create the "events" table if it doesn't exist
         session.query(r#"
@@ -33,33 +112,107 @@ impl ScyllaEventStore {
                     PRIMARY KEY (entity_id, sequence_number)
                 );"#, (), ).await?;
 
-        Ok(Self { session })
+        let mut insert_event = session
+            .prepare("INSERT INTO events (entity_id, sequence_number, event_payload) VALUES (?, ?, ?) IF NOT EXISTS")
+            .await?;
+        insert_event.set_consistency(config.consistency);
+
+        // Conditional as well: the rows of lightweight transactions are only changed by lightweight transactions
+        let mut delete_event = session
+            .prepare("DELETE FROM events WHERE entity_id = ? AND sequence_number = ? IF EXISTS")
+            .await?;
+        delete_event.set_consistency(config.consistency);
+
+        let mut select_events = session
+            .prepare("SELECT sequence_number, event_payload FROM events WHERE entity_id = ? ORDER BY sequence_number ASC")
+            .await?;
+        select_events.set_consistency(config.consistency);
+        select_events.set_page_size(config.page_size);
+
+        Ok(Self { session, insert_event, delete_event, select_events, consistency: config.consistency })
+    }
+
+    /// The statement once per values, in a conditional batch: the values must be of a single partition.
+    async fn batch<V: scylla::serialize::row::SerializeRow>(&self, statement: &PreparedStatement, values: Vec<V>)
+        -> Result<QueryResult, scylla::transport::errors::QueryError> {
+
+        let mut batch = Batch::new(BatchType::Logged);
+        batch.set_consistency(self.consistency);
+        for _ in &values {
+            batch.append_statement(statement.clone());
+        }
+        self.session.batch(&batch, values).await
     }
 }
 
 impl<E: Serialize + DeserializeOwned + Send + Sync> EventsJournal<E> for ScyllaEventStore {
     async fn persist_event(&self, entity_id: i64, evt_w_seq: &SequencedEvent<E>) -> Result<(), &'static str> {
-        let query = "INSERT INTO events (entity_id, sequence_number, event_payload) VALUES (?, ?, ?)";
-        let values = (entity_id, evt_w_seq.sequence_number, serde_json::to_string(&evt_w_seq.event).unwrap());
+        let serialized_event = serde_json::to_string(&evt_w_seq.event).map_err(|_| SERIALIZE_EVENT_FAILED)?;
+        let values = (entity_id, evt_w_seq.sequence_number, serialized_event);
 
-        self.session.query(query, &values).await.map_err(|_| "Failed to persist event")?;
+        let result = self.session.execute(&self.insert_event, &values).await.map_err(|_| PERSIST_EVENT_FAILED)?;
+        if !applied(&result) {
+            return Err(PERSIST_EVENT_FAILED);
+        }
         Ok(())
     }
 
+    async fn persist_events(&self, events: &[(i64, SequencedEvent<E>)]) -> Result<(), &'static str> {
+        // The conditions of a batch are checked against the rows before the batch, not between its statements
+        let mut keys = HashSet::with_capacity(events.len());
+        if !events.iter().all(|(entity_id, seq_event)| keys.insert((*entity_id, seq_event.sequence_number))) {
+            return Err(PERSIST_EVENT_FAILED);
+        }
+
+        let mut values_by_entity: HashMap<i64, Vec<(i64, i64, String)>> = HashMap::new();
+        for (entity_id, seq_event) in events {
+            let serialized_event = serde_json::to_string(&seq_event.event).map_err(|_| SERIALIZE_EVENT_FAILED)?;
+            values_by_entity.entry(*entity_id).or_default().push((*entity_id, seq_event.sequence_number, serialized_event));
+        }
+
+        // One conditional batch per partition, sent concurrently
+        let partitions: Vec<_> = values_by_entity.into_values().collect();
+        let results = join_all(partitions.iter().map(|values| self.batch(&self.insert_event, values.clone()))).await;
+        if results.iter().all(|result| result.as_ref().is_ok_and(applied)) {
+            return Ok(());
+        }
+
+        // Undo the partitions written, the others were not
+        let written = partitions.into_iter().zip(results)
+            .filter(|(_, result)| result.as_ref().is_ok_and(applied))
+            .map(|(values, _)| {
+                let keys: Vec<(i64, i64)> = values.into_iter().map(|(entity_id, sequence_number, _)| (entity_id, sequence_number)).collect();
+                async move { self.batch(&self.delete_event, keys).await }
+            });
+        let _ = join_all(written).await;
+        Err(PERSIST_EVENT_FAILED)
+    }
+
     async fn retrieve_events(&self, entity_id: i64) -> Result<Vec<SequencedEvent<E>>, &'static str> {
-        let query = "SELECT sequence_number, event_payload FROM events WHERE entity_id = ? ORDER BY sequence_number ASC";
-        let mut events = Vec::new();
+        // Long streams are fetched page by page, not in a single response
+        let mut rows = self.session
+            .execute_iter(self.select_events.clone(), (entity_id,))
+            .await
+            .map_err(|_| RETRIEVE_EVENTS_FAILED)?
+            .into_typed::<(i64, String)>();
 
-        if let Some(rows) = self.session.query(query, (entity_id,)).await.unwrap().rows {
-            for row in rows.into_typed::<(i64, String)>() {
-                let (sequence_number, event_payload) = row.map_err(|_| "Deserialization Error")?;
-                let event: E = serde_json::from_str(&event_payload).map_err(|_| "Deserialization Error")?;
-                events.push(SequencedEvent { sequence_number, event });
-            }
+        let mut events = Vec::new();
+        while let Some(row) = rows.next().await {
+            let (sequence_number, event_payload) = row.map_err(|_| RETRIEVE_EVENTS_FAILED)?;
+            let event: E = serde_json::from_str(&event_payload).map_err(|_| DESERIALIZE_EVENT_FAILED)?;
+            events.push(SequencedEvent { sequence_number, event });
         }
 
         Ok(events)
     }
 }
 
-
+/// First column of the result of a lightweight transaction.
+fn applied(result: &QueryResult) -> bool {
+    result.rows.as_ref()
+        .and_then(|rows| rows.first())
+        .and_then(|row| row.columns.first())
+        .and_then(|column| column.as_ref())
+        .and_then(|value| value.as_boolean())
+        .unwrap_or(false)
+}
@@ -0,0 +1,287 @@
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use r2d2::Pool;
+use r2d2_sqlite::SqliteConnectionManager;
+use rusqlite::{params, Connection, OptionalExtension, TransactionBehavior};
+use reactive_service_domain::aggregate_root::SequencedEvent;
+use reactive_service_application::scheduler::to_millis;
+use reactive_service_application::command_errors::{DESERIALIZE_COMMAND_FAILED, DESERIALIZE_EVENT_FAILED, LOAD_CHECKPOINT_FAILED, NO_DB_CONNECTION, PERSIST_EVENT_FAILED, REMOVE_SCHEDULED_COMMAND_FAILED, RETRIEVE_EVENTS_FAILED, RETRIEVE_SCHEDULED_COMMANDS_FAILED, SAVE_CHECKPOINT_FAILED, SCHEDULE_COMMAND_FAILED, SERIALIZE_COMMAND_FAILED, SERIALIZE_EVENT_FAILED};
+use crate::catch_up::{AppendedPosition, CheckpointStore};
+use crate::order_service::{EventsJournal, GlobalEventsJournal, Positioned};
+use crate::scheduler::{Scheduled, ScheduleStore};
+
+/// Events journal in a local SQLite database file, in WAL mode.
+///
+/// SQLite calls are blocking: they run on the blocking thread pool of tokio, each one with a pooled connection.
+/// Reads are concurrent; writes are serialized by SQLite, a writer waiting for the lock up to the busy timeout.
+///
+/// The global position of an event is assigned when it is inserted, the writes being serialized:
+/// the positions are committed in order. Only the writes of this store wake up the catch-up subscriptions waiting
+/// for new events, the ones of other processes are read once the wait times out.
+pub struct SqliteEventStore {
+    pool: Pool<SqliteConnectionManager>,
+    appended: AppendedPosition,
+}
+
+impl SqliteEventStore {
+    /// Open, or create, the database at `path`.
+    pub async fn new(path: impl Into<PathBuf>) -> Result<Self, Box<dyn std::error::Error>> {
+        let path = path.into();
+        let (pool, last_position) = tokio::task::spawn_blocking(move || Self::open(path).map_err(|e| e.to_string())).await??;
+        Ok(Self { pool, appended: AppendedPosition::new(last_position) })
+    }
+
+    /// The pool, and the last position.
+    fn open(path: PathBuf) -> Result<(Pool<SqliteConnectionManager>, i64), Box<dyn std::error::Error>> {
+        // Readers don't block the writer; in WAL mode NORMAL only risks the last commits on a power loss
+        let manager = SqliteConnectionManager::file(path).with_init(|connection| {
+            connection.pragma_update(None, "synchronous", "NORMAL")?;
+            connection.busy_timeout(Duration::from_secs(5))
+        });
+        let pool = Pool::new(manager)?;
+
+        // The journal mode is kept by the database file
+        let connection = pool.get()?;
+        connection.pragma_update(None, "journal_mode", "WAL")?;
+
+        connection.execute(
+            "CREATE TABLE IF NOT EXISTS events (
+                entity_id INTEGER NOT NULL,
+                sequence_number INTEGER NOT NULL,
+                payload TEXT NOT NULL,
+                position INTEGER,
+                PRIMARY KEY(entity_id, sequence_number)
+            )",
+            [],
+        )?;
+        // The databases created before the global positions, numbered in their insertion order
+        let has_position: bool = connection.query_row(
+            "SELECT COUNT(*) > 0 FROM pragma_table_info('events') WHERE name = 'position'", [], |row| row.get(0))?;
+        if !has_position {
+            connection.execute_batch(
+                "BEGIN IMMEDIATE;
+                 ALTER TABLE events ADD COLUMN position INTEGER;
+                 UPDATE events SET position = rowid;
+                 COMMIT;")?;
+        }
+        connection.execute_batch(
+            "CREATE UNIQUE INDEX IF NOT EXISTS events_position ON events (position);
+             CREATE TABLE IF NOT EXISTS checkpoints (
+                consumer TEXT PRIMARY KEY,
+                position INTEGER NOT NULL
+             );
+             CREATE TABLE IF NOT EXISTS scheduled_commands (
+                key TEXT PRIMARY KEY,
+                due_at INTEGER NOT NULL,
+                payload TEXT NOT NULL
+             );
+             CREATE INDEX IF NOT EXISTS scheduled_commands_due_at ON scheduled_commands (due_at);")?;
+
+        let last_position = last_position(&connection)?;
+        Ok((pool, last_position))
+    }
+
+    async fn insert(&self, rows: Vec<(i64, i64, String)>) -> Result<(), &'static str> {
+        let pool = self.pool.clone();
+        let last_position = tokio::task::spawn_blocking(move || {
+            // All or nothing, and a single sync of the WAL. Immediate: takes the write lock upfront,
+            // instead of failing to upgrade a read lock when another connection is writing.
+            let mut connection = pool.get().map_err(|_| NO_DB_CONNECTION)?;
+            let transaction = connection.transaction_with_behavior(TransactionBehavior::Immediate).map_err(|_| PERSIST_EVENT_FAILED)?;
+            let last_position = insert_events(&transaction, &rows)?;
+            transaction.commit().map(|_| last_position).map_err(|_| PERSIST_EVENT_FAILED)
+        }).await.map_err(|_| PERSIST_EVENT_FAILED)??;
+        self.appended.advance(last_position);
+        Ok(())
+    }
+
+    /// Run a blocking call with a pooled connection.
+    async fn with_connection<T, F>(&self, call: F) -> Result<T, &'static str>
+    where
+        T: Send + 'static,
+        F: FnOnce(&Connection) -> Result<T, &'static str> + Send + 'static,
+    {
+        let pool = self.pool.clone();
+        tokio::task::spawn_blocking(move || {
+            let connection = pool.get().map_err(|_| NO_DB_CONNECTION)?;
+            call(&connection)
+        }).await.map_err(|_| NO_DB_CONNECTION)?
+    }
+}
+
+impl<E: Serialize + DeserializeOwned + Send + Sync> EventsJournal<E> for SqliteEventStore {
+    async fn persist_event(&self, entity_id: i64, seq_event: &SequencedEvent<E>) -> Result<(), &'static str> {
+        let serialized_event = serde_json::to_string(&seq_event.event).map_err(|_| SERIALIZE_EVENT_FAILED)?;
+        self.insert(vec![(entity_id, seq_event.sequence_number, serialized_event)]).await
+    }
+
+    async fn persist_events(&self, events: &[(i64, SequencedEvent<E>)]) -> Result<(), &'static str> {
+        let rows = events.iter()
+            .map(|(entity_id, seq_event)| {
+                let serialized_event = serde_json::to_string(&seq_event.event).map_err(|_| SERIALIZE_EVENT_FAILED)?;
+                Ok((*entity_id, seq_event.sequence_number, serialized_event))
+            })
+            .collect::<Result<Vec<(i64, i64, String)>, &'static str>>()?;
+        self.insert(rows).await
+    }
+
+    async fn retrieve_events(&self, entity_id: i64) -> Result<Vec<SequencedEvent<E>>, &'static str> {
+        let pool = self.pool.clone();
+        let rows = tokio::task::spawn_blocking(move || {
+            let connection = pool.get().map_err(|_| NO_DB_CONNECTION)?;
+            let mut statement = connection
+                .prepare_cached("SELECT sequence_number, payload FROM events WHERE entity_id = ?1 ORDER BY sequence_number ASC")
+                .map_err(|_| RETRIEVE_EVENTS_FAILED)?;
+            let rows = statement
+                .query_map([entity_id], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?)))
+                .map_err(|_| RETRIEVE_EVENTS_FAILED)?;
+            rows.collect::<Result<Vec<(i64, String)>, _>>().map_err(|_| RETRIEVE_EVENTS_FAILED)
+        }).await.map_err(|_| RETRIEVE_EVENTS_FAILED)??;
+
+        rows.into_iter()
+            .map(|(sequence_number, event_payload)| {
+                let event: E = serde_json::from_str(&event_payload).map_err(|_| DESERIALIZE_EVENT_FAILED)?;
+
+                Ok(SequencedEvent {
+                    sequence_number,
+                    event,
+                })
+            })
+            .collect()
+    }
+}
+
+impl<E: Serialize + DeserializeOwned + Send + Sync> GlobalEventsJournal<E> for SqliteEventStore {
+    async fn read_all(&self, from_position: i64, batch_size: usize) -> Result<Vec<Positioned<E>>, &'static str> {
+        let rows = self.with_connection(move |connection| {
+            let mut statement = connection
+                .prepare_cached(
+                    "SELECT position, entity_id, sequence_number, payload FROM events
+                     WHERE position >= ?1 ORDER BY position ASC LIMIT ?2")
+                .map_err(|_| RETRIEVE_EVENTS_FAILED)?;
+            let rows = statement
+                .query_map(params![from_position, batch_size as i64], |row| {
+                    Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)?, row.get::<_, i64>(2)?, row.get::<_, String>(3)?))
+                })
+                .map_err(|_| RETRIEVE_EVENTS_FAILED)?;
+            rows.collect::<Result<Vec<(i64, i64, i64, String)>, _>>().map_err(|_| RETRIEVE_EVENTS_FAILED)
+        }).await?;
+
+        rows.into_iter()
+            .map(|(position, entity_id, sequence_number, event_payload)| {
+                let event: E = serde_json::from_str(&event_payload).map_err(|_| DESERIALIZE_EVENT_FAILED)?;
+                Ok(Positioned { position, entity_id, event: SequencedEvent { sequence_number, event } })
+            })
+            .collect()
+    }
+
+    async fn last_position(&self) -> Result<i64, &'static str> {
+        self.with_connection(|connection| last_position(connection).map_err(|_| RETRIEVE_EVENTS_FAILED)).await
+    }
+
+    async fn wait_for_position(&self, position: i64, timeout: Duration) -> Result<(), &'static str> {
+        self.appended.wait_for(position, timeout).await;
+        Ok(())
+    }
+}
+
+impl CheckpointStore for SqliteEventStore {
+    async fn load_checkpoint(&self, consumer: &str) -> Result<i64, &'static str> {
+        let consumer = consumer.to_owned();
+        self.with_connection(move |connection| {
+            let position = connection
+                .query_row("SELECT position FROM checkpoints WHERE consumer = ?1", [consumer], |row| row.get(0))
+                .optional()
+                .map_err(|_| LOAD_CHECKPOINT_FAILED)?;
+            Ok(position.unwrap_or(0))
+        }).await
+    }
+
+    async fn save_checkpoint(&self, consumer: &str, position: i64) -> Result<(), &'static str> {
+        let consumer = consumer.to_owned();
+        self.with_connection(move |connection| {
+            connection
+                .execute(
+                    "INSERT INTO checkpoints (consumer, position) VALUES (?1, ?2)
+                     ON CONFLICT (consumer) DO UPDATE SET position = excluded.position",
+                    params![consumer, position],
+                )
+                .map_err(|_| SAVE_CHECKPOINT_FAILED)?;
+            Ok(())
+        }).await
+    }
+}
+
+impl ScheduleStore for SqliteEventStore {
+    async fn schedule(&self, scheduled: &Scheduled) -> Result<(), &'static str> {
+        let (key, due_at) = (scheduled.key(), scheduled.due_at_millis());
+        let payload = serde_json::to_string(&scheduled.command).map_err(|_| SERIALIZE_COMMAND_FAILED)?;
+        self.with_connection(move |connection| {
+            connection
+                .execute(
+                    "INSERT INTO scheduled_commands (key, due_at, payload) VALUES (?1, ?2, ?3)
+                     ON CONFLICT (key) DO UPDATE SET due_at = excluded.due_at, payload = excluded.payload",
+                    params![key, due_at, payload],
+                )
+                .map_err(|_| SCHEDULE_COMMAND_FAILED)?;
+            Ok(())
+        }).await
+    }
+
+    async fn due(&self, now: SystemTime, limit: usize) -> Result<Vec<Scheduled>, &'static str> {
+        let now = to_millis(now);
+        let rows = self.with_connection(move |connection| {
+            let mut statement = connection
+                .prepare_cached("SELECT due_at, payload FROM scheduled_commands WHERE due_at <= ?1 ORDER BY due_at LIMIT ?2")
+                .map_err(|_| RETRIEVE_SCHEDULED_COMMANDS_FAILED)?;
+            let rows = statement
+                .query_map(params![now, limit as i64], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?)))
+                .map_err(|_| RETRIEVE_SCHEDULED_COMMANDS_FAILED)?;
+            rows.collect::<Result<Vec<_>, _>>().map_err(|_| RETRIEVE_SCHEDULED_COMMANDS_FAILED)
+        }).await?;
+
+        rows.into_iter()
+            .map(|(due_at, payload)| {
+                let command = serde_json::from_str(&payload).map_err(|_| DESERIALIZE_COMMAND_FAILED)?;
+                Ok(Scheduled::from_millis(due_at, command))
+            })
+            .collect()
+    }
+
+    async fn remove(&self, delivered: &Scheduled) -> Result<(), &'static str> {
+        let (key, due_at) = (delivered.key(), delivered.due_at_millis());
+        let payload = serde_json::to_string(&delivered.command).map_err(|_| SERIALIZE_COMMAND_FAILED)?;
+        self.with_connection(move |connection| {
+            connection
+                .execute(
+                    "DELETE FROM scheduled_commands WHERE key = ?1 AND due_at = ?2 AND payload = ?3",
+                    params![key, due_at, payload],
+                )
+                .map_err(|_| REMOVE_SCHEDULED_COMMAND_FAILED)?;
+            Ok(())
+        }).await
+    }
+}
+
+/// Insert the events after the last position, returning the position of the last one.
+fn insert_events(connection: &Connection, rows: &[(i64, i64, String)]) -> Result<i64, &'static str> {
+    let mut statement = connection
+        .prepare_cached(
+            "INSERT INTO events (entity_id, sequence_number, payload, position)
+             SELECT ?1, ?2, ?3, IFNULL(MAX(position), 0) + 1 FROM events
+             RETURNING position")
+        .map_err(|_| PERSIST_EVENT_FAILED)?;
+    let mut last_position = 0;
+    for (entity_id, sequence_number, payload) in rows {
+        last_position = statement
+            .query_row(params![entity_id, sequence_number, payload], |row| row.get(0))
+            .map_err(|_| PERSIST_EVENT_FAILED)?;
+    }
+    Ok(last_position)
+}
+
+fn last_position(connection: &Connection) -> rusqlite::Result<i64> {
+    connection.query_row("SELECT IFNULL(MAX(position), 0) FROM events", [], |row| row.get(0))
+}
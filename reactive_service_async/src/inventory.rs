@@ -0,0 +1,115 @@
+use std::future::Future;
+use std::time::SystemTime;
+use reactive_service_domain::inventory_item::{InventoryItem, InventoryItemCommand, InventoryItemEvent, InventoryItemState};
+use reactive_service_domain::non_empty_cart::{NonEmptyCart, Sku};
+use reactive_service_application::inventory::{cart_items, item_id};
+use crate::entity_host::EntityHost;
+use crate::order_service::{EventsJournal, OrderId, Versioned};
+
+pub use reactive_service_application::inventory::{Inventory, InventoryConfig, LocalInventory};
+
+/// The stock reserved at checkout by the `OrderService`, without blocking the runtime, e.g. the `InventoryService`.
+/// The same contract as the `Inventory` port, the one the fulfillment performs its steps with.
+pub trait AsyncInventory {
+    /// Reserve the items of the cart for the order, all or none. Reserving them again is a no-op.
+    fn reserve_stock(&self, order_id: OrderId, cart: &NonEmptyCart) -> impl Future<Output = Result<(), &'static str>> + Send;
+
+    /// Release the stock reserved for the order. Releasing it again, or a stock never reserved, is a no-op.
+    fn release_stock(&self, order_id: OrderId, cart: &NonEmptyCart) -> impl Future<Output = Result<(), &'static str>> + Send;
+
+    /// Take the stock reserved for the order out of the inventory. Shipping it again is a no-op.
+    fn ship_stock(&self, order_id: OrderId, cart: &NonEmptyCart) -> impl Future<Output = Result<(), &'static str>> + Send;
+}
+
+impl<I: AsyncInventory + Sync> AsyncInventory for &I {
+    fn reserve_stock(&self, order_id: OrderId, cart: &NonEmptyCart) -> impl Future<Output = Result<(), &'static str>> + Send {
+        (**self).reserve_stock(order_id, cart)
+    }
+
+    fn release_stock(&self, order_id: OrderId, cart: &NonEmptyCart) -> impl Future<Output = Result<(), &'static str>> + Send {
+        (**self).release_stock(order_id, cart)
+    }
+
+    fn ship_stock(&self, order_id: OrderId, cart: &NonEmptyCart) -> impl Future<Output = Result<(), &'static str>> + Send {
+        (**self).ship_stock(order_id, cart)
+    }
+}
+
+impl AsyncInventory for LocalInventory {
+    async fn reserve_stock(&self, order_id: OrderId, cart: &NonEmptyCart) -> Result<(), &'static str> {
+        Inventory::reserve_stock(self, order_id, cart)
+    }
+
+    async fn release_stock(&self, order_id: OrderId, cart: &NonEmptyCart) -> Result<(), &'static str> {
+        Inventory::release_stock(self, order_id, cart)
+    }
+
+    async fn ship_stock(&self, order_id: OrderId, cart: &NonEmptyCart) -> Result<(), &'static str> {
+        Inventory::ship_stock(self, order_id, cart)
+    }
+}
+
+/// The host of the `InventoryItem` entities, one per SKU, in a journal of their own.
+///
+/// The items of a cart are reserved one at a time, each under the lock of its entity: the orders of a hot SKU
+/// wait for each other, the other SKUs are reserved concurrently.
+pub struct InventoryService<J: EventsJournal<InventoryItemEvent>> {
+    items: EntityHost<InventoryItem, J>,
+    config: InventoryConfig,
+}
+
+impl<J: EventsJournal<InventoryItemEvent> + Sync> InventoryService<J> {
+    pub fn new(events_journal: J) -> Self {
+        Self::with_config(events_journal, InventoryConfig::default())
+    }
+
+    pub fn with_config(events_journal: J, config: InventoryConfig) -> Self {
+        Self { items: EntityHost::new(events_journal), config }
+    }
+
+    /// Add the units received to the stock of the SKU.
+    pub async fn receive_stock(&self, sku: &Sku, quantity: u32) -> Result<InventoryItemState, &'static str> {
+        let (state, _) = self.items.handle(item_id(sku), |_| Ok(InventoryItemCommand::Receive { quantity })).await?;
+        Ok(state)
+    }
+
+    /// The stock of the SKU, with its sequence number.
+    pub async fn get_stock(&self, sku: &Sku) -> Result<Versioned<InventoryItemState>, &'static str> {
+        self.items.query(item_id(sku)).await
+    }
+
+    async fn release_items(&self, order_id: OrderId, items: &[(i64, u32)]) -> Result<(), &'static str> {
+        for (item_id, _) in items {
+            self.items.handle(*item_id, |_| Ok(InventoryItemCommand::Release { order_id })).await?;
+        }
+        Ok(())
+    }
+}
+
+impl<J: EventsJournal<InventoryItemEvent> + Sync> AsyncInventory for InventoryService<J> {
+    /// On a SKU short of stock, the items reserved before it are released.
+    async fn reserve_stock(&self, order_id: OrderId, cart: &NonEmptyCart) -> Result<(), &'static str> {
+        let items = cart_items(cart);
+        let expires_at = SystemTime::now() + self.config.reservation_ttl;
+        for (index, (item_id, quantity)) in items.iter().enumerate() {
+            let reserve = InventoryItemCommand::Reserve { order_id, quantity: *quantity, expires_at, at: SystemTime::now() };
+            if let Err(err) = self.items.handle(*item_id, |_| Ok(reserve)).await {
+                // A release failing leaves its reservations to expire
+                let _ = self.release_items(order_id, &items[..index]).await;
+                return Err(err);
+            }
+        }
+        Ok(())
+    }
+
+    async fn release_stock(&self, order_id: OrderId, cart: &NonEmptyCart) -> Result<(), &'static str> {
+        self.release_items(order_id, &cart_items(cart)).await
+    }
+
+    async fn ship_stock(&self, order_id: OrderId, cart: &NonEmptyCart) -> Result<(), &'static str> {
+        for (item_id, _) in cart_items(cart) {
+            self.items.handle(item_id, |_| Ok(InventoryItemCommand::Ship { order_id, at: SystemTime::now() })).await?;
+        }
+        Ok(())
+    }
+}
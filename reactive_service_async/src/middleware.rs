@@ -0,0 +1,225 @@
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+use reactive_service_domain::order_entity::OrderEvent;
+use tokio::sync::Semaphore;
+use tokio::time::Instant;
+use reactive_service_application::command_errors::{COMMAND_TIMED_OUT, LIMIT_COMMANDS_FAILED, TOO_MANY_COMMANDS};
+use crate::inventory::AsyncInventory;
+use crate::order_service::{CommandResult, EventsJournal, OrderCommand, OrderService, Principal};
+use crate::payment_processor::PaymentProcessor;
+use crate::shipping_calculator::ShippingCalculator;
+use crate::tax_calculator::TaxCalculator;
+
+pub use reactive_service_application::middleware::{
+    CommandLatencies, CommandTrace, ConcurrencyLimitConfig, CommandTracer, LatencyHistogram, LogTracer, LATENCY_BUCKETS
+};
+
+/// Handles the order commands on behalf of a principal: the `OrderService`, or a middleware around another handler.
+/// Shared by the tasks issuing the commands.
+pub trait CommandHandler {
+    fn call(&self, principal: &Principal, command: OrderCommand) -> impl Future<Output = CommandResult> + Send;
+}
+
+impl<E, S, T, P, I> CommandHandler for OrderService<E, S, T, P, I>
+where
+    E: EventsJournal<OrderEvent> + Sync,
+    S: ShippingCalculator + Sync,
+    T: TaxCalculator + Sync,
+    P: PaymentProcessor + Sync,
+    I: AsyncInventory + Sync,
+{
+    async fn call(&self, principal: &Principal, command: OrderCommand) -> CommandResult {
+        self.handle_as(principal, command).await
+    }
+}
+
+impl<H: CommandHandler + Sync> CommandHandler for &H {
+    fn call(&self, principal: &Principal, command: OrderCommand) -> impl Future<Output = CommandResult> + Send {
+        (**self).call(principal, command)
+    }
+}
+
+impl<H: CommandHandler + Send + Sync> CommandHandler for Arc<H> {
+    fn call(&self, principal: &Principal, command: OrderCommand) -> impl Future<Output = CommandResult> + Send {
+        (**self).call(principal, command)
+    }
+}
+
+/// Wraps a `CommandHandler` into a middleware.
+pub trait Layer<H: CommandHandler> {
+    type Handler: CommandHandler;
+
+    fn layer(self, inner: H) -> Self::Handler;
+}
+
+/// A chain of middlewares around a `CommandHandler`, e.g. the `OrderService`. The last layer added is the outermost:
+/// the first to see a command, and the last to see its result.
+pub struct Pipeline<H: CommandHandler> {
+    handler: H,
+}
+
+impl<H: CommandHandler> Pipeline<H> {
+    pub fn new(handler: H) -> Self {
+        Self { handler }
+    }
+
+    /// Wrap the handlers added so far with the layer.
+    pub fn layer<L: Layer<H>>(self, layer: L) -> Pipeline<L::Handler> {
+        Pipeline { handler: layer.layer(self.handler) }
+    }
+
+    pub async fn handle_as(&self, principal: &Principal, command: OrderCommand) -> CommandResult {
+        self.handler.call(principal, command).await
+    }
+}
+
+impl<H: CommandHandler + Sync> CommandHandler for Pipeline<H> {
+    fn call(&self, principal: &Principal, command: OrderCommand) -> impl Future<Output = CommandResult> + Send {
+        self.handler.call(principal, command)
+    }
+}
+
+/// Traces every command once over, to the `CommandTracer`. A command cancelled, e.g. by an outer timeout, isn't traced.
+pub struct TraceLayer<C: CommandTracer> {
+    tracer: C,
+}
+
+impl<C: CommandTracer> TraceLayer<C> {
+    pub fn new(tracer: C) -> Self {
+        Self { tracer }
+    }
+}
+
+impl<H: CommandHandler + Sync, C: CommandTracer + Sync> Layer<H> for TraceLayer<C> {
+    type Handler = Traced<H, C>;
+
+    fn layer(self, inner: H) -> Self::Handler {
+        Traced { inner, tracer: self.tracer }
+    }
+}
+
+pub struct Traced<H: CommandHandler, C: CommandTracer> {
+    inner: H,
+    tracer: C,
+}
+
+impl<H: CommandHandler + Sync, C: CommandTracer + Sync> CommandHandler for Traced<H, C> {
+    async fn call(&self, principal: &Principal, command: OrderCommand) -> CommandResult {
+        let (order_id, name) = (command.order_id(), command.name());
+        let start_time = Instant::now();
+        let result = self.inner.call(principal, command).await;
+        self.tracer.on_command(&CommandTrace::new(order_id, name, principal, start_time.elapsed(), &result));
+        result
+    }
+}
+
+/// Records the latency of every command in the `CommandLatencies`, by kind of command.
+pub struct LatencyLayer {
+    latencies: Arc<CommandLatencies>,
+}
+
+impl LatencyLayer {
+    /// Record in the latencies, shared with their readers.
+    pub fn new(latencies: Arc<CommandLatencies>) -> Self {
+        Self { latencies }
+    }
+}
+
+impl<H: CommandHandler + Sync> Layer<H> for LatencyLayer {
+    type Handler = Timed<H>;
+
+    fn layer(self, inner: H) -> Self::Handler {
+        Timed { inner, latencies: self.latencies }
+    }
+}
+
+pub struct Timed<H: CommandHandler> {
+    inner: H,
+    latencies: Arc<CommandLatencies>,
+}
+
+impl<H: CommandHandler + Sync> CommandHandler for Timed<H> {
+    async fn call(&self, principal: &Principal, command: OrderCommand) -> CommandResult {
+        let name = command.name();
+        let start_time = Instant::now();
+        let result = self.inner.call(principal, command).await;
+        self.latencies.record(name, start_time.elapsed(), result.is_err());
+        result
+    }
+}
+
+/// Fails the commands not over within the timeout, cancelling them at their next await point.
+///
+/// The events of a command may be persisted before it is cancelled: a timeout doesn't mean the order is unchanged,
+/// the caller queries it to know. The entity is then restored from the journal on its next command.
+pub struct TimeoutLayer {
+    timeout: Duration,
+}
+
+impl TimeoutLayer {
+    pub fn new(timeout: Duration) -> Self {
+        Self { timeout }
+    }
+}
+
+impl<H: CommandHandler + Sync> Layer<H> for TimeoutLayer {
+    type Handler = WithTimeout<H>;
+
+    fn layer(self, inner: H) -> Self::Handler {
+        WithTimeout { inner, timeout: self.timeout }
+    }
+}
+
+pub struct WithTimeout<H: CommandHandler> {
+    inner: H,
+    timeout: Duration,
+}
+
+impl<H: CommandHandler + Sync> CommandHandler for WithTimeout<H> {
+    async fn call(&self, principal: &Principal, command: OrderCommand) -> CommandResult {
+        tokio::time::timeout(self.timeout, self.inner.call(principal, command))
+            .await
+            .map_err(|_| COMMAND_TIMED_OUT)?
+    }
+}
+
+/// Limits the commands handled at once, e.g. to the connections of the journal: the others wait for their turn,
+/// in the order they came. A command still waiting after `max_wait` fails, without any side effect.
+pub struct ConcurrencyLimitLayer {
+    config: ConcurrencyLimitConfig,
+}
+
+impl ConcurrencyLimitLayer {
+    pub fn new(max_in_flight: usize) -> Self {
+        Self::with_config(ConcurrencyLimitConfig { max_in_flight, ..ConcurrencyLimitConfig::default() })
+    }
+
+    pub fn with_config(config: ConcurrencyLimitConfig) -> Self {
+        Self { config }
+    }
+}
+
+impl<H: CommandHandler + Sync> Layer<H> for ConcurrencyLimitLayer {
+    type Handler = ConcurrencyLimited<H>;
+
+    fn layer(self, inner: H) -> Self::Handler {
+        ConcurrencyLimited { inner, permits: Semaphore::new(self.config.max_in_flight), max_wait: self.config.max_wait }
+    }
+}
+
+pub struct ConcurrencyLimited<H: CommandHandler> {
+    inner: H,
+    permits: Semaphore,
+    max_wait: Duration,
+}
+
+impl<H: CommandHandler + Sync> CommandHandler for ConcurrencyLimited<H> {
+    async fn call(&self, principal: &Principal, command: OrderCommand) -> CommandResult {
+        let _permit = tokio::time::timeout(self.max_wait, self.permits.acquire())
+            .await
+            .map_err(|_| TOO_MANY_COMMANDS)?
+            .map_err(|_| LIMIT_COMMANDS_FAILED)?;
+        self.inner.call(principal, command).await
+    }
+}
@@ -0,0 +1,185 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant, SystemTime};
+use reactive_service_domain::aggregate_root::AggregateRoot;
+use reactive_service_domain::order_entity::{OrderEntity, OrderEvent};
+use crate::catch_up::{CatchUpSubscription, CheckpointStore};
+use crate::entity_host::EntityHost;
+use crate::inventory::Inventory;
+use crate::order_service::{EventsJournal, GlobalEventsJournal, OrderId, Versioned};
+use crate::payment_processor::PaymentProcessor;
+use crate::shipment_service::ShipmentService;
+use tracing::{error, warn};
+
+pub use reactive_service_application::order_fulfillment::{
+    FulfillmentAction, FulfillmentCommand, FulfillmentConfig, FulfillmentEvent, FulfillmentOrder, FulfillmentRetries,
+    FulfillmentState, FulfillmentStatus, FulfillmentStep, OrderFulfillment, PendingStep, StepTimeouts, FULFILLMENT_CONSUMER
+};
+
+/// A concurrent call moved the fulfillment on while its action was performed.
+const MOVED_ON: &str = "Fulfillment moved on meanwhile";
+
+/// Follows the events of the orders, and fulfills each order once it is completed: hosts the `OrderFulfillment`
+/// process managers, persisted in a journal of their own, and performs their steps with the ports.
+///
+/// A fulfillment failing, e.g. on a compensation failing, is logged then retried with a backoff,
+/// while the orders completed after it are fulfilled. The checkpoint only moves past the completion of an order
+/// once its fulfillment is over: a fulfillment interrupted by a restart is resumed where it stopped
+/// when the completion is delivered again.
+pub struct FulfillmentManager<'a, J, F, C, I, P, S>
+where
+    J: GlobalEventsJournal<OrderEvent>,
+    F: EventsJournal<FulfillmentEvent>,
+    C: CheckpointStore,
+    I: Inventory,
+    P: PaymentProcessor,
+    S: ShipmentService,
+{
+    checkpoints: &'a C,
+    subscription: CatchUpSubscription<'a, OrderEvent, J>,
+    fulfillments: EntityHost<OrderFulfillment, F>,
+    retries: FulfillmentRetries,
+    orders: &'a J,
+    inventory: I,
+    payment_processor: P,
+    shipment_service: S,
+    config: FulfillmentConfig,
+}
+
+impl<'a, J, F, C, I, P, S> FulfillmentManager<'a, J, F, C, I, P, S>
+where
+    J: GlobalEventsJournal<OrderEvent>,
+    F: EventsJournal<FulfillmentEvent>,
+    C: CheckpointStore,
+    I: Inventory,
+    P: PaymentProcessor,
+    S: ShipmentService,
+{
+    /// Follow the orders from the checkpoint of the manager.
+    pub async fn new(orders: &'a J, checkpoints: &'a C, fulfillments_journal: F, inventory: I, payment_processor: P,
+               shipment_service: S, config: FulfillmentConfig) -> Result<Self, &'static str> {
+
+        let subscription = CatchUpSubscription::from_checkpoint(orders, checkpoints, FULFILLMENT_CONSUMER, config.catch_up.clone()).await?;
+        Ok(Self {
+            checkpoints,
+            subscription,
+            fulfillments: EntityHost::new(fulfillments_journal),
+            retries: FulfillmentRetries::default(),
+            orders,
+            inventory,
+            payment_processor,
+            shipment_service,
+            config,
+        })
+    }
+
+    /// Start the fulfillment of the completed order, if needed, then move it on until it is over.
+    /// The actions are performed with the ports out of the lock of the fulfillment, then recorded under it.
+    pub async fn fulfill(&self, order_id: OrderId) -> Result<FulfillmentState, &'static str> {
+        if self.fulfillments.query(order_id).await?.state.status == FulfillmentStatus::NotStarted {
+            let mut order = OrderEntity::default();
+            order.restore_from_events(self.orders.retrieve_events(order_id).await?)?;
+            let start = FulfillmentCommand::start(order.get_state(), self.config.step_timeouts.clone(), SystemTime::now())?;
+            self.fulfillments.handle(order_id, |_| Ok(start)).await?;
+        }
+
+        loop {
+            let Versioned { state, sequence_number } = self.fulfillments.query(order_id).await?;
+            let Some(action) = state.next_action(SystemTime::now()) else { return Ok(state) };
+            let command = action.perform(order_id, &state, &self.inventory, &self.payment_processor, &self.shipment_service)?;
+            let recorded = self.fulfillments.handle(order_id, |fulfillment| {
+                if fulfillment.get_sequence_number() != sequence_number {
+                    return Err(MOVED_ON);
+                }
+                Ok(command)
+            }).await;
+            match recorded {
+                // Moved on: the next action is decided again, the ports are idempotent
+                Ok(_) | Err(MOVED_ON) => {},
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    /// The fulfillment of the order, with its sequence number.
+    pub async fn get_fulfillment(&self, order_id: OrderId) -> Result<Versioned<FulfillmentState>, &'static str> {
+        self.fulfillments.query(order_id).await
+    }
+
+    /// Fulfill the orders completed so far. Returns the number of events processed.
+    pub async fn catch_up(&mut self) -> Result<usize, &'static str> {
+        let mut processed = 0;
+        loop {
+            match self.process(Duration::ZERO).await? {
+                0 => return Ok(processed),
+                count => processed += count,
+            }
+        }
+    }
+
+    /// Retry the failed fulfillments due, then fulfill the orders completed in the next batch of events,
+    /// once there is one within `timeout`, and save the checkpoint. Returns the number of events in the batch.
+    pub async fn process(&mut self, timeout: Duration) -> Result<usize, &'static str> {
+        if self.retry_due().await {
+            self.save_checkpoint().await?;
+        }
+        let Some(first) = self.subscription.recv_timeout(timeout).await? else { return Ok(0) };
+        let mut batch = vec![first];
+        while batch.len() < self.config.catch_up.batch_size {
+            match self.subscription.recv_timeout(Duration::ZERO).await {
+                Ok(Some(positioned)) => batch.push(positioned),
+                // The events not read yet are read on the next call
+                Ok(None) | Err(_) => break,
+            }
+        }
+
+        for positioned in &batch {
+            if let OrderEvent::Completed { .. } = positioned.event.event {
+                if let Err(err) = self.fulfill(positioned.entity_id).await {
+                    self.failed(positioned.position, positioned.entity_id, err);
+                }
+            }
+        }
+        self.save_checkpoint().await?;
+        Ok(batch.len())
+    }
+
+    /// Number of the fulfillments failed, waiting to be retried.
+    pub fn failed_fulfillments(&self) -> usize {
+        self.retries.len()
+    }
+
+    fn failed(&mut self, position: i64, order_id: OrderId, err: &'static str) {
+        let failures = self.retries.failed(position, order_id, Instant::now(), &self.config);
+        warn!(order_id, error = err, failures, "fulfillment failed, it will be retried");
+    }
+
+    /// Retry the failed fulfillments due. Returns whether one is over.
+    async fn retry_due(&mut self) -> bool {
+        let mut over = false;
+        for (position, order_id) in self.retries.due(Instant::now()) {
+            match self.fulfill(order_id).await {
+                Ok(_) => {
+                    self.retries.over(position);
+                    over = true;
+                },
+                Err(err) => self.failed(position, order_id, err),
+            }
+        }
+        over
+    }
+
+    async fn save_checkpoint(&self) -> Result<(), &'static str> {
+        self.checkpoints.save_checkpoint(FULFILLMENT_CONSUMER, self.retries.checkpoint(self.subscription.position())).await
+    }
+
+    /// Fulfill the orders as they complete until `stop` is set, e.g. from a task of its own.
+    /// The errors of the journals are logged, then the orders followed again after the retry delay.
+    pub async fn run(&mut self, stop: &AtomicBool) {
+        while !stop.load(Ordering::Relaxed) {
+            if let Err(err) = self.process(self.config.catch_up.max_wait).await {
+                error!(error = err, "failed to follow the completed orders");
+                tokio::time::sleep(self.config.retry_delay).await;
+            }
+        }
+    }
+}
@@ -1,38 +1,68 @@
-use std::collections::HashMap;
-use std::time::Instant;
+use std::sync::Arc;
+use std::time::Duration;
 use reactive_service_domain::aggregate_root::{AggregateRoot, SequencedEvent};
-use reactive_service_domain::non_empty_cart::NonEmptyCart;
-use reactive_service_domain::order_entity::{OrderEntity, OrderEntityCommand, OrderEvent};
-use reactive_service_domain::order_state::{DeliveryAddress, Money, OrderState};
-use tokio::sync::{Mutex, RwLock};
-use crate::payment_processor::{PaymentProcessor, PaymentToken};
+use reactive_service_domain::order_entity::{OrderEntity, OrderEvent};
+use crate::entity_host::EntityHost;
+use crate::event_bus::EventBus;
+use crate::inventory::{AsyncInventory, LocalInventory};
+use crate::payment_processor::PaymentProcessor;
+use tokio::time::Instant;
+use tracing::{info_span, Instrument};
+
+pub use reactive_service_application::order_commands::{
+    CommandResult, ExpireOrder, OrderCommand, OrderId, PayOrder, UpdateCart, UpdateDeliveryAddress
+};
+pub use reactive_service_application::authorization::Principal;
+pub use reactive_service_application::metrics::{MetricsRegistry, NoMetrics, PrometheusRegistry};
+pub use reactive_service_application::order_queries::{OrderView, QueryResult, Versioned};
+pub use reactive_service_application::subscriptions::{Committed, Positioned, SubscriptionError};
+pub use reactive_service_application::shipping_calculator::ShippingCalculator;
+pub use reactive_service_application::tax_calculator::TaxCalculator;
 
 pub trait EventsJournal<Event> {
     fn persist_event(&self, entity_id: OrderId, evt_w_seq: &SequencedEvent<Event>) -> impl std::future::Future<Output = Result<(), &'static str>> + Send;
     fn retrieve_events(&self, entity_id: OrderId) -> impl std::future::Future<Output = Result<Vec<SequencedEvent<Event>>, &'static str>> + Send;
-}
 
-pub trait ShippingCalculator {
-    fn shipping_cost(&self, cart: &NonEmptyCart, delivery_address: &DeliveryAddress) -> Money;
+    /// Persist the events of several entities with one call, all or nothing:
+    /// if it fails, none of the events is persisted, so the caller may retry any of them.
+    fn persist_events(&self, events: &[(OrderId, SequencedEvent<Event>)]) -> impl std::future::Future<Output = Result<(), &'static str>> + Send
+    where
+        Self: Sync,
+        Event: Sync;
 }
 
-pub trait TaxCalculator {
-    fn tax_cost(&self, cart: &NonEmptyCart, shipping_cost: &Money) -> Money;
-}
+/// A journal keeping the global order of its events, across the entities: each persisted event gets the next position.
+/// A position is only readable once the lower ones are, a reader following the positions never skips an event.
+pub trait GlobalEventsJournal<Event>: EventsJournal<Event> {
+    /// Up to `batch_size` events, by position, from `from_position` included.
+    fn read_all(&self, from_position: i64, batch_size: usize) -> impl std::future::Future<Output = Result<Vec<Positioned<Event>>, &'static str>> + Send;
 
-type OrderId = i64;
+    /// Position of the last persisted event, 0 when there is none.
+    fn last_position(&self) -> impl std::future::Future<Output = Result<i64, &'static str>> + Send;
 
+    /// Wait until an event may be readable at `position`, or for `timeout`.
+    /// It may return early: the caller reads again to know.
+    fn wait_for_position(&self, position: i64, timeout: Duration) -> impl std::future::Future<Output = Result<(), &'static str>> + Send;
+}
+
+/// The host of the `OrderEntity`, with the ports needed to turn an `OrderCommand` into an entity command.
+/// The stock of an order is reserved in the `AsyncInventory` when it is paid for, without any inventory by default.
+///
+/// Each command is traced in a `command` span, with the `order_id` and the `command` name,
+/// and its latency and errors are reported to the `MetricsRegistry`, none by default.
 pub struct OrderService<
     E: EventsJournal<OrderEvent>,
     S: ShippingCalculator,
     T: TaxCalculator,
-    P: PaymentProcessor
+    P: PaymentProcessor,
+    I: AsyncInventory = LocalInventory
 > {
-    orders: RwLock<HashMap<OrderId, Mutex<OrderEntity>>>,
-    events_journal: E,
+    orders: EntityHost<OrderEntity, E>,
     shipping_calculator: S,
     tax_calculator: T,
-    payment_processor: P
+    payment_processor: P,
+    inventory: I,
+    metrics: Arc<dyn MetricsRegistry + Send + Sync>,
 }
 
 impl <E, S, T, P> OrderService<E, S, T, P>
@@ -44,153 +74,117 @@ where
 {
 
     pub fn new(events_journal: E, shipping_calculator: S, tax_calculator: T, payment_processor: P) -> Self {
+        Self::with_inventory(events_journal, shipping_calculator, tax_calculator, payment_processor, LocalInventory{})
+    }
+}
+
+impl <E, S, T, P, I> OrderService<E, S, T, P, I>
+where
+    E: EventsJournal<OrderEvent>,
+    S: ShippingCalculator,
+    T: TaxCalculator,
+    P: PaymentProcessor,
+    I: AsyncInventory
+{
+
+    pub fn with_inventory(events_journal: E, shipping_calculator: S, tax_calculator: T, payment_processor: P, inventory: I) -> Self {
         Self {
-            orders: RwLock::new(HashMap::default()),
-            events_journal,
+            orders: EntityHost::new(events_journal),
             shipping_calculator,
             tax_calculator,
-            payment_processor
+            payment_processor,
+            inventory,
+            metrics: Arc::new(NoMetrics),
         }
     }
 
-    async fn create_and_process_entity_command<F>(&self, entity_id: OrderId, create_command: F)
-      -> Result<(OrderState, Vec<SequencedEvent<OrderEvent>>), &'static str>
-    where
-        F: FnOnce(&mut OrderEntity, &S, &T, &P) -> Result<OrderEntityCommand, &'static str>,
-    {
-        // let start_time = Instant::now();
-
-        {
-            // Check if the aggregate is already in the map without locking (read-only access)
-            let read_lock = self.orders.read().await;
-            if !read_lock.contains_key(&entity_id) {
-                // Only if entity does not exist we will need to lock (write access) the hashmap.
-                drop(read_lock); // Drop the read lock, allowing to have a write lock later.
-
-                // Create the entity from events
-                let events = self.events_journal.retrieve_events(entity_id).await?;
-                let mut entity = OrderEntity::default();
-                let _ = entity.restore_from_events(events)?;
-
-                // This is the only place we lock the Hashmap, just the time of the insert,
-                // minimal contention only when we access an entity not in memory yet
-                let mut write_lock = self.orders.write().await;
-                write_lock.entry(entity_id).or_insert_with(|| Mutex::new(entity));
-            }
-        } // At that point we know the entity is present in the map, we also drop the read_lock
-
-        // println!("Retrieve entity => {:?} microseconds", start_time.elapsed().as_micros());
+    /// Report the metrics of the commands, of the journal and of the orders in memory to the registry.
+    pub fn with_metrics(self, metrics: Arc<dyn MetricsRegistry + Send + Sync>) -> Self {
+        Self { orders: self.orders.with_metrics(metrics.clone()), metrics, ..self }
+    }
 
-        let read_lock = self.orders.read().await;
-        // Now, we'll lock the entity for the time needed to handle and apply the command.
-        let entity_mutex = read_lock.get(&entity_id).ok_or("Can't retrieve the entity")?;
-        let mut order = entity_mutex.lock().await;
+    /// Handle any order command as an admin, for the trusted callers: the scheduler, the fulfillment, the tools.
+    pub async fn handle_as_admin(&self, command: OrderCommand) -> CommandResult {
+        self.handle_as(&Principal::Admin, command).await
+    }
 
-        let entity_command: OrderEntityCommand = create_command(
-            &mut order, &self.shipping_calculator, &self.tax_calculator, &self.payment_processor
-        )?;
+    /// Handle any order command on behalf of the principal: restore the entity if needed, then lock it for the time of the command.
+    /// At checkout, the principal is authorized and the stock reserved first, out of the lock:
+    /// the command then fails if the order changed meanwhile. If the order isn't completed, the reservation expires.
+    pub async fn handle_as(&self, principal: &Principal, command: OrderCommand) -> CommandResult {
+        let (order_id, name) = (command.order_id(), command.name());
+        let start_time = Instant::now();
+        let result = self.checkout_and_handle(principal, command)
+            .instrument(info_span!("command", order_id, command = name))
+            .await;
+        self.metrics.observe_command(name, start_time.elapsed(), result.as_ref().err().copied());
+        result
+    }
 
-        let (state, events) = order.handle_command(entity_command)?;
-        // println!("Process command => {:?} microseconds", start_time.elapsed().as_micros());
-        for evt in &events {
-            self.events_journal.persist_event(entity_id, evt).await?;
+    async fn checkout_and_handle(&self, principal: &Principal, command: OrderCommand) -> CommandResult {
+        let order_id = command.order_id();
+        let mut checked_out = None;
+        if let OrderCommand::PayOrder(_) = command {
+            let checkout = self.orders.inspect(order_id, |order| {
+                principal.authorize_order(order).map(|_| {
+                    command.checkout_cart(order.get_state()).cloned().map(|cart| (cart, order.get_sequence_number()))
+                })
+            }).await??;
+            if let Some((cart, sequence_number)) = checkout {
+                self.inventory.reserve_stock(order_id, &cart).await?;
+                checked_out = Some(sequence_number);
+            }
         }
-        // println!("Persist events  => {:?} microseconds", start_time.elapsed().as_micros());
-        Ok((state.clone(), events)) // We return the result and entity_mutex is dropped
+        self.orders.handle(order_id, |order| {
+            if checked_out.is_some_and(|sequence_number| sequence_number != order.get_sequence_number()) {
+                return Err("Order changed during checkout");
+            }
+            command.entity_command(principal, order, &self.shipping_calculator, &self.tax_calculator, &self.payment_processor)
+        }).await
     }
 
-    pub async fn update_cart(&self, cmd: UpdateCart)
-        -> Result<(OrderState, Vec<SequencedEvent<OrderEvent>>), &'static str> {
-
-        let update_cart_command_builder =
-            |order_entity: &mut OrderEntity, shipping_calculator: &S, tax_calculator: &T, _: &P| -> Result<OrderEntityCommand, &'static str> {
-
-                let cart = cmd.cart;
-                match order_entity.get_state() {
-
-                    OrderState::Empty(_) | OrderState::WithCart(_) =>
-                        Ok(OrderEntityCommand::AddCart{cart}),
-
-                    OrderState::WithAddress(with_addr) => {
-                        let shipping_cost = shipping_calculator.shipping_cost(&cart, with_addr.get_delivery_address());
-                        let tax: Money = tax_calculator.tax_cost(&cart, &shipping_cost);
-                        Ok(OrderEntityCommand::UpdateCart {cart, shipping_cost, tax})
-                    },
-
-                    OrderState::Completed(_) => Err("Can't update the cart on a completed order."),
-                }
-            };
-
-        self.create_and_process_entity_command(cmd.order_id, update_cart_command_builder).await
+    pub async fn update_cart(&self, principal: &Principal, cmd: UpdateCart) -> CommandResult {
+        self.handle_as(principal, OrderCommand::UpdateCart(cmd)).await
     }
 
-
-
-    pub async fn update_delivery_address(&self, cmd: UpdateDeliveryAddress)
-       -> Result<(OrderState, Vec<SequencedEvent<OrderEvent>>), &'static str> {
-
-        let update_addr_command_builder =
-            |order_entity: &mut OrderEntity, shipping_calculator: &S, tax_calculator: &T, _: &P|
-             -> Result<OrderEntityCommand, &'static str> {
-
-                let delivery_address = cmd.delivery_address;
-                match order_entity.get_state() {
-
-                    OrderState::Empty(_) => Err("Can't add address to an empty cart."),
-
-                    OrderState::WithCart(with_cart) => {
-                        let cart = with_cart.get_cart();
-                        let shipping_cost = shipping_calculator.shipping_cost(cart, &delivery_address);
-                        let tax: Money = tax_calculator.tax_cost(cart, &shipping_cost);
-                        Ok(OrderEntityCommand::UpdateDeliveryAddress { delivery_address, shipping_cost, tax })
-                    },
-
-                    OrderState::WithAddress(with_addr) => {
-                        let cart = with_addr.get_cart();
-                        let shipping_cost = shipping_calculator.shipping_cost(cart, &delivery_address);
-                        let tax: Money = tax_calculator.tax_cost(cart, &shipping_cost);
-                        Ok(OrderEntityCommand::UpdateDeliveryAddress { delivery_address, shipping_cost, tax })
-                    },
-
-                    OrderState::Completed(_) => Err("Can't update address on a completed order."),
-                }
-            };
-
-        self.create_and_process_entity_command(cmd.order_id, update_addr_command_builder).await
+    pub async fn update_delivery_address(&self, principal: &Principal, cmd: UpdateDeliveryAddress) -> CommandResult {
+        self.handle_as(principal, OrderCommand::UpdateDeliveryAddress(cmd)).await
     }
 
+    pub async fn pay_order(&self, principal: &Principal, cmd: PayOrder) -> CommandResult {
+        self.handle_as(principal, OrderCommand::PayOrder(cmd)).await
+    }
 
-    pub async fn pay_order(&self, cmd: PayOrder)
-       -> Result<(OrderState, Vec<SequencedEvent<OrderEvent>>), &'static str> {
-
-        let pay_order_command_builder =
-            |order_entity: &mut OrderEntity, _: &S, _: &T, payment_processor: &P|
-             -> Result<OrderEntityCommand, &'static str> {
-
-                let payment_token = cmd.payment_token;
-                match order_entity.get_state() {
+    /// The current state of the order, if the principal may read it: a customer only reads the orders they own.
+    pub async fn get_order_as(&self, principal: &Principal, order_id: OrderId) -> QueryResult {
+        self.orders.inspect(order_id, |order| principal.authorize_order(order)).await??;
+        self.orders.query(order_id).await
+    }
 
-                    OrderState::Empty(_) | OrderState::WithCart(_) =>
-                        Err("Order not ready to be paid."),
+    /// The current state of the order, with its sequence number. Doesn't wait for the commands in progress.
+    pub async fn get_order(&self, order_id: OrderId) -> QueryResult {
+        self.orders.query(order_id).await
+    }
 
-                    OrderState::WithAddress(_) => {
-                        let invoice = payment_processor.pay_with_token(payment_token);
-                        Ok(OrderEntityCommand::Complete{invoice})
-                    },
+    /// The state of the order, once its sequence number is at least `sequence_number`.
+    /// Fails if the order doesn't reach this version within `timeout`.
+    pub async fn get_order_at_least(&self, order_id: OrderId, sequence_number: i64, timeout: Duration) -> QueryResult {
+        self.orders.query_at_least(order_id, sequence_number, timeout).await
+    }
 
-                    OrderState::Completed(_) => Err("Order is already paid."),
-                }
-            };
+    /// The events of the orders, published once persisted.
+    /// Subscribe to the events of every order with `subscribe_all`, or of a single one with `subscribe`.
+    pub fn event_bus(&self) -> &EventBus<OrderEvent> {
+        self.orders.event_bus()
+    }
 
-        self.create_and_process_entity_command(cmd.order_id, pay_order_command_builder).await
+    /// The stock reserved for the orders.
+    pub fn inventory(&self) -> &I {
+        &self.inventory
     }
 
+    /// The journal of the orders, e.g. to follow their events from a position with a `CatchUpSubscription`.
+    pub fn events_journal(&self) -> &E {
+        self.orders.events_journal()
+    }
 }
-
-#[derive(Debug, Clone)]
-pub struct UpdateCart{pub order_id: OrderId, pub cart: NonEmptyCart}
-#[derive(Debug)]
-pub struct UpdateDeliveryAddress{pub order_id: OrderId, pub delivery_address: DeliveryAddress}
-#[derive(Debug)]
-pub struct PayOrder{pub order_id: OrderId, pub payment_token: PaymentToken}
-
@@ -0,0 +1,70 @@
+use std::fs::{File, OpenOptions};
+use std::future::Future;
+use std::io::{Stdout, Write};
+use std::path::Path;
+use std::sync::Mutex;
+use tokio::sync::mpsc;
+
+pub use reactive_service_application::outbox::{OutboxMessage, OutboxRelayConfig, ParkedMessage};
+
+/// Where the outbox relay publishes the events to other services, e.g. a message broker.
+///
+/// The delivery is at least once: a message is published again when the relay fails before recording it is.
+/// The consumers deduplicate the messages on their position.
+pub trait MessagePublisher {
+    /// Publish the message, once it is durably received: an error is retried with a backoff.
+    fn publish(&self, message: &OutboxMessage) -> impl Future<Output = Result<(), &'static str>> + Send;
+}
+
+/// Publishes to the consumers of the same process, through a channel.
+pub struct InProcessPublisher {
+    sender: mpsc::UnboundedSender<OutboxMessage>,
+}
+
+impl InProcessPublisher {
+    /// The publisher, and the receiver of its messages.
+    pub fn new() -> (Self, mpsc::UnboundedReceiver<OutboxMessage>) {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        (Self { sender }, receiver)
+    }
+}
+
+impl MessagePublisher for InProcessPublisher {
+    async fn publish(&self, message: &OutboxMessage) -> Result<(), &'static str> {
+        self.sender.send(message.clone()).map_err(|_| "Failed to publish message")
+    }
+}
+
+/// Publishes each message as a line of JSON, flushed before the message is acknowledged.
+///
+/// The writes are blocking, but short: a line at a time.
+pub struct JsonLinesPublisher<W: Write> {
+    writer: Mutex<W>,
+}
+
+impl<W: Write> JsonLinesPublisher<W> {
+    pub fn new(writer: W) -> Self {
+        Self { writer: Mutex::new(writer) }
+    }
+}
+
+impl JsonLinesPublisher<Stdout> {
+    pub fn stdout() -> Self {
+        Self::new(std::io::stdout())
+    }
+}
+
+impl JsonLinesPublisher<File> {
+    /// Append the messages to the file, created if needed.
+    pub fn file(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        Ok(Self::new(OpenOptions::new().create(true).append(true).open(path)?))
+    }
+}
+
+impl<W: Write + Send> MessagePublisher for JsonLinesPublisher<W> {
+    async fn publish(&self, message: &OutboxMessage) -> Result<(), &'static str> {
+        let mut writer = self.writer.lock().map_err(|_| "Failed to publish message")?;
+        writeln!(writer, "{}", message.to_json_line()).map_err(|_| "Failed to publish message")?;
+        writer.flush().map_err(|_| "Failed to publish message")
+    }
+}
@@ -1,17 +1 @@
-use reactive_service_domain::order_state::Invoice;
-
-#[derive(Debug, Clone)]
-pub struct PaymentToken(String);
-
-pub trait PaymentProcessor {
-    fn pay_with_token(&self, payment_token: PaymentToken) -> Invoice;
-}
-
-pub struct LocalPaymentProcessor {}
-
-impl PaymentProcessor for LocalPaymentProcessor {
-    fn pay_with_token(&self, payment_token: PaymentToken) -> Invoice {
-        let _ = payment_token.0;
-        Invoice{}
-    }
-}
\ No newline at end of file
+pub use reactive_service_application::payment_processor::{LocalPaymentProcessor, PaymentProcessor, PaymentToken};
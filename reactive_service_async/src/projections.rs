@@ -0,0 +1,133 @@
+use std::future::Future;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use reactive_service_domain::order_entity::OrderEvent;
+use reactive_service_application::command_errors::{APPLY_EVENTS_FAILED, LOAD_CHECKPOINT_FAILED};
+use crate::catch_up::{CatchUpConfig, CatchUpSubscription};
+use crate::order_service::{GlobalEventsJournal, Positioned};
+
+pub use reactive_service_application::projections::{
+    CheckpointedModel, OrderStatus, OrderSummaries, OrderSummary, ReadModel, SalesBySku, SkuSales
+};
+
+/// A view of the events of the journal, kept up to date by a `Projector`.
+///
+/// The view is stored with its checkpoint, the position of the last event applied to it:
+/// a batch of events and the checkpoint are saved together, all or nothing.
+/// The events at or before the checkpoint are skipped: an event delivered again, e.g. after a failure, is applied once.
+pub trait Projection<E> {
+    /// Position of the last event applied to the view, 0 when it is empty.
+    fn checkpoint(&self) -> impl Future<Output = Result<i64, &'static str>> + Send;
+
+    /// Apply the events after the checkpoint, in their order, and move the checkpoint to the last one.
+    fn apply(&self, events: &[Positioned<E>]) -> impl Future<Output = Result<(), &'static str>> + Send;
+
+    /// Empty the view and its checkpoint, to rebuild it from the start of the journal.
+    fn reset(&self) -> impl Future<Output = Result<(), &'static str>> + Send;
+}
+
+/// A `ReadModel` kept in memory, with its checkpoint.
+/// The lock is never held across an await point, so a blocking lock is fine.
+pub struct InMemoryProjection<M> {
+    view: Mutex<CheckpointedModel<M>>,
+}
+
+impl<M: ReadModel> InMemoryProjection<M> {
+    pub fn new() -> Self {
+        Self { view: Mutex::new(CheckpointedModel::default()) }
+    }
+
+    /// Query the view, as of its checkpoint.
+    pub fn read<R>(&self, query: impl FnOnce(&M) -> R) -> Result<R, &'static str> {
+        let view = self.view.lock().map_err(|_| "Failed to query projection")?;
+        Ok(query(view.model()))
+    }
+}
+
+impl<M: ReadModel> Default for InMemoryProjection<M> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<M: ReadModel + Send> Projection<OrderEvent> for InMemoryProjection<M> {
+    async fn checkpoint(&self) -> Result<i64, &'static str> {
+        Ok(self.view.lock().map_err(|_| LOAD_CHECKPOINT_FAILED)?.checkpoint())
+    }
+
+    async fn apply(&self, events: &[Positioned<OrderEvent>]) -> Result<(), &'static str> {
+        self.view.lock().map_err(|_| APPLY_EVENTS_FAILED)?.apply(events);
+        Ok(())
+    }
+
+    async fn reset(&self) -> Result<(), &'static str> {
+        let mut view = self.view.lock().map_err(|_| "Failed to reset projection")?;
+        *view = CheckpointedModel::default();
+        Ok(())
+    }
+}
+
+/// Applies the events of a journal to a projection, by batches, from its checkpoint:
+/// the history first, then the new events as they are persisted.
+pub struct Projector<'a, E, J: GlobalEventsJournal<E>, P: Projection<E>> {
+    journal: &'a J,
+    projection: &'a P,
+    config: CatchUpConfig,
+    subscription: CatchUpSubscription<'a, E, J>,
+}
+
+impl<'a, E, J: GlobalEventsJournal<E>, P: Projection<E>> Projector<'a, E, J, P> {
+    /// Follow the journal from the checkpoint of the projection.
+    /// The batches are up to `config.batch_size` events, each one applied at once.
+    pub async fn new(journal: &'a J, projection: &'a P, config: CatchUpConfig) -> Result<Self, &'static str> {
+        let subscription = CatchUpSubscription::with_config(journal, projection.checkpoint().await?, config.clone());
+        Ok(Self { journal, projection, config, subscription })
+    }
+
+    /// Empty the view, then apply every event of the journal again. Returns the number of events applied.
+    pub async fn rebuild(&mut self) -> Result<usize, &'static str> {
+        self.projection.reset().await?;
+        self.subscription = CatchUpSubscription::with_config(self.journal, 0, self.config.clone());
+        self.catch_up().await
+    }
+
+    /// Apply the events persisted so far. Returns their number.
+    pub async fn catch_up(&mut self) -> Result<usize, &'static str> {
+        let mut applied = 0;
+        loop {
+            match self.project(Duration::ZERO).await? {
+                0 => return Ok(applied),
+                count => applied += count,
+            }
+        }
+    }
+
+    /// Apply the next batch of events, once there is one within `timeout`. Returns the number of events in the batch.
+    pub async fn project(&mut self, timeout: Duration) -> Result<usize, &'static str> {
+        let Some(first) = self.subscription.recv_timeout(timeout).await? else { return Ok(0) };
+        let first_position = first.position;
+        let mut batch = vec![first];
+        while batch.len() < self.config.batch_size {
+            match self.subscription.recv_timeout(Duration::ZERO).await? {
+                Some(positioned) => batch.push(positioned),
+                None => break,
+            }
+        }
+
+        if let Err(err) = self.projection.apply(&batch).await {
+            // Delivered again on the next call
+            self.subscription = CatchUpSubscription::with_config(self.journal, first_position - 1, self.config.clone());
+            return Err(err);
+        }
+        Ok(batch.len())
+    }
+
+    /// Keep the view up to date until `stop` is set, e.g. from a task of its own.
+    pub async fn run(&mut self, stop: &AtomicBool) -> Result<(), &'static str> {
+        while !stop.load(Ordering::Relaxed) {
+            self.project(self.config.max_wait).await?;
+        }
+        Ok(())
+    }
+}
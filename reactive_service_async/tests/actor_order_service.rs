@@ -0,0 +1,148 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::time::Duration;
+    use tokio::sync::RwLock;
+
+    use reactive_service_domain::aggregate_root::SequencedEvent;
+    use reactive_service_domain::non_empty_cart::Sku;
+    use reactive_service_domain::order_entity::OrderEvent;
+    use reactive_service_domain::order_state::{DeliveryAddress, OrderState, Street};
+    use reactive_service_async::actor_order_service::{ActorOrderService, ActorOrderServiceConfig};
+    use reactive_service_async::infra::inmem_journal::InMemoryJournal;
+    use reactive_service_async::order_service::{EventsJournal, OrderId, Principal, UpdateDeliveryAddress};
+    use reactive_service_async::payment_processor::LocalPaymentProcessor;
+    use reactive_service_async::shipping_calculator::LocalShippingCalculator;
+    use reactive_service_async::tax_calculator::LocalTaxCalculator;
+    use crate::common::update_cart_of;
+
+    const HELD_ORDER: OrderId = 1;
+
+    /// An in-memory journal counting the restores, which can crash on a persist, or hold the persists of `HELD_ORDER`.
+    #[derive(Clone)]
+    struct ProbedJournal {
+        journal: Arc<InMemoryJournal<OrderEvent>>,
+        retrievals: Arc<AtomicUsize>,
+        crash_next_persist: Arc<AtomicBool>,
+        // The persists of HELD_ORDER wait while the test holds the write lock
+        gate: Arc<RwLock<()>>,
+    }
+
+    impl ProbedJournal {
+        fn new() -> Self {
+            Self {
+                journal: Arc::new(InMemoryJournal::new().unwrap()),
+                retrievals: Arc::default(),
+                crash_next_persist: Arc::default(),
+                gate: Arc::default(),
+            }
+        }
+    }
+
+    impl ProbedJournal {
+        async fn probe(&self, mut entity_ids: impl Iterator<Item = OrderId>) {
+            if entity_ids.any(|entity_id| entity_id == HELD_ORDER) {
+                let _open = self.gate.read().await;
+            }
+            if self.crash_next_persist.swap(false, Ordering::SeqCst) {
+                panic!("Journal crashed");
+            }
+        }
+    }
+
+    impl EventsJournal<OrderEvent> for ProbedJournal {
+        async fn persist_event(&self, entity_id: OrderId, evt_w_seq: &SequencedEvent<OrderEvent>) -> Result<(), &'static str> {
+            self.probe(std::iter::once(entity_id)).await;
+            self.journal.persist_event(entity_id, evt_w_seq).await
+        }
+
+        async fn retrieve_events(&self, entity_id: OrderId) -> Result<Vec<SequencedEvent<OrderEvent>>, &'static str> {
+            self.retrievals.fetch_add(1, Ordering::SeqCst);
+            self.journal.retrieve_events(entity_id).await
+        }
+
+        async fn persist_events(&self, events: &[(OrderId, SequencedEvent<OrderEvent>)]) -> Result<(), &'static str> {
+            self.probe(events.iter().map(|(entity_id, _)| *entity_id)).await;
+            self.journal.persist_events(events).await
+        }
+    }
+
+    fn service(journal: &ProbedJournal, config: ActorOrderServiceConfig) -> ActorOrderService {
+        ActorOrderService::with_config(journal.clone(), LocalShippingCalculator{}, LocalTaxCalculator{}, LocalPaymentProcessor{}, config)
+    }
+
+    fn update_delivery_address(order_id: i64) -> UpdateDeliveryAddress {
+        let delivery_address = DeliveryAddress { street: Street("1 Main Street".to_owned()), postal_code: "H0H 0H0".parse().unwrap() };
+        UpdateDeliveryAddress { order_id, delivery_address }
+    }
+
+    fn quantity(state: &OrderState) -> u16 {
+        let OrderState::WithCart(order) = state else { panic!("Not with a cart: {:?}", state) };
+        order.get_cart().get_items()[&Sku("apple".to_owned())].0
+    }
+
+    #[tokio::test]
+    async fn passivates_an_idle_order_then_restores_it() {
+        let journal = ProbedJournal::new();
+        let config = ActorOrderServiceConfig { passivate_after: Duration::from_millis(50), ..Default::default() };
+        let service = service(&journal, config);
+
+        service.update_cart(&Principal::Admin, update_cart_of(2, 1)).await.unwrap();
+        service.update_cart(&Principal::Admin, update_cart_of(2, 2)).await.unwrap();
+        assert_eq!(journal.retrievals.load(Ordering::SeqCst), 1);
+
+        tokio::time::sleep(Duration::from_millis(300)).await;
+
+        let (state, events) = service.update_delivery_address(&Principal::Admin, update_delivery_address(2)).await.unwrap();
+        assert_eq!(journal.retrievals.load(Ordering::SeqCst), 2);
+        assert_eq!(events[0].sequence_number, 3);
+        assert!(matches!(state, OrderState::WithAddress(_)));
+    }
+
+    #[tokio::test]
+    async fn restores_a_crashed_order_from_the_journal() {
+        let journal = ProbedJournal::new();
+        let service = service(&journal, ActorOrderServiceConfig::default());
+        service.update_cart(&Principal::Admin, update_cart_of(2, 1)).await.unwrap();
+
+        journal.crash_next_persist.store(true, Ordering::SeqCst);
+        let crashed = service.update_cart(&Principal::Admin, update_cart_of(2, 2)).await;
+        assert_eq!(crashed.err(), Some("Order actor stopped before replying"));
+
+        // The event of the crashed command was never persisted: the new incarnation is back to the first one
+        let (_, events) = service.update_delivery_address(&Principal::Admin, update_delivery_address(2)).await.unwrap();
+        assert_eq!(events[0].sequence_number, 2);
+        assert_eq!(journal.retrievals.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn rejects_the_commands_past_a_full_mailbox_without_blocking_the_other_orders() {
+        let journal = ProbedJournal::new();
+        let service = service(&journal, ActorOrderServiceConfig { mailbox_capacity: 3, ..Default::default() });
+
+        let held = journal.gate.write().await;
+        let mut pending = Vec::new();
+        for quantity in 1..=5 {
+            let service = service.clone();
+            pending.push(tokio::spawn(async move { service.update_cart(&Principal::Admin, update_cart_of(HELD_ORDER, quantity)).await }));
+            // Sent one after the other
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+
+        // HELD_ORDER's actor is stuck on its first command, its mailbox full: the supervisor still serves the other orders
+        let other = tokio::time::timeout(Duration::from_secs(5), service.update_cart(&Principal::Admin, update_cart_of(2, 1))).await;
+        assert!(other.unwrap().is_ok());
+
+        drop(held);
+        let mut replies = Vec::new();
+        for handle in pending {
+            let reply = tokio::time::timeout(Duration::from_secs(5), handle).await.unwrap().unwrap();
+            replies.push(reply.map(|(state, events)| (events[0].sequence_number, quantity(&state))));
+        }
+        // Applied in the order they were sent, the one past the mailbox rejected
+        assert_eq!(replies, vec![Ok((1, 1)), Ok((2, 2)), Ok((3, 3)), Ok((4, 4)), Err("Order actor mailbox is full")]);
+    }
+}
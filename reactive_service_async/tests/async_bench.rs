@@ -1,44 +1,161 @@
 #[cfg(test)]
 mod tests {
     use std::collections::HashMap;
+    use std::future::Future;
     use std::sync::Arc;
-    use std::time::{Duration, Instant};
+    use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
     use reactive_service_domain::non_empty_cart::{NonEmptyCart, Quantity, Sku};
     use tokio::sync::Semaphore;
+    use reactive_service_async::infra::group_commit_journal::{GroupCommitConfig, GroupCommitJournal};
+    use reactive_service_async::infra::inmem_journal::InMemoryJournal;
     use reactive_service_async::infra::postgres_events_store::PostgresEventStore;
     use reactive_service_async::infra::scylla_event_store::ScyllaEventStore;
-    use reactive_service_async::order_service::{EventsJournal, OrderService, UpdateCart};
+    use reactive_service_async::actor_order_service::ActorOrderService;
+    use reactive_service_async::inventory::{AsyncInventory, InventoryService};
+    use reactive_service_async::order_service::{EventsJournal, OrderService, Principal, UpdateCart};
     use reactive_service_async::payment_processor::LocalPaymentProcessor;
     use reactive_service_async::shipping_calculator::LocalShippingCalculator;
     use reactive_service_async::tax_calculator::LocalTaxCalculator;
+    use reactive_service_domain::aggregate_root::SequencedEvent;
+    use reactive_service_domain::inventory_item::InventoryItemEvent;
     use reactive_service_domain::order_entity::OrderEvent;
+    use reactive_service_domain::order_state::OrderState;
+
+    /// The bench only needs the update_cart command, common to both runtimes.
+    trait UpdateCartService: Send + Sync + 'static {
+        fn update_cart(&self, cmd: UpdateCart)
+            -> impl Future<Output = Result<(OrderState, Vec<SequencedEvent<OrderEvent>>), &'static str>> + Send;
+    }
+
+    impl<E: EventsJournal<OrderEvent> + Send + Sync + 'static> UpdateCartService
+        for OrderService<E, LocalShippingCalculator, LocalTaxCalculator, LocalPaymentProcessor> {
+        fn update_cart(&self, cmd: UpdateCart)
+            -> impl Future<Output = Result<(OrderState, Vec<SequencedEvent<OrderEvent>>), &'static str>> + Send {
+            OrderService::update_cart(self, &Principal::Admin, cmd)
+        }
+    }
+
+    impl UpdateCartService for ActorOrderService {
+        fn update_cart(&self, cmd: UpdateCart)
+            -> impl Future<Output = Result<(OrderState, Vec<SequencedEvent<OrderEvent>>), &'static str>> + Send {
+            ActorOrderService::update_cart(self, &Principal::Admin, cmd)
+        }
+    }
 
     #[tokio::test(flavor = "current_thread")]
     // #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    #[ignore = "needs a PostgreSQL server on localhost"]
     async fn bench_postgres() {
         let events_journal = PostgresEventStore::new().await.unwrap();
         let max_concurrent_tasks = 10;
-        bench_throughput(events_journal, max_concurrent_tasks).await
+        bench_throughput(new_order_service(events_journal), max_concurrent_tasks).await
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn bench_in_memory() {
+        // Without any database: the cost of the service itself
+        let events_journal = InMemoryJournal::new().unwrap();
+        let max_concurrent_tasks = 10;
+        bench_throughput(new_order_service(events_journal), max_concurrent_tasks).await
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn bench_in_memory_actors() {
+        let events_journal = InMemoryJournal::new().unwrap();
+        let max_concurrent_tasks = 10;
+        bench_throughput(new_actor_order_service(events_journal), max_concurrent_tasks).await
     }
 
     #[tokio::test(flavor = "current_thread")]
+    #[ignore = "needs a PostgreSQL server on localhost"]
+    async fn bench_postgres_actors() {
+        let events_journal = PostgresEventStore::new().await.unwrap();
+        let max_concurrent_tasks = 10;
+        bench_throughput(new_actor_order_service(events_journal), max_concurrent_tasks).await
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    #[ignore = "needs a PostgreSQL server on localhost"]
+    async fn bench_postgres_group_commit() {
+        let events_journal = GroupCommitJournal::new(
+            PostgresEventStore::new().await.unwrap(),
+            GroupCommitConfig::default()
+        );
+        // Groups only fill up with many commands in flight, with few of them the linger is pure latency.
+        // Compare with bench_postgres at the same concurrency.
+        let max_concurrent_tasks = 100;
+        bench_throughput(new_order_service(events_journal), max_concurrent_tasks).await
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    #[ignore = "needs a ScyllaDB node on 127.0.0.1:9042"]
     async fn bench_scylla() {
         // Note: to run a single node Scylla
         // docker run --rm -it -p 9042:9042 scylladb/scylla
         let events_journal = ScyllaEventStore::new("127.0.0.1:9042").await.unwrap();
         let max_concurrent_tasks = 200;
-        bench_throughput(events_journal, max_concurrent_tasks).await
+        bench_throughput(new_order_service(events_journal), max_concurrent_tasks).await
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    #[ignore = "needs a ScyllaDB node on 127.0.0.1:9042"]
+    async fn bench_scylla_actors() {
+        let events_journal = ScyllaEventStore::new("127.0.0.1:9042").await.unwrap();
+        let max_concurrent_tasks = 200;
+        bench_throughput(new_actor_order_service(events_journal), max_concurrent_tasks).await
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    #[ignore = "needs a ScyllaDB node on 127.0.0.1:9042"]
+    async fn bench_scylla_group_commit() {
+        let events_journal = GroupCommitJournal::new(
+            ScyllaEventStore::new("127.0.0.1:9042").await.unwrap(),
+            GroupCommitConfig::default()
+        );
+        let max_concurrent_tasks = 200;
+        bench_throughput(new_order_service(events_journal), max_concurrent_tasks).await
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn bench_reserve_hot_skus_in_memory() {
+        // Every order reserves one of the few hot SKUs: their reservations wait for each other
+        let inventory = InventoryService::new(InMemoryJournal::new().unwrap());
+        let max_concurrent_tasks = 10;
+        bench_reserve_stock(inventory, max_concurrent_tasks).await
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    #[ignore = "needs a PostgreSQL server on localhost"]
+    async fn bench_reserve_hot_skus_postgres() {
+        let inventory = InventoryService::new(PostgresEventStore::new().await.unwrap());
+        let max_concurrent_tasks = 10;
+        bench_reserve_stock(inventory, max_concurrent_tasks).await
+    }
+
+    fn new_order_service<E: EventsJournal<OrderEvent>>(events_journal: E)
+        -> OrderService<E, LocalShippingCalculator, LocalTaxCalculator, LocalPaymentProcessor> {
+        OrderService::new(
+            events_journal,
+            LocalShippingCalculator{},
+            LocalTaxCalculator{},
+            LocalPaymentProcessor{}
+        )
     }
-    async fn bench_throughput<E: EventsJournal<OrderEvent> + Send + Sync + 'static>(
-        events_journal: E, max_concurrent_tasks: usize) {
 
-        let service = Arc::new(OrderService::new(
+    fn new_actor_order_service<E: EventsJournal<OrderEvent> + Send + Sync + 'static>(events_journal: E)
+        -> ActorOrderService {
+        ActorOrderService::new(
             events_journal,
             LocalShippingCalculator{},
             LocalTaxCalculator{},
             LocalPaymentProcessor{}
-        ));
+        )
+    }
+
+    async fn bench_throughput<O: UpdateCartService>(order_service: O, max_concurrent_tasks: usize) {
+
+        let service = Arc::new(order_service);
         
         let number_entities = 1000;
 
@@ -84,8 +201,10 @@ mod tests {
                         let service_arc = service.clone();
 
                         let handle = tokio::spawn(async move {
+                            let command_start_time = Instant::now();
                             let _ = service_arc.update_cart(cmd).await;
                             drop(permit);
+                            command_start_time.elapsed()
                         });
 
                         handles.push(handle);
@@ -97,26 +216,80 @@ mod tests {
                 }
             }
 
+            let mut latencies = Vec::with_capacity(handles.len());
             for handle in handles {
-                handle.await.expect("Task panicked");
+                latencies.push(handle.await.expect("Task panicked"));
             }
 
             let elapsed_time = start_time.elapsed();
             let commands_per_sec = num_commands as f64 / elapsed_time.as_secs_f64();
             println!("Commands/seq {:?}", human_readable_format(commands_per_sec));
+            print_latency_percentiles(latencies);
+        }
+
+    }
+
+    async fn bench_reserve_stock<J>(inventory: InventoryService<J>, max_concurrent_tasks: usize)
+    where
+        J: EventsJournal<InventoryItemEvent> + Send + Sync + 'static,
+    {
+        let hot_skus = 4;
+        let cold_skus = 1000;
+        for sku in (0..hot_skus).map(|n| format!("hot-{}", n)).chain((0..cold_skus).map(|n| format!("cold-{}", n))) {
+            inventory.receive_stock(&Sku(sku), 1_000_000).await.unwrap();
         }
 
+        let inventory = Arc::new(inventory);
+        let semaphore = Arc::new(Semaphore::new(max_concurrent_tasks));
+        let num_orders = 10000;
+        // Unique orders across the runs: the journal may be shared
+        let first_order_id = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_nanos() as i64;
+        let start_time = Instant::now();
+
+        let mut handles = Vec::with_capacity(num_orders as usize);
+        for n in 0..num_orders {
+            let permit = semaphore.clone().acquire_owned().await.unwrap();
+            let cart = NonEmptyCart::new(HashMap::from([
+                (Sku(format!("hot-{}", n % hot_skus)), Quantity(1)),
+                (Sku(format!("cold-{}", n % cold_skus)), Quantity(2)),
+            ])).unwrap();
+            let inventory = inventory.clone();
+
+            handles.push(tokio::spawn(async move {
+                let command_start_time = Instant::now();
+                inventory.reserve_stock(first_order_id + n, &cart).await.unwrap();
+                drop(permit);
+                command_start_time.elapsed()
+            }));
+        }
+
+        let mut latencies = Vec::with_capacity(handles.len());
+        for handle in handles {
+            latencies.push(handle.await.expect("Task panicked"));
+        }
+
+        let elapsed_time = start_time.elapsed();
+        let reservations_per_sec = num_orders as f64 / elapsed_time.as_secs_f64();
+        println!("Reservations/sec {:?}", human_readable_format(reservations_per_sec));
+        print_latency_percentiles(latencies);
+    }
+
+    fn print_latency_percentiles(mut latencies: Vec<Duration>) {
+        latencies.sort();
+        let percentile = |p: f64| latencies[((latencies.len() - 1) as f64 * p) as usize].as_micros();
+        println!("Latency p50 {}µs, p95 {}µs, p99 {}µs, max {}µs",
+                 percentile(0.50), percentile(0.95), percentile(0.99), percentile(1.0));
     }
 
     fn human_readable_format(n: f64) -> String {
         if n < 1_000.0 {
-            return format!("{:.2}", n);
+            format!("{:.2}", n)
         } else if n < 1_000_000.0 {
-            return format!("{:.2}k", n / 1_000.0);
+            format!("{:.2}k", n / 1_000.0)
         } else if n < 1_000_000_000.0 {
-            return format!("{:.2}M", n / 1_000_000.0);
+            format!("{:.2}M", n / 1_000_000.0)
         } else {
-            return format!("{:.2}G", n / 1_000_000_000.0);
+            format!("{:.2}G", n / 1_000_000_000.0)
         }
     }
 }
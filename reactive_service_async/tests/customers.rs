@@ -0,0 +1,69 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use reactive_service_domain::customer::{CustomerCommand, CustomerEvent, Profile};
+    use reactive_service_domain::order_state::{DeliveryAddress, OrderState, Street};
+    use reactive_service_async::actor_order_service::ActorOrderService;
+    use reactive_service_async::customer_service::CustomerService;
+    use reactive_service_async::infra::inmem_journal::InMemoryJournal;
+    use reactive_service_async::order_service::{OrderCommand, PayOrder, Principal, UpdateDeliveryAddress};
+    use reactive_service_async::payment_processor::{LocalPaymentProcessor, PaymentToken};
+    use reactive_service_async::shipping_calculator::LocalShippingCalculator;
+    use reactive_service_async::tax_calculator::LocalTaxCalculator;
+    use crate::common::{service, update_cart};
+
+    const JANE: Principal = Principal::Customer(1);
+    const JOHN: Principal = Principal::Customer(2);
+
+    fn update_delivery_address(order_id: i64) -> OrderCommand {
+        let delivery_address = DeliveryAddress { street: Street("1 Main Street".to_owned()), postal_code: "H0H 0H0".parse().unwrap() };
+        UpdateDeliveryAddress { order_id, delivery_address }.into()
+    }
+
+    fn pay_order(order_id: i64) -> OrderCommand {
+        PayOrder { order_id, payment_token: PaymentToken::new("token") }.into()
+    }
+
+    #[tokio::test]
+    async fn only_the_owner_or_an_admin_changes_an_order() {
+        let service = service();
+        service.handle_as(&JANE, update_cart(1).into()).await.unwrap();
+
+        assert_eq!(service.handle_as(&JOHN, update_delivery_address(1)).await.err(), Some("Not the owner of the order"));
+        service.handle_as(&JANE, update_delivery_address(1)).await.unwrap();
+        service.handle_as(&Principal::Admin, pay_order(1)).await.unwrap();
+        assert!(matches!(service.get_order(1).await.unwrap().state, OrderState::Completed(_)));
+    }
+
+    #[tokio::test]
+    async fn a_customer_cant_take_over_an_order_created_by_an_admin() {
+        let service = service();
+        service.handle_as_admin(update_cart(1).into()).await.unwrap();
+
+        assert_eq!(service.handle_as(&JANE, update_cart(1).into()).await.err(), Some("Not the owner of the order"));
+        service.handle_as_admin(update_delivery_address(1)).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn the_actors_only_let_the_owner_change_an_order() {
+        let service = ActorOrderService::new(
+            InMemoryJournal::new().unwrap(), LocalShippingCalculator{}, LocalTaxCalculator{}, LocalPaymentProcessor{}
+        );
+        service.handle_as(&JANE, update_cart(1).into()).await.unwrap();
+
+        assert_eq!(service.handle_as(&JOHN, update_delivery_address(1)).await.err(), Some("Not the owner of the order"));
+        service.handle_as(&JANE, update_delivery_address(1)).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn a_customer_only_manages_their_own_profile() {
+        let customers = CustomerService::new(InMemoryJournal::<CustomerEvent>::new().unwrap());
+        let profile = Profile { name: "Jane".to_owned(), email: "jane@example.com".to_owned() };
+
+        customers.handle(&JANE, 1, CustomerCommand::Register { profile: profile.clone() }).await.unwrap();
+        assert_eq!(customers.get_customer(&JOHN, 1).await.err(), Some("Not the customer"));
+        assert_eq!(customers.get_customer(&Principal::Admin, 1).await.unwrap().state.profile, Some(profile.clone()));
+        assert_eq!(customers.handle(&JOHN, 1, CustomerCommand::UpdateProfile { profile }).await.err(), Some("Not the customer"));
+    }
+}
@@ -0,0 +1,86 @@
+#[cfg(test)]
+mod tests {
+    use futures::future::join_all;
+    use serde_derive::{Deserialize, Serialize};
+
+    use reactive_service_domain::aggregate_root::{AggregateRoot, SequencedEvent};
+    use reactive_service_async::entity_host::EntityHost;
+    use reactive_service_async::infra::file_journal::FileJournal;
+    use reactive_service_async::infra::inmem_journal::InMemoryJournal;
+
+    /// A minimal aggregate, to check the host isn't tied to the orders.
+    #[derive(Default)]
+    struct Counter { value: u32, sequence_number: i64 }
+
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    struct Added(u32);
+
+    impl AggregateRoot for Counter {
+        type State = u32;
+        type Command = u32;
+        type Error = &'static str;
+        type Event = Added;
+
+        fn restore_from_events(&mut self, events: Vec<SequencedEvent<Added>>) -> Result<&u32, &'static str> {
+            for seq_event in events {
+                self.value += seq_event.event.0;
+                self.sequence_number = seq_event.sequence_number;
+            }
+            Ok(&self.value)
+        }
+
+        fn get_state(&self) -> &u32 { &self.value }
+
+        fn get_sequence_number(&self) -> i64 { self.sequence_number }
+
+        fn handle_command(&mut self, amount: u32) -> Result<(&u32, Vec<SequencedEvent<Added>>), &'static str> {
+            if amount == 0 {
+                return Err("Nothing to add");
+            }
+            self.value += amount;
+            self.sequence_number += 1;
+            Ok((&self.value, vec![SequencedEvent { sequence_number: self.sequence_number, event: Added(amount) }]))
+        }
+    }
+
+    #[tokio::test]
+    async fn hosts_any_aggregate() {
+        let host: EntityHost<Counter, _> = EntityHost::new(InMemoryJournal::new().unwrap());
+
+        host.handle(1, |_| Ok(2)).await.unwrap();
+        let (state, events) = host.handle(1, |counter| Ok(counter.value + 1)).await.unwrap();
+        assert_eq!(state, 5);
+        assert_eq!(events[0].sequence_number, 2);
+
+        assert_eq!(host.handle(1, |_| Ok(0)).await.err(), Some("Nothing to add"));
+        assert_eq!(host.handle(1, |_| Err("Rejected")).await.err(), Some("Rejected"));
+    }
+
+    #[tokio::test]
+    async fn handles_the_commands_of_an_entity_one_at_a_time() {
+        let host: EntityHost<Counter, _> = EntityHost::new(InMemoryJournal::new().unwrap());
+
+        join_all((0..400).map(|_| host.handle(1, |_| Ok(1)))).await
+            .into_iter()
+            .for_each(|result| { result.unwrap(); });
+
+        let (state, events) = host.handle(1, |_| Ok(1)).await.unwrap();
+        assert_eq!(state, 401);
+        assert_eq!(events[0].sequence_number, 401);
+    }
+
+    #[tokio::test]
+    async fn restores_the_entities_from_the_journal() {
+        let directory = tempfile::tempdir().unwrap();
+        {
+            let host: EntityHost<Counter, _> = EntityHost::new(FileJournal::new(directory.path()).await.unwrap());
+            host.handle(1, |_| Ok(2)).await.unwrap();
+            host.handle(1, |_| Ok(3)).await.unwrap();
+        }
+
+        let host: EntityHost<Counter, _> = EntityHost::new(FileJournal::new(directory.path()).await.unwrap());
+        let (state, events) = host.handle(1, |_| Ok(1)).await.unwrap();
+        assert_eq!(state, 6);
+        assert_eq!(events[0].sequence_number, 3);
+    }
+}
@@ -0,0 +1,94 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use reactive_service_domain::aggregate_root::SequencedEvent;
+    use reactive_service_domain::order_entity::OrderEvent;
+    use reactive_service_async::event_bus::EventBus;
+    use reactive_service_async::infra::inmem_journal::InMemoryJournal;
+    use reactive_service_async::order_service::{EventsJournal, OrderService, Principal, SubscriptionError};
+    use reactive_service_async::payment_processor::LocalPaymentProcessor;
+    use reactive_service_async::shipping_calculator::LocalShippingCalculator;
+    use reactive_service_async::tax_calculator::LocalTaxCalculator;
+    use crate::common::update_cart;
+
+    fn service<E: EventsJournal<OrderEvent>>(journal: E)
+        -> OrderService<E, LocalShippingCalculator, LocalTaxCalculator, LocalPaymentProcessor> {
+        OrderService::new(journal, LocalShippingCalculator{}, LocalTaxCalculator{}, LocalPaymentProcessor{})
+    }
+
+    /// Fails to persist anything, and has no events.
+    struct FailingJournal;
+
+    impl EventsJournal<OrderEvent> for FailingJournal {
+        async fn persist_event(&self, _: i64, _: &SequencedEvent<OrderEvent>) -> Result<(), &'static str> {
+            Err("Failed to persist event")
+        }
+
+        async fn retrieve_events(&self, _: i64) -> Result<Vec<SequencedEvent<OrderEvent>>, &'static str> {
+            Ok(vec![])
+        }
+
+        async fn persist_events(&self, _: &[(i64, SequencedEvent<OrderEvent>)]) -> Result<(), &'static str> {
+            Err("Failed to persist event")
+        }
+    }
+
+    #[tokio::test]
+    async fn publishes_the_committed_events() {
+        let service = service(InMemoryJournal::new().unwrap());
+        let mut all_orders = service.event_bus().subscribe_all();
+        let mut order_2 = service.event_bus().subscribe(2);
+
+        service.update_cart(&Principal::Admin, update_cart(1)).await.unwrap();
+        service.update_cart(&Principal::Admin, update_cart(2)).await.unwrap();
+        service.update_cart(&Principal::Admin, update_cart(2)).await.unwrap();
+
+        let mut received = Vec::new();
+        for _ in 0..3 {
+            let committed = all_orders.recv().await.unwrap();
+            received.push((committed.entity_id, committed.event.sequence_number));
+        }
+        assert_eq!(received, vec![(1, 1), (2, 1), (2, 2)]);
+
+        assert_eq!(order_2.recv().await.unwrap().event.sequence_number, 1);
+        assert_eq!(order_2.recv().await.unwrap().event.sequence_number, 2);
+        assert!(tokio::time::timeout(Duration::from_millis(10), order_2.recv()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn only_publishes_once_persisted() {
+        let service = service(FailingJournal);
+        let mut all_orders = service.event_bus().subscribe_all();
+
+        assert!(service.update_cart(&Principal::Admin, update_cart(1)).await.is_err());
+        assert!(tokio::time::timeout(Duration::from_millis(10), all_orders.recv()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn reports_the_events_missed_by_a_slow_subscriber() {
+        let event_bus = EventBus::new(2);
+        let mut subscription = event_bus.subscribe_all();
+
+        let events: Vec<SequencedEvent<u32>> = (1..=5).map(|n| SequencedEvent { sequence_number: n, event: 0 }).collect();
+        event_bus.publish(1, &events);
+
+        assert_eq!(subscription.recv().await.err(), Some(SubscriptionError::Lagged(3)));
+        assert_eq!(subscription.recv().await.unwrap().event.sequence_number, 4);
+        assert_eq!(subscription.recv().await.unwrap().event.sequence_number, 5);
+    }
+
+    #[tokio::test]
+    async fn closes_the_subscriptions_with_the_service() {
+        let service = service(InMemoryJournal::new().unwrap());
+        let mut subscription = service.event_bus().subscribe(1);
+
+        service.update_cart(&Principal::Admin, update_cart(1)).await.unwrap();
+        drop(service);
+
+        assert_eq!(subscription.recv().await.unwrap().event.sequence_number, 1);
+        assert_eq!(subscription.recv().await.err(), Some(SubscriptionError::Closed));
+    }
+}
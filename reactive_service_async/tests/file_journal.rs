@@ -0,0 +1,58 @@
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use reactive_service_async::infra::file_journal::FileJournal;
+    use reactive_service_async::order_service::{EventsJournal, OrderService, Principal, UpdateCart};
+    use reactive_service_async::payment_processor::LocalPaymentProcessor;
+    use reactive_service_async::shipping_calculator::LocalShippingCalculator;
+    use reactive_service_async::tax_calculator::LocalTaxCalculator;
+    use reactive_service_domain::aggregate_root::SequencedEvent;
+    use reactive_service_domain::non_empty_cart::{NonEmptyCart, Quantity, Sku};
+    use reactive_service_domain::order_entity::OrderEvent;
+
+    #[tokio::test]
+    async fn persists_events_across_restarts() {
+        let directory = tempfile::tempdir().unwrap();
+        {
+            let journal = FileJournal::new(directory.path()).await.unwrap();
+            journal.persist_events(&[
+                (1, SequencedEvent { sequence_number: 1, event: "first".to_owned() }),
+                (1, SequencedEvent { sequence_number: 2, event: "second".to_owned() }),
+            ]).await.unwrap();
+        }
+
+        let journal = FileJournal::new(directory.path()).await.unwrap();
+        let events: Vec<SequencedEvent<String>> = journal.retrieve_events(1).await.unwrap();
+        assert_eq!(events.iter().map(|e| e.event.as_str()).collect::<Vec<_>>(), vec!["first", "second"]);
+        assert!(journal.persist_event(1, &SequencedEvent { sequence_number: 2, event: "duplicate".to_owned() }).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn restores_orders_from_the_journal() {
+        let directory = tempfile::tempdir().unwrap();
+        let update_cart = || UpdateCart {
+            order_id: 1,
+            cart: NonEmptyCart::new(HashMap::from([(Sku("apple".to_owned()), Quantity(1))])).unwrap()
+        };
+        {
+            let service = OrderService::new(
+                FileJournal::new(directory.path()).await.unwrap(),
+                LocalShippingCalculator{}, LocalTaxCalculator{}, LocalPaymentProcessor{}
+            );
+            service.update_cart(&Principal::Admin, update_cart()).await.unwrap();
+        }
+
+        // A new service has to restore the order to append its next event
+        let service = OrderService::new(
+            FileJournal::new(directory.path()).await.unwrap(),
+            LocalShippingCalculator{}, LocalTaxCalculator{}, LocalPaymentProcessor{}
+        );
+        let (_, events) = service.update_cart(&Principal::Admin, update_cart()).await.unwrap();
+        assert_eq!(events[0].sequence_number, 2);
+
+        let journal = FileJournal::new(directory.path()).await.unwrap();
+        let events: Vec<SequencedEvent<OrderEvent>> = journal.retrieve_events(1).await.unwrap();
+        assert_eq!(events.len(), 2);
+    }
+}
@@ -0,0 +1,67 @@
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use reactive_service_domain::aggregate_root::SequencedEvent;
+    use reactive_service_async::infra::group_commit_journal::{GroupCommitConfig, GroupCommitJournal};
+    use reactive_service_async::infra::inmem_journal::InMemoryJournal;
+    use reactive_service_async::order_service::{EventsJournal, OrderId};
+
+    const REJECTED_ORDER: OrderId = 13;
+
+    /// An in-memory journal recording the size of each group written, which rejects the events of `REJECTED_ORDER`.
+    #[derive(Clone)]
+    struct RejectingJournal {
+        journal: Arc<InMemoryJournal<String>>,
+        groups: Arc<Mutex<Vec<usize>>>,
+    }
+
+    impl EventsJournal<String> for RejectingJournal {
+        async fn persist_event(&self, entity_id: OrderId, evt_w_seq: &SequencedEvent<String>) -> Result<(), &'static str> {
+            if entity_id == REJECTED_ORDER {
+                return Err("Rejected event");
+            }
+            self.journal.persist_event(entity_id, evt_w_seq).await
+        }
+
+        async fn retrieve_events(&self, entity_id: OrderId) -> Result<Vec<SequencedEvent<String>>, &'static str> {
+            self.journal.retrieve_events(entity_id).await
+        }
+
+        async fn persist_events(&self, events: &[(OrderId, SequencedEvent<String>)]) -> Result<(), &'static str> {
+            self.groups.lock().unwrap().push(events.len());
+            // All or nothing
+            if events.iter().any(|(entity_id, _)| *entity_id == REJECTED_ORDER) {
+                return Err("Rejected group");
+            }
+            self.journal.persist_events(events).await
+        }
+    }
+
+    fn event(sequence_number: i64) -> SequencedEvent<String> {
+        SequencedEvent { sequence_number, event: format!("event {}", sequence_number) }
+    }
+
+    #[tokio::test]
+    async fn a_failed_group_only_fails_the_rejected_events() {
+        let inner = RejectingJournal { journal: Arc::new(InMemoryJournal::new().unwrap()), groups: Arc::default() };
+        // The three writes below always make a single group
+        let config = GroupCommitConfig { max_batch_size: 3, linger: Duration::from_secs(5) };
+        let journal = GroupCommitJournal::new(inner.clone(), config);
+
+        let first_event = event(1);
+        let (first, rejected, second) = tokio::join!(
+            journal.persist_event(1, &first_event),
+            journal.persist_event(REJECTED_ORDER, &first_event),
+            journal.persist_event(2, &first_event),
+        );
+
+        assert!(first.is_ok() && second.is_ok());
+        assert_eq!(rejected.err(), Some("Rejected event"));
+        assert_eq!(*inner.groups.lock().unwrap(), vec![3]);
+        assert_eq!(journal.retrieve_events(1).await.unwrap().len(), 1);
+        assert_eq!(journal.retrieve_events(2).await.unwrap().len(), 1);
+        assert!(journal.retrieve_events(REJECTED_ORDER).await.unwrap().is_empty());
+    }
+}
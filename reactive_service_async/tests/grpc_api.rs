@@ -0,0 +1,253 @@
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::net::SocketAddr;
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tokio::net::TcpListener;
+    use tokio::sync::oneshot;
+    use tokio::task::JoinHandle;
+    use tonic::transport::Channel;
+    use tonic::{Code, Request};
+
+    use reactive_service_domain::non_empty_cart::{NonEmptyCart, Quantity, Sku};
+    use reactive_service_domain::order_entity::OrderEvent;
+    use reactive_service_domain::order_state::{Currency, DeliveryAddress, Money, OrderState, Street};
+    use reactive_service_async::api::ApiAuthConfig;
+    use reactive_service_async::grpc_api::{self, proto};
+    use reactive_service_async::grpc_api::proto::orders_client::OrdersClient;
+    use reactive_service_async::infra::inmem_journal::InMemoryJournal;
+    use reactive_service_async::order_service::OrderService;
+    use reactive_service_async::payment_processor::LocalPaymentProcessor;
+    use reactive_service_async::shipping_calculator::LocalShippingCalculator;
+    use reactive_service_async::tax_calculator::LocalTaxCalculator;
+
+    struct Server {
+        address: SocketAddr,
+        shutdown: oneshot::Sender<()>,
+        task: JoinHandle<Result<(), tonic::transport::Error>>,
+    }
+
+    async fn start() -> Server {
+        let service = OrderService::new(
+            InMemoryJournal::<OrderEvent>::new().unwrap(), LocalShippingCalculator{}, LocalTaxCalculator{}, LocalPaymentProcessor{}
+        );
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let (shutdown, stopped) = oneshot::channel();
+        let auth = ApiAuthConfig { admin_token: Some("secret".to_owned()) };
+        let task = tokio::spawn(grpc_api::serve(listener, Arc::new(service), auth, async { let _ = stopped.await; }));
+        Server { address, shutdown, task }
+    }
+
+    async fn client(server: &Server) -> OrdersClient<Channel> {
+        OrdersClient::connect(format!("http://{}", server.address)).await.unwrap()
+    }
+
+    fn as_customer<T>(customer_id: &str, message: T) -> Request<T> {
+        let mut request = Request::new(message);
+        request.metadata_mut().insert("x-customer-id", customer_id.parse().unwrap());
+        request
+    }
+
+    fn as_admin<T>(admin_token: &str, message: T) -> Request<T> {
+        let mut request = Request::new(message);
+        request.metadata_mut().insert("x-admin-token", admin_token.parse().unwrap());
+        request
+    }
+
+    fn update_cart(order_id: i64, quantity: u32) -> proto::UpdateCartRequest {
+        proto::UpdateCartRequest { order_id, cart: Some(proto::Cart { items: HashMap::from([("apple".to_owned(), quantity)]) }) }
+    }
+
+    fn update_delivery_address(order_id: i64, postal_code: &str) -> proto::UpdateDeliveryAddressRequest {
+        let delivery_address = proto::DeliveryAddress { street: "1 Main Street".to_owned(), postal_code: postal_code.to_owned() };
+        proto::UpdateDeliveryAddressRequest { order_id, delivery_address: Some(delivery_address) }
+    }
+
+    fn pay_order(order_id: i64) -> proto::PayOrderRequest {
+        proto::PayOrderRequest { order_id, payment_token: "tok".to_owned() }
+    }
+
+    fn cart(items: &[(&str, u16)]) -> NonEmptyCart {
+        NonEmptyCart::new(items.iter().map(|(sku, quantity)| (Sku(sku.to_string()), Quantity(*quantity))).collect()).unwrap()
+    }
+
+    fn cad(amount_cents: u32) -> Money {
+        Money { amount_cents, currency: Currency::Cad }
+    }
+
+    #[tokio::test]
+    async fn checks_out_an_order() {
+        let server = start().await;
+        let mut client = client(&server).await;
+
+        let reply = client.update_cart(as_customer("7", update_cart(1, 2))).await.unwrap().into_inner();
+        assert_eq!(reply.sequence_number, 1);
+        assert!(matches!(reply.state.and_then(|state| state.state), Some(proto::order_state::State::WithCart(_))));
+
+        client.update_delivery_address(as_customer("7", update_delivery_address(1, "h0h0h0"))).await.unwrap();
+        client.pay_order(as_customer("7", pay_order(1))).await.unwrap();
+
+        let reply = client.get_order(as_customer("7", proto::GetOrderRequest { order_id: 1 })).await.unwrap().into_inner();
+        assert_eq!((reply.order_id, reply.sequence_number), (1, 3));
+        let state: OrderState = reply.state.unwrap().try_into().unwrap();
+        let OrderState::Completed(completed) = state else { panic!("Not completed: {:?}", state) };
+        assert_eq!(completed.get_delivery_address().postal_code.to_string(), "H0H 0H0");
+    }
+
+    #[tokio::test]
+    async fn rejects_invalid_requests() {
+        let server = start().await;
+        let mut client = client(&server).await;
+
+        let empty_cart = proto::UpdateCartRequest { order_id: 1, cart: Some(proto::Cart { items: HashMap::new() }) };
+        let status = client.update_cart(as_customer("7", empty_cart)).await.unwrap_err();
+        assert_eq!((status.code(), status.message()), (Code::InvalidArgument, "Cart can't be empty"));
+
+        let status = client.update_cart(as_customer("7", update_cart(1, 70_000))).await.unwrap_err();
+        assert_eq!((status.code(), status.message()), (Code::InvalidArgument, "Quantity too large"));
+
+        let status = client.update_cart(as_customer("7", proto::UpdateCartRequest { order_id: 1, cart: None })).await.unwrap_err();
+        assert_eq!((status.code(), status.message()), (Code::InvalidArgument, "Missing cart"));
+
+        client.update_cart(as_customer("7", update_cart(1, 1))).await.unwrap();
+        let status = client.update_delivery_address(as_customer("7", update_delivery_address(1, "90210"))).await.unwrap_err();
+        assert_eq!((status.code(), status.message()), (Code::InvalidArgument, "Invalid postal code format"));
+    }
+
+    #[tokio::test]
+    async fn maps_the_domain_errors_onto_status_codes() {
+        let server = start().await;
+        let mut client = client(&server).await;
+
+        let status = client.get_order(as_customer("7", proto::GetOrderRequest { order_id: 1 })).await.unwrap_err();
+        assert_eq!(status.code(), Code::NotFound);
+
+        client.update_cart(as_customer("7", update_cart(1, 1))).await.unwrap();
+
+        let status = client.pay_order(as_customer("7", pay_order(1))).await.unwrap_err();
+        assert_eq!((status.code(), status.message()), (Code::FailedPrecondition, "Order not ready to be paid."));
+
+        let status = client.update_cart(as_customer("8", update_cart(1, 1))).await.unwrap_err();
+        assert_eq!((status.code(), status.message()), (Code::PermissionDenied, "Not the owner of the order"));
+    }
+
+    #[tokio::test]
+    async fn watches_the_events_of_an_order() {
+        let server = start().await;
+        let mut client = client(&server).await;
+        client.update_cart(as_customer("7", update_cart(1, 1))).await.unwrap();
+        client.update_cart(as_customer("7", update_cart(2, 1))).await.unwrap();
+
+        let watch = proto::WatchOrderRequest { order_id: 1, after_sequence_number: 0 };
+        let mut events = client.watch_order(as_customer("7", watch)).await.unwrap().into_inner();
+        client.update_delivery_address(as_customer("7", update_delivery_address(1, "H0H 0H0"))).await.unwrap();
+        client.update_cart(as_customer("7", update_cart(2, 3))).await.unwrap();
+        client.pay_order(as_customer("7", pay_order(1))).await.unwrap();
+
+        let mut received = Vec::new();
+        for _ in 0..3 {
+            let committed = tokio::time::timeout(Duration::from_secs(5), events.message()).await.unwrap().unwrap().unwrap();
+            assert_eq!(committed.order_id, 1);
+            let event: OrderEvent = committed.event.unwrap().try_into().unwrap();
+            received.push((committed.sequence_number, event));
+        }
+        assert!(matches!(received.as_slice(), [
+            (1, OrderEvent::UpdatedCart { .. }),
+            (2, OrderEvent::UpdatedDeliveryAddress { .. }),
+            (3, OrderEvent::Completed { .. }),
+        ]));
+
+        let watch = proto::WatchOrderRequest { order_id: 1, after_sequence_number: 2 };
+        let mut resumed = client.watch_order(as_customer("7", watch)).await.unwrap().into_inner();
+        let committed = resumed.message().await.unwrap().unwrap();
+        assert_eq!(committed.sequence_number, 3);
+    }
+
+    #[tokio::test]
+    async fn only_the_owner_watches_an_order() {
+        let server = start().await;
+        let mut client = client(&server).await;
+        client.update_cart(as_customer("7", update_cart(1, 1))).await.unwrap();
+
+        let watch = proto::WatchOrderRequest { order_id: 1, after_sequence_number: 0 };
+        let status = client.watch_order(as_customer("8", watch)).await.unwrap_err();
+        assert_eq!(status.code(), Code::PermissionDenied);
+
+        let mut events = client.watch_order(as_customer("7", watch)).await.unwrap().into_inner();
+        assert_eq!(events.message().await.unwrap().unwrap().sequence_number, 1);
+    }
+
+    #[tokio::test]
+    async fn authenticates_the_calls() {
+        let server = start().await;
+        let mut client = client(&server).await;
+        client.update_cart(as_customer("7", update_cart(1, 1))).await.unwrap();
+
+        let status = client.update_cart(update_cart(1, 2)).await.unwrap_err();
+        assert_eq!((status.code(), status.message()), (Code::Unauthenticated, "Missing customer id"));
+        let status = client.get_order(proto::GetOrderRequest { order_id: 1 }).await.unwrap_err();
+        assert_eq!((status.code(), status.message()), (Code::Unauthenticated, "Missing customer id"));
+        let watch = proto::WatchOrderRequest { order_id: 1, after_sequence_number: 0 };
+        assert_eq!(client.watch_order(watch).await.unwrap_err().code(), Code::Unauthenticated);
+
+        let status = client.get_order(as_admin("guess", proto::GetOrderRequest { order_id: 1 })).await.unwrap_err();
+        assert_eq!((status.code(), status.message()), (Code::Unauthenticated, "Invalid admin token"));
+
+        let status = client.get_order(as_customer("8", proto::GetOrderRequest { order_id: 1 })).await.unwrap_err();
+        assert_eq!((status.code(), status.message()), (Code::PermissionDenied, "Not the owner of the order"));
+
+        let reply = client.get_order(as_admin("secret", proto::GetOrderRequest { order_id: 1 })).await.unwrap().into_inner();
+        assert_eq!(reply.sequence_number, 1);
+        client.update_cart(as_admin("secret", update_cart(1, 2))).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn ends_the_watches_on_shutdown() {
+        let server = start().await;
+        let mut client = client(&server).await;
+        client.update_cart(as_customer("7", update_cart(1, 1))).await.unwrap();
+        let watch = proto::WatchOrderRequest { order_id: 1, after_sequence_number: 1 };
+        let mut events = client.watch_order(as_customer("7", watch)).await.unwrap().into_inner();
+
+        server.shutdown.send(()).unwrap();
+
+        let status = events.message().await.unwrap_err();
+        assert_eq!((status.code(), status.message()), (Code::Unavailable, "Server is stopping"));
+        tokio::time::timeout(Duration::from_secs(5), server.task).await.unwrap().unwrap().unwrap();
+    }
+
+    #[test]
+    fn converts_the_events_both_ways() {
+        let delivery_address = DeliveryAddress { street: Street("1 Main Street".to_owned()), postal_code: "H0H 0H0".parse().unwrap() };
+        let events = [
+            OrderEvent::UpdatedCart { cart: cart(&[("apple", 1), ("pear", 2)]), customer_id: Some(7) },
+            OrderEvent::UpdatedDeliveryAddress { delivery_address: delivery_address.clone(), shipping_cost: cad(200), tax: cad(130) },
+            OrderEvent::UpdatedCartOnExistingDeliveryAddress { cart: cart(&[("apple", 3)]), shipping_cost: cad(300), tax: cad(140) },
+            OrderEvent::Expired,
+        ];
+
+        for event in events {
+            let converted: OrderEvent = proto::OrderEvent::from(&event).try_into().unwrap();
+            assert_eq!(serde_json::to_value(&converted).unwrap(), serde_json::to_value(&event).unwrap());
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_messages() {
+        let no_currency = proto::Money { amount_cents: 100, currency: proto::Currency::Unspecified.into() };
+        assert_eq!(Money::try_from(no_currency).err(), Some("Invalid currency"));
+
+        let address_without_tax = proto::OrderState {
+            state: Some(proto::order_state::State::WithAddress(proto::WithAddress {
+                cart: Some(proto::Cart { items: HashMap::from([("apple".to_owned(), 1)]) }),
+                delivery_address: Some(proto::DeliveryAddress { street: "1 Main Street".to_owned(), postal_code: "H0H 0H0".to_owned() }),
+                shipping_cost: Some((&cad(200)).into()),
+                tax: None,
+            })),
+        };
+        assert_eq!(OrderState::try_from(address_without_tax).err(), Some("Missing amount"));
+        assert_eq!(OrderEvent::try_from(proto::OrderEvent { event: None }).err(), Some("Missing order event"));
+    }
+}
@@ -0,0 +1,193 @@
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+    use std::sync::Arc;
+    use serde_json::{json, Value};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+    use tokio::sync::oneshot;
+    use tokio::task::JoinHandle;
+
+    use reactive_service_domain::order_entity::OrderEvent;
+    use reactive_service_async::api::ApiAuthConfig;
+    use reactive_service_async::http_api;
+    use reactive_service_async::infra::inmem_journal::InMemoryJournal;
+    use reactive_service_async::order_service::{OrderService, PrometheusRegistry};
+    use reactive_service_async::payment_processor::LocalPaymentProcessor;
+    use reactive_service_async::shipping_calculator::LocalShippingCalculator;
+    use reactive_service_async::tax_calculator::LocalTaxCalculator;
+
+    const CUSTOMER: &[(&str, &str)] = &[("X-Customer-Id", "7")];
+    const ADMIN: &[(&str, &str)] = &[("X-Admin-Token", "secret")];
+
+    struct Server {
+        address: SocketAddr,
+        shutdown: oneshot::Sender<()>,
+        task: JoinHandle<std::io::Result<()>>,
+    }
+
+    async fn start() -> Server {
+        let service = OrderService::new(
+            InMemoryJournal::<OrderEvent>::new().unwrap(), LocalShippingCalculator{}, LocalTaxCalculator{}, LocalPaymentProcessor{}
+        );
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let (shutdown, stopped) = oneshot::channel();
+        let auth = ApiAuthConfig { admin_token: Some("secret".to_owned()) };
+        let task = tokio::spawn(http_api::serve(listener, Arc::new(service), auth, async { let _ = stopped.await; }));
+        Server { address, shutdown, task }
+    }
+
+    /// The status and the JSON body of the response, over a connection of its own.
+    async fn request(address: SocketAddr, method: &str, path: &str, headers: &[(&str, &str)], body: Option<Value>) -> (u16, Value) {
+        let (status, body) = request_text(address, method, path, headers, body).await;
+        (status, serde_json::from_str(&body).unwrap_or(Value::Null))
+    }
+
+    async fn request_text(address: SocketAddr, method: &str, path: &str, headers: &[(&str, &str)], body: Option<Value>) -> (u16, String) {
+        let body = body.map(|body| body.to_string()).unwrap_or_default();
+        let mut request = format!("{method} {path} HTTP/1.1\r\nHost: {address}\r\nConnection: close\r\n");
+        if !body.is_empty() {
+            request.push_str(&format!("Content-Type: application/json\r\nContent-Length: {}\r\n", body.len()));
+        }
+        for (name, value) in headers {
+            request.push_str(&format!("{name}: {value}\r\n"));
+        }
+        request.push_str("\r\n");
+        request.push_str(&body);
+
+        let mut stream = TcpStream::connect(address).await.unwrap();
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        let status = head.split(' ').nth(1).unwrap().parse().unwrap();
+        (status, body.to_owned())
+    }
+
+    fn cart() -> Value {
+        json!({ "items": { "apple": 2 } })
+    }
+
+    fn address() -> Value {
+        json!({ "street": "1 Main Street", "postal_code": "h0h0h0" })
+    }
+
+    #[tokio::test]
+    async fn reports_its_health() {
+        let server = start().await;
+
+        assert_eq!(request(server.address, "GET", "/health", &[], None).await, (200, json!({ "status": "ok" })));
+    }
+
+    #[tokio::test]
+    async fn serves_the_metrics_next_to_the_api() {
+        let registry = Arc::new(PrometheusRegistry::default());
+        let service = OrderService::new(
+            InMemoryJournal::<OrderEvent>::new().unwrap(), LocalShippingCalculator{}, LocalTaxCalculator{}, LocalPaymentProcessor{}
+        ).with_metrics(registry.clone());
+        let router = http_api::router(Arc::new(service), ApiAuthConfig::default()).merge(http_api::metrics_router(registry));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, router).await });
+
+        assert_eq!(request(address, "PUT", "/orders/1/cart", CUSTOMER, Some(cart())).await.0, 200);
+        let (status, metrics) = request_text(address, "GET", "/metrics", &[], None).await;
+        assert_eq!(status, 200);
+        assert!(metrics.lines().any(|line| line == "order_command_duration_seconds_count{command=\"update_cart\"} 1"), "{}", metrics);
+    }
+
+    #[tokio::test]
+    async fn checks_out_an_order() {
+        let server = start().await;
+
+        let (status, order) = request(server.address, "PUT", "/orders/1/cart", CUSTOMER, Some(cart())).await;
+        assert_eq!(status, 200);
+        assert_eq!((order["status"].as_str(), order["sequence_number"].as_i64()), (Some("WithCart"), Some(1)));
+        assert_eq!(order["cart"], json!({ "apple": 2 }));
+
+        let (status, order) = request(server.address, "PUT", "/orders/1/delivery-address", CUSTOMER, Some(address())).await;
+        assert_eq!(status, 200);
+        assert_eq!(order["delivery_address"], json!({ "street": "1 Main Street", "postal_code": "H0H 0H0" }));
+        assert!(order["shipping_cost"]["amount_cents"].is_u64());
+
+        let (status, order) = request(server.address, "POST", "/orders/1/payment", CUSTOMER, Some(json!({ "payment_token": "tok" }))).await;
+        assert_eq!((status, order["status"].as_str()), (200, Some("Completed")));
+
+        let (status, order) = request(server.address, "GET", "/orders/1", CUSTOMER, None).await;
+        assert_eq!(status, 200);
+        assert_eq!((order["order_id"].as_i64(), order["status"].as_str(), order["sequence_number"].as_i64()), (Some(1), Some("Completed"), Some(3)));
+    }
+
+    #[tokio::test]
+    async fn rejects_invalid_requests() {
+        let server = start().await;
+
+        let (status, error) = request(server.address, "PUT", "/orders/1/cart", CUSTOMER, Some(json!({ "items": {} }))).await;
+        assert_eq!((status, error["error"].as_str()), (400, Some("Cart can't be empty")));
+
+        let invalid_address = json!({ "street": "1 Main Street", "postal_code": "90210" });
+        let (status, error) = request(server.address, "PUT", "/orders/1/delivery-address", CUSTOMER, Some(invalid_address)).await;
+        assert_eq!((status, error["error"].as_str()), (400, Some("Invalid postal code format")));
+
+        let (status, error) = request(server.address, "PUT", "/orders/1/cart", CUSTOMER, Some(json!({ "items": { "apple": -1 } }))).await;
+        assert_eq!(status, 422);
+        assert!(error["error"].is_string());
+
+        let (status, _) = request(server.address, "GET", "/orders/one", CUSTOMER, None).await;
+        assert_eq!(status, 400);
+
+        let (status, error) = request(server.address, "PUT", "/orders/1/cart", &[("X-Customer-Id", "me")], Some(cart())).await;
+        assert_eq!((status, error["error"].as_str()), (401, Some("Invalid customer id")));
+    }
+
+    #[tokio::test]
+    async fn maps_the_domain_errors_onto_status_codes() {
+        let server = start().await;
+
+        let (status, error) = request(server.address, "GET", "/orders/1", CUSTOMER, None).await;
+        assert_eq!((status, error["error"].as_str()), (404, Some("Order not found")));
+
+        let (status, _) = request(server.address, "PUT", "/orders/1/cart", &[("X-Customer-Id", "7")], Some(cart())).await;
+        assert_eq!(status, 200);
+
+        let (status, error) = request(server.address, "POST", "/orders/1/payment", &[("X-Customer-Id", "7")], Some(json!({ "payment_token": "tok" }))).await;
+        assert_eq!((status, error["error"].as_str()), (409, Some("Order not ready to be paid.")));
+
+        let (status, error) = request(server.address, "PUT", "/orders/1/cart", &[("X-Customer-Id", "8")], Some(cart())).await;
+        assert_eq!((status, error["error"].as_str()), (403, Some("Not the owner of the order")));
+    }
+
+    #[tokio::test]
+    async fn authenticates_the_callers() {
+        let server = start().await;
+        assert_eq!(request(server.address, "PUT", "/orders/1/cart", CUSTOMER, Some(cart())).await.0, 200);
+
+        let (status, error) = request(server.address, "GET", "/orders/1", &[], None).await;
+        assert_eq!((status, error["error"].as_str()), (401, Some("Missing customer id")));
+        let (status, error) = request(server.address, "PUT", "/orders/1/cart", &[], Some(cart())).await;
+        assert_eq!((status, error["error"].as_str()), (401, Some("Missing customer id")));
+
+        let (status, error) = request(server.address, "GET", "/orders/1", &[("X-Admin-Token", "guess")], None).await;
+        assert_eq!((status, error["error"].as_str()), (401, Some("Invalid admin token")));
+
+        let (status, error) = request(server.address, "GET", "/orders/1", &[("X-Customer-Id", "8")], None).await;
+        assert_eq!((status, error["error"].as_str()), (403, Some("Not the owner of the order")));
+
+        let (status, order) = request(server.address, "GET", "/orders/1", ADMIN, None).await;
+        assert_eq!((status, order["sequence_number"].as_i64()), (200, Some(1)));
+        assert_eq!(request(server.address, "PUT", "/orders/1/delivery-address", ADMIN, Some(address())).await.0, 200);
+    }
+
+    #[tokio::test]
+    async fn shuts_down_gracefully() {
+        let server = start().await;
+        assert_eq!(request(server.address, "GET", "/health", &[], None).await.0, 200);
+
+        server.shutdown.send(()).unwrap();
+
+        server.task.await.unwrap().unwrap();
+        assert!(TcpStream::connect(server.address).await.is_err());
+    }
+}
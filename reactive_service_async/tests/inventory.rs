@@ -0,0 +1,88 @@
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::time::{Duration, SystemTime};
+
+    use futures::future::join_all;
+    use reactive_service_async::infra::inmem_journal::InMemoryJournal;
+    use reactive_service_async::inventory::{AsyncInventory, InventoryConfig, InventoryService};
+    use reactive_service_async::order_service::{OrderService, PayOrder, Principal, UpdateCart, UpdateDeliveryAddress};
+    use reactive_service_async::payment_processor::{LocalPaymentProcessor, PaymentToken};
+    use reactive_service_async::shipping_calculator::LocalShippingCalculator;
+    use reactive_service_async::tax_calculator::LocalTaxCalculator;
+    use reactive_service_domain::inventory_item::InventoryItemEvent;
+    use reactive_service_domain::non_empty_cart::{NonEmptyCart, Quantity, Sku};
+    use reactive_service_domain::order_entity::OrderEvent;
+    use reactive_service_domain::order_state::{DeliveryAddress, OrderState, Street};
+
+    type Stock = InventoryService<InMemoryJournal<InventoryItemEvent>>;
+    type Service<'a> = OrderService<InMemoryJournal<OrderEvent>, LocalShippingCalculator, LocalTaxCalculator, LocalPaymentProcessor, &'a Stock>;
+
+    fn apple() -> Sku {
+        Sku("apple".to_owned())
+    }
+
+    fn cart(items: &[(&str, u16)]) -> NonEmptyCart {
+        NonEmptyCart::new(items.iter().map(|(sku, quantity)| (Sku(sku.to_string()), Quantity(*quantity))).collect::<HashMap<_, _>>()).unwrap()
+    }
+
+    async fn stock(items: &[(&str, u32)]) -> Stock {
+        let stock = InventoryService::new(InMemoryJournal::new().unwrap());
+        for (sku, quantity) in items {
+            stock.receive_stock(&Sku(sku.to_string()), *quantity).await.unwrap();
+        }
+        stock
+    }
+
+    fn service(stock: &Stock) -> Service<'_> {
+        OrderService::with_inventory(
+            InMemoryJournal::new().unwrap(), LocalShippingCalculator{}, LocalTaxCalculator{}, LocalPaymentProcessor{}, stock
+        )
+    }
+
+    async fn check_out(service: &Service<'_>, order_id: i64, cart: NonEmptyCart) -> Result<(), &'static str> {
+        service.update_cart(&Principal::Admin, UpdateCart { order_id, cart }).await.unwrap();
+        let delivery_address = DeliveryAddress { street: Street("1 Main Street".to_owned()), postal_code: "H0H 0H0".parse().unwrap() };
+        service.update_delivery_address(&Principal::Admin, UpdateDeliveryAddress { order_id, delivery_address }).await.unwrap();
+        service.pay_order(&Principal::Admin, PayOrder { order_id, payment_token: PaymentToken::new("token") }).await.map(|_| ())
+    }
+
+    #[tokio::test]
+    async fn reserves_the_stock_of_the_order_on_checkout() {
+        let stock = stock(&[("apple", 3), ("chocolate", 1)]).await;
+        let service = service(&stock);
+        let now = SystemTime::now();
+
+        check_out(&service, 1, cart(&[("apple", 2), ("chocolate", 1)])).await.unwrap();
+        assert_eq!(stock.get_stock(&apple()).await.unwrap().state.available(now), 1);
+
+        // Short of chocolate: the apple is released, the order stays unpaid
+        assert_eq!(check_out(&service, 2, cart(&[("apple", 1), ("chocolate", 1)])).await.err(), Some("Insufficient stock"));
+        assert_eq!(stock.get_stock(&apple()).await.unwrap().state.available(now), 1);
+        assert!(matches!(service.get_order(2).await.unwrap().state, OrderState::WithAddress(_)));
+    }
+
+    #[tokio::test]
+    async fn an_expired_checkout_gives_its_stock_back() {
+        let stock = InventoryService::with_config(InMemoryJournal::new().unwrap(), InventoryConfig { reservation_ttl: Duration::from_millis(50) });
+        stock.receive_stock(&apple(), 1).await.unwrap();
+        stock.reserve_stock(1, &cart(&[("apple", 1)])).await.unwrap();
+        assert_eq!(stock.reserve_stock(2, &cart(&[("apple", 1)])).await.err(), Some("Insufficient stock"));
+
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        stock.reserve_stock(2, &cart(&[("apple", 1)])).await.unwrap();
+        assert_eq!(stock.ship_stock(1, &cart(&[("apple", 1)])).await.err(), Some("No reservation for the order"));
+    }
+
+    #[tokio::test]
+    async fn never_reserves_more_than_the_stock_of_a_hot_sku() {
+        let stock = stock(&[("apple", 100)]).await;
+        let service = service(&stock);
+
+        let results = join_all((0..200).map(|order_id| check_out(&service, order_id, cart(&[("apple", 1)])))).await;
+
+        assert_eq!(results.iter().filter(|result| result.is_ok()).count(), 100);
+        let apple = stock.get_stock(&apple()).await.unwrap().state;
+        assert_eq!((apple.reservations.len(), apple.available(SystemTime::now())), (100, 0));
+    }
+}
@@ -0,0 +1,59 @@
+#[cfg(test)]
+mod tests {
+    use reactive_service_async::infra::file_journal::FileJournal;
+    use reactive_service_async::infra::group_commit_journal::{GroupCommitConfig, GroupCommitJournal};
+    use reactive_service_async::infra::inmem_journal::InMemoryJournal;
+    use reactive_service_async::infra::postgres_events_store::PostgresEventStore;
+    use reactive_service_async::infra::scylla_event_store::ScyllaEventStore;
+    use reactive_service_async::infra::sqlite_event_store::SqliteEventStore;
+    use reactive_service_async::journal_conformance::{check_deserialization_failure, check_global_order, check_journal};
+
+    #[tokio::test]
+    async fn in_memory_journal() {
+        let journal = InMemoryJournal::new().unwrap();
+        check_journal(&journal).await;
+        check_global_order(&journal).await;
+    }
+
+    #[tokio::test]
+    async fn group_commit_journal() {
+        let journal = GroupCommitJournal::new(InMemoryJournal::new().unwrap(), GroupCommitConfig::default());
+        check_journal(&journal).await;
+        check_global_order(&journal).await;
+    }
+
+    #[tokio::test]
+    async fn file_journal() {
+        let directory = tempfile::tempdir().unwrap();
+        let journal = FileJournal::new(directory.path()).await.unwrap();
+        check_journal(&journal).await;
+        check_global_order(&journal).await;
+        check_deserialization_failure(&journal).await;
+    }
+
+    #[tokio::test]
+    async fn sqlite_event_store() {
+        let directory = tempfile::tempdir().unwrap();
+        let journal = SqliteEventStore::new(directory.path().join("events.db")).await.unwrap();
+        check_journal(&journal).await;
+        check_global_order(&journal).await;
+        check_deserialization_failure(&journal).await;
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a PostgreSQL server on localhost"]
+    async fn postgres_event_store() {
+        let journal = PostgresEventStore::new().await.unwrap();
+        check_journal(&journal).await;
+        check_global_order(&journal).await;
+        check_deserialization_failure(&journal).await;
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a ScyllaDB node on 127.0.0.1:9042"]
+    async fn scylla_event_store() {
+        let journal = ScyllaEventStore::new("127.0.0.1:9042").await.unwrap();
+        check_journal(&journal).await;
+        check_deserialization_failure(&journal).await;
+    }
+}
@@ -0,0 +1,113 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::time::Duration;
+    use tokio::sync::Notify;
+
+    use reactive_service_domain::aggregate_root::SequencedEvent;
+    use reactive_service_domain::order_entity::OrderEvent;
+    use reactive_service_domain::order_state::{Empty, OrderState};
+    use reactive_service_async::infra::inmem_journal::InMemoryJournal;
+    use reactive_service_async::middleware::{
+        CommandHandler, CommandLatencies, CommandTrace, ConcurrencyLimitConfig, ConcurrencyLimitLayer, LatencyLayer, Pipeline,
+        TimeoutLayer, TraceLayer
+    };
+    use reactive_service_async::order_service::{
+        CommandResult, EventsJournal, OrderCommand, OrderId, OrderService, PayOrder, Principal
+    };
+    use reactive_service_async::payment_processor::{LocalPaymentProcessor, PaymentToken};
+    use reactive_service_async::shipping_calculator::LocalShippingCalculator;
+    use reactive_service_async::tax_calculator::LocalTaxCalculator;
+    use crate::common::update_cart;
+
+    fn service<E: EventsJournal<OrderEvent>>(journal: E) -> OrderService<E, LocalShippingCalculator, LocalTaxCalculator, LocalPaymentProcessor> {
+        OrderService::new(journal, LocalShippingCalculator{}, LocalTaxCalculator{}, LocalPaymentProcessor{})
+    }
+
+    /// Persists its first event late.
+    struct SlowOnce { journal: InMemoryJournal<OrderEvent>, slow: AtomicBool }
+
+    impl EventsJournal<OrderEvent> for SlowOnce {
+        async fn persist_event(&self, entity_id: OrderId, evt_w_seq: &SequencedEvent<OrderEvent>) -> Result<(), &'static str> {
+            if self.slow.swap(false, Ordering::SeqCst) {
+                tokio::time::sleep(Duration::from_millis(200)).await;
+            }
+            self.journal.persist_event(entity_id, evt_w_seq).await
+        }
+
+        async fn retrieve_events(&self, entity_id: OrderId) -> Result<Vec<SequencedEvent<OrderEvent>>, &'static str> {
+            self.journal.retrieve_events(entity_id).await
+        }
+
+        async fn persist_events(&self, events: &[(OrderId, SequencedEvent<OrderEvent>)]) -> Result<(), &'static str> {
+            if self.slow.swap(false, Ordering::SeqCst) {
+                tokio::time::sleep(Duration::from_millis(200)).await;
+            }
+            self.journal.persist_events(events).await
+        }
+    }
+
+    /// Handles each command once released.
+    #[derive(Default)]
+    struct Gated { release: Notify }
+
+    impl CommandHandler for Gated {
+        async fn call(&self, _: &Principal, _: OrderCommand) -> CommandResult {
+            self.release.notified().await;
+            Ok((OrderState::Empty(Empty {}), vec![]))
+        }
+    }
+
+    #[tokio::test]
+    async fn traces_and_measures_the_commands() {
+        let traces = Arc::new(Mutex::new(Vec::<CommandTrace>::new()));
+        let latencies = Arc::new(CommandLatencies::default());
+        let recorded = traces.clone();
+        let pipeline = Pipeline::new(service(InMemoryJournal::new().unwrap()))
+            .layer(LatencyLayer::new(latencies.clone()))
+            .layer(TraceLayer::new(move |trace: &CommandTrace| recorded.lock().unwrap().push(trace.clone())));
+
+        pipeline.handle_as(&Principal::Customer(3), update_cart(1).into()).await.unwrap();
+        let pay_order = OrderCommand::PayOrder(PayOrder { order_id: 1, payment_token: PaymentToken::new("token") });
+        assert!(pipeline.handle_as(&Principal::Admin, pay_order).await.is_err());
+
+        let outcomes: Vec<_> = traces.lock().unwrap().iter().map(|trace| (trace.command, trace.customer_id, trace.outcome)).collect();
+        assert_eq!(outcomes, vec![("update_cart", Some(3), Ok(1)), ("pay_order", None, Err("Order not ready to be paid."))]);
+        assert_eq!(latencies.get("update_cart").map(|histogram| (histogram.count(), histogram.errors())), Some((1, 0)));
+        assert_eq!(latencies.get("pay_order").map(|histogram| (histogram.count(), histogram.errors())), Some((1, 1)));
+    }
+
+    #[tokio::test]
+    async fn an_order_cancelled_while_persisted_is_restored_from_the_journal() {
+        let journal = SlowOnce { journal: InMemoryJournal::new().unwrap(), slow: AtomicBool::new(true) };
+        let service = service(journal);
+        let pipeline = Pipeline::new(&service).layer(TimeoutLayer::new(Duration::from_millis(20)));
+
+        assert_eq!(pipeline.handle_as(&Principal::Admin, update_cart(1).into()).await.err(), Some("Command timed out"));
+        let (_, events) = pipeline.handle_as(&Principal::Admin, update_cart(1).into()).await.unwrap();
+        assert_eq!(events.iter().map(|event| event.sequence_number).collect::<Vec<_>>(), vec![1]);
+        assert_eq!(service.get_order(1).await.unwrap().sequence_number, 1);
+    }
+
+    #[tokio::test]
+    async fn a_command_over_the_limit_waits_for_its_turn() {
+        let config = ConcurrencyLimitConfig { max_in_flight: 1, max_wait: Duration::from_millis(50) };
+        let gated = Arc::new(Gated::default());
+        let pipeline = Arc::new(Pipeline::new(gated.clone()).layer(ConcurrencyLimitLayer::with_config(config)));
+
+        let first = tokio::spawn({
+            let pipeline = pipeline.clone();
+            async move { pipeline.handle_as(&Principal::Admin, update_cart(1).into()).await }
+        });
+        tokio::task::yield_now().await;
+        assert_eq!(pipeline.handle_as(&Principal::Admin, update_cart(2).into()).await.err(), Some("Too many commands in progress"));
+
+        gated.release.notify_one();
+        assert!(first.await.unwrap().is_ok());
+        gated.release.notify_one();
+        assert!(pipeline.handle_as(&Principal::Admin, update_cart(2).into()).await.is_ok());
+    }
+}
@@ -0,0 +1,53 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use reactive_service_domain::order_state::OrderState;
+    use reactive_service_async::order_service::Principal;
+    use crate::common::{service, update_cart};
+
+    #[tokio::test]
+    async fn returns_the_state_and_the_sequence_number() {
+        let service = service();
+
+        let order = service.get_order(1).await.unwrap();
+        assert!(matches!(order.state, OrderState::Empty(_)));
+        assert_eq!(order.sequence_number, 0);
+
+        service.update_cart(&Principal::Admin, update_cart(1)).await.unwrap();
+        service.update_cart(&Principal::Admin, update_cart(1)).await.unwrap();
+
+        let order = service.get_order(1).await.unwrap();
+        assert!(matches!(order.state, OrderState::WithCart(_)));
+        assert_eq!(order.sequence_number, 2);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn waits_for_the_version() {
+        let service = Arc::new(service());
+
+        let waiting = tokio::spawn({
+            let service = service.clone();
+            async move { service.get_order_at_least(1, 2, Duration::from_secs(5)).await }
+        });
+
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        service.update_cart(&Principal::Admin, update_cart(1)).await.unwrap();
+        service.update_cart(&Principal::Admin, update_cart(1)).await.unwrap();
+
+        assert_eq!(waiting.await.unwrap().unwrap().sequence_number, 2);
+    }
+
+    #[tokio::test]
+    async fn times_out_waiting_for_the_version() {
+        let service = service();
+        service.update_cart(&Principal::Admin, update_cart(1)).await.unwrap();
+
+        let result = service.get_order_at_least(1, 2, Duration::from_millis(50)).await;
+        assert_eq!(result.err(), Some("Timed out waiting for the version"));
+        assert_eq!(service.get_order_at_least(1, 1, Duration::ZERO).await.unwrap().sequence_number, 1);
+    }
+}
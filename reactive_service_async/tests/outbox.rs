@@ -0,0 +1,114 @@
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    use reactive_service_domain::aggregate_root::SequencedEvent;
+    use reactive_service_async::infra::postgres_events_store::PostgresEventStore;
+    use reactive_service_async::infra::postgres_outbox::PostgresOutboxRelay;
+    use reactive_service_async::order_service::EventsJournal;
+    use reactive_service_async::outbox::{InProcessPublisher, JsonLinesPublisher, MessagePublisher, OutboxMessage, OutboxRelayConfig};
+    use tokio::sync::mpsc::UnboundedReceiver;
+
+    fn sequenced(sequence_number: i64) -> SequencedEvent<String> {
+        SequencedEvent { sequence_number, event: format!("event {}", sequence_number) }
+    }
+
+    /// Unique across the runs, for the shared database.
+    fn unique_id() -> i64 {
+        SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_nanos() as i64 / 8 * 8
+    }
+
+    /// Fails to publish the messages of an entity, while it is poisoned.
+    struct PoisonedPublisher {
+        inner: InProcessPublisher,
+        poisoned_entity_id: i64,
+        poisoned: AtomicBool,
+    }
+
+    impl MessagePublisher for PoisonedPublisher {
+        async fn publish(&self, message: &OutboxMessage) -> Result<(), &'static str> {
+            if message.entity_id == self.poisoned_entity_id && self.poisoned.load(Ordering::Relaxed) {
+                return Err("Poisoned message");
+            }
+            self.inner.publish(message).await
+        }
+    }
+
+    /// The messages received of the given entities: the outbox is shared by the runs.
+    fn received(receiver: &mut UnboundedReceiver<OutboxMessage>, entity_ids: &[i64]) -> Vec<(i64, i64)> {
+        std::iter::from_fn(|| receiver.try_recv().ok())
+            .filter(|message| entity_ids.contains(&message.entity_id))
+            .map(|message| (message.entity_id, message.sequence_number))
+            .collect()
+    }
+
+    /// One test for the relays: they share the outbox.
+    #[tokio::test]
+    async fn postgres_outbox_relays_the_events_in_order_and_parks_the_poison() {
+        let store = PostgresEventStore::with_outbox().await.unwrap();
+        let without_outbox = PostgresEventStore::new().await.unwrap();
+        let entity_id = unique_id();
+        let (other, poison, not_in_outbox) = (entity_id + 1, entity_id + 2, entity_id + 3);
+        let entity_ids = [entity_id, other, poison, not_in_outbox];
+
+        let (inner, mut receiver) = InProcessPublisher::new();
+        let publisher = PoisonedPublisher { inner, poisoned_entity_id: poison, poisoned: AtomicBool::new(true) };
+        let config = OutboxRelayConfig { initial_backoff: Duration::ZERO, max_attempts: 3, ..OutboxRelayConfig::default() };
+        let relay = PostgresOutboxRelay::new(&store, publisher, config);
+
+        store.persist_events(&[(entity_id, sequenced(1)), (other, sequenced(1))]).await.unwrap();
+        store.persist_event(entity_id, &sequenced(2)).await.unwrap();
+        without_outbox.persist_event(not_in_outbox, &sequenced(1)).await.unwrap();
+        store.persist_event(poison, &sequenced(1)).await.unwrap();
+        store.persist_event(entity_id, &sequenced(3)).await.unwrap();
+
+        // Blocked by the poison until it is parked
+        while relay.relay().await.unwrap() > 0 {}
+        assert_eq!(received(&mut receiver, &entity_ids), vec![(entity_id, 1), (other, 1), (entity_id, 2)]);
+        relay.relay().await.unwrap();
+        relay.relay().await.unwrap();
+        while relay.relay().await.unwrap() > 0 {}
+        assert_eq!(received(&mut receiver, &entity_ids), vec![(entity_id, 3)]);
+
+        let parked = relay.parked().await.unwrap().into_iter().find(|parked| parked.message.entity_id == poison).expect("Not parked");
+        assert_eq!((parked.attempts, parked.last_error.as_str(), parked.message.payload.as_str()), (3, "Poisoned message", r#""event 1""#));
+
+        relay.publisher().poisoned.store(false, Ordering::Relaxed);
+        assert!(relay.unpark(parked.message.position).await.unwrap());
+        assert!(!relay.unpark(parked.message.position).await.unwrap());
+        while relay.relay().await.unwrap() > 0 {}
+        assert_eq!(received(&mut receiver, &entity_ids), vec![(poison, 1)]);
+
+        // Woken up by the new events
+        let stop = AtomicBool::new(false);
+        let persisting = async {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            store.persist_event(other, &sequenced(2)).await.unwrap();
+            let message = tokio::time::timeout(Duration::from_secs(5), receiver.recv()).await.expect("Not relayed").unwrap();
+            assert_eq!((message.entity_id, message.sequence_number), (other, 2));
+            stop.store(true, Ordering::Relaxed);
+        };
+        let (_, running) = tokio::join!(persisting, relay.run(&stop));
+        running.unwrap();
+    }
+
+    #[tokio::test]
+    async fn json_lines_publisher_appends_to_the_file() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("messages.jsonl");
+        let publisher = JsonLinesPublisher::file(&path).unwrap();
+        for position in 1..=2 {
+            let message = OutboxMessage { position, entity_id: 1, sequence_number: position, payload: format!(r#""event {}""#, position) };
+            publisher.publish(&message).await.unwrap();
+        }
+
+        let lines: Vec<serde_json::Value> = std::fs::read_to_string(&path).unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[1]["position"], 2);
+        assert_eq!(lines[1]["event"], "event 2");
+    }
+}
@@ -0,0 +1,143 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    use reactive_service_domain::non_empty_cart::{NonEmptyCart, Quantity, Sku};
+    use reactive_service_domain::order_state::{DeliveryAddress, Street};
+    use reactive_service_async::catch_up::CatchUpConfig;
+    use reactive_service_async::infra::postgres_projections::{PostgresOrderSummaries, PostgresSalesBySku};
+    use reactive_service_async::order_service::{GlobalEventsJournal, PayOrder, Principal, UpdateCart, UpdateDeliveryAddress};
+    use reactive_service_async::payment_processor::PaymentToken;
+    use reactive_service_async::projections::{
+        InMemoryProjection, OrderStatus, OrderSummaries, Projection, Projector, SalesBySku, SkuSales
+    };
+    use crate::common::{service, Service};
+
+    fn cart(sku: &str, quantity: u16) -> NonEmptyCart {
+        NonEmptyCart::new(HashMap::from([(Sku(sku.to_owned()), Quantity(quantity))])).unwrap()
+    }
+
+    /// An order with a cart, completed or not.
+    async fn place_order(service: &Service, order_id: i64, cart: NonEmptyCart, completed: bool) {
+        service.update_cart(&Principal::Admin, UpdateCart { order_id, cart }).await.unwrap();
+        if completed {
+            let delivery_address = DeliveryAddress { street: Street("1 Main Street".to_owned()), postal_code: "H0H 0H0".parse().unwrap() };
+            service.update_delivery_address(&Principal::Admin, UpdateDeliveryAddress { order_id, delivery_address }).await.unwrap();
+            service.pay_order(&Principal::Admin, PayOrder { order_id, payment_token: PaymentToken::new("token") }).await.unwrap();
+        }
+    }
+
+    /// Unique across the runs, for the shared database.
+    fn unique_id() -> i64 {
+        SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_nanos() as i64
+    }
+
+    fn small_batches() -> CatchUpConfig {
+        CatchUpConfig { batch_size: 2, ..CatchUpConfig::default() }
+    }
+
+    #[tokio::test]
+    async fn projects_the_history() {
+        let service = service();
+        place_order(&service, 1, cart("apple", 2), true).await;
+        place_order(&service, 2, cart("apple", 3), true).await;
+        place_order(&service, 3, cart("apple", 7), false).await;
+
+        let summaries = InMemoryProjection::<OrderSummaries>::new();
+        let sales = InMemoryProjection::<SalesBySku>::new();
+        assert_eq!(Projector::new(service.events_journal(), &summaries, small_batches()).await.unwrap().catch_up().await.unwrap(), 7);
+        assert_eq!(Projector::new(service.events_journal(), &sales, small_batches()).await.unwrap().catch_up().await.unwrap(), 7);
+
+        let summary = summaries.read(|view| view.get(1).cloned()).unwrap().unwrap();
+        assert_eq!((summary.status, summary.total_cents, summary.postal_code.as_deref()), (OrderStatus::Completed, 330, Some("H0H 0H0")));
+        assert_eq!(summaries.read(|view| view.get(3).map(|s| s.status)).unwrap(), Some(OrderStatus::WithCart));
+        assert_eq!(sales.read(|view| view.get(&Sku("apple".to_owned())).cloned()).unwrap(), Some(SkuSales { quantity: 5, orders: 2 }));
+        assert_eq!(summaries.checkpoint().await.unwrap(), 7);
+    }
+
+    #[tokio::test]
+    async fn applies_the_redelivered_events_once() {
+        let service = service();
+        place_order(&service, 1, cart("apple", 2), true).await;
+        let events = service.events_journal().read_all(1, 100).await.unwrap();
+
+        let sales = InMemoryProjection::<SalesBySku>::new();
+        sales.apply(&events[..2]).await.unwrap();
+        sales.apply(&events).await.unwrap();
+        sales.apply(&events).await.unwrap();
+        assert_eq!(sales.read(|view| view.get(&Sku("apple".to_owned())).cloned()).unwrap(), Some(SkuSales { quantity: 2, orders: 1 }));
+    }
+
+    #[tokio::test]
+    async fn resumes_from_the_checkpoint_and_rebuilds() {
+        let service = service();
+        place_order(&service, 1, cart("apple", 2), true).await;
+        let summaries = InMemoryProjection::<OrderSummaries>::new();
+        Projector::new(service.events_journal(), &summaries, small_batches()).await.unwrap().catch_up().await.unwrap();
+
+        place_order(&service, 2, cart("apple", 1), false).await;
+        let mut projector = Projector::new(service.events_journal(), &summaries, small_batches()).await.unwrap();
+        assert_eq!(projector.catch_up().await.unwrap(), 1);
+        assert_eq!(summaries.read(|view| view.len()).unwrap(), 2);
+
+        assert_eq!(projector.rebuild().await.unwrap(), 4);
+        assert_eq!(summaries.read(|view| view.len()).unwrap(), 2);
+        assert_eq!(summaries.checkpoint().await.unwrap(), 4);
+    }
+
+    #[tokio::test]
+    async fn follows_the_new_events() {
+        let service = service();
+        let summaries = InMemoryProjection::<OrderSummaries>::new();
+        let mut projector = Projector::new(service.events_journal(), &summaries, small_batches()).await.unwrap();
+        let placing = async {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            place_order(&service, 1, cart("apple", 1), false).await;
+        };
+        let (_, projected) = tokio::join!(placing, projector.project(Duration::from_secs(5)));
+        assert_eq!(projected.unwrap(), 1);
+        assert_eq!(summaries.read(|view| view.get(1).map(|s| s.status)).unwrap(), Some(OrderStatus::WithCart));
+    }
+
+    /// The view tables are shared by the runs: the projection is rebuilt from a journal of its own.
+    #[tokio::test]
+    async fn postgres_order_summaries() {
+        let service = service();
+        let order_id = unique_id();
+        place_order(&service, order_id, cart("apple", 1), true).await;
+        place_order(&service, order_id + 1, cart("apple", 1), false).await;
+
+        let summaries = PostgresOrderSummaries::new().await.unwrap();
+        let mut projector = Projector::new(service.events_journal(), &summaries, small_batches()).await.unwrap();
+        assert_eq!(projector.rebuild().await.unwrap(), 4);
+        assert_eq!(summaries.checkpoint().await.unwrap(), 4);
+        summaries.apply(&service.events_journal().read_all(1, 100).await.unwrap()).await.unwrap();
+
+        let summary = summaries.get(order_id).await.unwrap().unwrap();
+        assert_eq!((summary.status, summary.total_cents, summary.postal_code.as_deref()), (OrderStatus::Completed, 330, Some("H0H 0H0")));
+        assert_eq!(summary.last_updated, 3);
+        assert_eq!(summaries.get(order_id + 1).await.unwrap().map(|s| s.status), Some(OrderStatus::WithCart));
+        assert_eq!(summaries.get(order_id + 2).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn postgres_sales_by_sku() {
+        let service = service();
+        let sku = format!("apple-{}", unique_id());
+        place_order(&service, 1, cart(&sku, 2), true).await;
+        place_order(&service, 2, cart(&sku, 3), true).await;
+        place_order(&service, 3, cart(&sku, 7), false).await;
+
+        let sales = PostgresSalesBySku::new().await.unwrap();
+        let mut projector = Projector::new(service.events_journal(), &sales, small_batches()).await.unwrap();
+        assert_eq!(projector.rebuild().await.unwrap(), 7);
+        sales.apply(&service.events_journal().read_all(1, 100).await.unwrap()).await.unwrap();
+
+        assert_eq!(sales.get(&Sku(sku.clone())).await.unwrap(), Some(SkuSales { quantity: 5, orders: 2 }));
+        assert_eq!(projector.rebuild().await.unwrap(), 7);
+        assert_eq!(sales.get(&Sku(sku)).await.unwrap(), Some(SkuSales { quantity: 5, orders: 2 }));
+    }
+}
@@ -0,0 +1,129 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    use reactive_service_domain::order_entity::OrderEvent;
+    use reactive_service_domain::order_state::{DeliveryAddress, OrderState, Street};
+    use reactive_service_async::catch_up::InMemoryCheckpointStore;
+    use reactive_service_async::infra::inmem_journal::InMemoryJournal;
+    use reactive_service_async::infra::postgres_events_store::PostgresEventStore;
+    use reactive_service_async::infra::sqlite_event_store::SqliteEventStore;
+    use reactive_service_async::order_service::{EventsJournal, ExpireOrder, OrderService, PayOrder, Principal, UpdateDeliveryAddress};
+    use reactive_service_async::payment_processor::{LocalPaymentProcessor, PaymentToken};
+    use reactive_service_async::scheduler::{
+        Clock, CommandScheduler, InMemoryScheduleStore, ManualClock, Scheduled, ScheduledCommand, ScheduleStore, SchedulerConfig
+    };
+    use reactive_service_async::shipping_calculator::LocalShippingCalculator;
+    use reactive_service_async::tax_calculator::LocalTaxCalculator;
+    use crate::common::update_cart;
+
+    const TTL: Duration = Duration::from_secs(60);
+
+    fn service<E: EventsJournal<OrderEvent>>(journal: E) -> OrderService<E, LocalShippingCalculator, LocalTaxCalculator, LocalPaymentProcessor> {
+        OrderService::new(journal, LocalShippingCalculator{}, LocalTaxCalculator{}, LocalPaymentProcessor{})
+    }
+
+    fn config() -> SchedulerConfig {
+        SchedulerConfig { order_ttl: TTL, ..SchedulerConfig::default() }
+    }
+
+    fn expiry(order_id: i64, sequence_number: i64, due_at: SystemTime) -> Scheduled {
+        Scheduled { due_at, command: ScheduledCommand::ExpireOrder(ExpireOrder { order_id, sequence_number }) }
+    }
+
+    /// Unique across the runs, for the shared database.
+    fn unique_id() -> i64 {
+        SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_nanos() as i64
+    }
+
+    #[tokio::test]
+    async fn expires_an_order_left_untouched_for_the_ttl() {
+        let service = service(InMemoryJournal::new().unwrap());
+        let (checkpoints, clock) = (InMemoryCheckpointStore::default(), ManualClock::default());
+        let mut scheduler = CommandScheduler::new(service.events_journal(), &checkpoints, InMemoryScheduleStore::default(), &clock, config())
+            .await
+            .unwrap();
+
+        service.update_cart(&Principal::Admin, update_cart(1)).await.unwrap();
+        scheduler.catch_up().await.unwrap();
+        clock.advance(TTL / 2);
+        service.update_cart(&Principal::Admin, update_cart(1)).await.unwrap();
+        scheduler.catch_up().await.unwrap();
+
+        // Scheduled again by the last change
+        clock.advance(TTL / 2);
+        assert_eq!(scheduler.deliver_due(&service).await.unwrap(), 0);
+        clock.advance(TTL / 2);
+        assert_eq!(scheduler.deliver_due(&service).await.unwrap(), 1);
+        assert!(matches!(service.get_order(1).await.unwrap().state, OrderState::Expired(_)));
+        assert_eq!(scheduler.deliver_due(&service).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn a_paid_order_never_expires() {
+        let service = service(InMemoryJournal::new().unwrap());
+        let (checkpoints, clock) = (InMemoryCheckpointStore::default(), ManualClock::default());
+        let mut scheduler = CommandScheduler::new(service.events_journal(), &checkpoints, InMemoryScheduleStore::default(), &clock, config())
+            .await
+            .unwrap();
+
+        service.update_cart(&Principal::Admin, update_cart(1)).await.unwrap();
+        let delivery_address = DeliveryAddress { street: Street("1 Main Street".to_owned()), postal_code: "H0H 0H0".parse().unwrap() };
+        service.update_delivery_address(&Principal::Admin, UpdateDeliveryAddress { order_id: 1, delivery_address }).await.unwrap();
+        service.pay_order(&Principal::Admin, PayOrder { order_id: 1, payment_token: PaymentToken::new("token") }).await.unwrap();
+        scheduler.catch_up().await.unwrap();
+
+        // The stale expiry is rejected, then removed
+        clock.advance(TTL);
+        assert_eq!(scheduler.deliver_due(&service).await.unwrap(), 1);
+        assert!(matches!(service.get_order(1).await.unwrap().state, OrderState::Completed(_)));
+        assert!(scheduler.store().due(clock.now() + TTL, 10).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn the_scheduled_expiries_survive_a_restart() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("events.db");
+        let clock = ManualClock::default();
+        {
+            let service = service(SqliteEventStore::new(&path).await.unwrap());
+            let store = service.events_journal();
+            service.update_cart(&Principal::Admin, update_cart(1)).await.unwrap();
+            let mut scheduler = CommandScheduler::new(store, store, store, &clock, config()).await.unwrap();
+            assert_eq!(scheduler.catch_up().await.unwrap(), 1);
+        }
+
+        let service = service(SqliteEventStore::new(&path).await.unwrap());
+        let store = service.events_journal();
+        let mut scheduler = CommandScheduler::new(store, store, store, &clock, config()).await.unwrap();
+        assert_eq!(scheduler.catch_up().await.unwrap(), 0);
+        clock.advance(TTL);
+        assert_eq!(scheduler.deliver_due(&service).await.unwrap(), 1);
+        assert!(matches!(service.get_order(1).await.unwrap().state, OrderState::Expired(_)));
+    }
+
+    #[tokio::test]
+    async fn postgres_replaces_a_pending_command_scheduled_again() {
+        let store = PostgresEventStore::new().await.unwrap();
+        let order_id = unique_id();
+        let now = SystemTime::now();
+        let (first, second) = (expiry(order_id, 1, now), expiry(order_id, 2, now));
+
+        store.schedule(&first).await.unwrap();
+        store.schedule(&second).await.unwrap();
+        let pending = || async {
+            store.due(now + TTL, 1_000_000).await.unwrap().into_iter()
+                .filter(|scheduled| scheduled.key() == first.key())
+                .map(|scheduled| scheduled.command)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(pending().await, vec![second.command.clone()]);
+
+        store.remove(&first).await.unwrap();
+        assert_eq!(pending().await.len(), 1);
+        store.remove(&second).await.unwrap();
+        assert!(pending().await.is_empty());
+    }
+}
@@ -0,0 +1,84 @@
+/// Integration tests against a local single node, e.g.
+/// `docker run --rm -p 9042:9042 scylladb/scylla --smp 1`
+#[cfg(test)]
+mod tests {
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    use reactive_service_async::infra::scylla_event_store::{ScyllaEventStore, ScyllaEventStoreConfig};
+    use reactive_service_async::order_service::EventsJournal;
+    use reactive_service_domain::aggregate_root::SequencedEvent;
+    use reactive_service_domain::order_entity::OrderEvent;
+    use scylla::statement::Consistency;
+
+    /// The keyspace is kept between runs, each test uses its own entities.
+    fn unique_entity_id() -> i64 {
+        SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_nanos() as i64
+    }
+
+    async fn event_store(page_size: i32) -> ScyllaEventStore {
+        ScyllaEventStore::with_config(ScyllaEventStoreConfig {
+            consistency: Consistency::One,
+            page_size,
+            ..ScyllaEventStoreConfig::default()
+        }).await.unwrap()
+    }
+
+    fn sequenced(sequence_number: i64) -> SequencedEvent<String> {
+        SequencedEvent { sequence_number, event: format!("event {}", sequence_number) }
+    }
+
+    #[tokio::test]
+    async fn retrieves_events_in_sequence_order() {
+        let store = event_store(1000).await;
+        let entity_id = unique_entity_id();
+
+        for sequence_number in [3, 1, 2] {
+            store.persist_event(entity_id, &sequenced(sequence_number)).await.unwrap();
+        }
+
+        let events: Vec<SequencedEvent<String>> = store.retrieve_events(entity_id).await.unwrap();
+        let sequence_numbers: Vec<i64> = events.iter().map(|e| e.sequence_number).collect();
+        assert_eq!(sequence_numbers, vec![1, 2, 3]);
+        assert_eq!(events[0].event, "event 1");
+    }
+
+    #[tokio::test]
+    async fn retrieves_streams_longer_than_a_page() {
+        let store = event_store(10).await;
+        let entity_id = unique_entity_id();
+
+        let events: Vec<(i64, SequencedEvent<String>)> = (1..=95).map(|n| (entity_id, sequenced(n))).collect();
+        store.persist_events(&events).await.unwrap();
+
+        let retrieved: Vec<SequencedEvent<String>> = store.retrieve_events(entity_id).await.unwrap();
+        assert_eq!(retrieved.len(), 95);
+        assert!(retrieved.iter().zip(1..).all(|(e, n)| e.sequence_number == n));
+    }
+
+    #[tokio::test]
+    async fn retrieves_an_empty_stream() {
+        let store = event_store(1000).await;
+
+        let events: Vec<SequencedEvent<String>> = store.retrieve_events(unique_entity_id()).await.unwrap();
+        assert!(events.is_empty());
+    }
+
+    #[tokio::test]
+    async fn reports_events_that_cant_be_deserialized() {
+        let store = event_store(1000).await;
+        let entity_id = unique_entity_id();
+        store.persist_event(entity_id, &sequenced(1)).await.unwrap();
+
+        let result: Result<Vec<SequencedEvent<OrderEvent>>, &'static str> = store.retrieve_events(entity_id).await;
+        assert_eq!(result.err(), Some("Failed to deserialize event"));
+    }
+
+    #[tokio::test]
+    async fn rejects_invalid_keyspace_names() {
+        let result = ScyllaEventStore::with_config(ScyllaEventStoreConfig {
+            keyspace: "ddd; DROP KEYSPACE ddd".to_owned(),
+            ..ScyllaEventStoreConfig::default()
+        }).await;
+        assert!(result.is_err());
+    }
+}
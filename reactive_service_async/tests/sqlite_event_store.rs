@@ -0,0 +1,63 @@
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use reactive_service_async::infra::sqlite_event_store::SqliteEventStore;
+    use reactive_service_async::order_service::{EventsJournal, OrderService, Principal, UpdateCart};
+    use reactive_service_async::payment_processor::LocalPaymentProcessor;
+    use reactive_service_async::shipping_calculator::LocalShippingCalculator;
+    use reactive_service_async::tax_calculator::LocalTaxCalculator;
+    use reactive_service_domain::aggregate_root::SequencedEvent;
+    use reactive_service_domain::non_empty_cart::{NonEmptyCart, Quantity, Sku};
+    use reactive_service_domain::order_entity::OrderEvent;
+
+    fn sequenced(sequence_number: i64) -> SequencedEvent<String> {
+        SequencedEvent { sequence_number, event: format!("event {}", sequence_number) }
+    }
+
+    #[tokio::test]
+    async fn rejects_a_batch_with_a_duplicate_sequence_number_as_a_whole() {
+        let directory = tempfile::tempdir().unwrap();
+        let store = SqliteEventStore::new(directory.path().join("events.db")).await.unwrap();
+
+        store.persist_event(1, &sequenced(1)).await.unwrap();
+        assert!(store.persist_events(&[(2, sequenced(1)), (1, sequenced(1))]).await.is_err());
+
+        let events: Vec<SequencedEvent<String>> = store.retrieve_events(2).await.unwrap();
+        assert!(events.is_empty());
+    }
+
+    #[tokio::test]
+    async fn reports_events_that_cant_be_deserialized() {
+        let directory = tempfile::tempdir().unwrap();
+        let store = SqliteEventStore::new(directory.path().join("events.db")).await.unwrap();
+        store.persist_event(1, &sequenced(1)).await.unwrap();
+
+        let result: Result<Vec<SequencedEvent<OrderEvent>>, &'static str> = store.retrieve_events(1).await;
+        assert_eq!(result.err(), Some("Failed to deserialize event"));
+    }
+
+    #[tokio::test]
+    async fn restores_orders_after_a_restart() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("events.db");
+        let update_cart = || UpdateCart {
+            order_id: 1,
+            cart: NonEmptyCart::new(HashMap::from([(Sku("apple".to_owned()), Quantity(1))])).unwrap()
+        };
+        {
+            let service = OrderService::new(
+                SqliteEventStore::new(&path).await.unwrap(),
+                LocalShippingCalculator{}, LocalTaxCalculator{}, LocalPaymentProcessor{}
+            );
+            service.update_cart(&Principal::Admin, update_cart()).await.unwrap();
+        }
+
+        let service = OrderService::new(
+            SqliteEventStore::new(&path).await.unwrap(),
+            LocalShippingCalculator{}, LocalTaxCalculator{}, LocalPaymentProcessor{}
+        );
+        let (_, events) = service.update_cart(&Principal::Admin, update_cart()).await.unwrap();
+        assert_eq!(events[0].sequence_number, 2);
+    }
+}
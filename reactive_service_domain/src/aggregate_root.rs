@@ -18,9 +18,68 @@ pub trait AggregateRoot {
 
     fn get_state(&self) -> &Self::State;
 
+    /// Sequence number of the last event applied, 0 for an entity without events.
+    fn get_sequence_number(&self) -> i64;
+
     /// Handle a command
     /// Success: Return the updated read only state + the sequence of applied events.
     /// Failure: Return an error, the state is unchanged. 
     fn handle_command(&mut self, command: Self::Command) 
         -> Result<(&Self::State, Vec<SequencedEvent<Self::Event>>), Self::Error>;
 }
+
+/// The state of an aggregate only defined by its decisions and their events, hosted by an `EventSourced` entity.
+pub trait EventSourcedState {
+    type Command;
+    type Error;
+    type Event: Clone;
+
+    /// The events of the command, the state is unchanged.
+    fn decide(&self, command: Self::Command) -> Result<Vec<Self::Event>, Self::Error>;
+
+    /// Evolve the state by an event, just decided or restored.
+    fn apply(&mut self, event: Self::Event) -> Result<(), Self::Error>;
+}
+
+/// The entity of an `EventSourcedState`, with the sequence number of its last event.
+#[derive(Default)]
+pub struct EventSourced<S> {
+    state: S,
+    sequence_number: i64,
+}
+
+impl<S: EventSourcedState> AggregateRoot for EventSourced<S> {
+    type State = S;
+    type Command = S::Command;
+    type Error = S::Error;
+    type Event = S::Event;
+
+    fn restore_from_events(&mut self, events: Vec<SequencedEvent<Self::Event>>) -> Result<&Self::State, Self::Error> {
+        for seq_event in events {
+            self.state.apply(seq_event.event)?;
+            self.sequence_number = seq_event.sequence_number;
+        }
+        Ok(&self.state)
+    }
+
+    fn get_state(&self) -> &Self::State {
+        &self.state
+    }
+
+    fn get_sequence_number(&self) -> i64 {
+        self.sequence_number
+    }
+
+    fn handle_command(&mut self, command: Self::Command)
+        -> Result<(&Self::State, Vec<SequencedEvent<Self::Event>>), Self::Error> {
+
+        let events = self.state.decide(command)?;
+        let mut seq_events = Vec::with_capacity(events.len());
+        for event in events {
+            self.state.apply(event.clone())?;
+            self.sequence_number += 1;
+            seq_events.push(SequencedEvent { sequence_number: self.sequence_number, event });
+        }
+        Ok((&self.state, seq_events))
+    }
+}
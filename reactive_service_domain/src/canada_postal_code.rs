@@ -7,6 +7,8 @@ use serde::{Deserialize, Serialize};
 
 use std::{fmt::Display, str::FromStr};
 
+pub const INVALID_POSTAL_CODE: &str = "Invalid postal code format";
+
 /// Canadian Postal code. Validate input with or without the middle optional space (A1A 0B0 or A1A0B0).
 /// Input is sanitized with trim and uppercase.
 /// If the input pass validation, the postal code is stored as 6 chars without space: A1A0B0.
@@ -27,7 +29,7 @@ use std::{fmt::Display, str::FromStr};
 /// assert_eq!(format!("{}", "A1A 0B0".parse::<CanadaPostalCode>().unwrap()), "A1A 0B0");
 /// assert_eq!(format!("{}", "A1A0B0".parse::<CanadaPostalCode>().unwrap()), "A1A 0B0");
 /// ```
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct CanadaPostalCode(heapless::String<6>);
 
 impl FromStr for CanadaPostalCode {
@@ -40,7 +42,7 @@ impl FromStr for CanadaPostalCode {
             let no_space = sanitized.replace(' ', "");
             Ok(Self(heapless::String::from_str(&no_space).unwrap()))
         } else {
-            Err("Invalid postal code format")
+            Err(INVALID_POSTAL_CODE)
         }
     }
 }
@@ -0,0 +1,111 @@
+use serde_derive::{Deserialize, Serialize};
+
+use crate::aggregate_root::{EventSourced, EventSourcedState};
+use crate::order_state::DeliveryAddress;
+
+pub type CustomerId = i64;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Profile {
+    pub name: String,
+    pub email: String,
+}
+
+/// A reference to a payment method kept by the payment processor, never the card itself.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PaymentMethodRef(pub String);
+
+#[derive(Debug, Clone, Default)]
+pub struct CustomerState {
+    /// None until the customer is registered
+    pub profile: Option<Profile>,
+    pub saved_addresses: Vec<DeliveryAddress>,
+    pub default_payment_method: Option<PaymentMethodRef>,
+}
+
+impl CustomerState {
+    fn saved_address(&self, address: &DeliveryAddress) -> Option<usize> {
+        self.saved_addresses.iter().position(|saved| saved == address)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum CustomerCommand {
+    Register { profile: Profile },
+    UpdateProfile { profile: Profile },
+    SaveAddress { address: DeliveryAddress },
+    RemoveAddress { address: DeliveryAddress },
+    SetDefaultPaymentMethod { payment_method: PaymentMethodRef },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum CustomerEvent {
+    Registered { profile: Profile },
+    ProfileUpdated { profile: Profile },
+    AddressSaved { address: DeliveryAddress },
+    AddressRemoved { address: DeliveryAddress },
+    DefaultPaymentMethodSet { payment_method: PaymentMethodRef },
+}
+
+/// A customer: the profile, the delivery addresses saved for the next orders, and the default payment method.
+/// The orders record the id of the customer creating them, only this customer may change them.
+pub type Customer = EventSourced<CustomerState>;
+
+impl EventSourcedState for CustomerState {
+    type Command = CustomerCommand;
+    type Error = &'static str;
+    type Event = CustomerEvent;
+
+    fn decide(&self, command: CustomerCommand) -> Result<Vec<CustomerEvent>, &'static str> {
+        if self.profile.is_none() && !matches!(command, CustomerCommand::Register { .. }) {
+            return Err("Customer not registered");
+        }
+        match command {
+            CustomerCommand::Register { profile } => {
+                match &self.profile {
+                    None => Ok(vec![CustomerEvent::Registered { profile }]),
+                    // The registration delivered again
+                    Some(registered) if *registered == profile => Ok(vec![]),
+                    Some(_) => Err("Customer already registered"),
+                }
+            },
+            CustomerCommand::UpdateProfile { profile } => {
+                if self.profile.as_ref() == Some(&profile) {
+                    return Ok(vec![]);
+                }
+                Ok(vec![CustomerEvent::ProfileUpdated { profile }])
+            },
+            CustomerCommand::SaveAddress { address } => {
+                if self.saved_address(&address).is_some() {
+                    return Ok(vec![]);
+                }
+                Ok(vec![CustomerEvent::AddressSaved { address }])
+            },
+            CustomerCommand::RemoveAddress { address } => {
+                if self.saved_address(&address).is_none() {
+                    return Ok(vec![]);
+                }
+                Ok(vec![CustomerEvent::AddressRemoved { address }])
+            },
+            CustomerCommand::SetDefaultPaymentMethod { payment_method } => {
+                if self.default_payment_method.as_ref() == Some(&payment_method) {
+                    return Ok(vec![]);
+                }
+                Ok(vec![CustomerEvent::DefaultPaymentMethodSet { payment_method }])
+            },
+        }
+    }
+
+    fn apply(&mut self, event: CustomerEvent) -> Result<(), &'static str> {
+        match event {
+            CustomerEvent::Registered { profile } | CustomerEvent::ProfileUpdated { profile } => self.profile = Some(profile),
+            CustomerEvent::AddressSaved { address } => self.saved_addresses.push(address),
+            CustomerEvent::AddressRemoved { address } => {
+                let index = self.saved_address(&address).ok_or("Address not saved")?;
+                self.saved_addresses.remove(index);
+            },
+            CustomerEvent::DefaultPaymentMethodSet { payment_method } => self.default_payment_method = Some(payment_method),
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,131 @@
+use std::collections::{HashMap, HashSet};
+use std::time::SystemTime;
+use serde_derive::{Deserialize, Serialize};
+
+use crate::aggregate_root::{EventSourced, EventSourcedState};
+
+pub const NON_POSITIVE_QUANTITY: &str = "Quantity must be positive";
+
+/// The stock held for an order, until it is shipped, released, or it expires.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Reservation {
+    pub quantity: u32,
+    pub expires_at: SystemTime,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct InventoryItemState {
+    /// The units in the warehouse, reserved or not
+    pub on_hand: u32,
+    /// By order id. An expired reservation stays until it is released, its units are available again.
+    pub reservations: HashMap<i64, Reservation>,
+    /// The orders shipped, reserving or releasing their stock again is a no-op
+    pub shipped: HashSet<i64>,
+}
+
+impl InventoryItemState {
+    /// The units not held by a reservation at `at`.
+    pub fn available(&self, at: SystemTime) -> u32 {
+        let reserved: u32 = self.reservations.values()
+            .filter(|reservation| reservation.expires_at > at)
+            .map(|reservation| reservation.quantity)
+            .sum();
+        self.on_hand.saturating_sub(reserved)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum InventoryItemCommand {
+    Receive { quantity: u32 },
+    /// Hold the units for the order until `expires_at`. Reserving again replaces the reservation.
+    Reserve { order_id: i64, quantity: u32, expires_at: SystemTime, at: SystemTime },
+    Release { order_id: i64 },
+    /// Take the reserved units out of the stock, the reservation must not be expired at `at`
+    Ship { order_id: i64, at: SystemTime },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum InventoryItemEvent {
+    Received { quantity: u32 },
+    Reserved { order_id: i64, quantity: u32, expires_at: SystemTime },
+    ReservationReleased { order_id: i64 },
+    Shipped { order_id: i64, quantity: u32 },
+}
+
+/// The stock of a SKU: the units received, and the ones reserved for the orders until they are shipped.
+/// A reservation expires, e.g. for an order never fulfilled: its units are available to the next reservations.
+pub type InventoryItem = EventSourced<InventoryItemState>;
+
+impl EventSourcedState for InventoryItemState {
+    type Command = InventoryItemCommand;
+    type Error = &'static str;
+    type Event = InventoryItemEvent;
+
+    fn decide(&self, command: InventoryItemCommand) -> Result<Vec<InventoryItemEvent>, &'static str> {
+        match command {
+            InventoryItemCommand::Receive { quantity } => {
+                if quantity == 0 {
+                    return Err(NON_POSITIVE_QUANTITY);
+                }
+                Ok(vec![InventoryItemEvent::Received { quantity }])
+            },
+            InventoryItemCommand::Reserve { order_id, quantity, expires_at, at } => {
+                if quantity == 0 {
+                    return Err(NON_POSITIVE_QUANTITY);
+                }
+                if self.shipped.contains(&order_id) {
+                    return Ok(vec![]);
+                }
+                let current = self.reservations.get(&order_id).filter(|reservation| reservation.expires_at > at);
+                if current.is_some_and(|current| current.quantity == quantity && current.expires_at >= expires_at) {
+                    return Ok(vec![]);
+                }
+                // The units held by the order count as available to itself
+                if self.available(at) + current.map_or(0, |current| current.quantity) < quantity {
+                    return Err("Insufficient stock");
+                }
+                // Reserving is when the expired reservations of the other orders are cleaned up
+                let mut events: Vec<InventoryItemEvent> = self.reservations.iter()
+                    .filter(|(id, reservation)| **id != order_id && reservation.expires_at <= at)
+                    .map(|(id, _)| InventoryItemEvent::ReservationReleased { order_id: *id })
+                    .collect();
+                events.push(InventoryItemEvent::Reserved { order_id, quantity, expires_at });
+                Ok(events)
+            },
+            InventoryItemCommand::Release { order_id } => {
+                if !self.reservations.contains_key(&order_id) {
+                    return Ok(vec![]);
+                }
+                Ok(vec![InventoryItemEvent::ReservationReleased { order_id }])
+            },
+            InventoryItemCommand::Ship { order_id, at } => {
+                if self.shipped.contains(&order_id) {
+                    return Ok(vec![]);
+                }
+                match self.reservations.get(&order_id) {
+                    Some(reservation) if reservation.expires_at > at =>
+                        Ok(vec![InventoryItemEvent::Shipped { order_id, quantity: reservation.quantity }]),
+                    _ => Err("No reservation for the order"),
+                }
+            },
+        }
+    }
+
+    fn apply(&mut self, event: InventoryItemEvent) -> Result<(), &'static str> {
+        match event {
+            InventoryItemEvent::Received { quantity } => self.on_hand += quantity,
+            InventoryItemEvent::Reserved { order_id, quantity, expires_at } => {
+                self.reservations.insert(order_id, Reservation { quantity, expires_at });
+            },
+            InventoryItemEvent::ReservationReleased { order_id } => {
+                self.reservations.remove(&order_id).ok_or("No reservation for the order")?;
+            },
+            InventoryItemEvent::Shipped { order_id, quantity } => {
+                self.reservations.remove(&order_id).ok_or("No reservation for the order")?;
+                self.on_hand = self.on_hand.checked_sub(quantity).ok_or("Not enough stock on hand")?;
+                self.shipped.insert(order_id);
+            },
+        }
+        Ok(())
+    }
+}
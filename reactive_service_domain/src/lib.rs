@@ -2,4 +2,6 @@ pub mod canada_postal_code;
 pub mod aggregate_root;
 pub mod order_state;
 pub mod order_entity;
+pub mod customer;
 pub mod non_empty_cart;
+pub mod inventory_item;
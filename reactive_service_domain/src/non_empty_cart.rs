@@ -1,6 +1,8 @@
 use std::collections::HashMap;
 use serde_derive::{Deserialize, Serialize};
 
+pub const EMPTY_CART: &str = "Cart can't be empty";
+
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct Sku(pub String);
 
@@ -14,7 +16,9 @@ pub struct NonEmptyCart {
 
 impl NonEmptyCart {
     pub fn new(cart: HashMap<Sku, Quantity>) -> Result<Self, &'static str> {
-       if cart.is_empty() { Err("Cart can't be empty") }
+       if cart.is_empty() { Err(EMPTY_CART) }
        else { Ok(Self{cart}) }
     }
+
+    pub fn get_items(&self) -> &HashMap<Sku, Quantity> { &self.cart }
 }
\ No newline at end of file
@@ -1,11 +1,14 @@
 use serde_derive::{Deserialize, Serialize};
 
 use crate::aggregate_root::{AggregateRoot, SequencedEvent};
+use crate::customer::CustomerId;
 use crate::non_empty_cart::NonEmptyCart;
-use crate::order_state::{Completed, DeliveryAddress, Empty, Invoice, Money, OrderState, WithAddress, WithCart};
+use crate::order_state::{Completed, DeliveryAddress, Empty, Expired, Invoice, Money, OrderState, WithAddress, WithCart};
 
 pub struct OrderEntity {
     order_state: OrderState,
+    /// The customer who created the order, none for the orders created before the customers
+    customer_id: Option<CustomerId>,
     sequence_number: i64
 }
 
@@ -13,6 +16,7 @@ impl Default for OrderEntity {
     fn default() -> Self {
         OrderEntity{
             order_state: OrderState::Empty(Empty{}),
+            customer_id: None,
             sequence_number: 0,
         }
     }
@@ -28,6 +32,7 @@ impl AggregateRoot for OrderEntity {
     fn restore_from_events(&mut self, events: Vec<SequencedEvent<Self::Event>>) -> Result<&Self::State, Self::Error> {
         for seq_event in events {
             let current_state = std::mem::replace(&mut self.order_state, OrderState::Empty(Empty{}));
+            self.record_customer(&seq_event.event);
             match Self::apply_event(current_state, seq_event.event) {
                 Ok(new_state) => {
                     self.order_state = new_state;
@@ -46,6 +51,10 @@ impl AggregateRoot for OrderEntity {
         &self.order_state
     }
 
+    fn get_sequence_number(&self) -> i64 {
+        self.sequence_number
+    }
+
     fn handle_command(&mut self, command: Self::Command)
       -> Result<(&Self::State, Vec<SequencedEvent<Self::Event>>), Self::Error> {
         // Handle required mutations here
@@ -59,6 +68,7 @@ impl AggregateRoot for OrderEntity {
                 self.order_state = new_state;
 
                 let seq_events = events.iter().map(|evt| {
+                    self.record_customer(evt);
                     self.sequence_number += 1;
                     SequencedEvent{sequence_number: self.sequence_number, event: evt.to_owned()}
                 }).collect();
@@ -78,6 +88,17 @@ impl AggregateRoot for OrderEntity {
 
 impl OrderEntity {
 
+    /// The customer who created the order, to authorize the commands on it.
+    pub fn get_customer_id(&self) -> Option<CustomerId> {
+        self.customer_id
+    }
+
+    fn record_customer(&mut self, order_event: &OrderEvent) {
+        if let OrderEvent::UpdatedCart { customer_id: Some(customer_id), .. } = order_event {
+            self.customer_id.get_or_insert(*customer_id);
+        }
+    }
+
     fn handle_command_with_state(&self, current_state: OrderState, command: OrderEntityCommand)
         -> Result<(OrderState, Vec<OrderEvent>), (OrderState, &'static str)> {
 
@@ -89,7 +110,9 @@ impl OrderEntity {
             OrderState::WithAddress(order_with_addr) =>
                 self.with_addr_command_handler(order_with_addr, command),
             OrderState::Completed(completed_order) =>
-                self.with_completed_order(completed_order, command)
+                self.with_completed_order(completed_order, command),
+            OrderState::Expired(expired_order) =>
+                self.with_expired_order(expired_order, command)
         }
     }
 
@@ -97,9 +120,9 @@ impl OrderEntity {
         -> Result<(OrderState, Vec<OrderEvent>), (OrderState, &'static str)> {
 
         match command {
-            OrderEntityCommand::AddCart { cart } => {
+            OrderEntityCommand::AddCart { cart, customer_id } => {
                 let new_state = OrderState::WithCart(order_empty.add_cart(cart.clone()));
-                let events = vec![OrderEvent::UpdatedCart{cart}];
+                let events = vec![OrderEvent::UpdatedCart{cart, customer_id}];
                 Ok((new_state,events))
             },
             OrderEntityCommand::UpdateCart { .. } => Err((OrderState::Empty(order_empty), "Invalid state")),
@@ -107,6 +130,7 @@ impl OrderEntity {
                 Err((OrderState::Empty(order_empty), "Can't add a delivery address on an empty cart")),
             OrderEntityCommand::Complete{..} => 
                 Err((OrderState::Empty(order_empty), "Order is not ready for payment")),
+            OrderEntityCommand::Expire => Err((OrderState::Empty(order_empty), "Order is not open")),
         }
     }
 
@@ -115,9 +139,10 @@ impl OrderEntity {
         -> Result<(OrderState, Vec<OrderEvent>), (OrderState, &'static str)> {
 
         match command {
-            OrderEntityCommand::AddCart {cart} => {
+            OrderEntityCommand::AddCart {cart, ..} => {
                 let new_state = OrderState::WithCart(order_with_cart.update_cart(cart.clone()));
-                let events = vec![OrderEvent::UpdatedCart{cart}];
+                // The customer is only recorded at the creation of the order
+                let events = vec![OrderEvent::UpdatedCart{cart, customer_id: None}];
                 Ok((new_state,events))
             },
             OrderEntityCommand::UpdateCart { .. } => Err((OrderState::WithCart(order_with_cart), "Cart already present")),
@@ -131,6 +156,7 @@ impl OrderEntity {
                 Ok((new_state,events))
             },
             OrderEntityCommand::Complete{..} => Err((OrderState::WithCart(order_with_cart), "Order is not ready for payment")),
+            OrderEntityCommand::Expire => Ok((OrderState::Expired(order_with_cart.expire()), vec![OrderEvent::Expired])),
         }
     }
 
@@ -159,7 +185,8 @@ impl OrderEntity {
                     OrderEvent::Completed { invoice }
                 ];
                 Ok((new_state, events))
-            }
+            },
+            OrderEntityCommand::Expire => Ok((OrderState::Expired(order_with_addr.expire()), vec![OrderEvent::Expired])),
         }
     }
 
@@ -169,13 +196,19 @@ impl OrderEntity {
         Err((OrderState::Completed(completed_order), "Order is completed"))
     }
 
+    fn with_expired_order(&self, expired_order: Expired, _command: OrderEntityCommand)
+        -> Result<(OrderState, Vec<OrderEvent>), (OrderState, &'static str)> {
+
+        Err((OrderState::Expired(expired_order), "Order is expired"))
+    }
+
     fn apply_event(order_state: OrderState, order_event: OrderEvent)
         -> Result<OrderState, (OrderState, &'static str)> {
 
         match order_state {
             OrderState::Empty(empty_order) =>
                 match order_event {
-                    OrderEvent::UpdatedCart { cart } =>
+                    OrderEvent::UpdatedCart { cart, .. } =>
                         Ok(OrderState::WithCart(empty_order.add_cart(cart))),
                     OrderEvent::UpdatedCartOnExistingDeliveryAddress {..} =>
                         Err((OrderState::Empty(empty_order), "Cannot apply UpdatedCart event to an Empty order")),
@@ -183,11 +216,13 @@ impl OrderEntity {
                         Err((OrderState::Empty(empty_order), "Cannot apply DeliveryAddress event to an EmptyOrder")),
                     OrderEvent::Completed{..} =>
                         Err((OrderState::Empty(empty_order), "Cannot apply Completed event to an EmptyOrder")),
+                    OrderEvent::Expired =>
+                        Err((OrderState::Empty(empty_order), "Cannot apply Expired event to an EmptyOrder")),
                 }
             ,
             OrderState::WithCart(with_cart) => {
                 match order_event {
-                    OrderEvent::UpdatedCart { cart } =>
+                    OrderEvent::UpdatedCart { cart, .. } =>
                         Ok(OrderState::WithCart(with_cart.update_cart(cart))),
                     OrderEvent::UpdatedCartOnExistingDeliveryAddress {..} =>
                         Err((OrderState::WithCart(with_cart), "Cannot apply UpdatedCart event to an WithCart order")),
@@ -196,7 +231,9 @@ impl OrderEntity {
                             delivery_address, shipping_cost, tax
                         ))),
                     OrderEvent::Completed{..} =>
-                        Err((OrderState::WithCart(with_cart), "Cannot apply Completed event to an WithCart order"))
+                        Err((OrderState::WithCart(with_cart), "Cannot apply Completed event to an WithCart order")),
+                    OrderEvent::Expired =>
+                        Ok(OrderState::Expired(with_cart.expire())),
                 }
             }
             OrderState::WithAddress(with_addr) =>
@@ -213,9 +250,13 @@ impl OrderEntity {
                         ))),
                     OrderEvent::Completed{invoice} =>
                         Ok(OrderState::Completed(with_addr.complete_order(invoice))),
+                    OrderEvent::Expired =>
+                        Ok(OrderState::Expired(with_addr.expire())),
                 },
             OrderState::Completed(_) =>
                 Err((order_state, "Cannot apply further events to a Completed order")),
+            OrderState::Expired(_) =>
+                Err((order_state, "Cannot apply further events to an Expired order")),
         }
     }
 
@@ -223,19 +264,25 @@ impl OrderEntity {
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum OrderEvent{
-    UpdatedCart {cart: NonEmptyCart},
+    UpdatedCart {
+        cart: NonEmptyCart,
+        /// The customer creating the order, absent from the events written before the customers
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        customer_id: Option<CustomerId>
+    },
     UpdatedDeliveryAddress {
         delivery_address: DeliveryAddress,
         shipping_cost: Money,
         tax: Money
     },
     UpdatedCartOnExistingDeliveryAddress {cart: NonEmptyCart, shipping_cost: Money, tax: Money},
-    Completed{invoice: Invoice}
+    Completed{invoice: Invoice},
+    Expired
 }
 
 #[derive(Debug, Clone)]
 pub enum OrderEntityCommand {
-    AddCart{cart: NonEmptyCart},
+    AddCart{cart: NonEmptyCart, customer_id: Option<CustomerId>},
     UpdateCart{
         cart: NonEmptyCart,
         shipping_cost: Money,
@@ -246,5 +293,7 @@ pub enum OrderEntityCommand {
         shipping_cost: Money,
         tax: Money
     },
-    Complete{invoice: Invoice}
+    Complete{invoice: Invoice},
+    /// Abandon the cart of an order not paid yet
+    Expire
 }
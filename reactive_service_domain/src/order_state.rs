@@ -14,12 +14,16 @@ use crate::{canada_postal_code::CanadaPostalCode, non_empty_cart::NonEmptyCart};
 //     WithAddress --> WithAddress: update_cart(NonEmptyCart, ShippingCost, Tax)<br/> or <br/>update_delivery_address(DeliveryAddress, ShippingCost, Tax)
 // 
 //     WithAddress --> Completed: pay_with_token(PaymentToken)
+//
+//     WithCart --> Expired: expire()
+//     WithAddress --> Expired: expire()
 #[derive(Debug, Clone)]
 pub enum OrderState {
     Empty(Empty),
     WithCart(WithCart),
     WithAddress(WithAddress),
-    Completed(Completed)
+    Completed(Completed),
+    Expired(Expired)
 }
 
 impl Default for OrderState {
@@ -56,6 +60,10 @@ impl WithCart {
             tax,
         }
     }
+
+    pub fn expire(self) -> Expired {
+        Expired { cart: self.cart }
+    }
     
 }
 
@@ -70,6 +78,8 @@ pub struct WithAddress {
 impl WithAddress {
     pub fn get_cart(&self) -> &NonEmptyCart { &self.cart }
     pub fn get_delivery_address(&self) -> &DeliveryAddress { &self.delivery_address }
+    pub fn get_shipping_cost(&self) -> &Money { &self.shipping_cost }
+    pub fn get_tax(&self) -> &Money { &self.tax }
 
     pub fn update_cart(self, cart: NonEmptyCart, shipping_cost: Money, tax: Money) -> Self {
         Self { cart, shipping_cost, tax, ..self }
@@ -88,6 +98,10 @@ impl WithAddress {
             invoice
         }
     }
+
+    pub fn expire(self) -> Expired {
+        Expired { cart: self.cart }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -99,13 +113,30 @@ pub struct Completed {
     invoice: Invoice
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+impl Completed {
+    pub fn get_cart(&self) -> &NonEmptyCart { &self.cart }
+    pub fn get_delivery_address(&self) -> &DeliveryAddress { &self.delivery_address }
+    pub fn get_shipping_cost(&self) -> &Money { &self.shipping_cost }
+    pub fn get_tax(&self) -> &Money { &self.tax }
+}
+
+/// An order left untouched too long before its payment: its cart is abandoned.
+#[derive(Debug, Clone)]
+pub struct Expired {
+    cart: NonEmptyCart
+}
+
+impl Expired {
+    pub fn get_cart(&self) -> &NonEmptyCart { &self.cart }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct DeliveryAddress {
     pub street: Street,
     pub postal_code: CanadaPostalCode
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Street(pub String);
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
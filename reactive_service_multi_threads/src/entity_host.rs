@@ -0,0 +1,194 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, RwLock};
+use std::time::{Duration, Instant};
+use reactive_service_domain::aggregate_root::{AggregateRoot, SequencedEvent};
+use reactive_service_application::metrics::{MetricsRegistry, NoMetrics};
+use reactive_service_application::order_queries::Versioned;
+use tracing::info_span;
+use reactive_service_application::command_errors::{ENTITIES_POISONED, ENTITY_NOT_RETRIEVED, ENTITY_POISONED, VERSION_TIMED_OUT};
+use crate::event_bus::EventBus;
+use crate::order_service::EventsJournal;
+
+pub type EntityId = i64;
+
+/// The events applied by a command on an entity of the aggregate `A`.
+pub type AppliedEvents<A> = Vec<SequencedEvent<<A as AggregateRoot>::Event>>;
+
+/// Owned result of a command on an entity of the aggregate `A`.
+pub type HostResult<A> = Result<(<A as AggregateRoot>::State, AppliedEvents<A>), &'static str>;
+
+struct Slot<A: AggregateRoot> {
+    /// `None` until restored from the journal, or after its events failed to be persisted
+    entity: Mutex<Option<A>>,
+    /// Last persisted state of the entity, read by the queries without locking the entity
+    published: Mutex<Option<Versioned<A::State>>>,
+    changed: Condvar,
+}
+
+/// Hosts the entities of any aggregate, shared by many threads: restores them from the journal on their first command,
+/// keeps them in memory, and persists the events of their commands.
+///
+/// Each entity has its own lock, held for the time of a command and of the persistence of its events:
+/// the commands of an entity are handled one at a time, the commands of different entities in parallel.
+/// Once its events are persisted, the state is published for the queries: they don't wait for the commands,
+/// and the events are published to the subscribers, in sequence for each entity.
+///
+/// The `OrderService` is the host of the `OrderEntity`, with the ports it needs to build the entity commands.
+/// The durable journals serialize the events, the aggregate events must then be `Serialize` and `DeserializeOwned`.
+///
+/// The restores, commands and persistence of the entities are traced in `load`, `handle` and `persist` spans,
+/// and the latency of the journal and the number of entities in memory reported to its `MetricsRegistry`.
+pub struct EntityHost<A: AggregateRoot, J: EventsJournal<A::Event>> {
+    entities: RwLock<HashMap<EntityId, Arc<Slot<A>>>>,
+    events_journal: J,
+    event_bus: EventBus<A::Event>,
+    metrics: Arc<dyn MetricsRegistry + Send + Sync>,
+}
+
+impl<A, J> EntityHost<A, J>
+where
+    A: AggregateRoot<Error = &'static str> + Default,
+    A::State: Clone,
+    A::Event: Clone,
+    J: EventsJournal<A::Event>,
+{
+    pub fn new(events_journal: J) -> Self {
+        Self { entities: RwLock::new(HashMap::default()), events_journal, event_bus: EventBus::default(), metrics: Arc::new(NoMetrics) }
+    }
+
+    /// Report the metrics of the entities and of their journal to the registry.
+    pub fn with_metrics(self, metrics: Arc<dyn MetricsRegistry + Send + Sync>) -> Self {
+        Self { metrics, ..self }
+    }
+
+    /// The committed events of the entities, to subscribe to them.
+    pub fn event_bus(&self) -> &EventBus<A::Event> {
+        &self.event_bus
+    }
+
+    /// The journal of the entities, e.g. to read their events in the global order.
+    pub fn events_journal(&self) -> &J {
+        &self.events_journal
+    }
+
+    /// Handle a command, built from the current entity, then persist its events.
+    /// If persisting fails, the entity is dropped, to be restored from the journal on its next command.
+    pub fn handle<F>(&self, entity_id: EntityId, to_command: F) -> HostResult<A>
+    where
+        F: FnOnce(&A) -> Result<A::Command, &'static str>,
+    {
+        let slot = self.slot(entity_id)?;
+        // Now, we'll lock the entity for the time needed to handle the command and persist its events.
+        let mut guard = self.lock_entity(entity_id, &slot)?;
+        let entity = guard.as_mut().ok_or(ENTITY_NOT_RETRIEVED)?;
+
+        let (state, events) = info_span!("handle", entity_id).in_scope(|| {
+            let command = to_command(entity)?;
+            entity.handle_command(command).map(|(state, events)| (state.clone(), events))
+        })?;
+
+        if let Err(err) = self.persist(entity_id, &events) {
+            // The entity is ahead of the journal
+            *guard = None;
+            return Err(err);
+        }
+        // Still under the entity lock: the events of an entity are published in sequence
+        self.event_bus.publish(entity_id, &events);
+        publish(&slot, Versioned { state: state.clone(), sequence_number: entity.get_sequence_number() })?;
+        Ok((state, events)) // We return the result and the entity lock is released
+    }
+
+    /// The last persisted state of the entity, with its sequence number.
+    /// Only locks the entity to restore it from the journal, if it is not in memory yet.
+    pub fn query(&self, entity_id: EntityId) -> Result<Versioned<A::State>, &'static str> {
+        let slot = self.slot(entity_id)?;
+        let published = self.published(entity_id, &slot)?;
+        published.clone().ok_or(ENTITY_NOT_RETRIEVED)
+    }
+
+    /// The state of the entity, once its sequence number is at least `sequence_number`.
+    /// Fails if the entity doesn't reach this version within `timeout`.
+    pub fn query_at_least(&self, entity_id: EntityId, sequence_number: i64, timeout: Duration)
+        -> Result<Versioned<A::State>, &'static str> {
+
+        let slot = self.slot(entity_id)?;
+        let published = self.published(entity_id, &slot)?;
+        let (published, wait) = slot.changed
+            .wait_timeout_while(published, timeout, |published| {
+                published.as_ref().map_or(true, |published| published.sequence_number < sequence_number)
+            })
+            .map_err(|_| ENTITY_POISONED)?;
+
+        if wait.timed_out() {
+            return Err(VERSION_TIMED_OUT);
+        }
+        published.clone().ok_or(ENTITY_NOT_RETRIEVED)
+    }
+
+    /// The slot of the entity, created empty if the entity is not in memory yet.
+    fn slot(&self, entity_id: EntityId) -> Result<Arc<Slot<A>>, &'static str> {
+        // Check if the entity is already in the map without locking (read-only access)
+        if let Some(slot) = self.entities.read().map_err(|_| ENTITIES_POISONED)?.get(&entity_id) {
+            return Ok(slot.clone());
+        }
+        // This is the only place we lock the map, just the time of the insert,
+        // minimal contention only when we access an entity not in memory yet.
+        // The entity itself is restored out of the map lock, under its own lock.
+        let mut write_lock = self.entities.write().map_err(|_| ENTITIES_POISONED)?;
+        let slot = write_lock.entry(entity_id).or_insert_with(|| Arc::new(Slot {
+            entity: Mutex::new(None),
+            published: Mutex::new(None),
+            changed: Condvar::new(),
+        })).clone();
+        self.metrics.set_cached_entities(write_lock.len());
+        Ok(slot)
+    }
+
+    /// Lock the entity, restoring it from the journal if needed.
+    fn lock_entity<'a>(&self, entity_id: EntityId, slot: &'a Slot<A>) -> Result<MutexGuard<'a, Option<A>>, &'static str> {
+        let mut guard = slot.entity.lock().map_err(|_| ENTITY_POISONED)?;
+        if guard.is_none() {
+            let _span = info_span!("load", entity_id).entered();
+            let start_time = Instant::now();
+            let events = self.events_journal.retrieve_events(entity_id);
+            self.metrics.observe_journal("load", start_time.elapsed(), events.is_err());
+            let mut entity = A::default();
+            let _ = entity.restore_from_events(events?)?;
+            publish(slot, Versioned { state: entity.get_state().clone(), sequence_number: entity.get_sequence_number() })?;
+            *guard = Some(entity);
+        }
+        Ok(guard)
+    }
+
+    /// The published state, once the entity has been restored.
+    fn published<'a>(&self, entity_id: EntityId, slot: &'a Slot<A>)
+        -> Result<MutexGuard<'a, Option<Versioned<A::State>>>, &'static str> {
+
+        let published = slot.published.lock().map_err(|_| ENTITY_POISONED)?;
+        if published.is_some() {
+            return Ok(published);
+        }
+        drop(published);
+        drop(self.lock_entity(entity_id, slot)?);
+        slot.published.lock().map_err(|_| ENTITY_POISONED)
+    }
+
+    fn persist(&self, entity_id: EntityId, events: &[SequencedEvent<A::Event>]) -> Result<(), &'static str> {
+        let _span = info_span!("persist", entity_id, events = events.len()).entered();
+        let start_time = Instant::now();
+        let persisted = events.iter().try_for_each(|evt| self.events_journal.persist_event(entity_id, evt));
+        self.metrics.observe_journal("persist", start_time.elapsed(), persisted.is_err());
+        persisted
+    }
+}
+
+/// Make a persisted state visible to the queries, and wake up the ones waiting for a version.
+fn publish<A: AggregateRoot>(slot: &Slot<A>, versioned: Versioned<A::State>) -> Result<(), &'static str> {
+    let mut published = slot.published.lock().map_err(|_| ENTITY_POISONED)?;
+    // A restored entity is never ahead of what was published
+    if published.as_ref().map_or(true, |published| published.sequence_number <= versioned.sequence_number) {
+        *published = Some(versioned);
+        slot.changed.notify_all();
+    }
+    Ok(())
+}
@@ -0,0 +1,91 @@
+use std::path::PathBuf;
+use std::sync::{Mutex, MutexGuard};
+use std::time::Duration;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use reactive_service_domain::aggregate_root::SequencedEvent;
+use reactive_service_application::command_errors::{DESERIALIZE_EVENT_FAILED, JOURNAL_POISONED, SERIALIZE_EVENT_FAILED};
+use crate::catch_up::AppendedPosition;
+use crate::order_service::{EventsJournal, GlobalEventsJournal, OrderId, Positioned};
+pub use reactive_service_application::segmented_log::{FileJournalConfig, FsyncPolicy};
+use reactive_service_application::segmented_log::{Record, SegmentedLog};
+
+/// Embedded journal, appending the events serialized in JSON to the segment files of a `SegmentedLog`, in a directory.
+/// A record torn by a crash in the middle of a write is truncated when the journal is opened, any other corruption fails the opening.
+///
+/// Appends and reads of concurrent commands are serialized by a mutex.
+pub struct FileJournal {
+    log: Mutex<SegmentedLog>,
+    appended: AppendedPosition,
+}
+
+impl FileJournal {
+    /// Open, or create, the journal in `directory`, with the default settings.
+    pub fn new(directory: impl Into<PathBuf>) -> Result<Self, Box<dyn std::error::Error>> {
+        Self::with_config(FileJournalConfig { directory: directory.into(), ..FileJournalConfig::default() })
+    }
+
+    pub fn with_config(config: FileJournalConfig) -> Result<Self, Box<dyn std::error::Error>> {
+        let log = SegmentedLog::open(config)?;
+        let appended = AppendedPosition::new(log.last_position());
+        Ok(Self { log: Mutex::new(log), appended })
+    }
+
+    fn append(&self, records: Vec<Record>) -> Result<(), &'static str> {
+        let mut log = self.lock()?;
+        log.append(records)?;
+        self.appended.advance(log.last_position());
+        Ok(())
+    }
+
+    fn lock(&self) -> Result<MutexGuard<'_, SegmentedLog>, &'static str> {
+        self.log.lock().map_err(|_| JOURNAL_POISONED)
+    }
+}
+
+impl<E: Serialize + DeserializeOwned> EventsJournal<E> for FileJournal {
+    fn persist_event(&self, entity_id: OrderId, seq_event: &SequencedEvent<E>) -> Result<(), &'static str> {
+        let serialized_event = serde_json::to_vec(&seq_event.event).map_err(|_| SERIALIZE_EVENT_FAILED)?;
+        self.append(vec![(entity_id, seq_event.sequence_number, serialized_event)])
+    }
+
+    fn persist_events(&self, events: &[(OrderId, SequencedEvent<E>)]) -> Result<(), &'static str> {
+        let records = events.iter()
+            .map(|(entity_id, seq_event)| {
+                let serialized_event = serde_json::to_vec(&seq_event.event).map_err(|_| SERIALIZE_EVENT_FAILED)?;
+                Ok((*entity_id, seq_event.sequence_number, serialized_event))
+            })
+            .collect::<Result<Vec<Record>, &'static str>>()?;
+        self.append(records)
+    }
+
+    fn retrieve_events(&self, entity_id: OrderId) -> Result<Vec<SequencedEvent<E>>, &'static str> {
+        let events = self.lock()?.read(entity_id)?;
+        events.into_iter()
+            .map(|(sequence_number, event_payload)| {
+                let event: E = serde_json::from_slice(&event_payload).map_err(|_| DESERIALIZE_EVENT_FAILED)?;
+                Ok(SequencedEvent { sequence_number, event })
+            })
+            .collect()
+    }
+}
+
+impl<E: Serialize + DeserializeOwned> GlobalEventsJournal<E> for FileJournal {
+    fn read_all(&self, from_position: i64, batch_size: usize) -> Result<Vec<Positioned<E>>, &'static str> {
+        let records = self.lock()?.read_all(from_position, batch_size)?;
+        records.into_iter()
+            .map(|(position, (entity_id, sequence_number, event_payload))| {
+                let event: E = serde_json::from_slice(&event_payload).map_err(|_| DESERIALIZE_EVENT_FAILED)?;
+                Ok(Positioned { position, entity_id, event: SequencedEvent { sequence_number, event } })
+            })
+            .collect()
+    }
+
+    fn last_position(&self) -> Result<i64, &'static str> {
+        Ok(self.lock()?.last_position())
+    }
+
+    fn wait_for_position(&self, position: i64, timeout: Duration) -> Result<(), &'static str> {
+        self.appended.wait_for(position, timeout)
+    }
+}
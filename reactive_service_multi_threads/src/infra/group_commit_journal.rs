@@ -0,0 +1,154 @@
+use std::sync::Arc;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, SyncSender};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use reactive_service_domain::aggregate_root::SequencedEvent;
+use reactive_service_application::command_errors::PERSIST_EVENT_FAILED;
+use crate::order_service::{EventsJournal, GlobalEventsJournal, OrderId, Positioned};
+
+/// When to write a group of events.
+#[derive(Debug, Clone)]
+pub struct GroupCommitConfig {
+    /// Write as soon as this many events are waiting.
+    pub max_batch_size: usize,
+    /// Maximum time the first event of a group waits for others to join.
+    pub linger: Duration,
+}
+
+impl Default for GroupCommitConfig {
+    fn default() -> Self {
+        Self {
+            max_batch_size: 256,
+            linger: Duration::from_millis(2),
+        }
+    }
+}
+
+/// Journal decorator gathering the events persisted by concurrent commands, across orders,
+/// to write them with a single `persist_events` call of the underlying journal.
+/// Each `persist_event` blocks until its group is written, by a dedicated writer thread.
+///
+/// If the group write fails, none of its events is written, `persist_events` being all or nothing:
+/// they are retried one by one, so only the commands whose events can't be written (e.g. duplicated sequence number) fail.
+pub struct GroupCommitJournal<J, Event> {
+    journal: Arc<J>,
+    writes: Option<SyncSender<PendingWrite<Event>>>,
+    writer: Option<JoinHandle<()>>,
+}
+
+struct PendingWrite<Event> {
+    entity_id: OrderId,
+    event: SequencedEvent<Event>,
+    done: SyncSender<Result<(), &'static str>>,
+}
+
+impl<J, Event> GroupCommitJournal<J, Event>
+where
+    J: EventsJournal<Event> + Send + Sync + 'static,
+    Event: Send + 'static,
+{
+    pub fn new(journal: J, config: GroupCommitConfig) -> Self {
+        let journal = Arc::new(journal);
+        let max_batch_size = config.max_batch_size.max(1);
+        let (writes, pending_writes) = mpsc::sync_channel(max_batch_size * 2);
+
+        let writer_journal = journal.clone();
+        let writer = thread::Builder::new()
+            .name("group-commit-writer".to_owned())
+            .spawn(move || Self::run(&writer_journal, pending_writes, max_batch_size, config.linger))
+            .expect("Failed to spawn the group commit writer thread");
+
+        Self { journal, writes: Some(writes), writer: Some(writer) }
+    }
+
+    fn run(journal: &J, pending_writes: Receiver<PendingWrite<Event>>, max_batch_size: usize, linger: Duration) {
+        let mut group = Vec::with_capacity(max_batch_size);
+
+        // Ends when the GroupCommitJournal is dropped
+        while let Ok(first) = pending_writes.recv() {
+            group.push(first);
+
+            let deadline = Instant::now() + linger;
+            while group.len() < max_batch_size {
+                let timeout = deadline.saturating_duration_since(Instant::now());
+                match pending_writes.recv_timeout(timeout) {
+                    Ok(write) => group.push(write),
+                    Err(RecvTimeoutError::Timeout) | Err(RecvTimeoutError::Disconnected) => break,
+                }
+            }
+
+            Self::commit(journal, group.drain(..));
+        }
+    }
+
+    fn commit(journal: &J, group: impl Iterator<Item = PendingWrite<Event>>) {
+        let (events, dones): (Vec<(OrderId, SequencedEvent<Event>)>, Vec<_>) = group
+            .map(|write| ((write.entity_id, write.event), write.done))
+            .unzip();
+
+        match journal.persist_events(&events) {
+            Ok(()) => {
+                for done in dones {
+                    let _ = done.send(Ok(()));
+                }
+            },
+            Err(_) => {
+                for ((entity_id, event), done) in events.iter().zip(dones) {
+                    let _ = done.send(journal.persist_event(*entity_id, event));
+                }
+            }
+        }
+    }
+}
+
+impl<J, Event> Drop for GroupCommitJournal<J, Event> {
+    fn drop(&mut self) {
+        drop(self.writes.take());
+        if let Some(writer) = self.writer.take() {
+            let _ = writer.join();
+        }
+    }
+}
+
+impl<J, Event> EventsJournal<Event> for GroupCommitJournal<J, Event>
+where
+    J: EventsJournal<Event> + Send + Sync + 'static,
+    Event: Clone + Send + 'static,
+{
+    fn persist_event(&self, entity_id: OrderId, evt_w_seq: &SequencedEvent<Event>) -> Result<(), &'static str> {
+        let (done, written) = mpsc::sync_channel(1);
+        let write = PendingWrite { entity_id, event: evt_w_seq.clone(), done };
+        self.writes.as_ref()
+            .ok_or(PERSIST_EVENT_FAILED)?
+            .send(write)
+            .map_err(|_| PERSIST_EVENT_FAILED)?;
+        written.recv().map_err(|_| PERSIST_EVENT_FAILED)?
+    }
+
+    fn retrieve_events(&self, entity_id: OrderId) -> Result<Vec<SequencedEvent<Event>>, &'static str> {
+        self.journal.retrieve_events(entity_id)
+    }
+
+    fn persist_events(&self, events: &[(OrderId, SequencedEvent<Event>)]) -> Result<(), &'static str> {
+        // Already a group
+        self.journal.persist_events(events)
+    }
+}
+
+impl<J, Event> GlobalEventsJournal<Event> for GroupCommitJournal<J, Event>
+where
+    J: GlobalEventsJournal<Event> + Send + Sync + 'static,
+    Event: Clone + Send + 'static,
+{
+    fn read_all(&self, from_position: i64, batch_size: usize) -> Result<Vec<Positioned<Event>>, &'static str> {
+        self.journal.read_all(from_position, batch_size)
+    }
+
+    fn last_position(&self) -> Result<i64, &'static str> {
+        self.journal.last_position()
+    }
+
+    fn wait_for_position(&self, position: i64, timeout: Duration) -> Result<(), &'static str> {
+        self.journal.wait_for_position(position, timeout)
+    }
+}
@@ -0,0 +1,83 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::RwLock;
+use std::time::Duration;
+use reactive_service_domain::aggregate_root::SequencedEvent;
+use reactive_service_application::command_errors::{PERSIST_EVENT_FAILED, RETRIEVE_EVENTS_FAILED};
+use crate::catch_up::AppendedPosition;
+use crate::order_service::{EventsJournal, GlobalEventsJournal, Positioned};
+
+/// Journal kept in memory, for tests and benchmarks, shared by the threads behind a `RwLock`.
+/// As the durable journals, it rejects a sequence number already persisted for an entity,
+/// and persists a batch of events all or nothing.
+///
+/// The events are kept in a log, in the order they were persisted: the position of an event is its rank in the log.
+pub struct InMemoryJournal<E> {
+    events: RwLock<Events<E>>,
+    appended: AppendedPosition,
+}
+
+struct Events<E> {
+    log: Vec<Positioned<E>>,
+    /// Index in the log of the events of each entity, by sequence number
+    by_entity: HashMap<i64, BTreeMap<i64, usize>>,
+}
+
+impl <E> InMemoryJournal<E> {
+    pub fn new() -> Result<Self, Box<dyn std::error::Error>> {
+        let events = Events { log: Vec::new(), by_entity: HashMap::new() };
+        Ok(Self { events: RwLock::new(events), appended: AppendedPosition::new(0) })
+    }
+}
+
+impl<E: Clone> EventsJournal<E> for InMemoryJournal<E> {
+    fn persist_event(&self, aggregate_id: i64, seq_event: &SequencedEvent<E>) -> Result<(), &'static str> {
+        self.persist_events(std::slice::from_ref(&(aggregate_id, seq_event.clone())))
+    }
+
+    fn persist_events(&self, events: &[(i64, SequencedEvent<E>)]) -> Result<(), &'static str> {
+        let mut persisted = self.events.write().map_err(|_| PERSIST_EVENT_FAILED)?;
+        if has_duplicates(&persisted.by_entity, events) {
+            return Err(PERSIST_EVENT_FAILED);
+        }
+        for (aggregate_id, seq_event) in events {
+            let index = persisted.log.len();
+            persisted.by_entity.entry(*aggregate_id).or_default().insert(seq_event.sequence_number, index);
+            persisted.log.push(Positioned { position: index as i64 + 1, entity_id: *aggregate_id, event: seq_event.clone() });
+        }
+        self.appended.advance(persisted.log.len() as i64);
+        Ok(())
+    }
+
+    fn retrieve_events(&self, aggregate_id: i64) -> Result<Vec<SequencedEvent<E>>, &'static str> {
+        let persisted = self.events.read().map_err(|_| RETRIEVE_EVENTS_FAILED)?;
+        let events = persisted.by_entity.get(&aggregate_id)
+            .map(|indexes| indexes.values().map(|index| persisted.log[*index].event.clone()).collect())
+            .unwrap_or_default();
+        Ok(events)
+    }
+}
+
+impl<E: Clone> GlobalEventsJournal<E> for InMemoryJournal<E> {
+    fn read_all(&self, from_position: i64, batch_size: usize) -> Result<Vec<Positioned<E>>, &'static str> {
+        let persisted = self.events.read().map_err(|_| RETRIEVE_EVENTS_FAILED)?;
+        let start = usize::try_from(from_position - 1).unwrap_or(0).min(persisted.log.len());
+        Ok(persisted.log[start..].iter().take(batch_size).cloned().collect())
+    }
+
+    fn last_position(&self) -> Result<i64, &'static str> {
+        Ok(self.events.read().map_err(|_| RETRIEVE_EVENTS_FAILED)?.log.len() as i64)
+    }
+
+    fn wait_for_position(&self, position: i64, timeout: Duration) -> Result<(), &'static str> {
+        self.appended.wait_for(position, timeout)
+    }
+}
+
+/// True when an event of the batch reuses a sequence number, already persisted or earlier in the batch.
+fn has_duplicates<E>(persisted: &HashMap<i64, BTreeMap<i64, usize>>, events: &[(i64, SequencedEvent<E>)]) -> bool {
+    let mut batch = HashSet::with_capacity(events.len());
+    events.iter().any(|(aggregate_id, seq_event)| {
+        let key = (*aggregate_id, seq_event.sequence_number);
+        persisted.get(aggregate_id).is_some_and(|e| e.contains_key(&key.1)) || !batch.insert(key)
+    })
+}
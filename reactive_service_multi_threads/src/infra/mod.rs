@@ -1 +1,7 @@
 pub mod postgres_events_store;
+pub mod postgres_projections;
+pub mod postgres_outbox;
+pub mod group_commit_journal;
+pub mod file_journal;
+pub mod sqlite_event_store;
+pub mod inmem_journal;
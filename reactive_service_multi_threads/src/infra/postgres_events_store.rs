@@ -1,12 +1,78 @@
+use std::time::{Duration, SystemTime};
 use serde::Serialize;
 use serde::de::DeserializeOwned;
 use postgres::NoTls;
-use r2d2::Pool;
+use postgres::fallible_iterator::FallibleIterator;
+use r2d2::{Pool, PooledConnection};
 use r2d2_postgres::PostgresConnectionManager;
 use reactive_service_domain::aggregate_root::SequencedEvent;
-use crate::order_service::EventsJournal;
+use reactive_service_application::scheduler::to_millis;
+use reactive_service_application::command_errors::{DESERIALIZE_COMMAND_FAILED, DESERIALIZE_EVENT_FAILED, LOAD_CHECKPOINT_FAILED, NO_DB_CONNECTION, PERSIST_EVENT_FAILED, REMOVE_SCHEDULED_COMMAND_FAILED, RETRIEVE_EVENTS_FAILED, RETRIEVE_SCHEDULED_COMMANDS_FAILED, SAVE_CHECKPOINT_FAILED, SCHEDULE_COMMAND_FAILED, SERIALIZE_COMMAND_FAILED, SERIALIZE_EVENT_FAILED};
+use crate::catch_up::CheckpointStore;
+use crate::order_service::{EventsJournal, GlobalEventsJournal, Positioned};
+use crate::scheduler::{Scheduled, ScheduleStore};
 
-pub struct PostgresEventStore { pool: Pool<PostgresConnectionManager<NoTls>>}
+/// The global position of an event is a sequence, assigned when the event is inserted. Concurrent transactions
+/// would commit their positions out of order: a reader could see a position before a lower one is committed,
+/// and skip it. A statement trigger takes a lock held until the commit, before any position is assigned:
+/// the inserts are serialized, for the time of their transaction. Once committed, they notify the `events` channel
+/// with their last position.
+///
+/// The lock is taken once per statement: batching the events, e.g. behind a `GroupCommitJournal`, keeps its cost low.
+///
+/// The outbox holds the positions of the events left to publish, with their failed attempts.
+///
+/// The scheduled commands are due at a time of the clock of their scheduler, in milliseconds since the epoch.
+const SCHEMA: &str = "
+    BEGIN;
+    SELECT pg_advisory_xact_lock(7300);
+    CREATE TABLE IF NOT EXISTS events (
+        entity_id BIGINT NOT NULL,
+        sequence_number BIGINT NOT NULL,
+        payload TEXT NOT NULL,
+        PRIMARY KEY(entity_id, sequence_number)
+    );
+    ALTER TABLE events ADD COLUMN IF NOT EXISTS position BIGSERIAL;
+    CREATE UNIQUE INDEX IF NOT EXISTS events_position ON events (position);
+    CREATE OR REPLACE FUNCTION events_order_positions() RETURNS trigger LANGUAGE plpgsql AS $$
+        BEGIN
+            PERFORM pg_advisory_xact_lock(7301);
+            RETURN NULL;
+        END $$;
+    CREATE OR REPLACE FUNCTION events_notify_positions() RETURNS trigger LANGUAGE plpgsql AS $$
+        BEGIN
+            PERFORM pg_notify('events', (SELECT MAX(position) FROM inserted)::TEXT);
+            RETURN NULL;
+        END $$;
+    CREATE OR REPLACE TRIGGER events_order_positions BEFORE INSERT ON events
+        FOR EACH STATEMENT EXECUTE FUNCTION events_order_positions();
+    CREATE OR REPLACE TRIGGER events_notify_positions AFTER INSERT ON events REFERENCING NEW TABLE AS inserted
+        FOR EACH STATEMENT EXECUTE FUNCTION events_notify_positions();
+    CREATE TABLE IF NOT EXISTS checkpoints (
+        consumer TEXT PRIMARY KEY,
+        position BIGINT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS outbox (
+        position BIGINT PRIMARY KEY,
+        attempts INT NOT NULL DEFAULT 0,
+        next_attempt_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+        last_error TEXT,
+        parked BOOLEAN NOT NULL DEFAULT FALSE
+    );
+    CREATE TABLE IF NOT EXISTS scheduled_commands (
+        key TEXT PRIMARY KEY,
+        due_at BIGINT NOT NULL,
+        payload TEXT NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS scheduled_commands_due_at ON scheduled_commands (due_at);
+    COMMIT;
+";
+
+pub struct PostgresEventStore {
+    pool: Pool<PostgresConnectionManager<NoTls>>,
+    /// The persisted events are written to the outbox too, for a `PostgresOutboxRelay` to publish them
+    outbox: bool,
+}
 
 impl PostgresEventStore {
 
@@ -14,42 +80,93 @@ impl PostgresEventStore {
         let manager = PostgresConnectionManager::new(connection_str.parse()?, NoTls);
         let pool = Pool::new(manager)?;
 
+        // Serialized by a lock: concurrent stores would fail to replace the same functions
         let mut conn = pool.get()?;
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS events (
-                entity_id BIGINT NOT NULL,
-                sequence_number BIGINT NOT NULL,
-                payload TEXT NOT NULL,
-                PRIMARY KEY(entity_id, sequence_number)
-            )",
-            &[],
-        )?;
+        conn.batch_execute(SCHEMA)?;
+
+        Ok(Self{pool, outbox: false})
+    }
+
+    /// A store writing the persisted events to the outbox, in the same statement: they are published once committed,
+    /// and only then.
+    pub fn with_outbox(connection_str: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(Self { outbox: true, ..Self::new(connection_str)? })
+    }
 
-        Ok(Self{pool})
+    pub(crate) fn connection(&self) -> Result<PooledConnection<PostgresConnectionManager<NoTls>>, &'static str> {
+        self.pool.get().map_err(|_| NO_DB_CONNECTION)
+    }
+
+    /// The statement inserting the events, and their positions in the outbox when it is enabled.
+    fn insert_statement(&self, insert_events: &str) -> String {
+        if self.outbox {
+            format!("WITH inserted AS ({} RETURNING position) INSERT INTO outbox (position) SELECT position FROM inserted", insert_events)
+        } else {
+            insert_events.to_owned()
+        }
+    }
+
+    /// Listens to the `events` channel on a pooled connection, for the time of the wait.
+    pub(crate) fn wait_for_event(&self, position: i64, timeout: Duration) -> Result<(), &'static str> {
+        let mut conn = self.connection()?;
+        conn.batch_execute("LISTEN events").map_err(|_| RETRIEVE_EVENTS_FAILED)?;
+
+        // Committed before the LISTEN, its notification is missed
+        if last_position(&mut conn)? < position {
+            // Any notification will do, the caller reads again
+            let _ = conn.notifications().timeout_iter(timeout).next().map_err(|_| RETRIEVE_EVENTS_FAILED)?;
+        }
+
+        // Drop the notifications received since, they would end the next wait early
+        conn.batch_execute("UNLISTEN events").map_err(|_| RETRIEVE_EVENTS_FAILED)?;
+        let _ = conn.notifications().iter().count();
+        Ok(())
     }
 }
 
 impl<E: Serialize + DeserializeOwned> EventsJournal<E> for PostgresEventStore {
     fn persist_event(&self, entity_id: i64, seq_event: &SequencedEvent<E>) -> Result<(), &'static str> {
-        let mut conn = self.pool.get().expect("Failed to get a DB connection");
-        let serialized_event = serde_json::to_string(&seq_event.event).map_err(|_| "Failed to serialize event")?;
+        let mut conn = self.pool.get().map_err(|_| NO_DB_CONNECTION)?;
+        let serialized_event = serde_json::to_string(&seq_event.event).map_err(|_| SERIALIZE_EVENT_FAILED)?;
         conn.execute(
-            "INSERT INTO events (entity_id, sequence_number, payload) VALUES ($1, $2, $3)",
+            &self.insert_statement("INSERT INTO events (entity_id, sequence_number, payload) VALUES ($1, $2, $3)"),
             &[&entity_id, &seq_event.sequence_number, &serialized_event],
-        ).map_err(|_| "Failed to persist event")?;
+        ).map_err(|_| PERSIST_EVENT_FAILED)?;
         Ok(())
     }
+    fn persist_events(&self, events: &[(i64, SequencedEvent<E>)]) -> Result<(), &'static str> {
+        let mut entity_ids = Vec::with_capacity(events.len());
+        let mut sequence_numbers = Vec::with_capacity(events.len());
+        let mut serialized_events = Vec::with_capacity(events.len());
+        for (entity_id, seq_event) in events {
+            entity_ids.push(*entity_id);
+            sequence_numbers.push(seq_event.sequence_number);
+            serialized_events.push(serde_json::to_string(&seq_event.event).map_err(|_| SERIALIZE_EVENT_FAILED)?);
+        }
+
+        // A single statement whatever the number of events: one round-trip, and all or nothing.
+        let mut conn = self.pool.get().map_err(|_| NO_DB_CONNECTION)?;
+        conn.execute(
+            &self.insert_statement(
+                "INSERT INTO events (entity_id, sequence_number, payload)
+                 SELECT * FROM UNNEST($1::BIGINT[], $2::BIGINT[], $3::TEXT[])"
+            ),
+            &[&entity_ids, &sequence_numbers, &serialized_events],
+        ).map_err(|_| PERSIST_EVENT_FAILED)?;
+        Ok(())
+    }
+
     fn retrieve_events(&self, entity_id: i64) -> Result<Vec<SequencedEvent<E>>, &'static str> {
-        let mut conn = self.pool.get().expect("Failed to get a DB connection");
+        let mut conn = self.pool.get().map_err(|_| NO_DB_CONNECTION)?;
         let rows = conn
             .query("SELECT sequence_number, payload FROM events WHERE entity_id = $1 ORDER BY sequence_number ASC", &[&entity_id])
-            .map_err(|_| "Failed to retrieve events")?;
+            .map_err(|_| RETRIEVE_EVENTS_FAILED)?;
 
         rows.iter()
             .map(|row| {
                 let sequence_number: i64 = row.get(0);
                 let event_payload: String = row.get(1);
-                let event: E = serde_json::from_str(&event_payload).map_err(|_| "Failed to deserialize event")?;
+                let event: E = serde_json::from_str(&event_payload).map_err(|_| DESERIALIZE_EVENT_FAILED)?;
 
                 Ok(SequencedEvent {
                     sequence_number,
@@ -59,3 +176,94 @@ impl<E: Serialize + DeserializeOwned> EventsJournal<E> for PostgresEventStore {
             .collect()
     }
 }
+
+impl<E: Serialize + DeserializeOwned> GlobalEventsJournal<E> for PostgresEventStore {
+    fn read_all(&self, from_position: i64, batch_size: usize) -> Result<Vec<Positioned<E>>, &'static str> {
+        let mut conn = self.pool.get().map_err(|_| NO_DB_CONNECTION)?;
+        let rows = conn
+            .query(
+                "SELECT position, entity_id, sequence_number, payload FROM events
+                 WHERE position >= $1 ORDER BY position ASC LIMIT $2",
+                &[&from_position, &(batch_size as i64)],
+            )
+            .map_err(|_| RETRIEVE_EVENTS_FAILED)?;
+
+        rows.iter()
+            .map(|row| {
+                let event_payload: String = row.get(3);
+                let event: E = serde_json::from_str(&event_payload).map_err(|_| DESERIALIZE_EVENT_FAILED)?;
+                Ok(Positioned { position: row.get(0), entity_id: row.get(1), event: SequencedEvent { sequence_number: row.get(2), event } })
+            })
+            .collect()
+    }
+
+    fn last_position(&self) -> Result<i64, &'static str> {
+        let mut conn = self.pool.get().map_err(|_| NO_DB_CONNECTION)?;
+        last_position(&mut conn)
+    }
+
+    fn wait_for_position(&self, position: i64, timeout: Duration) -> Result<(), &'static str> {
+        self.wait_for_event(position, timeout)
+    }
+}
+
+impl CheckpointStore for PostgresEventStore {
+    fn load_checkpoint(&self, consumer: &str) -> Result<i64, &'static str> {
+        let mut conn = self.pool.get().map_err(|_| NO_DB_CONNECTION)?;
+        let row = conn.query_opt("SELECT position FROM checkpoints WHERE consumer = $1", &[&consumer])
+            .map_err(|_| LOAD_CHECKPOINT_FAILED)?;
+        Ok(row.map_or(0, |row| row.get(0)))
+    }
+
+    fn save_checkpoint(&self, consumer: &str, position: i64) -> Result<(), &'static str> {
+        let mut conn = self.pool.get().map_err(|_| NO_DB_CONNECTION)?;
+        conn.execute(
+            "INSERT INTO checkpoints (consumer, position) VALUES ($1, $2)
+             ON CONFLICT (consumer) DO UPDATE SET position = EXCLUDED.position",
+            &[&consumer, &position],
+        ).map_err(|_| SAVE_CHECKPOINT_FAILED)?;
+        Ok(())
+    }
+}
+
+impl ScheduleStore for PostgresEventStore {
+    fn schedule(&self, scheduled: &Scheduled) -> Result<(), &'static str> {
+        let payload = serde_json::to_string(&scheduled.command).map_err(|_| SERIALIZE_COMMAND_FAILED)?;
+        let mut conn = self.pool.get().map_err(|_| NO_DB_CONNECTION)?;
+        conn.execute(
+            "INSERT INTO scheduled_commands (key, due_at, payload) VALUES ($1, $2, $3)
+             ON CONFLICT (key) DO UPDATE SET due_at = EXCLUDED.due_at, payload = EXCLUDED.payload",
+            &[&scheduled.key(), &scheduled.due_at_millis(), &payload],
+        ).map_err(|_| SCHEDULE_COMMAND_FAILED)?;
+        Ok(())
+    }
+
+    fn due(&self, now: SystemTime, limit: usize) -> Result<Vec<Scheduled>, &'static str> {
+        let mut conn = self.pool.get().map_err(|_| NO_DB_CONNECTION)?;
+        let rows = conn.query(
+            "SELECT due_at, payload FROM scheduled_commands WHERE due_at <= $1 ORDER BY due_at LIMIT $2",
+            &[&to_millis(now), &(limit as i64)],
+        ).map_err(|_| RETRIEVE_SCHEDULED_COMMANDS_FAILED)?;
+        rows.iter()
+            .map(|row| {
+                let command = serde_json::from_str(row.get(1)).map_err(|_| DESERIALIZE_COMMAND_FAILED)?;
+                Ok(Scheduled::from_millis(row.get(0), command))
+            })
+            .collect()
+    }
+
+    fn remove(&self, delivered: &Scheduled) -> Result<(), &'static str> {
+        let payload = serde_json::to_string(&delivered.command).map_err(|_| SERIALIZE_COMMAND_FAILED)?;
+        let mut conn = self.pool.get().map_err(|_| NO_DB_CONNECTION)?;
+        conn.execute(
+            "DELETE FROM scheduled_commands WHERE key = $1 AND due_at = $2 AND payload = $3",
+            &[&delivered.key(), &delivered.due_at_millis(), &payload],
+        ).map_err(|_| REMOVE_SCHEDULED_COMMAND_FAILED)?;
+        Ok(())
+    }
+}
+
+pub(crate) fn last_position(conn: &mut postgres::Client) -> Result<i64, &'static str> {
+    let row = conn.query_one("SELECT COALESCE(MAX(position), 0) FROM events", &[]).map_err(|_| RETRIEVE_EVENTS_FAILED)?;
+    Ok(row.get(0))
+}
@@ -0,0 +1,210 @@
+use postgres::{NoTls, Row, Transaction};
+use r2d2::Pool;
+use r2d2_postgres::PostgresConnectionManager;
+use reactive_service_domain::non_empty_cart::Sku;
+use reactive_service_domain::order_entity::OrderEvent;
+use reactive_service_application::command_errors::{APPLY_EVENTS_FAILED, LOAD_CHECKPOINT_FAILED, NO_DB_CONNECTION};
+use crate::order_service::{OrderId, Positioned};
+use crate::projections::{OrderSummary, Projection, SkuSales};
+
+/// The views, and their checkpoints in the `checkpoints` table of the `PostgresEventStore`.
+/// The carts of the open orders are kept for the sales: a completed order doesn't carry its cart.
+const SCHEMA: &str = "
+    BEGIN;
+    SELECT pg_advisory_xact_lock(7300);
+    CREATE TABLE IF NOT EXISTS checkpoints (
+        consumer TEXT PRIMARY KEY,
+        position BIGINT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS order_summaries (
+        order_id BIGINT PRIMARY KEY,
+        status TEXT NOT NULL,
+        total_cents BIGINT NOT NULL,
+        postal_code TEXT,
+        last_updated BIGINT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS sku_sales (
+        sku TEXT PRIMARY KEY,
+        quantity BIGINT NOT NULL,
+        orders BIGINT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS sku_sales_open_carts (
+        order_id BIGINT NOT NULL,
+        sku TEXT NOT NULL,
+        quantity BIGINT NOT NULL,
+        PRIMARY KEY(order_id, sku)
+    );
+    COMMIT;
+";
+
+const ORDER_SUMMARIES: &str = "order_summaries";
+const SKU_SALES: &str = "sku_sales";
+
+type PostgresPool = Pool<PostgresConnectionManager<NoTls>>;
+
+/// The order summary view, in the `order_summaries` table.
+pub struct PostgresOrderSummaries { pool: PostgresPool }
+
+impl PostgresOrderSummaries {
+    pub fn new(connection_str: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(Self { pool: create_pool(connection_str)? })
+    }
+
+    pub fn get(&self, order_id: OrderId) -> Result<Option<OrderSummary>, &'static str> {
+        let mut conn = self.pool.get().map_err(|_| NO_DB_CONNECTION)?;
+        let row = conn.query_opt(
+            "SELECT order_id, status, total_cents, postal_code, last_updated FROM order_summaries WHERE order_id = $1",
+            &[&order_id],
+        ).map_err(|_| "Failed to query projection")?;
+        row.map(|row| summary_from_row(&row)).transpose()
+    }
+}
+
+impl Projection<OrderEvent> for PostgresOrderSummaries {
+    fn checkpoint(&self) -> Result<i64, &'static str> {
+        load_checkpoint(&self.pool, ORDER_SUMMARIES)
+    }
+
+    fn apply(&self, events: &[Positioned<OrderEvent>]) -> Result<(), &'static str> {
+        apply_after_checkpoint(&self.pool, ORDER_SUMMARIES, events, |transaction, positioned| {
+            let previous = transaction.query_opt(
+                "SELECT order_id, status, total_cents, postal_code, last_updated FROM order_summaries WHERE order_id = $1",
+                &[&positioned.entity_id],
+            ).map_err(|_| APPLY_EVENTS_FAILED)?;
+            let previous = previous.map(|row| summary_from_row(&row)).transpose()?;
+
+            let summary = OrderSummary::evolve(previous.as_ref(), positioned);
+            transaction.execute(
+                "INSERT INTO order_summaries (order_id, status, total_cents, postal_code, last_updated) VALUES ($1, $2, $3, $4, $5)
+                 ON CONFLICT (order_id) DO UPDATE SET status = EXCLUDED.status, total_cents = EXCLUDED.total_cents,
+                    postal_code = EXCLUDED.postal_code, last_updated = EXCLUDED.last_updated",
+                &[&summary.order_id, &summary.status.as_str(), &i64::from(summary.total_cents), &summary.postal_code, &summary.last_updated],
+            ).map_err(|_| APPLY_EVENTS_FAILED)?;
+            Ok(())
+        })
+    }
+
+    fn reset(&self) -> Result<(), &'static str> {
+        reset(&self.pool, ORDER_SUMMARIES, "DELETE FROM order_summaries")
+    }
+}
+
+/// The sales by SKU view, in the `sku_sales` table.
+pub struct PostgresSalesBySku { pool: PostgresPool }
+
+impl PostgresSalesBySku {
+    pub fn new(connection_str: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(Self { pool: create_pool(connection_str)? })
+    }
+
+    pub fn get(&self, sku: &Sku) -> Result<Option<SkuSales>, &'static str> {
+        let mut conn = self.pool.get().map_err(|_| NO_DB_CONNECTION)?;
+        let row = conn.query_opt("SELECT quantity, orders FROM sku_sales WHERE sku = $1", &[&sku.0])
+            .map_err(|_| "Failed to query projection")?;
+        Ok(row.map(|row| SkuSales { quantity: row.get::<_, i64>(0) as u64, orders: row.get::<_, i64>(1) as u64 }))
+    }
+}
+
+impl Projection<OrderEvent> for PostgresSalesBySku {
+    fn checkpoint(&self) -> Result<i64, &'static str> {
+        load_checkpoint(&self.pool, SKU_SALES)
+    }
+
+    fn apply(&self, events: &[Positioned<OrderEvent>]) -> Result<(), &'static str> {
+        apply_after_checkpoint(&self.pool, SKU_SALES, events, |transaction, positioned| {
+            let order_id = positioned.entity_id;
+            match &positioned.event.event {
+                OrderEvent::UpdatedCart { cart, .. } | OrderEvent::UpdatedCartOnExistingDeliveryAddress { cart, .. } => {
+                    let (skus, quantities): (Vec<&str>, Vec<i64>) = cart.get_items().iter()
+                        .map(|(sku, quantity)| (sku.0.as_str(), i64::from(quantity.0)))
+                        .unzip();
+                    transaction.execute("DELETE FROM sku_sales_open_carts WHERE order_id = $1", &[&order_id])
+                        .map_err(|_| APPLY_EVENTS_FAILED)?;
+                    transaction.execute(
+                        "INSERT INTO sku_sales_open_carts (order_id, sku, quantity)
+                         SELECT $1, * FROM UNNEST($2::TEXT[], $3::BIGINT[])",
+                        &[&order_id, &skus, &quantities],
+                    ).map_err(|_| APPLY_EVENTS_FAILED)?;
+                },
+                OrderEvent::UpdatedDeliveryAddress { .. } => {},
+                OrderEvent::Expired => {
+                    transaction.execute("DELETE FROM sku_sales_open_carts WHERE order_id = $1", &[&order_id])
+                        .map_err(|_| APPLY_EVENTS_FAILED)?;
+                },
+                OrderEvent::Completed { .. } => {
+                    transaction.execute(
+                        "INSERT INTO sku_sales (sku, quantity, orders)
+                         SELECT sku, quantity, 1 FROM sku_sales_open_carts WHERE order_id = $1
+                         ON CONFLICT (sku) DO UPDATE SET quantity = sku_sales.quantity + EXCLUDED.quantity, orders = sku_sales.orders + 1",
+                        &[&order_id],
+                    ).map_err(|_| APPLY_EVENTS_FAILED)?;
+                    transaction.execute("DELETE FROM sku_sales_open_carts WHERE order_id = $1", &[&order_id])
+                        .map_err(|_| APPLY_EVENTS_FAILED)?;
+                },
+            }
+            Ok(())
+        })
+    }
+
+    fn reset(&self) -> Result<(), &'static str> {
+        reset(&self.pool, SKU_SALES, "DELETE FROM sku_sales; DELETE FROM sku_sales_open_carts")
+    }
+}
+
+fn create_pool(connection_str: &str) -> Result<PostgresPool, Box<dyn std::error::Error>> {
+    let manager = PostgresConnectionManager::new(connection_str.parse()?, NoTls);
+    let pool = Pool::new(manager)?;
+    pool.get()?.batch_execute(SCHEMA)?;
+    Ok(pool)
+}
+
+fn load_checkpoint(pool: &PostgresPool, consumer: &str) -> Result<i64, &'static str> {
+    let mut conn = pool.get().map_err(|_| NO_DB_CONNECTION)?;
+    let row = conn.query_opt("SELECT position FROM checkpoints WHERE consumer = $1", &[&consumer])
+        .map_err(|_| LOAD_CHECKPOINT_FAILED)?;
+    Ok(row.map_or(0, |row| row.get(0)))
+}
+
+/// Apply the events after the checkpoint of the view, and move it, in one transaction.
+/// The checkpoint is locked first: a concurrent projector waits, then skips the events applied meanwhile.
+fn apply_after_checkpoint<F>(pool: &PostgresPool, consumer: &str, events: &[Positioned<OrderEvent>], mut apply_event: F)
+    -> Result<(), &'static str>
+where
+    F: FnMut(&mut Transaction, &Positioned<OrderEvent>) -> Result<(), &'static str>
+{
+    let mut conn = pool.get().map_err(|_| NO_DB_CONNECTION)?;
+    let mut transaction = conn.transaction().map_err(|_| APPLY_EVENTS_FAILED)?;
+    transaction.execute("INSERT INTO checkpoints (consumer, position) VALUES ($1, 0) ON CONFLICT (consumer) DO NOTHING", &[&consumer])
+        .map_err(|_| APPLY_EVENTS_FAILED)?;
+    let checkpoint: i64 = transaction.query_one("SELECT position FROM checkpoints WHERE consumer = $1 FOR UPDATE", &[&consumer])
+        .map_err(|_| APPLY_EVENTS_FAILED)?
+        .get(0);
+
+    let mut position = checkpoint;
+    for positioned in events.iter().filter(|positioned| positioned.position > checkpoint) {
+        apply_event(&mut transaction, positioned)?;
+        position = positioned.position;
+    }
+
+    transaction.execute("UPDATE checkpoints SET position = $2 WHERE consumer = $1", &[&consumer, &position])
+        .map_err(|_| APPLY_EVENTS_FAILED)?;
+    transaction.commit().map_err(|_| APPLY_EVENTS_FAILED)
+}
+
+fn reset(pool: &PostgresPool, consumer: &str, delete_view: &str) -> Result<(), &'static str> {
+    let mut conn = pool.get().map_err(|_| NO_DB_CONNECTION)?;
+    let mut transaction = conn.transaction().map_err(|_| "Failed to reset projection")?;
+    transaction.batch_execute(delete_view).map_err(|_| "Failed to reset projection")?;
+    transaction.execute("DELETE FROM checkpoints WHERE consumer = $1", &[&consumer]).map_err(|_| "Failed to reset projection")?;
+    transaction.commit().map_err(|_| "Failed to reset projection")
+}
+
+fn summary_from_row(row: &Row) -> Result<OrderSummary, &'static str> {
+    Ok(OrderSummary {
+        order_id: row.get(0),
+        status: row.get::<_, &str>(1).parse()?,
+        total_cents: u32::try_from(row.get::<_, i64>(2)).map_err(|_| "Invalid total")?,
+        postal_code: row.get(3),
+        last_updated: row.get(4),
+    })
+}
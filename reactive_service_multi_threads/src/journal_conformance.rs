@@ -0,0 +1,182 @@
+//! Conformance suite of the `EventsJournal` implementations, built with the `conformance` feature.
+//!
+//! Each check panics on the first misbehaviour, so it can be called from any test:
+//! ```ignore
+//! #[test]
+//! fn in_memory_journal_conforms() {
+//!     journal_conformance::check_journal(&InMemoryJournal::new().unwrap());
+//! }
+//! ```
+//! The checks only use entity ids of their own, so a durable journal can be checked without cleaning it up.
+
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use std::thread;
+use reactive_service_domain::aggregate_root::SequencedEvent;
+use crate::order_service::{EventsJournal, GlobalEventsJournal, OrderId};
+
+/// All the checks of an events journal, but the deserialization failure one.
+pub fn check_journal<J: EventsJournal<String> + Sync>(journal: &J) {
+    check_empty_streams(journal);
+    check_ordering(journal);
+    check_duplicate_detection(journal);
+    check_large_streams(journal);
+    check_concurrent_appends(journal);
+}
+
+/// An entity without events has an empty stream, an empty batch persists nothing.
+pub fn check_empty_streams<J: EventsJournal<String> + Sync>(journal: &J) {
+    let entity_id = new_entity_id();
+
+    assert!(retrieve(journal, entity_id).is_empty(), "Unknown entity with events");
+    journal.persist_events(&[]).expect("Failed to persist an empty batch");
+    assert!(retrieve(journal, entity_id).is_empty(), "Empty batch persisted events");
+}
+
+/// Events are retrieved by sequence number, whatever the order they were persisted in,
+/// and only with the events of their own entity.
+pub fn check_ordering<J: EventsJournal<String> + Sync>(journal: &J) {
+    let (entity_id, other_entity_id) = (new_entity_id(), new_entity_id());
+
+    journal.persist_event(entity_id, &sequenced(3)).expect("Failed to persist an event");
+    journal.persist_events(&[(entity_id, sequenced(1)), (other_entity_id, sequenced(1)), (entity_id, sequenced(2))])
+        
+        .expect("Failed to persist a batch of events");
+
+    let events = retrieve(journal, entity_id);
+    assert_eq!(sequence_numbers(&events), vec![1, 2, 3], "Events out of sequence");
+    assert_eq!(events[0].event, sequenced(1).event, "Event changed by the round-trip");
+    assert_eq!(sequence_numbers(&retrieve(journal, other_entity_id)), vec![1], "Events of another entity");
+}
+
+/// A sequence number is persisted once per entity, and a batch of events is all or nothing, across entities too.
+pub fn check_duplicate_detection<J: EventsJournal<String> + Sync>(journal: &J) {
+    let entity_id = new_entity_id();
+    journal.persist_event(entity_id, &sequenced(1)).expect("Failed to persist an event");
+
+    let duplicate = SequencedEvent { sequence_number: 1, event: "duplicate".to_owned() };
+    assert!(journal.persist_event(entity_id, &duplicate).is_err(), "Duplicate event accepted");
+    assert!(journal.persist_events(&[(entity_id, sequenced(2)), (entity_id, duplicate.clone())]).is_err(),
+            "Batch with a persisted sequence number accepted");
+    assert!(journal.persist_events(&[(entity_id, sequenced(3)), (entity_id, sequenced(3))]).is_err(),
+            "Batch with the same sequence number twice accepted");
+    let other_entity_id = new_entity_id();
+    assert!(journal.persist_events(&[(other_entity_id, sequenced(1)), (entity_id, duplicate.clone())]).is_err(),
+            "Batch of several entities with a persisted sequence number accepted");
+    assert!(retrieve(journal, other_entity_id).is_empty(), "Events of another entity of a rejected batch persisted");
+
+    let events = retrieve(journal, entity_id);
+    assert_eq!(sequence_numbers(&events), vec![1], "Events of a rejected batch persisted");
+    assert_eq!(events[0].event, sequenced(1).event, "Event overwritten by a duplicate");
+
+    // Other entities are not affected
+    journal.persist_event(new_entity_id(), &sequenced(1)).expect("Failed to persist an event");
+}
+
+/// Thousands of events in a stream, more than a page of the databases reading by pages.
+pub fn check_large_streams<J: EventsJournal<String> + Sync>(journal: &J) {
+    let entity_id = new_entity_id();
+    let count = 2_500;
+
+    for first in (1..=count).step_by(100) {
+        let batch: Vec<(OrderId, SequencedEvent<String>)> = (first..first + 100).map(|n| (entity_id, sequenced(n))).collect();
+        journal.persist_events(&batch).expect("Failed to persist a batch of events");
+    }
+
+    let events = retrieve(journal, entity_id);
+    assert_eq!(events.len(), count as usize, "Events missing from a large stream");
+    assert!(sequence_numbers(&events).into_iter().eq(1..=count), "Events out of sequence in a large stream");
+}
+
+/// Appends of concurrent commands, on different entities, are all persisted.
+pub fn check_concurrent_appends<J: EventsJournal<String> + Sync>(journal: &J) {
+    let entity_ids: Vec<OrderId> = (0..8).map(|_| new_entity_id()).collect();
+
+    thread::scope(|scope| {
+        for entity_id in &entity_ids {
+            scope.spawn(move || {
+                for sequence_number in 1..=50 {
+                    journal.persist_event(*entity_id, &sequenced(sequence_number)).expect("Failed to persist an event");
+                }
+            });
+        }
+    });
+
+    for entity_id in entity_ids {
+        let events = retrieve(journal, entity_id);
+        assert!(sequence_numbers(&events).into_iter().eq(1..=50), "Concurrent appends lost or out of sequence");
+    }
+}
+
+/// A persisted event that can't be read as the requested type is an error, not a panic nor a skipped event.
+/// Only for the journals serializing the events.
+pub fn check_deserialization_failure<J: EventsJournal<String> + EventsJournal<i64> + Sync>(journal: &J) {
+    let entity_id = new_entity_id();
+    EventsJournal::<String>::persist_event(journal, entity_id, &sequenced(1)).expect("Failed to persist an event");
+
+    let events = EventsJournal::<i64>::retrieve_events(journal, entity_id);
+    assert_eq!(events.err(), Some("Failed to deserialize event"));
+}
+
+/// The events are read in the order they were persisted, across the entities, by batches,
+/// and a reader waiting for a position is woken up once it is persisted.
+/// The journal must not be written with events of other types during the check, they would fail to be read.
+pub fn check_global_order<J: GlobalEventsJournal<String> + Sync>(journal: &J) {
+    let (entity_id, other_entity_id) = (new_entity_id(), new_entity_id());
+    let start = journal.last_position().expect("Failed to read the last position");
+
+    journal.persist_event(entity_id, &sequenced(1)).expect("Failed to persist an event");
+    journal.persist_events(&[(other_entity_id, sequenced(1)), (entity_id, sequenced(2))])
+        .expect("Failed to persist a batch of events");
+    journal.persist_event(entity_id, &sequenced(3)).expect("Failed to persist an event");
+
+    let events: Vec<_> = journal.read_all(start + 1, 1_000).expect("Failed to read the events")
+        .into_iter()
+        .filter(|positioned| positioned.entity_id == entity_id || positioned.entity_id == other_entity_id)
+        .collect();
+    let keys: Vec<(OrderId, i64)> = events.iter().map(|p| (p.entity_id, p.event.sequence_number)).collect();
+    assert_eq!(keys, vec![(entity_id, 1), (other_entity_id, 1), (entity_id, 2), (entity_id, 3)], "Events out of the persisted order");
+    assert_eq!(events[0].event.event, sequenced(1).event, "Event changed by the round-trip");
+    assert!(events.windows(2).all(|pair| pair[0].position < pair[1].position), "Positions not increasing");
+    assert!(events[0].position > start, "Position reused");
+    assert!(journal.last_position().expect("Failed to read the last position") >= events[3].position, "Last position behind");
+
+    let page = journal.read_all(events[0].position, 2).expect("Failed to read the events");
+    assert_eq!(page.len(), 2, "Batch size not honored");
+    assert_eq!(page[0].position, events[0].position, "Batch not starting at the position");
+
+    // Nothing yet at the next position: the wait times out
+    let next_position = journal.last_position().expect("Failed to read the last position") + 1;
+    journal.wait_for_position(next_position, Duration::from_millis(10)).expect("Failed to wait for a position");
+
+    thread::scope(|scope| {
+        let waiting = scope.spawn(|| {
+            let start_time = Instant::now();
+            journal.wait_for_position(next_position, Duration::from_secs(10)).expect("Failed to wait for a position");
+            start_time.elapsed()
+        });
+        thread::sleep(Duration::from_millis(50));
+        journal.persist_event(entity_id, &sequenced(4)).expect("Failed to persist an event");
+        assert!(waiting.join().unwrap() < Duration::from_secs(5), "Waiter not woken up by a new event");
+    });
+}
+
+/// Unique within the process, and across the runs.
+fn new_entity_id() -> OrderId {
+    static NEXT: AtomicI64 = AtomicI64::new(0);
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).expect("Clock before 1970").as_nanos() as i64;
+    // Far from the ids of the benchmarks
+    now / 1_000 * 1_000 + NEXT.fetch_add(1, Ordering::Relaxed) % 1_000
+}
+
+fn sequenced(sequence_number: i64) -> SequencedEvent<String> {
+    SequencedEvent { sequence_number, event: format!("event {}", sequence_number) }
+}
+
+fn retrieve<J: EventsJournal<String>>(journal: &J, entity_id: OrderId) -> Vec<SequencedEvent<String>> {
+    journal.retrieve_events(entity_id).expect("Failed to retrieve events")
+}
+
+fn sequence_numbers(events: &[SequencedEvent<String>]) -> Vec<i64> {
+    events.iter().map(|e| e.sequence_number).collect()
+}
@@ -0,0 +1,216 @@
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Instant;
+use reactive_service_domain::order_entity::OrderEvent;
+use reactive_service_application::command_errors::{LIMIT_COMMANDS_FAILED, TOO_MANY_COMMANDS};
+use crate::inventory::Inventory;
+use crate::order_service::{CommandResult, EventsJournal, OrderCommand, OrderService, Principal};
+use crate::payment_processor::PaymentProcessor;
+use crate::shipping_calculator::ShippingCalculator;
+use crate::tax_calculator::TaxCalculator;
+
+pub use reactive_service_application::middleware::{
+    CommandLatencies, CommandTrace, ConcurrencyLimitConfig, CommandTracer, LatencyHistogram, LogTracer, LATENCY_BUCKETS
+};
+
+/// Handles the order commands on behalf of a principal: the `OrderService`, or a middleware around another handler.
+/// Shared by the threads issuing the commands.
+pub trait CommandHandler {
+    fn call(&self, principal: &Principal, command: OrderCommand) -> CommandResult;
+}
+
+impl<E, S, T, P, I> CommandHandler for OrderService<E, S, T, P, I>
+where
+    E: EventsJournal<OrderEvent>,
+    S: ShippingCalculator,
+    T: TaxCalculator,
+    P: PaymentProcessor,
+    I: Inventory,
+{
+    fn call(&self, principal: &Principal, command: OrderCommand) -> CommandResult {
+        self.handle_as(principal, command)
+    }
+}
+
+impl<H: CommandHandler> CommandHandler for &H {
+    fn call(&self, principal: &Principal, command: OrderCommand) -> CommandResult {
+        (**self).call(principal, command)
+    }
+}
+
+impl<H: CommandHandler> CommandHandler for Arc<H> {
+    fn call(&self, principal: &Principal, command: OrderCommand) -> CommandResult {
+        (**self).call(principal, command)
+    }
+}
+
+/// Wraps a `CommandHandler` into a middleware.
+pub trait Layer<H: CommandHandler> {
+    type Handler: CommandHandler;
+
+    fn layer(self, inner: H) -> Self::Handler;
+}
+
+/// A chain of middlewares around a `CommandHandler`, e.g. the `OrderService`. The last layer added is the outermost:
+/// the first to see a command, and the last to see its result.
+pub struct Pipeline<H: CommandHandler> {
+    handler: H,
+}
+
+impl<H: CommandHandler> Pipeline<H> {
+    pub fn new(handler: H) -> Self {
+        Self { handler }
+    }
+
+    /// Wrap the handlers added so far with the layer.
+    pub fn layer<L: Layer<H>>(self, layer: L) -> Pipeline<L::Handler> {
+        Pipeline { handler: layer.layer(self.handler) }
+    }
+
+    pub fn handle_as(&self, principal: &Principal, command: OrderCommand) -> CommandResult {
+        self.handler.call(principal, command)
+    }
+}
+
+impl<H: CommandHandler> CommandHandler for Pipeline<H> {
+    fn call(&self, principal: &Principal, command: OrderCommand) -> CommandResult {
+        self.handler.call(principal, command)
+    }
+}
+
+/// Traces every command once over, to the `CommandTracer`.
+pub struct TraceLayer<C: CommandTracer> {
+    tracer: C,
+}
+
+impl<C: CommandTracer> TraceLayer<C> {
+    pub fn new(tracer: C) -> Self {
+        Self { tracer }
+    }
+}
+
+impl<H: CommandHandler, C: CommandTracer> Layer<H> for TraceLayer<C> {
+    type Handler = Traced<H, C>;
+
+    fn layer(self, inner: H) -> Self::Handler {
+        Traced { inner, tracer: self.tracer }
+    }
+}
+
+pub struct Traced<H: CommandHandler, C: CommandTracer> {
+    inner: H,
+    tracer: C,
+}
+
+impl<H: CommandHandler, C: CommandTracer> CommandHandler for Traced<H, C> {
+    fn call(&self, principal: &Principal, command: OrderCommand) -> CommandResult {
+        let (order_id, name) = (command.order_id(), command.name());
+        let start_time = Instant::now();
+        let result = self.inner.call(principal, command);
+        self.tracer.on_command(&CommandTrace::new(order_id, name, principal, start_time.elapsed(), &result));
+        result
+    }
+}
+
+/// Records the latency of every command in the `CommandLatencies`, by kind of command.
+pub struct LatencyLayer {
+    latencies: Arc<CommandLatencies>,
+}
+
+impl LatencyLayer {
+    /// Record in the latencies, shared with their readers.
+    pub fn new(latencies: Arc<CommandLatencies>) -> Self {
+        Self { latencies }
+    }
+}
+
+impl<H: CommandHandler> Layer<H> for LatencyLayer {
+    type Handler = Timed<H>;
+
+    fn layer(self, inner: H) -> Self::Handler {
+        Timed { inner, latencies: self.latencies }
+    }
+}
+
+pub struct Timed<H: CommandHandler> {
+    inner: H,
+    latencies: Arc<CommandLatencies>,
+}
+
+impl<H: CommandHandler> CommandHandler for Timed<H> {
+    fn call(&self, principal: &Principal, command: OrderCommand) -> CommandResult {
+        let name = command.name();
+        let start_time = Instant::now();
+        let result = self.inner.call(principal, command);
+        self.latencies.record(name, start_time.elapsed(), result.is_err());
+        result
+    }
+}
+
+/// Limits the commands handled at once, e.g. to the connections of the journal: the others wait for their turn.
+///
+/// A running command can't be interrupted on a thread: the timeout is on the wait. A command still waiting
+/// after `max_wait` fails, without any side effect.
+pub struct ConcurrencyLimitLayer {
+    config: ConcurrencyLimitConfig,
+}
+
+impl ConcurrencyLimitLayer {
+    pub fn new(max_in_flight: usize) -> Self {
+        Self::with_config(ConcurrencyLimitConfig { max_in_flight, ..ConcurrencyLimitConfig::default() })
+    }
+
+    pub fn with_config(config: ConcurrencyLimitConfig) -> Self {
+        Self { config }
+    }
+}
+
+impl<H: CommandHandler> Layer<H> for ConcurrencyLimitLayer {
+    type Handler = ConcurrencyLimited<H>;
+
+    fn layer(self, inner: H) -> Self::Handler {
+        ConcurrencyLimited { inner, in_flight: Mutex::new(0), turn: Condvar::new(), config: self.config }
+    }
+}
+
+pub struct ConcurrencyLimited<H: CommandHandler> {
+    inner: H,
+    in_flight: Mutex<usize>,
+    turn: Condvar,
+    config: ConcurrencyLimitConfig,
+}
+
+impl<H: CommandHandler> ConcurrencyLimited<H> {
+    fn acquire(&self) -> Result<Permit<'_>, &'static str> {
+        let in_flight = self.in_flight.lock().map_err(|_| LIMIT_COMMANDS_FAILED)?;
+        let (mut in_flight, wait) = self.turn
+            .wait_timeout_while(in_flight, self.config.max_wait, |in_flight| *in_flight >= self.config.max_in_flight)
+            .map_err(|_| LIMIT_COMMANDS_FAILED)?;
+        if wait.timed_out() && *in_flight >= self.config.max_in_flight {
+            return Err(TOO_MANY_COMMANDS);
+        }
+        *in_flight += 1;
+        Ok(Permit { in_flight: &self.in_flight, turn: &self.turn })
+    }
+}
+
+impl<H: CommandHandler> CommandHandler for ConcurrencyLimited<H> {
+    fn call(&self, principal: &Principal, command: OrderCommand) -> CommandResult {
+        let _permit = self.acquire()?;
+        self.inner.call(principal, command)
+    }
+}
+
+/// The turn of a command, given to the next one when dropped, even if the command panics.
+struct Permit<'a> {
+    in_flight: &'a Mutex<usize>,
+    turn: &'a Condvar,
+}
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        if let Ok(mut in_flight) = self.in_flight.lock() {
+            *in_flight -= 1;
+        }
+        self.turn.notify_one();
+    }
+}
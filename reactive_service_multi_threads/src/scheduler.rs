@@ -0,0 +1,181 @@
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, SystemTime};
+use reactive_service_domain::order_entity::OrderEvent;
+use reactive_service_application::command_errors::{REMOVE_SCHEDULED_COMMAND_FAILED, RETRIEVE_SCHEDULED_COMMANDS_FAILED, SCHEDULE_COMMAND_FAILED};
+use crate::catch_up::{CatchUpSubscription, CheckpointStore};
+use crate::inventory::Inventory;
+use crate::order_service::{EventsJournal, GlobalEventsJournal, OrderService, Positioned};
+use crate::payment_processor::PaymentProcessor;
+use crate::shipping_calculator::ShippingCalculator;
+use crate::tax_calculator::TaxCalculator;
+
+pub use reactive_service_application::clock::{Clock, ManualClock, SystemClock};
+pub use reactive_service_application::scheduler::{PendingCommands, Scheduled, ScheduledCommand, SchedulerConfig, SCHEDULER_CONSUMER};
+
+/// Where the commands scheduled for later wait to be due.
+pub trait ScheduleStore {
+    /// Schedule the command, replacing the one pending under the same key.
+    fn schedule(&self, scheduled: &Scheduled) -> Result<(), &'static str>;
+    /// Up to `limit` commands due at `now`, the earliest first.
+    fn due(&self, now: SystemTime, limit: usize) -> Result<Vec<Scheduled>, &'static str>;
+    /// Remove the command once delivered, unless it was scheduled again meanwhile.
+    fn remove(&self, delivered: &Scheduled) -> Result<(), &'static str>;
+}
+
+impl<St: ScheduleStore> ScheduleStore for &St {
+    fn schedule(&self, scheduled: &Scheduled) -> Result<(), &'static str> {
+        (**self).schedule(scheduled)
+    }
+
+    fn due(&self, now: SystemTime, limit: usize) -> Result<Vec<Scheduled>, &'static str> {
+        (**self).due(now, limit)
+    }
+
+    fn remove(&self, delivered: &Scheduled) -> Result<(), &'static str> {
+        (**self).remove(delivered)
+    }
+}
+
+/// Scheduled commands kept in memory, for tests and benchmarks.
+#[derive(Default)]
+pub struct InMemoryScheduleStore {
+    scheduled: Mutex<PendingCommands>,
+}
+
+impl ScheduleStore for InMemoryScheduleStore {
+    fn schedule(&self, scheduled: &Scheduled) -> Result<(), &'static str> {
+        self.scheduled.lock().map_err(|_| SCHEDULE_COMMAND_FAILED)?.schedule(scheduled);
+        Ok(())
+    }
+
+    fn due(&self, now: SystemTime, limit: usize) -> Result<Vec<Scheduled>, &'static str> {
+        Ok(self.scheduled.lock().map_err(|_| RETRIEVE_SCHEDULED_COMMANDS_FAILED)?.due(now, limit))
+    }
+
+    fn remove(&self, delivered: &Scheduled) -> Result<(), &'static str> {
+        self.scheduled.lock().map_err(|_| REMOVE_SCHEDULED_COMMAND_FAILED)?.remove(delivered);
+        Ok(())
+    }
+}
+
+/// Delivers the scheduled commands to the `OrderService` once they are due, on the time of its `Clock`.
+///
+/// Follows the events of the orders to schedule their expiry: each change of an open order schedules it again,
+/// `order_ttl` after the change is processed. The pending commands are in the `ScheduleStore`, and the position
+/// in the `CheckpointStore`: both survive a restart. A command is removed once handled, whether the order accepted it
+/// or not: an expiry made stale by a later change is rejected. Delivered again after a crash, it is rejected too.
+pub struct CommandScheduler<'a, J, K, St, C>
+where
+    J: GlobalEventsJournal<OrderEvent>,
+    K: CheckpointStore,
+    St: ScheduleStore,
+    C: Clock,
+{
+    orders: &'a J,
+    checkpoints: &'a K,
+    subscription: CatchUpSubscription<'a, OrderEvent, J>,
+    store: St,
+    clock: C,
+    config: SchedulerConfig,
+}
+
+impl<'a, J, K, St, C> CommandScheduler<'a, J, K, St, C>
+where
+    J: GlobalEventsJournal<OrderEvent>,
+    K: CheckpointStore,
+    St: ScheduleStore,
+    C: Clock,
+{
+    /// Follow the orders from the checkpoint of the scheduler.
+    pub fn new(orders: &'a J, checkpoints: &'a K, store: St, clock: C, config: SchedulerConfig) -> Result<Self, &'static str> {
+        let subscription = CatchUpSubscription::from_checkpoint(orders, checkpoints, SCHEDULER_CONSUMER, config.catch_up.clone())?;
+        Ok(Self { orders, checkpoints, subscription, store, clock, config })
+    }
+
+    /// Schedule a command, replacing the one pending under the same key.
+    pub fn schedule(&self, scheduled: &Scheduled) -> Result<(), &'static str> {
+        self.store.schedule(scheduled)
+    }
+
+    pub fn store(&self) -> &St {
+        &self.store
+    }
+
+    /// Schedule the expiry of the orders changed so far. Returns the number of events processed.
+    pub fn catch_up(&mut self) -> Result<usize, &'static str> {
+        let mut processed = 0;
+        loop {
+            match self.process(Duration::ZERO)? {
+                0 => return Ok(processed),
+                count => processed += count,
+            }
+        }
+    }
+
+    /// Schedule the expiry of the orders changed in the next batch of events, once there is one within `timeout`,
+    /// then save the checkpoint. Returns the number of events in the batch.
+    pub fn process(&mut self, timeout: Duration) -> Result<usize, &'static str> {
+        let Some(first) = self.subscription.recv_timeout(timeout)? else { return Ok(0) };
+        let first_position = first.position;
+        let mut batch = vec![first];
+        while batch.len() < self.config.catch_up.batch_size {
+            match self.subscription.recv_timeout(Duration::ZERO)? {
+                Some(positioned) => batch.push(positioned),
+                None => break,
+            }
+        }
+
+        if let Err(err) = self.schedule_batch(&batch) {
+            // Delivered again on the next call: scheduling an expiry again replaces it
+            self.subscription = CatchUpSubscription::with_config(self.orders, first_position - 1, self.config.catch_up.clone());
+            return Err(err);
+        }
+        Ok(batch.len())
+    }
+
+    fn schedule_batch(&self, batch: &[Positioned<OrderEvent>]) -> Result<(), &'static str> {
+        let now = self.clock.now();
+        for positioned in batch {
+            if let Some(expiry) = Scheduled::order_expiry(positioned.entity_id, &positioned.event, now, self.config.order_ttl) {
+                self.store.schedule(&expiry)?;
+            }
+        }
+        self.subscription.save_checkpoint(self.checkpoints, SCHEDULER_CONSUMER)
+    }
+
+    /// Handle the commands due by now, up to `batch_size`, as an admin. Returns the number of commands delivered.
+    pub fn deliver_due<E, S, T, P, I>(&self, service: &OrderService<E, S, T, P, I>) -> Result<usize, &'static str>
+    where
+        E: EventsJournal<OrderEvent>,
+        S: ShippingCalculator,
+        T: TaxCalculator,
+        P: PaymentProcessor,
+        I: Inventory,
+    {
+        let due = self.store.due(self.clock.now(), self.config.batch_size)?;
+        for scheduled in &due {
+            // Rejected by the order, e.g. a stale expiry, it is not delivered again
+            let _ = service.handle_as_admin(scheduled.command.clone().into());
+            self.store.remove(scheduled)?;
+        }
+        Ok(due.len())
+    }
+
+    /// Schedule and deliver the commands until `stop` is set, e.g. from a thread of its own.
+    /// The due commands are delivered at least every `max_wait` of the catch-up.
+    pub fn run<E, S, T, P, I>(&mut self, service: &OrderService<E, S, T, P, I>, stop: &AtomicBool) -> Result<(), &'static str>
+    where
+        E: EventsJournal<OrderEvent>,
+        S: ShippingCalculator,
+        T: TaxCalculator,
+        P: PaymentProcessor,
+        I: Inventory,
+    {
+        while !stop.load(Ordering::Relaxed) {
+            self.process(self.config.catch_up.max_wait)?;
+            while self.deliver_due(service)? == self.config.batch_size {}
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,268 @@
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Arc;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, SyncSender};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use reactive_service_domain::aggregate_root::AggregateRoot;
+use reactive_service_domain::order_entity::{OrderEntity, OrderEntityCommand, OrderEvent};
+use reactive_service_application::command_errors::{ENTITY_POISONED, SHARD_STOPPED};
+use tracing::error;
+use crate::order_service::{
+    CommandResult, EventsJournal, OrderCommand, OrderId, PayOrder, Principal, UpdateCart, UpdateDeliveryAddress
+};
+use crate::payment_processor::PaymentProcessor;
+use crate::shipping_calculator::ShippingCalculator;
+use crate::tax_calculator::TaxCalculator;
+
+/// Tuning of the sharded runtime.
+#[derive(Debug, Clone)]
+pub struct ShardedOrderServiceConfig {
+    /// Number of worker threads, each one owning a shard of the orders.
+    pub shards: usize,
+    /// Capacity of the bounded queue in front of each shard, submitting blocks when it is full.
+    pub queue_capacity: usize,
+    /// An order without any command for this long is dropped from its shard, to be restored on its next command.
+    pub passivate_after: Duration,
+}
+
+impl Default for ShardedOrderServiceConfig {
+    fn default() -> Self {
+        Self {
+            shards: thread::available_parallelism().map(|n| n.get()).unwrap_or(4),
+            queue_capacity: 1024,
+            passivate_after: Duration::from_secs(60),
+        }
+    }
+}
+
+/// Thread-per-core alternative to the `OrderService`.
+///
+/// Each worker thread exclusively owns the orders whose id hashes to its shard,
+/// in a plain `HashMap`: the hot path doesn't take any lock.
+/// Commands are routed to the owning shard over a bounded channel,
+/// and the result comes back through a `CommandHandle`.
+/// A command panicking drops its order, restored from the journal on its next command: the shard carries on.
+///
+/// Dropping the service closes the queues, the workers finish the queued commands and stop.
+pub struct ShardedOrderService {
+    shards: Vec<SyncSender<Envelope>>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl ShardedOrderService {
+
+    pub fn new<E, S, T, P>(events_journal: E, shipping_calculator: S, tax_calculator: T, payment_processor: P) -> Self
+    where
+        E: EventsJournal<OrderEvent> + Send + Sync + 'static,
+        S: ShippingCalculator + Send + Sync + 'static,
+        T: TaxCalculator + Send + Sync + 'static,
+        P: PaymentProcessor + Send + Sync + 'static,
+    {
+        Self::with_config(
+            events_journal, shipping_calculator, tax_calculator, payment_processor, ShardedOrderServiceConfig::default()
+        )
+    }
+
+    pub fn with_config<E, S, T, P>(events_journal: E, shipping_calculator: S, tax_calculator: T, payment_processor: P,
+                                   config: ShardedOrderServiceConfig) -> Self
+    where
+        E: EventsJournal<OrderEvent> + Send + Sync + 'static,
+        S: ShippingCalculator + Send + Sync + 'static,
+        T: TaxCalculator + Send + Sync + 'static,
+        P: PaymentProcessor + Send + Sync + 'static,
+    {
+        // The dependencies are only read, they are shared by all the shards
+        let dependencies = Arc::new(Dependencies { events_journal, shipping_calculator, tax_calculator, payment_processor });
+
+        let (shards, workers) = (0..config.shards.max(1))
+            .map(|shard_id| {
+                let (sender, queue) = mpsc::sync_channel(config.queue_capacity);
+                let shard = Shard {
+                    orders: HashMap::default(),
+                    dependencies: dependencies.clone(),
+                    passivate_after: config.passivate_after,
+                };
+                let worker = thread::Builder::new()
+                    .name(format!("order-shard-{}", shard_id))
+                    .spawn(move || shard.run(queue))
+                    .expect("Failed to spawn a shard worker thread");
+                (sender, worker)
+            })
+            .unzip();
+
+        Self { shards, workers }
+    }
+
+    pub fn submit_update_cart(&self, principal: &Principal, cmd: UpdateCart) -> CommandHandle {
+        self.submit_as(principal, OrderCommand::UpdateCart(cmd))
+    }
+
+    pub fn submit_update_delivery_address(&self, principal: &Principal, cmd: UpdateDeliveryAddress) -> CommandHandle {
+        self.submit_as(principal, OrderCommand::UpdateDeliveryAddress(cmd))
+    }
+
+    pub fn submit_pay_order(&self, principal: &Principal, cmd: PayOrder) -> CommandHandle {
+        self.submit_as(principal, OrderCommand::PayOrder(cmd))
+    }
+
+    /// Submit and wait, same signature as `OrderService::update_cart`
+    pub fn update_cart(&self, principal: &Principal, cmd: UpdateCart) -> CommandResult {
+        self.submit_update_cart(principal, cmd).wait()
+    }
+
+    /// Submit and wait, same signature as `OrderService::update_delivery_address`
+    pub fn update_delivery_address(&self, principal: &Principal, cmd: UpdateDeliveryAddress) -> CommandResult {
+        self.submit_update_delivery_address(principal, cmd).wait()
+    }
+
+    /// Submit and wait, same signature as `OrderService::pay_order`
+    pub fn pay_order(&self, principal: &Principal, cmd: PayOrder) -> CommandResult {
+        self.submit_pay_order(principal, cmd).wait()
+    }
+
+    /// Queue any order command to the shard owning its order, on behalf of the principal.
+    pub fn submit_as(&self, principal: &Principal, command: OrderCommand) -> CommandHandle {
+        let (reply, response) = mpsc::sync_channel(1);
+        let shard = &self.shards[self.shard_of(command.order_id())];
+        // If the worker is gone, the reply sender is dropped with the envelope and the handle reports it
+        let _ = shard.send(Envelope { principal: *principal, command, reply });
+        CommandHandle { response }
+    }
+
+    fn shard_of(&self, order_id: OrderId) -> usize {
+        let mut hasher = DefaultHasher::new();
+        order_id.hash(&mut hasher);
+        (hasher.finish() % self.shards.len() as u64) as usize
+    }
+}
+
+impl Drop for ShardedOrderService {
+    fn drop(&mut self) {
+        self.shards.clear();
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+/// Result of a submitted command, to be waited on.
+pub struct CommandHandle {
+    response: Receiver<CommandResult>,
+}
+
+impl CommandHandle {
+    /// Block until the shard has handled the command
+    pub fn wait(self) -> CommandResult {
+        self.response.recv().map_err(|_| SHARD_STOPPED)?
+    }
+
+    /// Return the result if the shard already handled the command, or the handle to try again later
+    pub fn try_wait(self) -> Result<CommandResult, Self> {
+        match self.response.try_recv() {
+            Ok(result) => Ok(result),
+            Err(mpsc::TryRecvError::Empty) => Err(self),
+            Err(mpsc::TryRecvError::Disconnected) => Ok(Err(SHARD_STOPPED)),
+        }
+    }
+}
+
+struct Envelope {
+    principal: Principal,
+    command: OrderCommand,
+    reply: SyncSender<CommandResult>,
+}
+
+struct Dependencies<E, S, T, P> {
+    events_journal: E,
+    shipping_calculator: S,
+    tax_calculator: T,
+    payment_processor: P,
+}
+
+struct ShardedOrder {
+    entity: OrderEntity,
+    last_command: Instant,
+}
+
+struct Shard<E, S, T, P> {
+    // Only accessed by the worker thread of the shard, no lock needed
+    orders: HashMap<OrderId, ShardedOrder>,
+    dependencies: Arc<Dependencies<E, S, T, P>>,
+    passivate_after: Duration,
+}
+
+impl<E, S, T, P> Shard<E, S, T, P>
+where
+    E: EventsJournal<OrderEvent>,
+    S: ShippingCalculator,
+    T: TaxCalculator,
+    P: PaymentProcessor,
+{
+    fn run(mut self, queue: Receiver<Envelope>) {
+        let mut next_passivation = Instant::now() + self.passivate_after;
+        loop {
+            match queue.recv_timeout(next_passivation.saturating_duration_since(Instant::now())) {
+                Ok(envelope) => {
+                    let result = self.recover(&envelope.principal, envelope.command);
+                    let _ = envelope.reply.send(result);
+                },
+                Err(RecvTimeoutError::Timeout) => {},
+                // The service is dropped
+                Err(RecvTimeoutError::Disconnected) => return,
+            }
+            let now = Instant::now();
+            if now >= next_passivation {
+                let passivate_after = self.passivate_after;
+                self.orders.retain(|_, order| now.duration_since(order.last_command) < passivate_after);
+                next_passivation = now + passivate_after;
+            }
+        }
+    }
+
+    /// Handle the command, dropping its order if it panics: its entity may be half-changed.
+    fn recover(&mut self, principal: &Principal, command: OrderCommand) -> CommandResult {
+        let order_id = command.order_id();
+        panic::catch_unwind(AssertUnwindSafe(|| self.handle(principal, command))).unwrap_or_else(|_| {
+            error!(order_id, "Order command panicked, the order will be restored from the journal");
+            self.orders.remove(&order_id);
+            Err(ENTITY_POISONED)
+        })
+    }
+
+    fn handle(&mut self, principal: &Principal, command: OrderCommand) -> CommandResult {
+        let order_id = command.order_id();
+        let dependencies = &self.dependencies;
+
+        let order = match self.orders.entry(order_id) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                let events = dependencies.events_journal.retrieve_events(order_id)?;
+                let mut entity = OrderEntity::default();
+                let _ = entity.restore_from_events(events)?;
+                entry.insert(ShardedOrder { entity, last_command: Instant::now() })
+            }
+        };
+        order.last_command = Instant::now();
+        let order = &mut order.entity;
+
+        let entity_command: OrderEntityCommand = command.entity_command(
+            principal, order, &dependencies.shipping_calculator, &dependencies.tax_calculator, &dependencies.payment_processor
+        )?;
+
+        let (state, events) = order.handle_command(entity_command)?;
+        let state = state.clone();
+
+        for evt in &events {
+            if let Err(err) = dependencies.events_journal.persist_event(order_id, evt) {
+                // The entity is ahead of the journal, evict it so the next command restores it
+                self.orders.remove(&order_id);
+                return Err(err);
+            }
+        }
+        Ok((state, events))
+    }
+}
@@ -0,0 +1 @@
+pub use reactive_service_application::shipment_service::{LocalShipmentService, ShipmentService};
@@ -1,19 +1 @@
-use reactive_service_domain::non_empty_cart::NonEmptyCart;
-use reactive_service_domain::order_state::{Currency, DeliveryAddress, Money};
-
-pub trait ShippingCalculator {
-    fn shipping_cost(&self, cart: &NonEmptyCart, delivery_address: &DeliveryAddress) -> Money;
-}
-
-pub struct LocalShippingCalculator {}
-
-impl ShippingCalculator for LocalShippingCalculator {
-    fn shipping_cost(&self, cart: &NonEmptyCart, delivery_address: &DeliveryAddress) -> Money {
-        let _ = cart;
-        let _ = delivery_address;
-        Money {
-            amount_cents: 200,
-            currency: Currency::Cad
-        }
-    }
-}
\ No newline at end of file
+pub use reactive_service_application::shipping_calculator::{LocalShippingCalculator, ShippingCalculator};
@@ -0,0 +1,162 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+    use std::time::Duration;
+
+    use reactive_service_domain::aggregate_root::SequencedEvent;
+    use reactive_service_domain::order_entity::OrderEvent;
+    use reactive_service_multi_threads::catch_up::{CatchUpConfig, CatchUpSubscription, CheckpointStore, InMemoryCheckpointStore};
+    use reactive_service_multi_threads::infra::file_journal::FileJournal;
+    use reactive_service_multi_threads::infra::inmem_journal::InMemoryJournal;
+    use reactive_service_multi_threads::infra::postgres_events_store::PostgresEventStore;
+    use reactive_service_multi_threads::infra::sqlite_event_store::SqliteEventStore;
+    use reactive_service_multi_threads::order_service::{EventsJournal, GlobalEventsJournal, OrderService, Principal};
+    use reactive_service_multi_threads::payment_processor::LocalPaymentProcessor;
+    use reactive_service_multi_threads::shipping_calculator::LocalShippingCalculator;
+    use reactive_service_multi_threads::tax_calculator::LocalTaxCalculator;
+    use crate::common::update_cart;
+
+    fn service<E: EventsJournal<OrderEvent>>(journal: E)
+        -> OrderService<E, LocalShippingCalculator, LocalTaxCalculator, LocalPaymentProcessor> {
+        OrderService::new(journal, LocalShippingCalculator{}, LocalTaxCalculator{}, LocalPaymentProcessor{})
+    }
+
+    fn sequenced(sequence_number: i64) -> SequencedEvent<String> {
+        SequencedEvent { sequence_number, event: format!("event {}", sequence_number) }
+    }
+
+    fn small_batches() -> CatchUpConfig {
+        CatchUpConfig { batch_size: 2, ..CatchUpConfig::default() }
+    }
+
+    /// Receives the history, then the events persisted by another thread, without a gap nor a duplicate.
+    fn catches_up_then_follows<J: GlobalEventsJournal<String> + Sync>(journal: &J) {
+        let start = journal.last_position().unwrap();
+        for sequence_number in 1..=5 {
+            journal.persist_event(1, &sequenced(sequence_number)).unwrap();
+        }
+
+        let mut subscription = CatchUpSubscription::with_config(journal, start, small_batches());
+        thread::scope(|scope| {
+            scope.spawn(|| {
+                thread::sleep(Duration::from_millis(50));
+                for sequence_number in 6..=10 {
+                    journal.persist_event(1, &sequenced(sequence_number)).unwrap();
+                }
+            });
+
+            for sequence_number in 1..=10 {
+                let positioned = subscription.recv_timeout(Duration::from_secs(5)).unwrap().expect("Missing event");
+                assert_eq!(positioned.entity_id, 1);
+                assert_eq!(positioned.event.sequence_number, sequence_number);
+                assert_eq!(positioned.position, subscription.position());
+            }
+        });
+        assert!(subscription.recv_timeout(Duration::from_millis(20)).unwrap().is_none());
+    }
+
+    #[test]
+    fn in_memory_journal_catches_up_then_follows() {
+        catches_up_then_follows(&InMemoryJournal::new().unwrap());
+    }
+
+    #[test]
+    fn file_journal_catches_up_then_follows() {
+        let directory = tempfile::tempdir().unwrap();
+        catches_up_then_follows(&FileJournal::new(directory.path()).unwrap());
+    }
+
+    #[test]
+    fn sqlite_event_store_catches_up_then_follows() {
+        let directory = tempfile::tempdir().unwrap();
+        catches_up_then_follows(&SqliteEventStore::new(directory.path().join("events.db")).unwrap());
+    }
+
+    #[test]
+    fn file_journal_keeps_the_positions_when_reopened() {
+        let directory = tempfile::tempdir().unwrap();
+        let journal = FileJournal::new(directory.path()).unwrap();
+        journal.persist_events(&[(1, sequenced(1)), (2, sequenced(1)), (1, sequenced(2))]).unwrap();
+        drop(journal);
+
+        let journal = FileJournal::new(directory.path()).unwrap();
+        journal.persist_event(2, &sequenced(2)).unwrap();
+        let events: Vec<(i64, i64, i64)> = GlobalEventsJournal::<String>::read_all(&journal, 1, 10).unwrap()
+            .into_iter()
+            .map(|p| (p.position, p.entity_id, p.event.sequence_number))
+            .collect();
+        assert_eq!(events, vec![(1, 1, 1), (2, 2, 1), (3, 1, 2), (4, 2, 2)]);
+    }
+
+    /// The notification of another connection wakes up the subscription.
+    #[test]
+    fn postgres_event_store_notifies_the_new_events() {
+        let journal = PostgresEventStore::new("postgresql://localhost").unwrap();
+        let writer = PostgresEventStore::new("postgresql://localhost").unwrap();
+        let service = service(writer);
+        let order_id = rand::random::<i64>().abs();
+
+        let start = GlobalEventsJournal::<OrderEvent>::last_position(&journal).unwrap();
+        // A wait longer than the test: only a notification gets the event in time
+        let config = CatchUpConfig { max_wait: Duration::from_secs(30), ..CatchUpConfig::default() };
+        let mut subscription = CatchUpSubscription::<OrderEvent, _>::with_config(&journal, start, config);
+        thread::scope(|scope| {
+            scope.spawn(|| {
+                thread::sleep(Duration::from_millis(200));
+                service.update_cart(&Principal::Admin, update_cart(order_id)).unwrap();
+            });
+
+            let positioned = subscription.recv_timeout(Duration::from_secs(10)).unwrap().expect("Missing event");
+            assert_eq!(positioned.entity_id, order_id);
+            assert!(matches!(positioned.event.event, OrderEvent::UpdatedCart { .. }));
+        });
+    }
+
+    #[test]
+    fn resumes_after_the_checkpoint() {
+        let service = service(InMemoryJournal::new().unwrap());
+        for order_id in 1..=3 {
+            service.update_cart(&Principal::Admin, update_cart(order_id)).unwrap();
+        }
+        let checkpoints = InMemoryCheckpointStore::default();
+        assert_eq!(checkpoints.load_checkpoint("projection").unwrap(), 0);
+
+        let mut subscription = CatchUpSubscription::from_checkpoint(service.events_journal(), &checkpoints, "projection", small_batches()).unwrap();
+        assert_eq!(subscription.recv().unwrap().entity_id, 1);
+        assert_eq!(subscription.recv().unwrap().entity_id, 2);
+        subscription.save_checkpoint(&checkpoints, "projection").unwrap();
+        // Received but not processed: not checkpointed
+        subscription.recv().unwrap();
+
+        let mut resumed = CatchUpSubscription::from_checkpoint(service.events_journal(), &checkpoints, "projection", small_batches()).unwrap();
+        assert_eq!(resumed.recv().unwrap().entity_id, 3);
+        assert!(resumed.recv_timeout(Duration::from_millis(20)).unwrap().is_none());
+    }
+
+    #[test]
+    fn sqlite_event_store_keeps_the_checkpoints() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("events.db");
+        let store = SqliteEventStore::new(&path).unwrap();
+        assert_eq!(store.load_checkpoint("projection").unwrap(), 0);
+        store.save_checkpoint("projection", 3).unwrap();
+        store.save_checkpoint("projection", 7).unwrap();
+        drop(store);
+
+        let store = SqliteEventStore::new(&path).unwrap();
+        assert_eq!(store.load_checkpoint("projection").unwrap(), 7);
+        assert_eq!(store.load_checkpoint("other").unwrap(), 0);
+    }
+
+    #[test]
+    fn postgres_event_store_keeps_the_checkpoints() {
+        let store = PostgresEventStore::new("postgresql://localhost").unwrap();
+        let consumer = format!("projection-{}", rand::random::<u64>());
+        assert_eq!(store.load_checkpoint(&consumer).unwrap(), 0);
+        store.save_checkpoint(&consumer, 3).unwrap();
+        store.save_checkpoint(&consumer, 7).unwrap();
+        assert_eq!(store.load_checkpoint(&consumer).unwrap(), 7);
+    }
+}
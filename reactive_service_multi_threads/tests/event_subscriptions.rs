@@ -0,0 +1,104 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::thread;
+    use std::time::Duration;
+
+    use reactive_service_domain::aggregate_root::SequencedEvent;
+    use reactive_service_domain::order_entity::OrderEvent;
+    use reactive_service_multi_threads::event_bus::EventBus;
+    use reactive_service_multi_threads::infra::inmem_journal::InMemoryJournal;
+    use reactive_service_multi_threads::order_service::{EventsJournal, OrderService, Principal, SubscriptionError};
+    use reactive_service_multi_threads::payment_processor::LocalPaymentProcessor;
+    use reactive_service_multi_threads::shipping_calculator::LocalShippingCalculator;
+    use reactive_service_multi_threads::tax_calculator::LocalTaxCalculator;
+    use crate::common::update_cart;
+
+    fn service<E: EventsJournal<OrderEvent>>(journal: E)
+        -> OrderService<E, LocalShippingCalculator, LocalTaxCalculator, LocalPaymentProcessor> {
+        OrderService::new(journal, LocalShippingCalculator{}, LocalTaxCalculator{}, LocalPaymentProcessor{})
+    }
+
+    /// Fails to persist anything, and has no events.
+    struct FailingJournal;
+
+    impl EventsJournal<OrderEvent> for FailingJournal {
+        fn persist_event(&self, _: i64, _: &SequencedEvent<OrderEvent>) -> Result<(), &'static str> {
+            Err("Failed to persist event")
+        }
+
+        fn retrieve_events(&self, _: i64) -> Result<Vec<SequencedEvent<OrderEvent>>, &'static str> {
+            Ok(vec![])
+        }
+
+        fn persist_events(&self, _: &[(i64, SequencedEvent<OrderEvent>)]) -> Result<(), &'static str> {
+            Err("Failed to persist event")
+        }
+    }
+
+    #[test]
+    fn publishes_the_committed_events() {
+        let service = service(InMemoryJournal::new().unwrap());
+        let all_orders = service.event_bus().subscribe_all();
+        let order_2 = service.event_bus().subscribe(2);
+
+        // Each subscriber receives the events of an order in sequence
+        thread::scope(|scope| {
+            for order_id in 1..=2 {
+                let service = &service;
+                scope.spawn(move || for _ in 0..50 { service.update_cart(&Principal::Admin, update_cart(order_id)).unwrap(); });
+            }
+        });
+
+        let mut last_sequence_numbers = HashMap::new();
+        for _ in 0..100 {
+            let committed = all_orders.recv().unwrap();
+            let last = last_sequence_numbers.insert(committed.entity_id, committed.event.sequence_number).unwrap_or(0);
+            assert_eq!(committed.event.sequence_number, last + 1);
+        }
+        for sequence_number in 1..=50 {
+            let committed = order_2.recv().unwrap();
+            assert_eq!((committed.entity_id, committed.event.sequence_number), (2, sequence_number));
+        }
+        assert!(order_2.recv_timeout(Duration::from_millis(10)).unwrap().is_none());
+    }
+
+    #[test]
+    fn only_publishes_once_persisted() {
+        let service = service(FailingJournal);
+        let all_orders = service.event_bus().subscribe_all();
+
+        assert!(service.update_cart(&Principal::Admin, update_cart(1)).is_err());
+        assert!(all_orders.recv_timeout(Duration::from_millis(10)).unwrap().is_none());
+    }
+
+    #[test]
+    fn reports_the_events_missed_by_a_slow_subscriber() {
+        let event_bus = EventBus::new(2);
+        let subscription = event_bus.subscribe_all();
+
+        let events: Vec<SequencedEvent<u32>> = (1..=5).map(|n| SequencedEvent { sequence_number: n, event: 0 }).collect();
+        event_bus.publish(1, &events);
+
+        assert_eq!(subscription.recv().err(), Some(SubscriptionError::Lagged(3)));
+        assert_eq!(subscription.recv().unwrap().event.sequence_number, 4);
+        assert_eq!(subscription.recv().unwrap().event.sequence_number, 5);
+    }
+
+    #[test]
+    fn closes_the_subscriptions_with_the_service() {
+        let service = service(InMemoryJournal::new().unwrap());
+        let subscription = service.event_bus().subscribe(1);
+
+        service.update_cart(&Principal::Admin, update_cart(1)).unwrap();
+        let waiting = thread::spawn(move || (subscription.recv(), subscription.recv()));
+        thread::sleep(Duration::from_millis(20));
+        drop(service);
+
+        let (first, second) = waiting.join().unwrap();
+        assert_eq!(first.unwrap().event.sequence_number, 1);
+        assert_eq!(second.err(), Some(SubscriptionError::Closed));
+    }
+}
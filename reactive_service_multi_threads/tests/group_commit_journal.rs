@@ -0,0 +1,68 @@
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::Duration;
+
+    use reactive_service_domain::aggregate_root::SequencedEvent;
+    use reactive_service_multi_threads::infra::group_commit_journal::{GroupCommitConfig, GroupCommitJournal};
+    use reactive_service_multi_threads::infra::inmem_journal::InMemoryJournal;
+    use reactive_service_multi_threads::order_service::{EventsJournal, OrderId};
+
+    const REJECTED_ORDER: OrderId = 13;
+
+    /// An in-memory journal recording the size of each group written, which rejects the events of `REJECTED_ORDER`.
+    #[derive(Clone)]
+    struct RejectingJournal {
+        journal: Arc<InMemoryJournal<String>>,
+        groups: Arc<Mutex<Vec<usize>>>,
+    }
+
+    impl EventsJournal<String> for RejectingJournal {
+        fn persist_event(&self, entity_id: OrderId, evt_w_seq: &SequencedEvent<String>) -> Result<(), &'static str> {
+            if entity_id == REJECTED_ORDER {
+                return Err("Rejected event");
+            }
+            self.journal.persist_event(entity_id, evt_w_seq)
+        }
+
+        fn retrieve_events(&self, entity_id: OrderId) -> Result<Vec<SequencedEvent<String>>, &'static str> {
+            self.journal.retrieve_events(entity_id)
+        }
+
+        fn persist_events(&self, events: &[(OrderId, SequencedEvent<String>)]) -> Result<(), &'static str> {
+            self.groups.lock().unwrap().push(events.len());
+            // All or nothing
+            if events.iter().any(|(entity_id, _)| *entity_id == REJECTED_ORDER) {
+                return Err("Rejected group");
+            }
+            self.journal.persist_events(events)
+        }
+    }
+
+    fn event(sequence_number: i64) -> SequencedEvent<String> {
+        SequencedEvent { sequence_number, event: format!("event {}", sequence_number) }
+    }
+
+    #[test]
+    fn a_failed_group_only_fails_the_rejected_events() {
+        let inner = RejectingJournal { journal: Arc::new(InMemoryJournal::new().unwrap()), groups: Arc::default() };
+        // The three writes below always make a single group
+        let config = GroupCommitConfig { max_batch_size: 3, linger: Duration::from_secs(5) };
+        let journal = GroupCommitJournal::new(inner.clone(), config);
+
+        let results = thread::scope(|scope| {
+            let journal = &journal;
+            [1, REJECTED_ORDER, 2]
+                .map(|order_id| scope.spawn(move || (order_id, journal.persist_event(order_id, &event(1)))))
+                .map(|write| write.join().unwrap())
+        });
+
+        let failed: Vec<_> = results.iter().filter(|(_, result)| result.is_err()).map(|(order_id, _)| *order_id).collect();
+        assert_eq!(failed, vec![REJECTED_ORDER]);
+        assert_eq!(*inner.groups.lock().unwrap(), vec![3]);
+        assert_eq!(journal.retrieve_events(1).unwrap().len(), 1);
+        assert_eq!(journal.retrieve_events(2).unwrap().len(), 1);
+        assert!(journal.retrieve_events(REJECTED_ORDER).unwrap().is_empty());
+    }
+}
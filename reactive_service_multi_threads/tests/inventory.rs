@@ -0,0 +1,118 @@
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::thread;
+    use std::time::{Duration, SystemTime};
+
+    use reactive_service_domain::inventory_item::InventoryItemEvent;
+    use reactive_service_domain::non_empty_cart::{NonEmptyCart, Quantity, Sku};
+    use reactive_service_domain::order_entity::OrderEvent;
+    use reactive_service_domain::order_state::{DeliveryAddress, OrderState, Street};
+    use reactive_service_multi_threads::catch_up::InMemoryCheckpointStore;
+    use reactive_service_multi_threads::infra::inmem_journal::InMemoryJournal;
+    use reactive_service_multi_threads::inventory::{Inventory, InventoryConfig, InventoryService};
+    use reactive_service_multi_threads::order_fulfillment::{FulfillmentConfig, FulfillmentManager, FulfillmentStatus};
+    use reactive_service_multi_threads::order_service::{OrderService, PayOrder, Principal, UpdateCart, UpdateDeliveryAddress};
+    use reactive_service_multi_threads::payment_processor::{LocalPaymentProcessor, PaymentToken};
+    use reactive_service_multi_threads::shipment_service::LocalShipmentService;
+    use reactive_service_multi_threads::shipping_calculator::LocalShippingCalculator;
+    use reactive_service_multi_threads::tax_calculator::LocalTaxCalculator;
+
+    type Stock = InventoryService<InMemoryJournal<InventoryItemEvent>>;
+    type Service<'a> = OrderService<InMemoryJournal<OrderEvent>, LocalShippingCalculator, LocalTaxCalculator, LocalPaymentProcessor, &'a Stock>;
+
+    fn apple() -> Sku {
+        Sku("apple".to_owned())
+    }
+
+    fn cart(items: &[(&str, u16)]) -> NonEmptyCart {
+        NonEmptyCart::new(items.iter().map(|(sku, quantity)| (Sku(sku.to_string()), Quantity(*quantity))).collect::<HashMap<_, _>>()).unwrap()
+    }
+
+    fn stock(items: &[(&str, u32)]) -> Stock {
+        let stock = InventoryService::new(InMemoryJournal::new().unwrap());
+        for (sku, quantity) in items {
+            stock.receive_stock(&Sku(sku.to_string()), *quantity).unwrap();
+        }
+        stock
+    }
+
+    fn service(stock: &Stock) -> Service<'_> {
+        OrderService::with_inventory(
+            InMemoryJournal::new().unwrap(), LocalShippingCalculator{}, LocalTaxCalculator{}, LocalPaymentProcessor{}, stock
+        )
+    }
+
+    fn check_out(service: &Service, order_id: i64, cart: NonEmptyCart) -> Result<(), &'static str> {
+        service.update_cart(&Principal::Admin, UpdateCart { order_id, cart }).unwrap();
+        let delivery_address = DeliveryAddress { street: Street("1 Main Street".to_owned()), postal_code: "H0H 0H0".parse().unwrap() };
+        service.update_delivery_address(&Principal::Admin, UpdateDeliveryAddress { order_id, delivery_address }).unwrap();
+        service.pay_order(&Principal::Admin, PayOrder { order_id, payment_token: PaymentToken::new("token") }).map(|_| ())
+    }
+
+    #[test]
+    fn reserves_the_stock_of_the_order_on_checkout() {
+        let stock = stock(&[("apple", 3), ("chocolate", 1)]);
+        let service = service(&stock);
+        let now = SystemTime::now();
+
+        check_out(&service, 1, cart(&[("apple", 2), ("chocolate", 1)])).unwrap();
+        assert_eq!(stock.get_stock(&apple()).unwrap().state.available(now), 1);
+
+        // Short of chocolate: the apple is released, the order stays unpaid
+        assert_eq!(check_out(&service, 2, cart(&[("apple", 1), ("chocolate", 1)])).err(), Some("Insufficient stock"));
+        assert_eq!(stock.get_stock(&apple()).unwrap().state.available(now), 1);
+        assert!(matches!(service.get_order(2).unwrap().state, OrderState::WithAddress(_)));
+    }
+
+    #[test]
+    fn an_expired_checkout_gives_its_stock_back() {
+        let stock = InventoryService::with_config(InMemoryJournal::new().unwrap(), InventoryConfig { reservation_ttl: Duration::from_millis(50) });
+        stock.receive_stock(&apple(), 1).unwrap();
+        stock.reserve_stock(1, &cart(&[("apple", 1)])).unwrap();
+        assert_eq!(stock.reserve_stock(2, &cart(&[("apple", 1)])).err(), Some("Insufficient stock"));
+
+        thread::sleep(Duration::from_millis(60));
+        stock.reserve_stock(2, &cart(&[("apple", 1)])).unwrap();
+        assert_eq!(stock.ship_stock(1, &cart(&[("apple", 1)])).err(), Some("No reservation for the order"));
+    }
+
+    #[test]
+    fn never_reserves_more_than_the_stock_of_a_hot_sku() {
+        let stock = stock(&[("apple", 100)]);
+        let service = service(&stock);
+
+        let checked_out: usize = thread::scope(|scope| {
+            let threads: Vec<_> = (0..8).map(|thread| {
+                let service = &service;
+                scope.spawn(move || {
+                    (0..25).filter(|index| check_out(service, thread * 25 + index, cart(&[("apple", 1)])).is_ok()).count()
+                })
+            }).collect();
+            threads.into_iter().map(|thread| thread.join().unwrap()).sum()
+        });
+
+        assert_eq!(checked_out, 100);
+        let apple = stock.get_stock(&apple()).unwrap().state;
+        assert_eq!((apple.reservations.len(), apple.available(SystemTime::now())), (100, 0));
+    }
+
+    #[test]
+    fn the_fulfillment_ships_the_stock_reserved_on_checkout() {
+        let stock = stock(&[("apple", 3)]);
+        let service = service(&stock);
+        check_out(&service, 1, cart(&[("apple", 2)])).unwrap();
+
+        let checkpoints = InMemoryCheckpointStore::default();
+        let mut manager = FulfillmentManager::new(
+            service.events_journal(), &checkpoints, InMemoryJournal::new().unwrap(),
+            service.inventory(), LocalPaymentProcessor{}, LocalShipmentService{}, FulfillmentConfig::default()
+        ).unwrap();
+        manager.catch_up().unwrap();
+
+        assert_eq!(manager.get_fulfillment(1).unwrap().state.status, FulfillmentStatus::Fulfilled);
+        let apple = stock.get_stock(&apple()).unwrap().state;
+        assert_eq!((apple.on_hand, apple.reservations.len()), (1, 0));
+        assert!(apple.shipped.contains(&1));
+    }
+}
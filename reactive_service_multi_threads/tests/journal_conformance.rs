@@ -0,0 +1,50 @@
+#[cfg(test)]
+mod tests {
+    use reactive_service_multi_threads::infra::file_journal::FileJournal;
+    use reactive_service_multi_threads::infra::group_commit_journal::{GroupCommitConfig, GroupCommitJournal};
+    use reactive_service_multi_threads::infra::inmem_journal::InMemoryJournal;
+    use reactive_service_multi_threads::infra::postgres_events_store::PostgresEventStore;
+    use reactive_service_multi_threads::infra::sqlite_event_store::SqliteEventStore;
+    use reactive_service_multi_threads::journal_conformance::{check_deserialization_failure, check_global_order, check_journal};
+
+    #[test]
+    fn in_memory_journal() {
+        let journal = InMemoryJournal::new().unwrap();
+        check_journal(&journal);
+        check_global_order(&journal);
+    }
+
+    #[test]
+    fn group_commit_journal() {
+        let journal = GroupCommitJournal::new(InMemoryJournal::new().unwrap(), GroupCommitConfig::default());
+        check_journal(&journal);
+        check_global_order(&journal);
+    }
+
+    #[test]
+    fn file_journal() {
+        let directory = tempfile::tempdir().unwrap();
+        let journal = FileJournal::new(directory.path()).unwrap();
+        check_journal(&journal);
+        check_global_order(&journal);
+        check_deserialization_failure(&journal);
+    }
+
+    #[test]
+    fn sqlite_event_store() {
+        let directory = tempfile::tempdir().unwrap();
+        let journal = SqliteEventStore::new(directory.path().join("events.db")).unwrap();
+        check_journal(&journal);
+        check_global_order(&journal);
+        check_deserialization_failure(&journal);
+    }
+
+    #[test]
+    #[ignore = "needs a PostgreSQL server on localhost"]
+    fn postgres_event_store() {
+        let journal = PostgresEventStore::new("postgresql://localhost").unwrap();
+        check_journal(&journal);
+        check_global_order(&journal);
+        check_deserialization_failure(&journal);
+    }
+}
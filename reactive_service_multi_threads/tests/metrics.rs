@@ -0,0 +1,80 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use std::fmt::Debug;
+    use std::sync::{Arc, Mutex};
+
+    use tracing::field::{Field, Visit};
+    use tracing::span::{Attributes, Id, Record};
+    use tracing::{Event, Metadata, Subscriber};
+
+    use reactive_service_multi_threads::order_service::{PayOrder, Principal, PrometheusRegistry};
+    use reactive_service_multi_threads::payment_processor::PaymentToken;
+    use crate::common::{service, update_cart};
+
+    /// Records the spans created, with their fields.
+    #[derive(Clone, Default)]
+    struct Spans(Arc<Mutex<Vec<String>>>);
+
+    struct Fields(String);
+
+    impl Visit for Fields {
+        fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+            self.0.push_str(&format!(" {}={:?}", field.name(), value));
+        }
+    }
+
+    impl Subscriber for Spans {
+        fn enabled(&self, _: &Metadata<'_>) -> bool { true }
+
+        fn new_span(&self, span: &Attributes<'_>) -> Id {
+            let mut fields = Fields(span.metadata().name().to_owned());
+            span.record(&mut fields);
+            let mut spans = self.0.lock().unwrap();
+            spans.push(fields.0);
+            Id::from_u64(spans.len() as u64)
+        }
+
+        fn record(&self, _: &Id, _: &Record<'_>) {}
+        fn record_follows_from(&self, _: &Id, _: &Id) {}
+        fn event(&self, _: &Event<'_>) {}
+        fn enter(&self, _: &Id) {}
+        fn exit(&self, _: &Id) {}
+    }
+
+    #[test]
+    fn reports_the_commands_the_journal_and_the_cache() {
+        let registry = Arc::new(PrometheusRegistry::default());
+        let service = service().with_metrics(registry.clone());
+
+        service.update_cart(&Principal::Admin, update_cart(1)).unwrap();
+        service.update_cart(&Principal::Admin, update_cart(2)).unwrap();
+        assert!(service.pay_order(&Principal::Admin, PayOrder { order_id: 1, payment_token: PaymentToken::new("token") }).is_err());
+
+        let text = registry.render();
+        for line in [
+            "order_command_duration_seconds_count{command=\"update_cart\"} 2",
+            "order_command_errors_total{command=\"pay_order\",error=\"Order not ready to be paid.\"} 1",
+            "events_journal_duration_seconds_count{operation=\"load\"} 2",
+            "events_journal_duration_seconds_count{operation=\"persist\"} 2",
+            "cached_entities 2",
+        ] {
+            assert!(text.lines().any(|rendered| rendered == line), "missing {} in\n{}", line, text);
+        }
+    }
+
+    #[test]
+    fn traces_the_load_handle_and_persist_of_a_command() {
+        let spans = Spans::default();
+        let service = service();
+        tracing::subscriber::with_default(spans.clone(), || service.update_cart(&Principal::Admin, update_cart(7)).unwrap());
+
+        assert_eq!(*spans.0.lock().unwrap(), vec![
+            "command order_id=7 command=\"update_cart\"",
+            "load entity_id=7",
+            "handle entity_id=7",
+            "persist entity_id=7 events=1",
+        ]);
+    }
+}
@@ -0,0 +1,106 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{mpsc, Arc, Mutex};
+    use std::thread;
+    use std::time::Duration;
+
+    use reactive_service_domain::order_state::{Empty, OrderState};
+    use reactive_service_multi_threads::middleware::{
+        CommandHandler, CommandLatencies, CommandTrace, ConcurrencyLimitConfig, ConcurrencyLimitLayer, LatencyLayer, Layer,
+        Pipeline, TraceLayer
+    };
+    use reactive_service_multi_threads::order_service::{CommandResult, OrderCommand, PayOrder, Principal};
+    use reactive_service_multi_threads::payment_processor::PaymentToken;
+    use crate::common::{service, update_cart};
+
+    /// Rejects the commands of the orders above a limit, without calling the handler.
+    struct MaxOrderId(i64);
+
+    struct Validated<H> { inner: H, max: i64 }
+
+    impl<H: CommandHandler> Layer<H> for MaxOrderId {
+        type Handler = Validated<H>;
+
+        fn layer(self, inner: H) -> Self::Handler {
+            Validated { inner, max: self.0 }
+        }
+    }
+
+    impl<H: CommandHandler> CommandHandler for Validated<H> {
+        fn call(&self, principal: &Principal, command: OrderCommand) -> CommandResult {
+            if command.order_id() > self.max {
+                return Err("Unknown order");
+            }
+            self.inner.call(principal, command)
+        }
+    }
+
+    /// Signals each command it starts, then waits for its release.
+    struct Blocking { started: Mutex<mpsc::Sender<()>>, release: Mutex<mpsc::Receiver<()>> }
+
+    impl CommandHandler for Blocking {
+        fn call(&self, _: &Principal, _: OrderCommand) -> CommandResult {
+            self.started.lock().unwrap().send(()).unwrap();
+            self.release.lock().unwrap().recv().unwrap();
+            Ok((OrderState::Empty(Empty {}), vec![]))
+        }
+    }
+
+    #[test]
+    fn traces_and_measures_the_commands() {
+        let traces = Arc::new(Mutex::new(Vec::<CommandTrace>::new()));
+        let latencies = Arc::new(CommandLatencies::default());
+        let recorded = traces.clone();
+        let pipeline = Pipeline::new(service())
+            .layer(LatencyLayer::new(latencies.clone()))
+            .layer(TraceLayer::new(move |trace: &CommandTrace| recorded.lock().unwrap().push(trace.clone())));
+
+        pipeline.handle_as(&Principal::Customer(3), update_cart(1).into()).unwrap();
+        let pay_order = OrderCommand::PayOrder(PayOrder { order_id: 1, payment_token: PaymentToken::new("token") });
+        assert!(pipeline.handle_as(&Principal::Admin, pay_order).is_err());
+
+        let traces = traces.lock().unwrap();
+        let outcomes: Vec<_> = traces.iter().map(|trace| (trace.command, trace.customer_id, trace.outcome)).collect();
+        assert_eq!(outcomes, vec![("update_cart", Some(3), Ok(1)), ("pay_order", None, Err("Order not ready to be paid."))]);
+        assert_eq!(latencies.get("update_cart").map(|histogram| (histogram.count(), histogram.errors())), Some((1, 0)));
+        assert_eq!(latencies.get("pay_order").map(|histogram| (histogram.count(), histogram.errors())), Some((1, 1)));
+    }
+
+    #[test]
+    fn the_outer_layers_see_the_commands_first() {
+        let latencies = Arc::new(CommandLatencies::default());
+        let service = service();
+        let inner_validation = Pipeline::new(&service).layer(MaxOrderId(10)).layer(LatencyLayer::new(latencies.clone()));
+        assert_eq!(inner_validation.handle_as(&Principal::Admin, update_cart(11).into()).err(), Some("Unknown order"));
+        assert_eq!(latencies.get("update_cart").map(|histogram| histogram.errors()), Some(1));
+
+        let outer_validation = Pipeline::new(&service).layer(LatencyLayer::new(latencies.clone())).layer(MaxOrderId(10));
+        assert_eq!(outer_validation.handle_as(&Principal::Admin, update_cart(11).into()).err(), Some("Unknown order"));
+        assert!(outer_validation.handle_as(&Principal::Admin, update_cart(1).into()).is_ok());
+        assert_eq!(latencies.get("update_cart").map(|histogram| histogram.count()), Some(2));
+    }
+
+    #[test]
+    fn a_command_over_the_limit_waits_for_its_turn() {
+        let (started, started_rx) = mpsc::channel();
+        let (release, release_rx) = mpsc::channel();
+        let blocking = Blocking { started: Mutex::new(started), release: Mutex::new(release_rx) };
+        let config = ConcurrencyLimitConfig { max_in_flight: 1, max_wait: Duration::from_millis(200) };
+        let pipeline = Pipeline::new(blocking).layer(ConcurrencyLimitLayer::with_config(config));
+
+        thread::scope(|scope| {
+            let first = scope.spawn(|| pipeline.handle_as(&Principal::Admin, update_cart(1).into()));
+            started_rx.recv().unwrap();
+            assert_eq!(pipeline.handle_as(&Principal::Admin, update_cart(2).into()).err(), Some("Too many commands in progress"));
+
+            let second = scope.spawn(|| pipeline.handle_as(&Principal::Admin, update_cart(2).into()));
+            release.send(()).unwrap();
+            assert!(first.join().unwrap().is_ok());
+            started_rx.recv().unwrap();
+            release.send(()).unwrap();
+            assert!(second.join().unwrap().is_ok());
+        });
+    }
+}
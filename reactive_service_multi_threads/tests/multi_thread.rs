@@ -3,17 +3,23 @@ mod tests {
     use std::collections::HashMap;
     use std::sync::Arc;
     use std::sync::atomic::{AtomicI64, Ordering};
-    use std::time::Instant;
+    use std::time::{Duration, Instant};
     use rayon::prelude::*;
 
+    use reactive_service_domain::inventory_item::InventoryItemEvent;
     use reactive_service_domain::non_empty_cart::{NonEmptyCart, Quantity, Sku};
+    use reactive_service_multi_threads::infra::group_commit_journal::{GroupCommitConfig, GroupCommitJournal};
+    use reactive_service_multi_threads::infra::inmem_journal::InMemoryJournal;
     use reactive_service_multi_threads::infra::postgres_events_store::PostgresEventStore;
-    use reactive_service_multi_threads::order_service::{OrderService, UpdateCart};
+    use reactive_service_multi_threads::inventory::{Inventory, InventoryService};
+    use reactive_service_multi_threads::order_service::{EventsJournal, OrderService, Principal, UpdateCart};
     use reactive_service_multi_threads::payment_processor::LocalPaymentProcessor;
+    use reactive_service_multi_threads::sharded_order_service::{ShardedOrderService, ShardedOrderServiceConfig};
     use reactive_service_multi_threads::shipping_calculator::LocalShippingCalculator;
     use reactive_service_multi_threads::tax_calculator::LocalTaxCalculator;
 
     #[test]
+    #[ignore = "needs a PostgreSQL server on localhost"]
     fn bench_throughput() {
 
         let event_journal= PostgresEventStore::new("postgresql://localhost").unwrap();
@@ -27,12 +33,122 @@ mod tests {
             LocalPaymentProcessor{}
         );
 
+        bench_update_cart(|cmd| { let _ = service.update_cart(&Principal::Admin, cmd); });
+    }
+
+    #[test]
+    fn bench_throughput_in_memory() {
+
+        // Without any database: the cost of the service itself
+        let service = OrderService::new(
+            InMemoryJournal::new().unwrap(),
+            LocalShippingCalculator{},
+            LocalTaxCalculator{},
+            LocalPaymentProcessor{}
+        );
+
+        bench_update_cart(|cmd| { let _ = service.update_cart(&Principal::Admin, cmd); });
+    }
+
+    #[test]
+    #[ignore = "needs a PostgreSQL server on localhost"]
+    fn bench_throughput_sharded() {
+
+        let event_journal= PostgresEventStore::new("postgresql://localhost").unwrap();
+
+        // Each shard owns its orders, the service is shared without any lock on the entities
+        let service = ShardedOrderService::with_config(
+            event_journal,
+            LocalShippingCalculator{},
+            LocalTaxCalculator{},
+            LocalPaymentProcessor{},
+            ShardedOrderServiceConfig { shards: 8, ..Default::default() }
+        );
+
+        bench_update_cart(|cmd| { let _ = service.update_cart(&Principal::Admin, cmd); });
+    }
+
+    #[test]
+    #[ignore = "needs a PostgreSQL server on localhost"]
+    fn bench_throughput_group_commit() {
+
+        // Concurrent commands, even on different orders, share their journal round-trips
+        let event_journal = GroupCommitJournal::new(
+            PostgresEventStore::new("postgresql://localhost").unwrap(),
+            GroupCommitConfig::default()
+        );
+
+        let service = OrderService::new(
+            event_journal,
+            LocalShippingCalculator{},
+            LocalTaxCalculator{},
+            LocalPaymentProcessor{}
+        );
+
+        bench_update_cart(|cmd| { let _ = service.update_cart(&Principal::Admin, cmd); });
+    }
+
+    #[test]
+    fn bench_reserve_hot_skus_in_memory() {
+
+        // Every order reserves one of the few hot SKUs: their reservations wait for each other
+        let inventory = InventoryService::new(InMemoryJournal::new().unwrap());
+        bench_reserve_stock(&inventory);
+    }
+
+    #[test]
+    #[ignore = "needs a PostgreSQL server on localhost"]
+    fn bench_reserve_hot_skus() {
+
+        let inventory = InventoryService::new(PostgresEventStore::new("postgresql://localhost").unwrap());
+        bench_reserve_stock(&inventory);
+    }
+
+    fn bench_reserve_stock<J: EventsJournal<InventoryItemEvent> + Sync>(inventory: &InventoryService<J>) {
+
+        let hot_skus = 4;
+        let cold_skus = 1000;
+        for sku in (0..hot_skus).map(|n| format!("hot-{}", n)).chain((0..cold_skus).map(|n| format!("cold-{}", n))) {
+            inventory.receive_stock(&Sku(sku), 1_000_000).unwrap();
+        }
+
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(8)
+            .build()
+            .unwrap();
+
+        let num_orders = 10000;
+        // Unique orders across the runs: the journal may be shared
+        let first_order_id = rand::random::<u32>() as i64 * num_orders;
+        let start_time = Instant::now();
+
+        let latencies: Vec<Duration> = pool.install(|| {
+            (0..num_orders).into_par_iter().map(|n| {
+                let cart = NonEmptyCart::new(HashMap::from([
+                    (Sku(format!("hot-{}", n % hot_skus)), Quantity(1)),
+                    (Sku(format!("cold-{}", n % cold_skus)), Quantity(2)),
+                ])).unwrap();
+
+                let command_start_time = Instant::now();
+                inventory.reserve_stock(first_order_id + n, &cart).unwrap();
+                command_start_time.elapsed()
+            }).collect()
+        });
+
+        let elapsed_time = start_time.elapsed();
+        let reservations_per_sec = num_orders as f64 / elapsed_time.as_secs_f64();
+        println!("Reservations/sec {:?}", human_readable_format(reservations_per_sec));
+        print_latency_percentiles(latencies);
+    }
+
+    fn bench_update_cart<F: Fn(UpdateCart) + Sync>(update_cart: F) {
+
         {
             // cycle over X entities
             let mut ring_iterator = (0i64..=1000i64).cycle();
             // warmup entities
             for _i in 0..1000 {
-                let _ = service.update_cart(UpdateCart {
+                update_cart(UpdateCart {
                     order_id: ring_iterator.next().unwrap(),
                     cart: NonEmptyCart::new(HashMap::from([(Sku("apple".to_owned()), Quantity(1))])).unwrap()
                 });
@@ -52,15 +168,16 @@ mod tests {
 
             let start_time = Instant::now();
 
-            pool.install(|| {
-                (0..num_commands).into_par_iter().for_each(|_| {
+            let latencies: Vec<Duration> = pool.install(|| {
+                (0..num_commands).into_par_iter().map(|_| {
                     let order_id = counter.fetch_add(1, Ordering::SeqCst);
                     if order_id > number_entities {
                         counter.swap(1, Ordering::SeqCst);
                     }
 
-                    let _ = service.update_cart(UpdateCart {
-                        order_id: order_id,
+                    let command_start_time = Instant::now();
+                    update_cart(UpdateCart {
+                        order_id,
                         cart: NonEmptyCart::new(HashMap::from(
                             [
                                 (Sku("apple".to_owned()), Quantity(1)),
@@ -68,25 +185,34 @@ mod tests {
                             ]
                         )).unwrap()
                     });
-                });
+                    command_start_time.elapsed()
+                }).collect()
             });
 
             let elapsed_time = start_time.elapsed();
             let commands_per_sec = num_commands as f64 / elapsed_time.as_secs_f64();
             println!("Commands/seq {:?}", human_readable_format(commands_per_sec));
+            print_latency_percentiles(latencies);
         }
 
     }
 
+    fn print_latency_percentiles(mut latencies: Vec<Duration>) {
+        latencies.sort();
+        let percentile = |p: f64| latencies[((latencies.len() - 1) as f64 * p) as usize].as_micros();
+        println!("Latency p50 {}µs, p95 {}µs, p99 {}µs, max {}µs",
+                 percentile(0.50), percentile(0.95), percentile(0.99), percentile(1.0));
+    }
+
     fn human_readable_format(n: f64) -> String {
         if n < 1_000.0 {
-            return format!("{:.2}", n);
+            format!("{:.2}", n)
         } else if n < 1_000_000.0 {
-            return format!("{:.2}k", n / 1_000.0);
+            format!("{:.2}k", n / 1_000.0)
         } else if n < 1_000_000_000.0 {
-            return format!("{:.2}M", n / 1_000_000.0);
+            format!("{:.2}M", n / 1_000_000.0)
         } else {
-            return format!("{:.2}G", n / 1_000_000_000.0);
+            format!("{:.2}G", n / 1_000_000_000.0)
         }
     }
 }
@@ -0,0 +1,218 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::thread;
+    use std::time::{Duration, SystemTime};
+
+    use reactive_service_domain::aggregate_root::SequencedEvent;
+    use reactive_service_domain::non_empty_cart::{NonEmptyCart, Quantity, Sku};
+    use reactive_service_domain::order_state::{DeliveryAddress, Invoice, Street};
+    use reactive_service_multi_threads::catch_up::{CatchUpConfig, CheckpointStore, InMemoryCheckpointStore};
+    use reactive_service_multi_threads::infra::inmem_journal::InMemoryJournal;
+    use reactive_service_multi_threads::inventory::Inventory;
+    use reactive_service_multi_threads::order_fulfillment::{
+        FulfillmentConfig, FulfillmentEvent, FulfillmentManager, FulfillmentOrder, FulfillmentStatus, FulfillmentStep,
+        StepTimeouts, FULFILLMENT_CONSUMER
+    };
+    use reactive_service_multi_threads::order_service::{EventsJournal, PayOrder, Principal, UpdateCart, UpdateDeliveryAddress};
+    use reactive_service_multi_threads::payment_processor::{PaymentProcessor, PaymentToken};
+    use reactive_service_multi_threads::shipment_service::ShipmentService;
+    use crate::common::{service, Service};
+
+    fn cart() -> NonEmptyCart {
+        NonEmptyCart::new(HashMap::from([(Sku("apple".to_owned()), Quantity(2))])).unwrap()
+    }
+
+    fn delivery_address() -> DeliveryAddress {
+        DeliveryAddress { street: Street("1 Main Street".to_owned()), postal_code: "H0H 0H0".parse().unwrap() }
+    }
+
+    /// An order with a cart, completed or not.
+    fn place_order(service: &Service, order_id: i64, completed: bool) {
+        service.update_cart(&Principal::Admin, UpdateCart { order_id, cart: cart() }).unwrap();
+        if completed {
+            service.update_delivery_address(&Principal::Admin, UpdateDeliveryAddress { order_id, delivery_address: delivery_address() }).unwrap();
+            service.pay_order(&Principal::Admin, PayOrder { order_id, payment_token: PaymentToken::new("token") }).unwrap();
+        }
+    }
+
+    /// Records the calls of the steps, and fails the ones given, once each.
+    #[derive(Default)]
+    struct Ports {
+        failing: Mutex<Vec<&'static str>>,
+        calls: Mutex<Vec<(i64, &'static str)>>,
+    }
+
+    impl Ports {
+        fn failing(names: &[&'static str]) -> Self {
+            Self { failing: Mutex::new(names.to_vec()), ..Self::default() }
+        }
+
+        fn call(&self, order_id: i64, name: &'static str) -> Result<(), &'static str> {
+            self.calls.lock().unwrap().push((order_id, name));
+            let mut failing = self.failing.lock().unwrap();
+            match failing.iter().position(|failing| *failing == name) {
+                Some(index) => {
+                    failing.remove(index);
+                    Err("Port unavailable")
+                },
+                None => Ok(()),
+            }
+        }
+
+        fn calls(&self, order_id: i64) -> Vec<&'static str> {
+            self.calls.lock().unwrap().iter().filter(|(id, _)| *id == order_id).map(|(_, name)| *name).collect()
+        }
+    }
+
+    impl Inventory for &Ports {
+        fn reserve_stock(&self, order_id: i64, _: &NonEmptyCart) -> Result<(), &'static str> { self.call(order_id, "reserve_stock") }
+        fn release_stock(&self, order_id: i64, _: &NonEmptyCart) -> Result<(), &'static str> { self.call(order_id, "release_stock") }
+        fn ship_stock(&self, order_id: i64, _: &NonEmptyCart) -> Result<(), &'static str> { self.call(order_id, "ship_stock") }
+    }
+
+    impl PaymentProcessor for &Ports {
+        fn pay_with_token(&self, _: PaymentToken) -> Invoice { Invoice{} }
+        fn capture_payment(&self, order_id: i64) -> Result<(), &'static str> { self.call(order_id, "capture_payment") }
+        fn refund_payment(&self, order_id: i64) -> Result<(), &'static str> { self.call(order_id, "refund_payment") }
+    }
+
+    impl ShipmentService for &Ports {
+        fn create_shipment(&self, order_id: i64, _: &NonEmptyCart, _: &DeliveryAddress) -> Result<(), &'static str> {
+            self.call(order_id, "create_shipment")
+        }
+        fn cancel_shipment(&self, order_id: i64) -> Result<(), &'static str> { self.call(order_id, "cancel_shipment") }
+    }
+
+    fn small_batches() -> FulfillmentConfig {
+        FulfillmentConfig {
+            catch_up: CatchUpConfig { batch_size: 2, ..CatchUpConfig::default() },
+            retry_delay: Duration::from_millis(50),
+            ..FulfillmentConfig::default()
+        }
+    }
+
+    #[test]
+    fn fulfills_the_completed_orders() {
+        let service = service();
+        place_order(&service, 1, true);
+        place_order(&service, 2, false);
+        let checkpoints = InMemoryCheckpointStore::default();
+        let ports = Ports::default();
+        let mut manager = FulfillmentManager::new(
+            service.events_journal(), &checkpoints, InMemoryJournal::new().unwrap(), &ports, &ports, &ports, small_batches()
+        ).unwrap();
+
+        assert_eq!(manager.catch_up().unwrap(), 4);
+        assert_eq!(manager.get_fulfillment(1).unwrap().state.status, FulfillmentStatus::Fulfilled);
+        assert_eq!(manager.get_fulfillment(2).unwrap().state.status, FulfillmentStatus::NotStarted);
+        assert_eq!(ports.calls(1), vec!["reserve_stock", "capture_payment", "create_shipment", "ship_stock"]);
+        assert_eq!(checkpoints.load_checkpoint(FULFILLMENT_CONSUMER).unwrap(), 4);
+        assert_eq!(manager.fulfill(2).err(), Some("Order is not completed"));
+    }
+
+    #[test]
+    fn compensates_then_retries_a_failed_compensation() {
+        let service = service();
+        place_order(&service, 1, true);
+        let checkpoints = InMemoryCheckpointStore::default();
+        let ports = Ports::failing(&["create_shipment", "refund_payment"]);
+        let mut manager = FulfillmentManager::new(
+            service.events_journal(), &checkpoints, InMemoryJournal::new().unwrap(), &ports, &ports, &ports, small_batches()
+        ).unwrap();
+
+        // The refund fails once: the checkpoint stays before the completion
+        assert_eq!(manager.catch_up().unwrap(), 3);
+        assert_eq!(manager.get_fulfillment(1).unwrap().state.status, FulfillmentStatus::Compensating);
+        assert_eq!(manager.failed_fulfillments(), 1);
+        assert_eq!(checkpoints.load_checkpoint(FULFILLMENT_CONSUMER).unwrap(), 2);
+
+        thread::sleep(Duration::from_millis(50));
+        assert_eq!(manager.catch_up().unwrap(), 0);
+        assert_eq!(manager.failed_fulfillments(), 0);
+        let state = manager.get_fulfillment(1).unwrap().state;
+        assert_eq!((state.status, state.failure.as_deref()), (FulfillmentStatus::Cancelled, Some("Port unavailable")));
+        assert_eq!(
+            ports.calls(1),
+            vec!["reserve_stock", "capture_payment", "create_shipment", "refund_payment", "refund_payment", "release_stock"]
+        );
+        assert_eq!(checkpoints.load_checkpoint(FULFILLMENT_CONSUMER).unwrap(), 3);
+    }
+
+    #[test]
+    fn fulfills_the_later_orders_while_a_failed_fulfillment_waits_for_its_retry() {
+        let service = service();
+        place_order(&service, 1, true);
+        place_order(&service, 2, true);
+        let checkpoints = InMemoryCheckpointStore::default();
+        let ports = Ports::failing(&["create_shipment", "refund_payment"]);
+        let mut manager = FulfillmentManager::new(
+            service.events_journal(), &checkpoints, InMemoryJournal::new().unwrap(), &ports, &ports, &ports, small_batches()
+        ).unwrap();
+
+        assert_eq!(manager.catch_up().unwrap(), 6);
+        assert_eq!(manager.get_fulfillment(1).unwrap().state.status, FulfillmentStatus::Compensating);
+        assert_eq!(manager.get_fulfillment(2).unwrap().state.status, FulfillmentStatus::Fulfilled);
+        assert_eq!(checkpoints.load_checkpoint(FULFILLMENT_CONSUMER).unwrap(), 2);
+
+        thread::sleep(Duration::from_millis(50));
+        assert_eq!(manager.catch_up().unwrap(), 0);
+        assert_eq!(manager.get_fulfillment(1).unwrap().state.status, FulfillmentStatus::Cancelled);
+        assert_eq!(ports.calls(2), vec!["reserve_stock", "capture_payment", "create_shipment", "ship_stock"]);
+        assert_eq!(checkpoints.load_checkpoint(FULFILLMENT_CONSUMER).unwrap(), 6);
+    }
+
+    #[test]
+    fn expires_a_step_left_in_flight_before_a_restart() {
+        let service = service();
+        place_order(&service, 1, true);
+        let fulfillments = InMemoryJournal::new().unwrap();
+        let order = FulfillmentOrder { cart: cart(), delivery_address: delivery_address() };
+        let deadline = SystemTime::now() - Duration::from_secs(1);
+        fulfillments.persist_events(&[
+            (1, SequencedEvent { sequence_number: 1, event: FulfillmentEvent::Started { order, timeouts: StepTimeouts::default() } }),
+            (1, SequencedEvent { sequence_number: 2, event: FulfillmentEvent::StepStarted { step: FulfillmentStep::ReserveStock, deadline } }),
+        ]).unwrap();
+
+        let checkpoints = InMemoryCheckpointStore::default();
+        let ports = Ports::default();
+        let mut manager = FulfillmentManager::new(
+            service.events_journal(), &checkpoints, fulfillments, &ports, &ports, &ports, small_batches()
+        ).unwrap();
+        manager.catch_up().unwrap();
+
+        let state = manager.get_fulfillment(1).unwrap().state;
+        assert_eq!((state.status, state.failure.as_deref()), (FulfillmentStatus::Cancelled, Some("Step timed out")));
+        assert_eq!(ports.calls(1), vec!["release_stock"]);
+    }
+
+    #[test]
+    fn follows_the_orders_as_they_complete() {
+        let service = service();
+        let checkpoints = InMemoryCheckpointStore::default();
+        let ports = Ports::default();
+        let mut manager = FulfillmentManager::new(
+            service.events_journal(), &checkpoints, InMemoryJournal::new().unwrap(), &ports, &ports, &ports, small_batches()
+        ).unwrap();
+
+        let stop = AtomicBool::new(false);
+        thread::scope(|scope| {
+            let running = scope.spawn(|| manager.run(&stop));
+            thread::sleep(Duration::from_millis(50));
+            place_order(&service, 1, true);
+            for _ in 0..100 {
+                if ports.calls(1).len() == 4 {
+                    break;
+                }
+                thread::sleep(Duration::from_millis(50));
+            }
+            stop.store(true, Ordering::Relaxed);
+            running.join().unwrap();
+        });
+        assert_eq!(manager.get_fulfillment(1).unwrap().state.status, FulfillmentStatus::Fulfilled);
+    }
+}
@@ -0,0 +1,52 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+    use std::time::Duration;
+
+    use reactive_service_domain::order_state::OrderState;
+    use reactive_service_multi_threads::order_service::Principal;
+    use crate::common::{service, update_cart};
+
+    #[test]
+    fn returns_the_state_and_the_sequence_number() {
+        let service = service();
+
+        let order = service.get_order(1).unwrap();
+        assert!(matches!(order.state, OrderState::Empty(_)));
+        assert_eq!(order.sequence_number, 0);
+
+        service.update_cart(&Principal::Admin, update_cart(1)).unwrap();
+        service.update_cart(&Principal::Admin, update_cart(1)).unwrap();
+
+        let order = service.get_order(1).unwrap();
+        assert!(matches!(order.state, OrderState::WithCart(_)));
+        assert_eq!(order.sequence_number, 2);
+    }
+
+    #[test]
+    fn waits_for_the_version() {
+        let service = service();
+
+        thread::scope(|scope| {
+            let waiting = scope.spawn(|| service.get_order_at_least(1, 2, Duration::from_secs(5)));
+
+            thread::sleep(Duration::from_millis(50));
+            service.update_cart(&Principal::Admin, update_cart(1)).unwrap();
+            service.update_cart(&Principal::Admin, update_cart(1)).unwrap();
+
+            assert_eq!(waiting.join().unwrap().unwrap().sequence_number, 2);
+        });
+    }
+
+    #[test]
+    fn times_out_waiting_for_the_version() {
+        let service = service();
+        service.update_cart(&Principal::Admin, update_cart(1)).unwrap();
+
+        let result = service.get_order_at_least(1, 2, Duration::from_millis(50));
+        assert_eq!(result.err(), Some("Timed out waiting for the version"));
+        assert_eq!(service.get_order_at_least(1, 1, Duration::ZERO).unwrap().sequence_number, 1);
+    }
+}
@@ -0,0 +1,110 @@
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::mpsc::Receiver;
+    use std::thread;
+    use std::time::Duration;
+
+    use reactive_service_domain::aggregate_root::SequencedEvent;
+    use reactive_service_multi_threads::infra::postgres_events_store::PostgresEventStore;
+    use reactive_service_multi_threads::infra::postgres_outbox::PostgresOutboxRelay;
+    use reactive_service_multi_threads::order_service::EventsJournal;
+    use reactive_service_multi_threads::outbox::{InProcessPublisher, JsonLinesPublisher, MessagePublisher, OutboxMessage, OutboxRelayConfig};
+
+    fn sequenced(sequence_number: i64) -> SequencedEvent<String> {
+        SequencedEvent { sequence_number, event: format!("event {}", sequence_number) }
+    }
+
+    /// Fails to publish the messages of an entity, while it is poisoned.
+    struct PoisonedPublisher {
+        inner: InProcessPublisher,
+        poisoned_entity_id: i64,
+        poisoned: AtomicBool,
+    }
+
+    impl MessagePublisher for PoisonedPublisher {
+        fn publish(&self, message: &OutboxMessage) -> Result<(), &'static str> {
+            if message.entity_id == self.poisoned_entity_id && self.poisoned.load(Ordering::Relaxed) {
+                return Err("Poisoned message");
+            }
+            self.inner.publish(message)
+        }
+    }
+
+    /// The messages received of the given entities: the outbox is shared by the runs.
+    fn received(receiver: &Receiver<OutboxMessage>, entity_ids: &[i64]) -> Vec<(i64, i64)> {
+        receiver.try_iter()
+            .filter(|message| entity_ids.contains(&message.entity_id))
+            .map(|message| (message.entity_id, message.sequence_number))
+            .collect()
+    }
+
+    /// One test for the relays: they share the outbox.
+    #[test]
+    fn postgres_outbox_relays_the_events_in_order_and_parks_the_poison() {
+        let store = PostgresEventStore::with_outbox("postgresql://localhost").unwrap();
+        let without_outbox = PostgresEventStore::new("postgresql://localhost").unwrap();
+        let entity_id = rand::random::<i64>().abs() / 8 * 8;
+        let (other, poison, not_in_outbox) = (entity_id + 1, entity_id + 2, entity_id + 3);
+        let entity_ids = [entity_id, other, poison, not_in_outbox];
+
+        let (inner, receiver) = InProcessPublisher::new();
+        let publisher = PoisonedPublisher { inner, poisoned_entity_id: poison, poisoned: AtomicBool::new(true) };
+        let config = OutboxRelayConfig { initial_backoff: Duration::ZERO, max_attempts: 3, ..OutboxRelayConfig::default() };
+        let relay = PostgresOutboxRelay::new(&store, publisher, config);
+
+        store.persist_events(&[(entity_id, sequenced(1)), (other, sequenced(1))]).unwrap();
+        store.persist_event(entity_id, &sequenced(2)).unwrap();
+        without_outbox.persist_event(not_in_outbox, &sequenced(1)).unwrap();
+        store.persist_event(poison, &sequenced(1)).unwrap();
+        store.persist_event(entity_id, &sequenced(3)).unwrap();
+
+        // Blocked by the poison until it is parked
+        while relay.relay().unwrap() > 0 {}
+        assert_eq!(received(&receiver, &entity_ids), vec![(entity_id, 1), (other, 1), (entity_id, 2)]);
+        relay.relay().unwrap();
+        relay.relay().unwrap();
+        while relay.relay().unwrap() > 0 {}
+        assert_eq!(received(&receiver, &entity_ids), vec![(entity_id, 3)]);
+
+        let parked = relay.parked().unwrap().into_iter().find(|parked| parked.message.entity_id == poison).expect("Not parked");
+        assert_eq!((parked.attempts, parked.last_error.as_str(), parked.message.payload.as_str()), (3, "Poisoned message", r#""event 1""#));
+
+        relay.publisher().poisoned.store(false, Ordering::Relaxed);
+        assert!(relay.unpark(parked.message.position).unwrap());
+        assert!(!relay.unpark(parked.message.position).unwrap());
+        while relay.relay().unwrap() > 0 {}
+        assert_eq!(received(&receiver, &entity_ids), vec![(poison, 1)]);
+
+        // Woken up by the new events
+        let stop = AtomicBool::new(false);
+        thread::scope(|scope| {
+            let running = scope.spawn(|| relay.run(&stop));
+            thread::sleep(Duration::from_millis(50));
+            store.persist_event(other, &sequenced(2)).unwrap();
+            let message = receiver.recv_timeout(Duration::from_secs(5)).expect("Not relayed");
+            assert_eq!((message.entity_id, message.sequence_number), (other, 2));
+            stop.store(true, Ordering::Relaxed);
+            running.join().unwrap().unwrap();
+        });
+    }
+
+    #[test]
+    fn json_lines_publisher_appends_to_the_file() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("messages.jsonl");
+        let publisher = JsonLinesPublisher::file(&path).unwrap();
+        for position in 1..=2 {
+            let message = OutboxMessage { position, entity_id: 1, sequence_number: position, payload: format!(r#""event {}""#, position) };
+            publisher.publish(&message).unwrap();
+        }
+
+        let lines: Vec<serde_json::Value> = std::fs::read_to_string(&path).unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[1]["position"], 2);
+        assert_eq!(lines[1]["event"], "event 2");
+    }
+}
@@ -0,0 +1,150 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::thread;
+    use std::time::Duration;
+
+    use reactive_service_domain::non_empty_cart::{NonEmptyCart, Quantity, Sku};
+    use reactive_service_domain::order_entity::OrderEvent;
+    use reactive_service_domain::order_state::{DeliveryAddress, Street};
+    use reactive_service_multi_threads::catch_up::CatchUpConfig;
+    use reactive_service_multi_threads::infra::postgres_projections::{PostgresOrderSummaries, PostgresSalesBySku};
+    use reactive_service_multi_threads::order_service::{
+        EventsJournal, GlobalEventsJournal, OrderService, PayOrder, Principal, UpdateCart, UpdateDeliveryAddress
+    };
+    use reactive_service_multi_threads::payment_processor::{LocalPaymentProcessor, PaymentToken};
+    use reactive_service_multi_threads::projections::{
+        InMemoryProjection, OrderStatus, OrderSummaries, Projection, Projector, SalesBySku, SkuSales
+    };
+    use reactive_service_multi_threads::shipping_calculator::LocalShippingCalculator;
+    use reactive_service_multi_threads::tax_calculator::LocalTaxCalculator;
+    use crate::common::service;
+
+    fn cart(sku: &str, quantity: u16) -> NonEmptyCart {
+        NonEmptyCart::new(HashMap::from([(Sku(sku.to_owned()), Quantity(quantity))])).unwrap()
+    }
+
+    /// An order with a cart, completed or not.
+    fn place_order<E: EventsJournal<OrderEvent>>(
+        service: &OrderService<E, LocalShippingCalculator, LocalTaxCalculator, LocalPaymentProcessor>,
+        order_id: i64, cart: NonEmptyCart, completed: bool
+    ) {
+        service.update_cart(&Principal::Admin, UpdateCart { order_id, cart }).unwrap();
+        if completed {
+            let delivery_address = DeliveryAddress { street: Street("1 Main Street".to_owned()), postal_code: "H0H 0H0".parse().unwrap() };
+            service.update_delivery_address(&Principal::Admin, UpdateDeliveryAddress { order_id, delivery_address }).unwrap();
+            service.pay_order(&Principal::Admin, PayOrder { order_id, payment_token: PaymentToken::new("token") }).unwrap();
+        }
+    }
+
+    fn small_batches() -> CatchUpConfig {
+        CatchUpConfig { batch_size: 2, ..CatchUpConfig::default() }
+    }
+
+    #[test]
+    fn projects_the_history() {
+        let service = service();
+        place_order(&service, 1, cart("apple", 2), true);
+        place_order(&service, 2, cart("apple", 3), true);
+        place_order(&service, 3, cart("apple", 7), false);
+
+        let summaries = InMemoryProjection::<OrderSummaries>::new();
+        let sales = InMemoryProjection::<SalesBySku>::new();
+        assert_eq!(Projector::new(service.events_journal(), &summaries, small_batches()).unwrap().catch_up().unwrap(), 7);
+        assert_eq!(Projector::new(service.events_journal(), &sales, small_batches()).unwrap().catch_up().unwrap(), 7);
+
+        let summary = summaries.read(|view| view.get(1).cloned()).unwrap().unwrap();
+        assert_eq!((summary.status, summary.total_cents, summary.postal_code.as_deref()), (OrderStatus::Completed, 330, Some("H0H 0H0")));
+        assert_eq!(summaries.read(|view| view.get(3).map(|s| s.status)).unwrap(), Some(OrderStatus::WithCart));
+        assert_eq!(sales.read(|view| view.get(&Sku("apple".to_owned())).cloned()).unwrap(), Some(SkuSales { quantity: 5, orders: 2 }));
+        assert_eq!(summaries.checkpoint().unwrap(), 7);
+    }
+
+    #[test]
+    fn applies_the_redelivered_events_once() {
+        let service = service();
+        place_order(&service, 1, cart("apple", 2), true);
+        let events = service.events_journal().read_all(1, 100).unwrap();
+
+        let sales = InMemoryProjection::<SalesBySku>::new();
+        sales.apply(&events[..2]).unwrap();
+        sales.apply(&events).unwrap();
+        sales.apply(&events).unwrap();
+        assert_eq!(sales.read(|view| view.get(&Sku("apple".to_owned())).cloned()).unwrap(), Some(SkuSales { quantity: 2, orders: 1 }));
+    }
+
+    #[test]
+    fn resumes_from_the_checkpoint_and_rebuilds() {
+        let service = service();
+        place_order(&service, 1, cart("apple", 2), true);
+        let summaries = InMemoryProjection::<OrderSummaries>::new();
+        Projector::new(service.events_journal(), &summaries, small_batches()).unwrap().catch_up().unwrap();
+
+        place_order(&service, 2, cart("apple", 1), false);
+        let mut projector = Projector::new(service.events_journal(), &summaries, small_batches()).unwrap();
+        assert_eq!(projector.catch_up().unwrap(), 1);
+        assert_eq!(summaries.read(|view| view.len()).unwrap(), 2);
+
+        assert_eq!(projector.rebuild().unwrap(), 4);
+        assert_eq!(summaries.read(|view| view.len()).unwrap(), 2);
+        assert_eq!(summaries.checkpoint().unwrap(), 4);
+    }
+
+    #[test]
+    fn follows_the_new_events() {
+        let service = service();
+        let summaries = InMemoryProjection::<OrderSummaries>::new();
+        let mut projector = Projector::new(service.events_journal(), &summaries, small_batches()).unwrap();
+        thread::scope(|scope| {
+            scope.spawn(|| {
+                thread::sleep(Duration::from_millis(50));
+                place_order(&service, 1, cart("apple", 1), false);
+            });
+            assert_eq!(projector.project(Duration::from_secs(5)).unwrap(), 1);
+        });
+        assert_eq!(summaries.read(|view| view.get(1).map(|s| s.status)).unwrap(), Some(OrderStatus::WithCart));
+    }
+
+    /// The view tables are shared by the runs: the projection is rebuilt from a journal of its own.
+    #[test]
+    fn postgres_order_summaries() {
+        let service = service();
+        let order_id = rand::random::<i64>().abs();
+        place_order(&service, order_id, cart("apple", 1), true);
+        place_order(&service, order_id + 1, cart("apple", 1), false);
+
+        let summaries = PostgresOrderSummaries::new("postgresql://localhost").unwrap();
+        let mut projector = Projector::new(service.events_journal(), &summaries, small_batches()).unwrap();
+        assert_eq!(projector.rebuild().unwrap(), 4);
+        assert_eq!(summaries.checkpoint().unwrap(), 4);
+        summaries.apply(&service.events_journal().read_all(1, 100).unwrap()).unwrap();
+
+        let summary = summaries.get(order_id).unwrap().unwrap();
+        assert_eq!((summary.status, summary.total_cents, summary.postal_code.as_deref()), (OrderStatus::Completed, 330, Some("H0H 0H0")));
+        assert_eq!(summary.last_updated, 3);
+        assert_eq!(summaries.get(order_id + 1).unwrap().map(|s| s.status), Some(OrderStatus::WithCart));
+        assert_eq!(summaries.get(order_id + 2).unwrap(), None);
+
+        assert_eq!(Projector::new(service.events_journal(), &summaries, small_batches()).unwrap().catch_up().unwrap(), 0);
+    }
+
+    #[test]
+    fn postgres_sales_by_sku() {
+        let service = service();
+        let sku = format!("apple-{}", rand::random::<u64>());
+        place_order(&service, 1, cart(&sku, 2), true);
+        place_order(&service, 2, cart(&sku, 3), true);
+        place_order(&service, 3, cart(&sku, 7), false);
+
+        let sales = PostgresSalesBySku::new("postgresql://localhost").unwrap();
+        let mut projector = Projector::new(service.events_journal(), &sales, small_batches()).unwrap();
+        assert_eq!(projector.rebuild().unwrap(), 7);
+        sales.apply(&service.events_journal().read_all(1, 100).unwrap()).unwrap();
+
+        assert_eq!(sales.get(&Sku(sku.clone())).unwrap(), Some(SkuSales { quantity: 5, orders: 2 }));
+        assert_eq!(projector.rebuild().unwrap(), 7);
+        assert_eq!(sales.get(&Sku(sku)).unwrap(), Some(SkuSales { quantity: 5, orders: 2 }));
+    }
+}
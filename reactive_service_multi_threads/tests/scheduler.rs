@@ -0,0 +1,118 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use reactive_service_domain::order_entity::OrderEvent;
+    use reactive_service_domain::order_state::{DeliveryAddress, OrderState, Street};
+    use reactive_service_multi_threads::catch_up::InMemoryCheckpointStore;
+    use reactive_service_multi_threads::infra::inmem_journal::InMemoryJournal;
+    use reactive_service_multi_threads::infra::postgres_events_store::PostgresEventStore;
+    use reactive_service_multi_threads::infra::sqlite_event_store::SqliteEventStore;
+    use reactive_service_multi_threads::order_service::{EventsJournal, ExpireOrder, OrderService, PayOrder, Principal, UpdateDeliveryAddress};
+    use reactive_service_multi_threads::payment_processor::{LocalPaymentProcessor, PaymentToken};
+    use reactive_service_multi_threads::scheduler::{
+        Clock, CommandScheduler, InMemoryScheduleStore, ManualClock, Scheduled, ScheduledCommand, ScheduleStore, SchedulerConfig
+    };
+    use reactive_service_multi_threads::shipping_calculator::LocalShippingCalculator;
+    use reactive_service_multi_threads::tax_calculator::LocalTaxCalculator;
+    use crate::common::update_cart;
+
+    const TTL: Duration = Duration::from_secs(60);
+
+    fn service<E: EventsJournal<OrderEvent>>(journal: E) -> OrderService<E, LocalShippingCalculator, LocalTaxCalculator, LocalPaymentProcessor> {
+        OrderService::new(journal, LocalShippingCalculator{}, LocalTaxCalculator{}, LocalPaymentProcessor{})
+    }
+
+    fn config() -> SchedulerConfig {
+        SchedulerConfig { order_ttl: TTL, ..SchedulerConfig::default() }
+    }
+
+    fn expiry(order_id: i64, sequence_number: i64, due_at: SystemTime) -> Scheduled {
+        Scheduled { due_at, command: ScheduledCommand::ExpireOrder(ExpireOrder { order_id, sequence_number }) }
+    }
+
+    #[test]
+    fn expires_an_order_left_untouched_for_the_ttl() {
+        let service = service(InMemoryJournal::new().unwrap());
+        let (checkpoints, clock) = (InMemoryCheckpointStore::default(), ManualClock::default());
+        let mut scheduler = CommandScheduler::new(service.events_journal(), &checkpoints, InMemoryScheduleStore::default(), &clock, config()).unwrap();
+
+        service.update_cart(&Principal::Admin, update_cart(1)).unwrap();
+        scheduler.catch_up().unwrap();
+        clock.advance(TTL / 2);
+        service.update_cart(&Principal::Admin, update_cart(1)).unwrap();
+        scheduler.catch_up().unwrap();
+
+        // Scheduled again by the last change
+        clock.advance(TTL / 2);
+        assert_eq!(scheduler.deliver_due(&service).unwrap(), 0);
+        clock.advance(TTL / 2);
+        assert_eq!(scheduler.deliver_due(&service).unwrap(), 1);
+        assert!(matches!(service.get_order(1).unwrap().state, OrderState::Expired(_)));
+        assert_eq!(scheduler.deliver_due(&service).unwrap(), 0);
+    }
+
+    #[test]
+    fn a_paid_order_never_expires() {
+        let service = service(InMemoryJournal::new().unwrap());
+        let (checkpoints, clock) = (InMemoryCheckpointStore::default(), ManualClock::default());
+        let mut scheduler = CommandScheduler::new(service.events_journal(), &checkpoints, InMemoryScheduleStore::default(), &clock, config()).unwrap();
+
+        service.update_cart(&Principal::Admin, update_cart(1)).unwrap();
+        let delivery_address = DeliveryAddress { street: Street("1 Main Street".to_owned()), postal_code: "H0H 0H0".parse().unwrap() };
+        service.update_delivery_address(&Principal::Admin, UpdateDeliveryAddress { order_id: 1, delivery_address }).unwrap();
+        service.pay_order(&Principal::Admin, PayOrder { order_id: 1, payment_token: PaymentToken::new("token") }).unwrap();
+        scheduler.catch_up().unwrap();
+
+        // The stale expiry is rejected, then removed
+        clock.advance(TTL);
+        assert_eq!(scheduler.deliver_due(&service).unwrap(), 1);
+        assert!(matches!(service.get_order(1).unwrap().state, OrderState::Completed(_)));
+        assert!(scheduler.store().due(clock.now() + TTL, 10).unwrap().is_empty());
+    }
+
+    #[test]
+    fn the_scheduled_expiries_survive_a_restart() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("events.db");
+        let clock = ManualClock::default();
+        {
+            let service = service(SqliteEventStore::new(&path).unwrap());
+            let store = service.events_journal();
+            service.update_cart(&Principal::Admin, update_cart(1)).unwrap();
+            let mut scheduler = CommandScheduler::new(store, store, store, &clock, config()).unwrap();
+            assert_eq!(scheduler.catch_up().unwrap(), 1);
+        }
+
+        let service = service(SqliteEventStore::new(&path).unwrap());
+        let store = service.events_journal();
+        let mut scheduler = CommandScheduler::new(store, store, store, &clock, config()).unwrap();
+        assert_eq!(scheduler.catch_up().unwrap(), 0);
+        clock.advance(TTL);
+        assert_eq!(scheduler.deliver_due(&service).unwrap(), 1);
+        assert!(matches!(service.get_order(1).unwrap().state, OrderState::Expired(_)));
+    }
+
+    #[test]
+    fn postgres_replaces_a_pending_command_scheduled_again() {
+        let store = PostgresEventStore::new("postgresql://localhost").unwrap();
+        let order_id = rand::random::<i64>().abs();
+        let now = SystemTime::now();
+        let (first, second) = (expiry(order_id, 1, now), expiry(order_id, 2, now));
+
+        store.schedule(&first).unwrap();
+        store.schedule(&second).unwrap();
+        let pending = |store: &PostgresEventStore| store.due(now + TTL, 1_000_000).unwrap().into_iter()
+            .filter(|scheduled| scheduled.key() == first.key())
+            .map(|scheduled| scheduled.command)
+            .collect::<Vec<_>>();
+        assert_eq!(pending(&store), vec![second.command.clone()]);
+
+        store.remove(&first).unwrap();
+        assert_eq!(pending(&store).len(), 1);
+        store.remove(&second).unwrap();
+        assert!(pending(&store).is_empty());
+    }
+}
@@ -0,0 +1,172 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{HashMap, HashSet};
+    use std::sync::{Arc, Mutex};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+    use std::time::Duration;
+
+    use reactive_service_domain::aggregate_root::SequencedEvent;
+    use reactive_service_domain::non_empty_cart::{Quantity, Sku};
+    use reactive_service_domain::order_entity::OrderEvent;
+    use reactive_service_domain::order_state::OrderState;
+    use reactive_service_multi_threads::infra::inmem_journal::InMemoryJournal;
+    use reactive_service_multi_threads::order_service::{EventsJournal, OrderId, PayOrder, Principal};
+    use reactive_service_multi_threads::payment_processor::{LocalPaymentProcessor, PaymentToken};
+    use reactive_service_multi_threads::sharded_order_service::{ShardedOrderService, ShardedOrderServiceConfig};
+    use reactive_service_multi_threads::shipping_calculator::LocalShippingCalculator;
+    use reactive_service_multi_threads::tax_calculator::LocalTaxCalculator;
+    use crate::common::update_cart_of;
+
+    /// An in-memory journal recording which thread persisted each order,
+    /// failing the persists of the `failing` orders, and crashing on the ones of the `crashing` orders.
+    #[derive(Clone)]
+    struct ProbedJournal {
+        journal: Arc<InMemoryJournal<OrderEvent>>,
+        writers: Arc<Mutex<HashMap<OrderId, HashSet<String>>>>,
+        retrievals: Arc<AtomicUsize>,
+        failing: Arc<Mutex<HashSet<OrderId>>>,
+        crashing: Arc<Mutex<HashSet<OrderId>>>,
+    }
+
+    impl ProbedJournal {
+        fn new() -> Self {
+            Self {
+                journal: Arc::new(InMemoryJournal::new().unwrap()),
+                writers: Arc::default(),
+                retrievals: Arc::default(),
+                failing: Arc::default(),
+                crashing: Arc::default(),
+            }
+        }
+    }
+
+    impl ProbedJournal {
+        fn probe(&self, entity_id: OrderId) -> Result<(), &'static str> {
+            let writer = thread::current().name().unwrap_or_default().to_owned();
+            self.writers.lock().unwrap().entry(entity_id).or_default().insert(writer);
+            if self.crashing.lock().unwrap().contains(&entity_id) {
+                panic!("Journal crashed");
+            }
+            if self.failing.lock().unwrap().contains(&entity_id) {
+                return Err("Failed to persist event");
+            }
+            Ok(())
+        }
+    }
+
+    impl EventsJournal<OrderEvent> for ProbedJournal {
+        fn persist_event(&self, entity_id: OrderId, evt_w_seq: &SequencedEvent<OrderEvent>) -> Result<(), &'static str> {
+            self.probe(entity_id)?;
+            self.journal.persist_event(entity_id, evt_w_seq)
+        }
+
+        fn retrieve_events(&self, entity_id: OrderId) -> Result<Vec<SequencedEvent<OrderEvent>>, &'static str> {
+            self.retrievals.fetch_add(1, Ordering::SeqCst);
+            self.journal.retrieve_events(entity_id)
+        }
+
+        fn persist_events(&self, events: &[(OrderId, SequencedEvent<OrderEvent>)]) -> Result<(), &'static str> {
+            events.iter().try_for_each(|(entity_id, _)| self.probe(*entity_id))?;
+            self.journal.persist_events(events)
+        }
+    }
+
+    fn service(journal: &ProbedJournal, shards: usize) -> ShardedOrderService {
+        let config = ShardedOrderServiceConfig { shards, ..Default::default() };
+        ShardedOrderService::with_config(journal.clone(), LocalShippingCalculator{}, LocalTaxCalculator{}, LocalPaymentProcessor{}, config)
+    }
+
+    #[test]
+    fn routes_each_order_to_a_single_shard() {
+        let journal = ProbedJournal::new();
+        let service = service(&journal, 4);
+
+        let handles: Vec<_> = (0..32).flat_map(|order_id| [1, 2].map(|quantity| {
+            service.submit_update_cart(&Principal::Admin, update_cart_of(order_id, quantity))
+        })).collect();
+        for handle in handles {
+            handle.wait().unwrap();
+        }
+
+        let writers = journal.writers.lock().unwrap();
+        assert_eq!(writers.len(), 32);
+        assert!(writers.values().all(|threads| threads.len() == 1));
+        let shards: HashSet<_> = writers.values().flatten().collect();
+        assert!(shards.len() > 1);
+        assert!(shards.iter().all(|shard| shard.starts_with("order-shard-")));
+    }
+
+    #[test]
+    fn handles_the_commands_of_an_order_in_submission_order() {
+        let journal = ProbedJournal::new();
+        let service = service(&journal, 2);
+
+        let handles: Vec<_> = (1..=50)
+            .map(|quantity| service.submit_update_cart(&Principal::Admin, update_cart_of(1, quantity)))
+            .collect();
+
+        for (quantity, handle) in (1..=50).zip(handles) {
+            let (state, events) = handle.wait().unwrap();
+            assert_eq!(events[0].sequence_number, quantity as i64);
+            let OrderState::WithCart(order) = state else { panic!("Not with a cart: {:?}", state) };
+            assert_eq!(order.get_cart().get_items()[&Sku("apple".to_owned())], Quantity(quantity));
+        }
+    }
+
+    #[test]
+    fn replies_with_the_errors_and_restores_an_order_which_failed_to_persist() {
+        let journal = ProbedJournal::new();
+        let service = service(&journal, 2);
+
+        let pay_order = PayOrder { order_id: 1, payment_token: PaymentToken::new("token") };
+        assert_eq!(service.pay_order(&Principal::Admin, pay_order).err(), Some("Order not ready to be paid."));
+
+        service.update_cart(&Principal::Admin, update_cart_of(1, 1)).unwrap();
+        journal.failing.lock().unwrap().insert(1);
+        assert_eq!(service.update_cart(&Principal::Admin, update_cart_of(1, 2)).err(), Some("Failed to persist event"));
+        journal.failing.lock().unwrap().clear();
+
+        // The order was evicted: restored from the journal, without the event which failed
+        let retrievals = journal.retrievals.load(Ordering::SeqCst);
+        let (_, events) = service.update_cart(&Principal::Admin, update_cart_of(1, 3)).unwrap();
+        assert_eq!(journal.retrievals.load(Ordering::SeqCst), retrievals + 1);
+        assert_eq!(events[0].sequence_number, 2);
+    }
+
+    #[test]
+    fn restores_an_order_whose_command_panicked_and_keeps_the_shard_running() {
+        let journal = ProbedJournal::new();
+        let service = service(&journal, 1);
+        service.update_cart(&Principal::Admin, update_cart_of(1, 1)).unwrap();
+        journal.crashing.lock().unwrap().insert(1);
+
+        assert_eq!(service.update_cart(&Principal::Admin, update_cart_of(1, 2)).err(), Some("Entity poisoned by a panic"));
+        service.update_cart(&Principal::Admin, update_cart_of(2, 1)).unwrap();
+        journal.crashing.lock().unwrap().clear();
+
+        let retrievals = journal.retrievals.load(Ordering::SeqCst);
+        let (_, events) = service.update_cart(&Principal::Admin, update_cart_of(1, 3)).unwrap();
+        assert_eq!(journal.retrievals.load(Ordering::SeqCst), retrievals + 1);
+        assert_eq!(events[0].sequence_number, 2);
+    }
+
+    #[test]
+    fn passivates_the_idle_orders() {
+        let journal = ProbedJournal::new();
+        let config = ShardedOrderServiceConfig { shards: 1, passivate_after: Duration::from_millis(50), ..Default::default() };
+        let service = ShardedOrderService::with_config(
+            journal.clone(), LocalShippingCalculator{}, LocalTaxCalculator{}, LocalPaymentProcessor{}, config
+        );
+        service.update_cart(&Principal::Admin, update_cart_of(1, 1)).unwrap();
+        service.update_cart(&Principal::Admin, update_cart_of(1, 2)).unwrap();
+        assert_eq!(journal.retrievals.load(Ordering::SeqCst), 1);
+
+        thread::sleep(Duration::from_millis(150));
+        let (_, events) = service.update_cart(&Principal::Admin, update_cart_of(1, 3)).unwrap();
+        assert_eq!(journal.retrievals.load(Ordering::SeqCst), 2);
+        assert_eq!(events[0].sequence_number, 3);
+    }
+}
@@ -0,0 +1,46 @@
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use reactive_service_domain::aggregate_root::SequencedEvent;
+    use reactive_service_multi_threads::infra::sqlite_event_store::SqliteEventStore;
+    use reactive_service_multi_threads::order_service::EventsJournal;
+
+    fn sequenced(sequence_number: i64) -> SequencedEvent<String> {
+        SequencedEvent { sequence_number, event: format!("event {}", sequence_number) }
+    }
+
+    #[test]
+    fn rejects_a_batch_with_a_duplicate_sequence_number_as_a_whole() {
+        let directory = tempfile::tempdir().unwrap();
+        let store = SqliteEventStore::new(directory.path().join("events.db")).unwrap();
+
+        store.persist_event(1, &sequenced(1)).unwrap();
+        assert!(store.persist_events(&[(2, sequenced(1)), (1, sequenced(1))]).is_err());
+
+        let events: Vec<SequencedEvent<String>> = store.retrieve_events(2).unwrap();
+        assert!(events.is_empty());
+    }
+
+    #[test]
+    fn persists_concurrent_appends_to_different_orders() {
+        let directory = tempfile::tempdir().unwrap();
+        let store = SqliteEventStore::new(directory.path().join("events.db")).unwrap();
+
+        thread::scope(|scope| {
+            for entity_id in 0..8 {
+                let store = &store;
+                scope.spawn(move || {
+                    for sequence_number in 1..=50 {
+                        store.persist_event(entity_id, &sequenced(sequence_number)).unwrap();
+                    }
+                });
+            }
+        });
+
+        for entity_id in 0..8 {
+            let events: Vec<SequencedEvent<String>> = store.retrieve_events(entity_id).unwrap();
+            assert!(events.iter().map(|e| e.sequence_number).eq(1..=50));
+        }
+    }
+}
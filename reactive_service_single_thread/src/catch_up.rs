@@ -0,0 +1,107 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use reactive_service_application::catch_up::CatchUpCursor;
+use crate::order_service::{GlobalEventsJournal, Positioned};
+
+pub use reactive_service_application::catch_up::CatchUpConfig;
+
+/// Where the consumers of the journal, e.g. the projections, are: the position of the last event each one processed.
+pub trait CheckpointStore {
+    /// Position of the last event processed by the consumer, 0 when it never saved one.
+    fn load_checkpoint(&mut self, consumer: &str) -> Result<i64, &'static str>;
+    fn save_checkpoint(&mut self, consumer: &str, position: i64) -> Result<(), &'static str>;
+}
+
+/// Checkpoints kept in memory, for tests and benchmarks.
+#[derive(Default)]
+pub struct InMemoryCheckpointStore {
+    positions: HashMap<String, i64>,
+}
+
+impl CheckpointStore for InMemoryCheckpointStore {
+    fn load_checkpoint(&mut self, consumer: &str) -> Result<i64, &'static str> {
+        Ok(self.positions.get(consumer).copied().unwrap_or(0))
+    }
+
+    fn save_checkpoint(&mut self, consumer: &str, position: i64) -> Result<(), &'static str> {
+        self.positions.insert(consumer.to_owned(), position);
+        Ok(())
+    }
+}
+
+/// Reads the events of every entity in the global order of the journal: the history first, by batches,
+/// then the new events as they are persisted. There is no switch to miss an event at:
+/// the live events are read from the journal too, once it notifies they are there.
+///
+/// Unlike the `EventBus`, a subscriber never lags: it reads at its own pace, and can resume from a checkpoint.
+/// The subscription doesn't own the journal, it is given on each call: the journal of the `OrderService`,
+/// between two commands, or a journal of its own, e.g. another connection to the database, waiting for the writes
+/// of the service.
+pub struct CatchUpSubscription<E> {
+    config: CatchUpConfig,
+    cursor: CatchUpCursor<E>,
+}
+
+impl<E> CatchUpSubscription<E> {
+    /// Subscribe to the events after `position`, 0 for all of them, with the default settings.
+    pub fn new(position: i64) -> Self {
+        Self::with_config(position, CatchUpConfig::default())
+    }
+
+    pub fn with_config(position: i64, config: CatchUpConfig) -> Self {
+        Self {
+            config,
+            cursor: CatchUpCursor::new(position),
+        }
+    }
+
+    /// Resume the subscription of a consumer after its last saved checkpoint.
+    pub fn from_checkpoint<C: CheckpointStore>(checkpoints: &mut C, consumer: &str, config: CatchUpConfig)
+        -> Result<Self, &'static str> {
+
+        Ok(Self::with_config(checkpoints.load_checkpoint(consumer)?, config))
+    }
+
+    /// Position of the last event received, to checkpoint once it is processed.
+    pub fn position(&self) -> i64 {
+        self.cursor.position()
+    }
+
+    /// Save the position of the last event received as the checkpoint of the consumer.
+    pub fn save_checkpoint<C: CheckpointStore>(&self, checkpoints: &mut C, consumer: &str) -> Result<(), &'static str> {
+        checkpoints.save_checkpoint(consumer, self.cursor.position())
+    }
+
+    /// Wait for the next event.
+    pub fn recv<J: GlobalEventsJournal<E>>(&mut self, journal: &mut J) -> Result<Positioned<E>, &'static str> {
+        loop {
+            if let Some(event) = self.recv_timeout(journal, self.config.max_wait)? {
+                return Ok(event);
+            }
+        }
+    }
+
+    /// Wait for the next event, `None` if there is none within `timeout`.
+    /// With a zero `timeout`, only reads the events already persisted: it never blocks the event loop for long.
+    pub fn recv_timeout<J: GlobalEventsJournal<E>>(&mut self, journal: &mut J, timeout: Duration)
+        -> Result<Option<Positioned<E>>, &'static str> {
+
+        let deadline = Instant::now() + timeout;
+        loop {
+            if let Some(event) = self.cursor.pop() {
+                return Ok(Some(event));
+            }
+
+            let batch = journal.read_all(self.cursor.next_position(), self.config.batch_size.max(1))?;
+            if self.cursor.push(batch) {
+                continue;
+            }
+
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Ok(None);
+            }
+            journal.wait_for_position(self.cursor.next_position(), remaining.min(self.config.max_wait))?;
+        }
+    }
+}
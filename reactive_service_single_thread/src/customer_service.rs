@@ -0,0 +1,30 @@
+use reactive_service_domain::customer::{Customer, CustomerCommand, CustomerEvent, CustomerId, CustomerState};
+use crate::entity_host::EntityHost;
+use crate::order_service::{EventsJournal, Versioned};
+
+pub use reactive_service_application::authorization::Principal;
+
+/// The host of the `Customer` entities, in a journal of their own.
+/// A customer only reads and changes their own profile, an admin any of them.
+pub struct CustomerService<J: EventsJournal<CustomerEvent>> {
+    customers: EntityHost<Customer, J>,
+}
+
+impl<J: EventsJournal<CustomerEvent>> CustomerService<J> {
+    pub fn new(events_journal: J) -> Self {
+        Self { customers: EntityHost::new(events_journal) }
+    }
+
+    pub fn handle(&mut self, principal: &Principal, customer_id: CustomerId, command: CustomerCommand)
+        -> Result<&CustomerState, &'static str> {
+        principal.authorize_customer(customer_id)?;
+        let (state, _) = self.customers.handle(customer_id, |_| Ok(command))?;
+        Ok(state)
+    }
+
+    /// The customer, with its sequence number.
+    pub fn get_customer(&mut self, principal: &Principal, customer_id: CustomerId) -> Result<Versioned<CustomerState>, &'static str> {
+        principal.authorize_customer(customer_id)?;
+        self.customers.query(customer_id)
+    }
+}
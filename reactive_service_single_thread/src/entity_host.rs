@@ -0,0 +1,147 @@
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::sync::Arc;
+use std::time::Instant;
+use reactive_service_domain::aggregate_root::{AggregateRoot, SequencedEvent};
+use reactive_service_application::metrics::{MetricsRegistry, NoMetrics};
+use reactive_service_application::order_queries::Versioned;
+use tracing::info_span;
+use reactive_service_application::command_errors::ENTITY_NOT_RETRIEVED;
+use crate::event_bus::EventBus;
+use crate::order_service::EventsJournal;
+
+pub type EntityId = i64;
+
+/// The events applied by a command on an entity of the aggregate `A`.
+pub type AppliedEvents<A> = Vec<SequencedEvent<<A as AggregateRoot>::Event>>;
+
+/// Hosts the entities of any aggregate: restores them from the journal on their first command,
+/// keeps them in memory, and persists the events of their commands.
+/// Once persisted, the events are published to the subscribers of the event bus.
+///
+/// The `OrderService` is the host of the `OrderEntity`, with the ports it needs to build the entity commands.
+/// The durable journals serialize the events, the aggregate events must then be `Serialize` and `DeserializeOwned`.
+///
+/// The restores, commands and persistence of the entities are traced in `load`, `handle` and `persist` spans,
+/// and the latency of the journal and the number of entities in memory reported to its `MetricsRegistry`.
+pub struct EntityHost<A: AggregateRoot, J: EventsJournal<A::Event>> {
+    entities: HashMap<EntityId, A>,
+    events_journal: J,
+    event_bus: EventBus<A::Event>,
+    metrics: Arc<dyn MetricsRegistry + Send + Sync>,
+}
+
+impl<A, J> EntityHost<A, J>
+where
+    A: AggregateRoot<Error = &'static str> + Default,
+    A::Event: Clone,
+    J: EventsJournal<A::Event>,
+{
+    pub fn new(events_journal: J) -> Self {
+        Self { entities: HashMap::default(), events_journal, event_bus: EventBus::default(), metrics: Arc::new(NoMetrics) }
+    }
+
+    /// Report the metrics of the entities and of their journal to the registry.
+    pub fn with_metrics(self, metrics: Arc<dyn MetricsRegistry + Send + Sync>) -> Self {
+        Self { metrics, ..self }
+    }
+
+    /// The committed events of the entities, to subscribe to them.
+    pub fn event_bus(&self) -> &EventBus<A::Event> {
+        &self.event_bus
+    }
+
+    /// The journal of the entities, e.g. to read their events in the global order between two commands.
+    pub fn events_journal(&mut self) -> &mut J {
+        &mut self.events_journal
+    }
+
+    /// Handle a command, built from the current entity, then persist its events.
+    /// If persisting fails, the entity is evicted, to be restored from the journal on its next command.
+    pub fn handle<F>(&mut self, entity_id: EntityId, to_command: F)
+        -> Result<(&A::State, AppliedEvents<A>), &'static str>
+    where
+        F: FnOnce(&A) -> Result<A::Command, &'static str>,
+    {
+        let events = self.process(entity_id, to_command)?;
+
+        if let Err(err) = self.persist(entity_id, &events) {
+            self.entities.remove(&entity_id);
+            return Err(err);
+        }
+        self.event_bus.publish(entity_id, &events);
+        let state = self.entities.get(&entity_id).ok_or(ENTITY_NOT_RETRIEVED)?.get_state();
+        Ok((state, events))
+    }
+
+    /// Apply a command on the in-memory entity, without persisting its events.
+    /// The caller persists them with `persist_events`, possibly with the events of other commands.
+    pub fn process<F>(&mut self, entity_id: EntityId, to_command: F) -> Result<AppliedEvents<A>, &'static str>
+    where
+        F: FnOnce(&A) -> Result<A::Command, &'static str>,
+    {
+        let entity = self.entity(entity_id)?;
+        let _span = info_span!("handle", entity_id).entered();
+        let command = to_command(entity)?;
+        let (_, events) = entity.handle_command(command)?;
+        Ok(events)
+    }
+
+    /// Persist the events of several entities with a single journal call.
+    /// If it fails, the entities are evicted, their in-memory state is ahead of the journal.
+    pub fn persist_events(&mut self, events: &[(EntityId, SequencedEvent<A::Event>)]) -> Result<(), &'static str> {
+        let span = info_span!("persist", events = events.len());
+        let start_time = Instant::now();
+        let persisted = span.in_scope(|| self.events_journal.persist_events(events));
+        self.metrics.observe_journal("persist", start_time.elapsed(), persisted.is_err());
+        if let Err(err) = persisted {
+            for (entity_id, _) in events {
+                self.entities.remove(entity_id);
+            }
+            return Err(err);
+        }
+        for (entity_id, evt) in events {
+            self.event_bus.publish(*entity_id, std::slice::from_ref(evt));
+        }
+        Ok(())
+    }
+
+    /// The current state of the entity, restored from the journal if needed.
+    pub fn get_state(&mut self, entity_id: EntityId) -> Result<&A::State, &'static str> {
+        Ok(self.entity(entity_id)?.get_state())
+    }
+
+    /// The current state of the entity, with its sequence number.
+    pub fn query(&mut self, entity_id: EntityId) -> Result<Versioned<A::State>, &'static str>
+    where
+        A::State: Clone,
+    {
+        let entity = self.entity(entity_id)?;
+        Ok(Versioned { state: entity.get_state().clone(), sequence_number: entity.get_sequence_number() })
+    }
+
+    fn entity(&mut self, entity_id: EntityId) -> Result<&mut A, &'static str> {
+        let cached = self.entities.len();
+        match self.entities.entry(entity_id) {
+            Entry::Occupied(entry) => Ok(entry.into_mut()),
+            Entry::Vacant(entry) => {
+                let _span = info_span!("load", entity_id).entered();
+                let start_time = Instant::now();
+                let events = self.events_journal.retrieve_events(entity_id);
+                self.metrics.observe_journal("load", start_time.elapsed(), events.is_err());
+                let mut entity = A::default();
+                let _ = entity.restore_from_events(events?)?;
+                self.metrics.set_cached_entities(cached + 1);
+                Ok(entry.insert(entity))
+            }
+        }
+    }
+
+    fn persist(&mut self, entity_id: EntityId, events: &[SequencedEvent<A::Event>]) -> Result<(), &'static str> {
+        let _span = info_span!("persist", entity_id, events = events.len()).entered();
+        let start_time = Instant::now();
+        let persisted = events.iter().try_for_each(|evt| self.events_journal.persist_event(entity_id, evt));
+        self.metrics.observe_journal("persist", start_time.elapsed(), persisted.is_err());
+        persisted
+    }
+}
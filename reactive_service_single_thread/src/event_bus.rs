@@ -0,0 +1 @@
+pub use reactive_service_application::event_bus::{EventBus, Subscription};
@@ -0,0 +1,245 @@
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, SyncSender};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use reactive_service_domain::order_entity::OrderEvent;
+use reactive_service_domain::order_state::OrderState;
+use reactive_service_application::command_errors::{EVENT_LOOP_STOPPED, EVENT_LOOP_STOPPED_BEFORE_REPLYING, VERSION_TIMED_OUT};
+use crate::order_service::{
+    CommandResult, EventsJournal, OrderCommand, OrderId, OrderService, PayOrder, Principal, QueryResult, ShippingCalculator, TaxCalculator,
+    UpdateCart, UpdateDeliveryAddress
+};
+use crate::event_bus::EventBus;
+use crate::inventory::Inventory;
+use crate::payment_processor::PaymentProcessor;
+
+/// Tuning of the event loop.
+#[derive(Debug, Clone)]
+pub struct EventLoopConfig {
+    /// Capacity of the bounded queue in front of the service, submitting blocks when it is full.
+    pub queue_capacity: usize,
+    /// Maximum number of queued commands handled together, their events persisted in one journal call.
+    pub max_batch_size: usize,
+}
+
+impl Default for EventLoopConfig {
+    fn default() -> Self {
+        Self {
+            queue_capacity: 1024,
+            max_batch_size: 128,
+        }
+    }
+}
+
+/// Single writer front-end of the `OrderService`.
+///
+/// The service, and all its orders, are owned by one dedicated thread. Any number of threads
+/// can submit requests through the bounded queue, each request carries its own reply channel.
+///
+/// The loop drains what is queued (up to `max_batch_size`) before touching the journal:
+/// under load the commands are handled in batch, with a single journal round-trip for all their events.
+/// The replies are sent only once the events are persisted.
+///
+/// Dropping the event loop closes the queue, the queued requests are handled before the thread stops.
+pub struct OrderServiceEventLoop {
+    queue: Option<SyncSender<Request>>,
+    worker: Option<JoinHandle<()>>,
+    event_bus: EventBus<OrderEvent>,
+}
+
+impl OrderServiceEventLoop {
+
+    pub fn spawn<E, S, T, P, I>(service: OrderService<E, S, T, P, I>, config: EventLoopConfig) -> Self
+    where
+        E: EventsJournal<OrderEvent> + Send + 'static,
+        S: ShippingCalculator + Send + 'static,
+        T: TaxCalculator + Send + 'static,
+        P: PaymentProcessor + Send + 'static,
+        I: Inventory + Send + 'static,
+    {
+        let event_bus = service.event_bus().clone();
+        let (queue, requests) = mpsc::sync_channel(config.queue_capacity);
+        let worker = thread::Builder::new()
+            .name("order-service-event-loop".to_owned())
+            .spawn(move || run(service, requests, config.max_batch_size.max(1)))
+            .expect("Failed to spawn the event loop thread");
+
+        Self { queue: Some(queue), worker: Some(worker), event_bus }
+    }
+
+    /// The events of the orders, published once persisted. Subscribing doesn't go through the queue.
+    pub fn event_bus(&self) -> &EventBus<OrderEvent> {
+        &self.event_bus
+    }
+
+    pub fn update_cart(&self, principal: &Principal, cmd: UpdateCart) -> CommandResult {
+        self.handle_as(principal, OrderCommand::UpdateCart(cmd))
+    }
+
+    pub fn update_delivery_address(&self, principal: &Principal, cmd: UpdateDeliveryAddress) -> CommandResult {
+        self.handle_as(principal, OrderCommand::UpdateDeliveryAddress(cmd))
+    }
+
+    pub fn pay_order(&self, principal: &Principal, cmd: PayOrder) -> CommandResult {
+        self.handle_as(principal, OrderCommand::PayOrder(cmd))
+    }
+
+    /// Queue any order command on behalf of the principal, and wait for the reply once its batch is persisted.
+    pub fn handle_as(&self, principal: &Principal, command: OrderCommand) -> CommandResult {
+        let (reply, response) = mpsc::sync_channel(1);
+        self.send(Request::Command { principal: *principal, command, reply })?;
+        response.recv().map_err(|_| EVENT_LOOP_STOPPED_BEFORE_REPLYING)?
+    }
+
+    pub fn get_state(&self, order_id: OrderId) -> Result<OrderState, &'static str> {
+        let (reply, response) = mpsc::sync_channel(1);
+        self.send(Request::GetState { order_id, reply })?;
+        response.recv().map_err(|_| EVENT_LOOP_STOPPED_BEFORE_REPLYING)?
+    }
+
+    /// The current state of the order, with its sequence number.
+    pub fn get_order(&self, order_id: OrderId) -> QueryResult {
+        self.send_query(order_id, 0, Duration::ZERO)
+    }
+
+    /// The state of the order, once its sequence number is at least `sequence_number`.
+    /// Fails if the order doesn't reach this version within `timeout`.
+    pub fn get_order_at_least(&self, order_id: OrderId, sequence_number: i64, timeout: Duration) -> QueryResult {
+        self.send_query(order_id, sequence_number, timeout)
+    }
+
+    fn send_query(&self, order_id: OrderId, sequence_number: i64, timeout: Duration) -> QueryResult {
+        let (reply, response) = mpsc::sync_channel(1);
+        let query = OrderQuery { order_id, sequence_number, deadline: Instant::now() + timeout, reply };
+        self.send(Request::GetOrder(query))?;
+        response.recv().map_err(|_| EVENT_LOOP_STOPPED_BEFORE_REPLYING)?
+    }
+
+    fn send(&self, request: Request) -> Result<(), &'static str> {
+        self.queue.as_ref()
+            .ok_or(EVENT_LOOP_STOPPED)?
+            .send(request)
+            .map_err(|_| EVENT_LOOP_STOPPED)
+    }
+}
+
+impl Drop for OrderServiceEventLoop {
+    fn drop(&mut self) {
+        drop(self.queue.take());
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+enum Request {
+    Command { principal: Principal, command: OrderCommand, reply: SyncSender<CommandResult> },
+    GetState { order_id: OrderId, reply: SyncSender<Result<OrderState, &'static str>> },
+    GetOrder(OrderQuery),
+}
+
+/// A query waiting for the order to reach `sequence_number`, until `deadline`.
+struct OrderQuery {
+    order_id: OrderId,
+    sequence_number: i64,
+    deadline: Instant,
+    reply: SyncSender<QueryResult>,
+}
+
+fn run<E, S, T, P, I>(mut service: OrderService<E, S, T, P, I>, requests: Receiver<Request>, max_batch_size: usize)
+where
+    E: EventsJournal<OrderEvent>,
+    S: ShippingCalculator,
+    T: TaxCalculator,
+    P: PaymentProcessor,
+    I: Inventory,
+{
+    let mut commands = Vec::with_capacity(max_batch_size);
+    let mut replies = Vec::with_capacity(max_batch_size);
+    let mut waiting: Vec<OrderQuery> = Vec::new();
+
+    loop {
+        // Block for the first request, until the first deadline of the waiting queries if any,
+        // then take whatever is already queued
+        let first = match waiting.iter().map(|query| query.deadline).min() {
+            None => match requests.recv() {
+                Ok(request) => Some(request),
+                Err(_) => break,
+            },
+            Some(deadline) => match requests.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                Ok(request) => Some(request),
+                Err(RecvTimeoutError::Timeout) => None,
+                Err(RecvTimeoutError::Disconnected) => break,
+            },
+        };
+        let mut next = first;
+        let mut drained = 0;
+
+        while let Some(request) = next.take() {
+            drained += 1;
+            match request {
+                Request::Command { principal, command, reply } => {
+                    commands.push((principal, command));
+                    replies.push(reply);
+                },
+                Request::GetState { order_id, reply } => {
+                    // A query must observe the commands queued before it
+                    flush(&mut service, &mut commands, &mut replies);
+                    let state = service.get_state(order_id).cloned();
+                    let _ = reply.send(state);
+                },
+                Request::GetOrder(query) => {
+                    flush(&mut service, &mut commands, &mut replies);
+                    waiting.extend(answer(&mut service, query));
+                }
+            }
+            if drained < max_batch_size {
+                next = requests.try_recv().ok();
+            }
+        }
+
+        flush(&mut service, &mut commands, &mut replies);
+        // The flushed commands may have brought the waiting orders to their version
+        waiting = waiting.into_iter().filter_map(|query| answer(&mut service, query)).collect();
+    }
+    // The queue is closed: the waiting queries are dropped, their callers get an error
+}
+
+/// Reply to the query if its version is reached, or its deadline passed. Otherwise, the query keeps waiting.
+fn answer<E, S, T, P, I>(service: &mut OrderService<E, S, T, P, I>, query: OrderQuery) -> Option<OrderQuery>
+where
+    E: EventsJournal<OrderEvent>,
+    S: ShippingCalculator,
+    T: TaxCalculator,
+    P: PaymentProcessor,
+    I: Inventory,
+{
+    let result = match service.get_order(query.order_id) {
+        Ok(order) if order.sequence_number < query.sequence_number => {
+            if Instant::now() < query.deadline {
+                return Some(query);
+            }
+            Err(VERSION_TIMED_OUT)
+        },
+        result => result,
+    };
+    let _ = query.reply.send(result);
+    None
+}
+
+fn flush<E, S, T, P, I>(service: &mut OrderService<E, S, T, P, I>,
+                     commands: &mut Vec<(Principal, OrderCommand)>, replies: &mut Vec<SyncSender<CommandResult>>)
+where
+    E: EventsJournal<OrderEvent>,
+    S: ShippingCalculator,
+    T: TaxCalculator,
+    P: PaymentProcessor,
+    I: Inventory,
+{
+    if commands.is_empty() {
+        return;
+    }
+    let results = service.handle_batch(std::mem::take(commands));
+    for (reply, result) in replies.drain(..).zip(results) {
+        let _ = reply.send(result);
+    }
+}
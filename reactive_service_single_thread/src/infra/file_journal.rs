@@ -0,0 +1,77 @@
+use std::path::PathBuf;
+use std::thread;
+use std::time::Duration;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use reactive_service_domain::aggregate_root::SequencedEvent;
+use reactive_service_application::command_errors::{DESERIALIZE_EVENT_FAILED, SERIALIZE_EVENT_FAILED};
+use crate::order_service::{EventsJournal, GlobalEventsJournal, OrderId, Positioned};
+pub use reactive_service_application::segmented_log::{FileJournalConfig, FsyncPolicy};
+use reactive_service_application::segmented_log::{Record, SegmentedLog};
+
+/// Embedded journal, appending the events serialized in JSON to the segment files of a `SegmentedLog`, in a directory.
+/// A record torn by a crash in the middle of a write is truncated when the journal is opened, any other corruption fails the opening.
+pub struct FileJournal { log: SegmentedLog }
+
+impl FileJournal {
+    /// Open, or create, the journal in `directory`, with the default settings.
+    pub fn new(directory: impl Into<PathBuf>) -> Result<Self, Box<dyn std::error::Error>> {
+        Self::with_config(FileJournalConfig { directory: directory.into(), ..FileJournalConfig::default() })
+    }
+
+    pub fn with_config(config: FileJournalConfig) -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(Self { log: SegmentedLog::open(config)? })
+    }
+}
+
+impl<E: Serialize + DeserializeOwned> EventsJournal<E> for FileJournal {
+    fn persist_event(&mut self, entity_id: OrderId, seq_event: &SequencedEvent<E>) -> Result<(), &'static str> {
+        let serialized_event = serde_json::to_vec(&seq_event.event).map_err(|_| SERIALIZE_EVENT_FAILED)?;
+        self.log.append(vec![(entity_id, seq_event.sequence_number, serialized_event)])
+    }
+
+    fn persist_events(&mut self, events: &[(OrderId, SequencedEvent<E>)]) -> Result<(), &'static str> {
+        let records = events.iter()
+            .map(|(entity_id, seq_event)| {
+                let serialized_event = serde_json::to_vec(&seq_event.event).map_err(|_| SERIALIZE_EVENT_FAILED)?;
+                Ok((*entity_id, seq_event.sequence_number, serialized_event))
+            })
+            .collect::<Result<Vec<Record>, &'static str>>()?;
+        self.log.append(records)
+    }
+
+    fn retrieve_events(&mut self, entity_id: OrderId) -> Result<Vec<SequencedEvent<E>>, &'static str> {
+        self.log.read(entity_id)?
+            .into_iter()
+            .map(|(sequence_number, event_payload)| {
+                let event: E = serde_json::from_slice(&event_payload).map_err(|_| DESERIALIZE_EVENT_FAILED)?;
+                Ok(SequencedEvent { sequence_number, event })
+            })
+            .collect()
+    }
+}
+
+impl<E: Serialize + DeserializeOwned> GlobalEventsJournal<E> for FileJournal {
+    fn read_all(&mut self, from_position: i64, batch_size: usize) -> Result<Vec<Positioned<E>>, &'static str> {
+        self.log.read_all(from_position, batch_size)?
+            .into_iter()
+            .map(|(position, (entity_id, sequence_number, event_payload))| {
+                let event: E = serde_json::from_slice(&event_payload).map_err(|_| DESERIALIZE_EVENT_FAILED)?;
+                Ok(Positioned { position, entity_id, event: SequencedEvent { sequence_number, event } })
+            })
+            .collect()
+    }
+
+    fn last_position(&mut self) -> Result<i64, &'static str> {
+        Ok(self.log.last_position())
+    }
+
+    /// Returns at once if `position` is readable. Otherwise the journal has a single writer, borrowed while waiting:
+    /// the wait lasts `timeout`.
+    fn wait_for_position(&mut self, position: i64, timeout: Duration) -> Result<(), &'static str> {
+        if position > self.log.last_position() {
+            thread::sleep(timeout);
+        }
+        Ok(())
+    }
+}
@@ -1,28 +1,77 @@
-use std::collections::HashMap;
-use std::marker::PhantomData;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::thread;
+use std::time::Duration;
 use reactive_service_domain::aggregate_root::SequencedEvent;
-use crate::order_service::EventsJournal;
+use reactive_service_application::command_errors::PERSIST_EVENT_FAILED;
+use crate::order_service::{EventsJournal, GlobalEventsJournal, Positioned};
 
+/// Journal kept in memory, for tests and benchmarks.
+/// As the durable journals, it rejects a sequence number already persisted for an entity,
+/// and persists a batch of events all or nothing.
+///
+/// The events are kept in a log, in the order they were persisted: the position of an event is its rank in the log.
 pub struct InMemoryJournal<E> {
-    events: HashMap<i64, Vec<SequencedEvent<E>>>
+    log: Vec<Positioned<E>>,
+    /// Index in the log of the events of each entity, by sequence number
+    by_entity: HashMap<i64, BTreeMap<i64, usize>>,
 }
 
 impl <E> InMemoryJournal<E> {
     pub fn new() -> Result<Self, Box<dyn std::error::Error>> {
-        Ok(Self { events: HashMap::default() })
+        Ok(Self { log: Vec::new(), by_entity: HashMap::default() })
     }
 }
 
 impl<E: Clone> EventsJournal<E> for InMemoryJournal<E> {
     fn persist_event(&mut self, aggregate_id: i64, seq_event: &SequencedEvent<E>) -> Result<(), &'static str> {
-        let _ = &self.events.entry(aggregate_id)
-            .or_default()
-            .push(seq_event.clone());
+        self.persist_events(std::slice::from_ref(&(aggregate_id, seq_event.clone())))
+    }
+
+    fn persist_events(&mut self, events: &[(i64, SequencedEvent<E>)]) -> Result<(), &'static str> {
+        if has_duplicates(&self.by_entity, events) {
+            return Err(PERSIST_EVENT_FAILED);
+        }
+        for (aggregate_id, seq_event) in events {
+            let index = self.log.len();
+            self.by_entity.entry(*aggregate_id).or_default().insert(seq_event.sequence_number, index);
+            self.log.push(Positioned { position: index as i64 + 1, entity_id: *aggregate_id, event: seq_event.clone() });
+        }
         Ok(())
     }
 
     fn retrieve_events(&mut self, aggregate_id: i64) -> Result<Vec<SequencedEvent<E>>, &'static str> {
-        let events = &self.events;
-        Ok(events.get(&aggregate_id).cloned().unwrap_or_else(Vec::new))
+        let events = self.by_entity.get(&aggregate_id)
+            .map(|indexes| indexes.values().map(|index| self.log[*index].event.clone()).collect())
+            .unwrap_or_default();
+        Ok(events)
+    }
+}
+
+impl<E: Clone> GlobalEventsJournal<E> for InMemoryJournal<E> {
+    fn read_all(&mut self, from_position: i64, batch_size: usize) -> Result<Vec<Positioned<E>>, &'static str> {
+        let start = usize::try_from(from_position - 1).unwrap_or(0).min(self.log.len());
+        Ok(self.log[start..].iter().take(batch_size).cloned().collect())
+    }
+
+    fn last_position(&mut self) -> Result<i64, &'static str> {
+        Ok(self.log.len() as i64)
+    }
+
+    /// Returns at once if `position` is readable. Otherwise nothing can be persisted while waiting,
+    /// the journal being borrowed: the wait lasts `timeout`.
+    fn wait_for_position(&mut self, position: i64, timeout: Duration) -> Result<(), &'static str> {
+        if position > self.log.len() as i64 {
+            thread::sleep(timeout);
+        }
+        Ok(())
     }
 }
+
+/// True when an event of the batch reuses a sequence number, already persisted or earlier in the batch.
+fn has_duplicates<E>(persisted: &HashMap<i64, BTreeMap<i64, usize>>, events: &[(i64, SequencedEvent<E>)]) -> bool {
+    let mut batch = HashSet::with_capacity(events.len());
+    events.iter().any(|(aggregate_id, seq_event)| {
+        let key = (*aggregate_id, seq_event.sequence_number);
+        persisted.get(aggregate_id).is_some_and(|e| e.contains_key(&key.1)) || !batch.insert(key)
+    })
+}
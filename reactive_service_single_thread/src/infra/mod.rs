@@ -1,2 +1,6 @@
 pub mod postgres_events_store;
-pub mod inmem_journal;
\ No newline at end of file
+pub mod postgres_projections;
+pub mod postgres_outbox;
+pub mod inmem_journal;
+pub mod file_journal;
+pub mod sqlite_event_store;
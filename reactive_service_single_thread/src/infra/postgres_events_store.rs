@@ -1,49 +1,163 @@
+use std::time::{Duration, SystemTime};
 use serde::Serialize;
 use serde::de::DeserializeOwned;
-use postgres::{Client, NoTls};
+use postgres::{Client, GenericClient, NoTls};
+use postgres::fallible_iterator::FallibleIterator;
+use postgres::types::ToSql;
 use reactive_service_domain::aggregate_root::SequencedEvent;
-use crate::order_service::EventsJournal;
+use reactive_service_application::scheduler::to_millis;
+use reactive_service_application::command_errors::{DESERIALIZE_COMMAND_FAILED, DESERIALIZE_EVENT_FAILED, LOAD_CHECKPOINT_FAILED, PERSIST_EVENT_FAILED, REMOVE_SCHEDULED_COMMAND_FAILED, RETRIEVE_EVENTS_FAILED, RETRIEVE_SCHEDULED_COMMANDS_FAILED, SAVE_CHECKPOINT_FAILED, SCHEDULE_COMMAND_FAILED, SERIALIZE_COMMAND_FAILED, SERIALIZE_EVENT_FAILED};
+use crate::catch_up::CheckpointStore;
+use crate::order_service::{EventsJournal, GlobalEventsJournal, Positioned};
+use crate::scheduler::{Scheduled, ScheduleStore};
 
-pub struct PostgresEventStore { client: postgres::Client }
+/// The global position of an event is a sequence, assigned when the event is inserted. Concurrent transactions
+/// would commit their positions out of order: a reader could see a position before a lower one is committed,
+/// and skip it. A statement trigger takes a lock held until the commit, before any position is assigned:
+/// the inserts are serialized, for the time of their transaction. Once committed, they notify the `events` channel
+/// with their last position.
+///
+/// The lock is taken once per statement: batching the events, e.g. behind a `GroupCommitJournal`, keeps its cost low.
+///
+/// The outbox holds the positions of the events left to publish, with their failed attempts.
+///
+/// The scheduled commands are due at a time of the clock of their scheduler, in milliseconds since the epoch.
+const SCHEMA: &str = "
+    BEGIN;
+    SELECT pg_advisory_xact_lock(7300);
+    CREATE TABLE IF NOT EXISTS events (
+        entity_id BIGINT NOT NULL,
+        sequence_number BIGINT NOT NULL,
+        payload TEXT NOT NULL,
+        PRIMARY KEY(entity_id, sequence_number)
+    );
+    ALTER TABLE events ADD COLUMN IF NOT EXISTS position BIGSERIAL;
+    CREATE UNIQUE INDEX IF NOT EXISTS events_position ON events (position);
+    CREATE OR REPLACE FUNCTION events_order_positions() RETURNS trigger LANGUAGE plpgsql AS $$
+        BEGIN
+            PERFORM pg_advisory_xact_lock(7301);
+            RETURN NULL;
+        END $$;
+    CREATE OR REPLACE FUNCTION events_notify_positions() RETURNS trigger LANGUAGE plpgsql AS $$
+        BEGIN
+            PERFORM pg_notify('events', (SELECT MAX(position) FROM inserted)::TEXT);
+            RETURN NULL;
+        END $$;
+    CREATE OR REPLACE TRIGGER events_order_positions BEFORE INSERT ON events
+        FOR EACH STATEMENT EXECUTE FUNCTION events_order_positions();
+    CREATE OR REPLACE TRIGGER events_notify_positions AFTER INSERT ON events REFERENCING NEW TABLE AS inserted
+        FOR EACH STATEMENT EXECUTE FUNCTION events_notify_positions();
+    CREATE TABLE IF NOT EXISTS checkpoints (
+        consumer TEXT PRIMARY KEY,
+        position BIGINT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS outbox (
+        position BIGINT PRIMARY KEY,
+        attempts INT NOT NULL DEFAULT 0,
+        next_attempt_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+        last_error TEXT,
+        parked BOOLEAN NOT NULL DEFAULT FALSE
+    );
+    CREATE TABLE IF NOT EXISTS scheduled_commands (
+        key TEXT PRIMARY KEY,
+        due_at BIGINT NOT NULL,
+        payload TEXT NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS scheduled_commands_due_at ON scheduled_commands (due_at);
+    COMMIT;
+";
+
+
+pub struct PostgresEventStore {
+    client: postgres::Client,
+    /// The persisted events are written to the outbox too, for a `PostgresOutboxRelay` to publish them
+    outbox: bool,
+}
 
 impl PostgresEventStore {
     pub fn new() -> Result<Self, Box<dyn std::error::Error>> {
 
         let mut client = Client::connect("host=localhost user=postgres password=postgres", NoTls)?;
 
-        client.execute(
-            "CREATE TABLE IF NOT EXISTS events (
-                entity_id BIGINT NOT NULL,
-                sequence_number BIGINT NOT NULL,
-                payload TEXT NOT NULL,
-                PRIMARY KEY(entity_id, sequence_number)
-            )",
-            &[],
-        )?;
+        // Serialized by a lock: concurrent stores would fail to replace the same functions
+        client.batch_execute(SCHEMA)?;
+
+        Ok(Self { client, outbox: false })
+    }
+
+    /// A store writing the persisted events to the outbox, in the same statement: they are published once committed,
+    /// and only then.
+    pub fn with_outbox() -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(Self { outbox: true, ..Self::new()? })
+    }
+
+    pub(crate) fn client(&mut self) -> &mut Client {
+        &mut self.client
+    }
+
+    pub(crate) fn last_event_position(&mut self) -> Result<i64, &'static str> {
+        let row = self.client.query_one("SELECT COALESCE(MAX(position), 0) FROM events", &[])
+            .map_err(|_| RETRIEVE_EVENTS_FAILED)?;
+        Ok(row.get(0))
+    }
+
+    /// Listens to the `events` channel for the time of the wait: the notifications of the own writes of the store
+    /// are not buffered in between.
+    pub(crate) fn wait_for_event(&mut self, position: i64, timeout: Duration) -> Result<(), &'static str> {
+        self.client.batch_execute("LISTEN events").map_err(|_| RETRIEVE_EVENTS_FAILED)?;
 
-        Ok(Self { client })
+        // Committed before the LISTEN, its notification is missed
+        if self.last_event_position()? < position {
+            // Any notification will do, the caller reads again
+            let _ = self.client.notifications().timeout_iter(timeout).next().map_err(|_| RETRIEVE_EVENTS_FAILED)?;
+        }
+
+        // Drop the notifications received since, they would end the next wait early
+        self.client.batch_execute("UNLISTEN events").map_err(|_| RETRIEVE_EVENTS_FAILED)?;
+        let _ = self.client.notifications().iter().count();
+        Ok(())
     }
 }
 
 impl<E: Serialize + DeserializeOwned> EventsJournal<E> for PostgresEventStore {
     fn persist_event(&mut self, entity_id: i64, seq_event: &SequencedEvent<E>) -> Result<(), &'static str> {
-        let serialized_event = serde_json::to_string(&seq_event.event).map_err(|_| "Failed to serialize event")?;
+        let serialized_event = serde_json::to_string(&seq_event.event).map_err(|_| SERIALIZE_EVENT_FAILED)?;
         self.client.execute(
-            "INSERT INTO events (entity_id, sequence_number, payload) VALUES ($1, $2, $3)",
+            &insert_statement("INSERT INTO events (entity_id, sequence_number, payload) VALUES ($1, $2, $3)".to_owned(), self.outbox),
             &[&entity_id, &seq_event.sequence_number, &serialized_event],
-        ).map_err(|_| "Failed to persist event")?;
+        ).map_err(|_| PERSIST_EVENT_FAILED)?;
         Ok(())
     }
+    fn persist_events(&mut self, events: &[(i64, SequencedEvent<E>)]) -> Result<(), &'static str> {
+        let rows = events.iter()
+            .map(|(entity_id, seq_event)| {
+                let serialized_event = serde_json::to_string(&seq_event.event).map_err(|_| SERIALIZE_EVENT_FAILED)?;
+                Ok((*entity_id, seq_event.sequence_number, serialized_event))
+            })
+            .collect::<Result<Vec<(i64, i64, String)>, &'static str>>()?;
+
+        // One round-trip when the batch fits in a single statement, else a transaction around the statements.
+        if rows.len() <= MAX_ROWS_PER_INSERT {
+            insert_events(&mut self.client, &rows, self.outbox)
+        } else {
+            let mut transaction = self.client.transaction().map_err(|_| PERSIST_EVENT_FAILED)?;
+            for chunk in rows.chunks(MAX_ROWS_PER_INSERT) {
+                insert_events(&mut transaction, chunk, self.outbox)?;
+            }
+            transaction.commit().map_err(|_| PERSIST_EVENT_FAILED)
+        }
+    }
+
     fn retrieve_events(&mut self, entity_id: i64) -> Result<Vec<SequencedEvent<E>>, &'static str> {
         let rows = self.client
             .query("SELECT sequence_number, payload FROM events WHERE entity_id = $1 ORDER BY sequence_number ASC", &[&entity_id])
-            .map_err(|_| "Failed to retrieve events")?;
+            .map_err(|_| RETRIEVE_EVENTS_FAILED)?;
 
         rows.iter()
             .map(|row| {
                 let sequence_number: i64 = row.get(0);
                 let event_payload: String = row.get(1);
-                let event: E = serde_json::from_str(&event_payload).map_err(|_| "Failed to deserialize event")?;
+                let event: E = serde_json::from_str(&event_payload).map_err(|_| DESERIALIZE_EVENT_FAILED)?;
 
                 Ok(SequencedEvent {
                     sequence_number,
@@ -53,3 +167,116 @@ impl<E: Serialize + DeserializeOwned> EventsJournal<E> for PostgresEventStore {
             .collect()
     }
 }
+
+impl<E: Serialize + DeserializeOwned> GlobalEventsJournal<E> for PostgresEventStore {
+    fn read_all(&mut self, from_position: i64, batch_size: usize) -> Result<Vec<Positioned<E>>, &'static str> {
+        let rows = self.client
+            .query(
+                "SELECT position, entity_id, sequence_number, payload FROM events
+                 WHERE position >= $1 ORDER BY position ASC LIMIT $2",
+                &[&from_position, &(batch_size as i64)],
+            )
+            .map_err(|_| RETRIEVE_EVENTS_FAILED)?;
+
+        rows.iter()
+            .map(|row| {
+                let event_payload: String = row.get(3);
+                let event: E = serde_json::from_str(&event_payload).map_err(|_| DESERIALIZE_EVENT_FAILED)?;
+                Ok(Positioned { position: row.get(0), entity_id: row.get(1), event: SequencedEvent { sequence_number: row.get(2), event } })
+            })
+            .collect()
+    }
+
+    fn last_position(&mut self) -> Result<i64, &'static str> {
+        self.last_event_position()
+    }
+
+    fn wait_for_position(&mut self, position: i64, timeout: Duration) -> Result<(), &'static str> {
+        self.wait_for_event(position, timeout)
+    }
+}
+
+impl CheckpointStore for PostgresEventStore {
+    fn load_checkpoint(&mut self, consumer: &str) -> Result<i64, &'static str> {
+        let row = self.client.query_opt("SELECT position FROM checkpoints WHERE consumer = $1", &[&consumer])
+            .map_err(|_| LOAD_CHECKPOINT_FAILED)?;
+        Ok(row.map_or(0, |row| row.get(0)))
+    }
+
+    fn save_checkpoint(&mut self, consumer: &str, position: i64) -> Result<(), &'static str> {
+        self.client.execute(
+            "INSERT INTO checkpoints (consumer, position) VALUES ($1, $2)
+             ON CONFLICT (consumer) DO UPDATE SET position = EXCLUDED.position",
+            &[&consumer, &position],
+        ).map_err(|_| SAVE_CHECKPOINT_FAILED)?;
+        Ok(())
+    }
+}
+
+impl ScheduleStore for PostgresEventStore {
+    fn schedule(&mut self, scheduled: &Scheduled) -> Result<(), &'static str> {
+        let payload = serde_json::to_string(&scheduled.command).map_err(|_| SERIALIZE_COMMAND_FAILED)?;
+        self.client.execute(
+            "INSERT INTO scheduled_commands (key, due_at, payload) VALUES ($1, $2, $3)
+             ON CONFLICT (key) DO UPDATE SET due_at = EXCLUDED.due_at, payload = EXCLUDED.payload",
+            &[&scheduled.key(), &scheduled.due_at_millis(), &payload],
+        ).map_err(|_| SCHEDULE_COMMAND_FAILED)?;
+        Ok(())
+    }
+
+    fn due(&mut self, now: SystemTime, limit: usize) -> Result<Vec<Scheduled>, &'static str> {
+        let rows = self.client.query(
+            "SELECT due_at, payload FROM scheduled_commands WHERE due_at <= $1 ORDER BY due_at LIMIT $2",
+            &[&to_millis(now), &(limit as i64)],
+        ).map_err(|_| RETRIEVE_SCHEDULED_COMMANDS_FAILED)?;
+        rows.iter()
+            .map(|row| {
+                let command = serde_json::from_str(row.get(1)).map_err(|_| DESERIALIZE_COMMAND_FAILED)?;
+                Ok(Scheduled::from_millis(row.get(0), command))
+            })
+            .collect()
+    }
+
+    fn remove(&mut self, delivered: &Scheduled) -> Result<(), &'static str> {
+        let payload = serde_json::to_string(&delivered.command).map_err(|_| SERIALIZE_COMMAND_FAILED)?;
+        self.client.execute(
+            "DELETE FROM scheduled_commands WHERE key = $1 AND due_at = $2 AND payload = $3",
+            &[&delivered.key(), &delivered.due_at_millis(), &payload],
+        ).map_err(|_| REMOVE_SCHEDULED_COMMAND_FAILED)?;
+        Ok(())
+    }
+}
+
+// Postgres accepts up to 65535 parameters per statement, 3 per row
+const MAX_ROWS_PER_INSERT: usize = 1000;
+
+/// A single multi-rows INSERT, all or nothing.
+fn insert_events<C: GenericClient>(client: &mut C, rows: &[(i64, i64, String)], outbox: bool) -> Result<(), &'static str> {
+    if rows.is_empty() {
+        return Ok(());
+    }
+
+    let mut statement = String::from("INSERT INTO events (entity_id, sequence_number, payload) VALUES ");
+    let mut params: Vec<&(dyn ToSql + Sync)> = Vec::with_capacity(rows.len() * 3);
+    for (i, (entity_id, sequence_number, payload)) in rows.iter().enumerate() {
+        if i > 0 {
+            statement.push_str(", ");
+        }
+        statement.push_str(&format!("(${}, ${}, ${})", i * 3 + 1, i * 3 + 2, i * 3 + 3));
+        params.push(entity_id);
+        params.push(sequence_number);
+        params.push(payload);
+    }
+
+    client.execute(&insert_statement(statement, outbox), &params).map_err(|_| PERSIST_EVENT_FAILED)?;
+    Ok(())
+}
+
+/// The statement inserting the events, and their positions in the outbox when it is enabled.
+fn insert_statement(insert_events: String, outbox: bool) -> String {
+    if outbox {
+        format!("WITH inserted AS ({} RETURNING position) INSERT INTO outbox (position) SELECT position FROM inserted", insert_events)
+    } else {
+        insert_events
+    }
+}
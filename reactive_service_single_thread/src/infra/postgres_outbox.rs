@@ -0,0 +1,114 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use postgres::Row;
+use crate::infra::postgres_events_store::PostgresEventStore;
+use crate::outbox::{MessagePublisher, OutboxMessage, OutboxRelayConfig, ParkedMessage};
+
+/// Publishes the events written to the outbox of a `PostgresEventStore`, in their order.
+///
+/// A message is removed from the outbox once published, in the transaction it was read in:
+/// a failure in between publishes it again. A failed message is retried after a backoff, the following ones wait for it,
+/// until it is parked after `max_attempts`: the relay moves on, and the message waits to be unparked.
+/// Relays on the same database take turns, by batch.
+///
+/// The relay has a store of its own, a connection apart from the one of the `OrderService`: it can wait for the events.
+pub struct PostgresOutboxRelay<P: MessagePublisher> {
+    store: PostgresEventStore,
+    publisher: P,
+    config: OutboxRelayConfig,
+}
+
+impl<P: MessagePublisher> PostgresOutboxRelay<P> {
+    pub fn new(store: PostgresEventStore, publisher: P, config: OutboxRelayConfig) -> Self {
+        Self { store, publisher, config }
+    }
+
+    pub fn publisher(&mut self) -> &mut P {
+        &mut self.publisher
+    }
+
+    /// Publish the messages due, up to a batch, until one fails. Returns the number of messages published.
+    pub fn relay(&mut self) -> Result<usize, &'static str> {
+        let mut transaction = self.store.client().transaction().map_err(|_| "Failed to relay messages")?;
+        transaction.batch_execute("SELECT pg_advisory_xact_lock(7302)").map_err(|_| "Failed to relay messages")?;
+        let rows = transaction.query(
+            "SELECT o.position, e.entity_id, e.sequence_number, e.payload, o.attempts, o.next_attempt_at <= now()
+             FROM outbox o JOIN events e ON e.position = o.position
+             WHERE NOT o.parked ORDER BY o.position LIMIT $1",
+            &[&(self.config.batch_size as i64)],
+        ).map_err(|_| "Failed to relay messages")?;
+
+        let mut published = 0;
+        for row in rows {
+            let is_due: bool = row.get(5);
+            if !is_due {
+                break;
+            }
+            let message = message_from_row(&row);
+            match self.publisher.publish(&message) {
+                Ok(()) => {
+                    transaction.execute("DELETE FROM outbox WHERE position = $1", &[&message.position])
+                        .map_err(|_| "Failed to relay messages")?;
+                    published += 1;
+                },
+                Err(err) => {
+                    let attempts = row.get::<_, i32>(4) + 1;
+                    let backoff = self.config.backoff(attempts as u32).as_secs_f64();
+                    let parked = attempts as u32 >= self.config.max_attempts;
+                    transaction.execute(
+                        "UPDATE outbox SET attempts = $2, last_error = $3, parked = $4,
+                            next_attempt_at = now() + make_interval(secs => $5)
+                         WHERE position = $1",
+                        &[&message.position, &attempts, &err, &parked, &backoff],
+                    ).map_err(|_| "Failed to relay messages")?;
+                    break;
+                },
+            }
+        }
+
+        transaction.commit().map_err(|_| "Failed to relay messages")?;
+        Ok(published)
+    }
+
+    /// Publish the messages as they are written, until `stop` is set, from a thread of its own.
+    /// Waits for new events between two rounds, up to `poll_interval`, e.g. for a retry.
+    pub fn run(&mut self, stop: &AtomicBool) -> Result<(), &'static str> {
+        while !stop.load(Ordering::Relaxed) {
+            if self.relay()? == 0 {
+                let next_position = self.store.last_event_position()? + 1;
+                self.store.wait_for_event(next_position, self.config.poll_interval)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The messages given up on, by position.
+    pub fn parked(&mut self) -> Result<Vec<ParkedMessage>, &'static str> {
+        let rows = self.store.client().query(
+            "SELECT o.position, e.entity_id, e.sequence_number, e.payload, o.attempts, o.last_error
+             FROM outbox o JOIN events e ON e.position = o.position
+             WHERE o.parked ORDER BY o.position",
+            &[],
+        ).map_err(|_| "Failed to retrieve parked messages")?;
+
+        Ok(rows.iter()
+            .map(|row| ParkedMessage {
+                message: message_from_row(row),
+                attempts: row.get::<_, i32>(4) as u32,
+                last_error: row.get::<_, Option<String>>(5).unwrap_or_default(),
+            })
+            .collect())
+    }
+
+    /// Publish a parked message again, with new attempts. Returns false if there is no such parked message.
+    pub fn unpark(&mut self, position: i64) -> Result<bool, &'static str> {
+        let updated = self.store.client().execute(
+            "UPDATE outbox SET parked = FALSE, attempts = 0, next_attempt_at = now() WHERE position = $1 AND parked",
+            &[&position],
+        ).map_err(|_| "Failed to unpark message")?;
+        Ok(updated == 1)
+    }
+}
+
+fn message_from_row(row: &Row) -> OutboxMessage {
+    OutboxMessage { position: row.get(0), entity_id: row.get(1), sequence_number: row.get(2), payload: row.get(3) }
+}
@@ -0,0 +1,230 @@
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use rusqlite::{params, Connection, OptionalExtension, TransactionBehavior};
+use reactive_service_domain::aggregate_root::SequencedEvent;
+use reactive_service_application::scheduler::to_millis;
+use reactive_service_application::command_errors::{DESERIALIZE_COMMAND_FAILED, DESERIALIZE_EVENT_FAILED, LOAD_CHECKPOINT_FAILED, PERSIST_EVENT_FAILED, REMOVE_SCHEDULED_COMMAND_FAILED, RETRIEVE_EVENTS_FAILED, RETRIEVE_SCHEDULED_COMMANDS_FAILED, SAVE_CHECKPOINT_FAILED, SCHEDULE_COMMAND_FAILED, SERIALIZE_COMMAND_FAILED, SERIALIZE_EVENT_FAILED};
+use crate::catch_up::CheckpointStore;
+use crate::order_service::{EventsJournal, GlobalEventsJournal, Positioned};
+use crate::scheduler::{Scheduled, ScheduleStore};
+
+/// Interval of the reads of a catch-up subscription waiting for the writes of another connection.
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Events journal in a local SQLite database file, in WAL mode.
+///
+/// The global position of an event is assigned when it is inserted, the writes being serialized:
+/// the positions are committed in order. SQLite doesn't notify the other connections of a write:
+/// a catch-up subscription waiting for new events polls the database.
+pub struct SqliteEventStore { connection: Connection }
+
+impl SqliteEventStore {
+    /// Open, or create, the database at `path`.
+    pub fn new(path: impl AsRef<Path>) -> Result<Self, Box<dyn std::error::Error>> {
+        let connection = Connection::open(path)?;
+
+        // Readers don't block the writer; in WAL mode NORMAL only risks the last commits on a power loss
+        connection.pragma_update(None, "journal_mode", "WAL")?;
+        connection.pragma_update(None, "synchronous", "NORMAL")?;
+        connection.busy_timeout(Duration::from_secs(5))?;
+
+        connection.execute(
+            "CREATE TABLE IF NOT EXISTS events (
+                entity_id INTEGER NOT NULL,
+                sequence_number INTEGER NOT NULL,
+                payload TEXT NOT NULL,
+                position INTEGER,
+                PRIMARY KEY(entity_id, sequence_number)
+            )",
+            [],
+        )?;
+        // The databases created before the global positions, numbered in their insertion order
+        let has_position: bool = connection.query_row(
+            "SELECT COUNT(*) > 0 FROM pragma_table_info('events') WHERE name = 'position'", [], |row| row.get(0))?;
+        if !has_position {
+            connection.execute_batch(
+                "BEGIN IMMEDIATE;
+                 ALTER TABLE events ADD COLUMN position INTEGER;
+                 UPDATE events SET position = rowid;
+                 COMMIT;")?;
+        }
+        connection.execute_batch(
+            "CREATE UNIQUE INDEX IF NOT EXISTS events_position ON events (position);
+             CREATE TABLE IF NOT EXISTS checkpoints (
+                consumer TEXT PRIMARY KEY,
+                position INTEGER NOT NULL
+             );
+             CREATE TABLE IF NOT EXISTS scheduled_commands (
+                key TEXT PRIMARY KEY,
+                due_at INTEGER NOT NULL,
+                payload TEXT NOT NULL
+             );
+             CREATE INDEX IF NOT EXISTS scheduled_commands_due_at ON scheduled_commands (due_at);")?;
+
+        Ok(Self { connection })
+    }
+}
+
+impl<E: Serialize + DeserializeOwned> EventsJournal<E> for SqliteEventStore {
+    fn persist_event(&mut self, entity_id: i64, seq_event: &SequencedEvent<E>) -> Result<(), &'static str> {
+        let serialized_event = serde_json::to_string(&seq_event.event).map_err(|_| SERIALIZE_EVENT_FAILED)?;
+        insert_events(&self.connection, &[(entity_id, seq_event.sequence_number, serialized_event)])
+    }
+
+    fn persist_events(&mut self, events: &[(i64, SequencedEvent<E>)]) -> Result<(), &'static str> {
+        let rows = events.iter()
+            .map(|(entity_id, seq_event)| {
+                let serialized_event = serde_json::to_string(&seq_event.event).map_err(|_| SERIALIZE_EVENT_FAILED)?;
+                Ok((*entity_id, seq_event.sequence_number, serialized_event))
+            })
+            .collect::<Result<Vec<(i64, i64, String)>, &'static str>>()?;
+
+        // All or nothing, and a single sync of the WAL. Immediate: takes the write lock upfront,
+        // instead of failing to upgrade a read lock when another connection is writing.
+        let transaction = self.connection.transaction_with_behavior(TransactionBehavior::Immediate).map_err(|_| PERSIST_EVENT_FAILED)?;
+        insert_events(&transaction, &rows)?;
+        transaction.commit().map_err(|_| PERSIST_EVENT_FAILED)
+    }
+
+    fn retrieve_events(&mut self, entity_id: i64) -> Result<Vec<SequencedEvent<E>>, &'static str> {
+        let mut statement = self.connection
+            .prepare_cached("SELECT sequence_number, payload FROM events WHERE entity_id = ?1 ORDER BY sequence_number ASC")
+            .map_err(|_| RETRIEVE_EVENTS_FAILED)?;
+        let rows = statement
+            .query_map([entity_id], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?)))
+            .map_err(|_| RETRIEVE_EVENTS_FAILED)?;
+
+        rows.map(|row| {
+                let (sequence_number, event_payload) = row.map_err(|_| RETRIEVE_EVENTS_FAILED)?;
+                let event: E = serde_json::from_str(&event_payload).map_err(|_| DESERIALIZE_EVENT_FAILED)?;
+
+                Ok(SequencedEvent {
+                    sequence_number,
+                    event,
+                })
+            })
+            .collect()
+    }
+}
+
+impl<E: Serialize + DeserializeOwned> GlobalEventsJournal<E> for SqliteEventStore {
+    fn read_all(&mut self, from_position: i64, batch_size: usize) -> Result<Vec<Positioned<E>>, &'static str> {
+        let mut statement = self.connection
+            .prepare_cached(
+                "SELECT position, entity_id, sequence_number, payload FROM events
+                 WHERE position >= ?1 ORDER BY position ASC LIMIT ?2")
+            .map_err(|_| RETRIEVE_EVENTS_FAILED)?;
+        let rows = statement
+            .query_map(params![from_position, batch_size as i64], |row| {
+                Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)?, row.get::<_, i64>(2)?, row.get::<_, String>(3)?))
+            })
+            .map_err(|_| RETRIEVE_EVENTS_FAILED)?;
+
+        rows.map(|row| {
+                let (position, entity_id, sequence_number, event_payload) = row.map_err(|_| RETRIEVE_EVENTS_FAILED)?;
+                let event: E = serde_json::from_str(&event_payload).map_err(|_| DESERIALIZE_EVENT_FAILED)?;
+                Ok(Positioned { position, entity_id, event: SequencedEvent { sequence_number, event } })
+            })
+            .collect()
+    }
+
+    fn last_position(&mut self) -> Result<i64, &'static str> {
+        last_position(&self.connection)
+    }
+
+    fn wait_for_position(&mut self, position: i64, timeout: Duration) -> Result<(), &'static str> {
+        let deadline = Instant::now() + timeout;
+        while last_position(&self.connection)? < position {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                break;
+            }
+            thread::sleep(remaining.min(POLL_INTERVAL));
+        }
+        Ok(())
+    }
+}
+
+impl CheckpointStore for SqliteEventStore {
+    fn load_checkpoint(&mut self, consumer: &str) -> Result<i64, &'static str> {
+        let position = self.connection
+            .query_row("SELECT position FROM checkpoints WHERE consumer = ?1", [consumer], |row| row.get(0))
+            .optional()
+            .map_err(|_| LOAD_CHECKPOINT_FAILED)?;
+        Ok(position.unwrap_or(0))
+    }
+
+    fn save_checkpoint(&mut self, consumer: &str, position: i64) -> Result<(), &'static str> {
+        self.connection
+            .execute(
+                "INSERT INTO checkpoints (consumer, position) VALUES (?1, ?2)
+                 ON CONFLICT (consumer) DO UPDATE SET position = excluded.position",
+                params![consumer, position],
+            )
+            .map_err(|_| SAVE_CHECKPOINT_FAILED)?;
+        Ok(())
+    }
+}
+
+impl ScheduleStore for SqliteEventStore {
+    fn schedule(&mut self, scheduled: &Scheduled) -> Result<(), &'static str> {
+        let payload = serde_json::to_string(&scheduled.command).map_err(|_| SERIALIZE_COMMAND_FAILED)?;
+        self.connection
+            .execute(
+                "INSERT INTO scheduled_commands (key, due_at, payload) VALUES (?1, ?2, ?3)
+                 ON CONFLICT (key) DO UPDATE SET due_at = excluded.due_at, payload = excluded.payload",
+                params![scheduled.key(), scheduled.due_at_millis(), payload],
+            )
+            .map_err(|_| SCHEDULE_COMMAND_FAILED)?;
+        Ok(())
+    }
+
+    fn due(&mut self, now: SystemTime, limit: usize) -> Result<Vec<Scheduled>, &'static str> {
+        let mut statement = self.connection
+            .prepare_cached("SELECT due_at, payload FROM scheduled_commands WHERE due_at <= ?1 ORDER BY due_at LIMIT ?2")
+            .map_err(|_| RETRIEVE_SCHEDULED_COMMANDS_FAILED)?;
+        let rows = statement
+            .query_map(params![to_millis(now), limit as i64], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?)))
+            .map_err(|_| RETRIEVE_SCHEDULED_COMMANDS_FAILED)?;
+
+        rows.map(|row| {
+                let (due_at, payload) = row.map_err(|_| RETRIEVE_SCHEDULED_COMMANDS_FAILED)?;
+                let command = serde_json::from_str(&payload).map_err(|_| DESERIALIZE_COMMAND_FAILED)?;
+                Ok(Scheduled::from_millis(due_at, command))
+            })
+            .collect()
+    }
+
+    fn remove(&mut self, delivered: &Scheduled) -> Result<(), &'static str> {
+        let payload = serde_json::to_string(&delivered.command).map_err(|_| SERIALIZE_COMMAND_FAILED)?;
+        self.connection
+            .execute(
+                "DELETE FROM scheduled_commands WHERE key = ?1 AND due_at = ?2 AND payload = ?3",
+                params![delivered.key(), delivered.due_at_millis(), payload],
+            )
+            .map_err(|_| REMOVE_SCHEDULED_COMMAND_FAILED)?;
+        Ok(())
+    }
+}
+
+/// Insert the events after the last position.
+/// Without a transaction, each event is committed on its own: a failure leaves the previous ones persisted.
+fn insert_events(connection: &Connection, rows: &[(i64, i64, String)]) -> Result<(), &'static str> {
+    let mut statement = connection
+        .prepare_cached(
+            "INSERT INTO events (entity_id, sequence_number, payload, position)
+             SELECT ?1, ?2, ?3, IFNULL(MAX(position), 0) + 1 FROM events")
+        .map_err(|_| PERSIST_EVENT_FAILED)?;
+    for (entity_id, sequence_number, payload) in rows {
+        statement.execute(params![entity_id, sequence_number, payload]).map_err(|_| PERSIST_EVENT_FAILED)?;
+    }
+    Ok(())
+}
+
+fn last_position(connection: &Connection) -> Result<i64, &'static str> {
+    connection.query_row("SELECT IFNULL(MAX(position), 0) FROM events", [], |row| row.get(0))
+        .map_err(|_| RETRIEVE_EVENTS_FAILED)
+}
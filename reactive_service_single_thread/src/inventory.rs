@@ -0,0 +1,79 @@
+use std::cell::RefCell;
+use std::time::SystemTime;
+use reactive_service_domain::inventory_item::{InventoryItem, InventoryItemCommand, InventoryItemEvent, InventoryItemState};
+use reactive_service_domain::non_empty_cart::{NonEmptyCart, Sku};
+use reactive_service_application::inventory::{cart_items, item_id};
+use crate::entity_host::EntityHost;
+use crate::order_service::{EventsJournal, OrderId, Versioned};
+
+pub use reactive_service_application::inventory::{Inventory, InventoryConfig, LocalInventory};
+
+/// The host of the `InventoryItem` entities, one per SKU, in a journal of their own: the `Inventory` port of the orders.
+///
+/// The ports are called through a shared reference, e.g. by the `OrderService` in the middle of a command:
+/// the host is borrowed for the time of each item command. It stays on the thread of its service.
+pub struct InventoryService<J: EventsJournal<InventoryItemEvent>> {
+    items: RefCell<EntityHost<InventoryItem, J>>,
+    config: InventoryConfig,
+}
+
+impl<J: EventsJournal<InventoryItemEvent>> InventoryService<J> {
+    pub fn new(events_journal: J) -> Self {
+        Self::with_config(events_journal, InventoryConfig::default())
+    }
+
+    pub fn with_config(events_journal: J, config: InventoryConfig) -> Self {
+        Self { items: RefCell::new(EntityHost::new(events_journal)), config }
+    }
+
+    /// Add the units received to the stock of the SKU.
+    pub fn receive_stock(&self, sku: &Sku, quantity: u32) -> Result<InventoryItemState, &'static str> {
+        self.handle(item_id(sku), InventoryItemCommand::Receive { quantity })
+    }
+
+    /// The stock of the SKU, with its sequence number.
+    pub fn get_stock(&self, sku: &Sku) -> Result<Versioned<InventoryItemState>, &'static str> {
+        self.items.borrow_mut().query(item_id(sku))
+    }
+
+    fn handle(&self, item_id: i64, command: InventoryItemCommand) -> Result<InventoryItemState, &'static str> {
+        let mut items = self.items.borrow_mut();
+        let (state, _) = items.handle(item_id, |_| Ok(command))?;
+        Ok(state.clone())
+    }
+
+    fn release_items(&self, order_id: OrderId, items: &[(i64, u32)]) -> Result<(), &'static str> {
+        for (item_id, _) in items {
+            self.handle(*item_id, InventoryItemCommand::Release { order_id })?;
+        }
+        Ok(())
+    }
+}
+
+impl<J: EventsJournal<InventoryItemEvent>> Inventory for InventoryService<J> {
+    /// On a SKU short of stock, the items reserved before it are released.
+    fn reserve_stock(&self, order_id: OrderId, cart: &NonEmptyCart) -> Result<(), &'static str> {
+        let items = cart_items(cart);
+        let expires_at = SystemTime::now() + self.config.reservation_ttl;
+        for (index, (item_id, quantity)) in items.iter().enumerate() {
+            let reserve = InventoryItemCommand::Reserve { order_id, quantity: *quantity, expires_at, at: SystemTime::now() };
+            if let Err(err) = self.handle(*item_id, reserve) {
+                // A release failing leaves its reservations to expire
+                let _ = self.release_items(order_id, &items[..index]);
+                return Err(err);
+            }
+        }
+        Ok(())
+    }
+
+    fn release_stock(&self, order_id: OrderId, cart: &NonEmptyCart) -> Result<(), &'static str> {
+        self.release_items(order_id, &cart_items(cart))
+    }
+
+    fn ship_stock(&self, order_id: OrderId, cart: &NonEmptyCart) -> Result<(), &'static str> {
+        for (item_id, _) in cart_items(cart) {
+            self.handle(item_id, InventoryItemCommand::Ship { order_id, at: SystemTime::now() })?;
+        }
+        Ok(())
+    }
+}
@@ -1,5 +1,19 @@
 pub mod order_service;
+pub mod entity_host;
+pub mod event_bus;
+pub mod catch_up;
+pub mod projections;
+pub mod outbox;
+pub mod order_fulfillment;
+pub mod scheduler;
+pub mod middleware;
+pub mod event_loop;
 pub mod infra;
 pub mod shipping_calculator;
 pub mod payment_processor;
+pub mod inventory;
+pub mod customer_service;
+pub mod shipment_service;
 pub mod tax_calculator;
+#[cfg(feature = "conformance")]
+pub mod journal_conformance;
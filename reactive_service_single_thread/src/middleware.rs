@@ -0,0 +1,143 @@
+use std::rc::Rc;
+use std::time::Instant;
+use reactive_service_domain::order_entity::OrderEvent;
+use crate::inventory::Inventory;
+use crate::order_service::{CommandResult, EventsJournal, OrderCommand, OrderService, Principal};
+use crate::payment_processor::PaymentProcessor;
+use crate::shipping_calculator::ShippingCalculator;
+use crate::tax_calculator::TaxCalculator;
+
+pub use reactive_service_application::middleware::{
+    CommandLatencies, CommandTrace, CommandTracer, LatencyHistogram, LogTracer, LATENCY_BUCKETS
+};
+
+/// Handles the order commands on behalf of a principal: the `OrderService`, or a middleware around another handler.
+/// The result is owned: a middleware can't hold the state borrowed from the service.
+///
+/// The commands are handled one at a time, on a single thread: there is no concurrency to limit,
+/// and no command to interrupt on a timeout.
+pub trait CommandHandler {
+    fn call(&mut self, principal: &Principal, command: OrderCommand) -> CommandResult;
+}
+
+impl<E, S, T, P, I> CommandHandler for OrderService<E, S, T, P, I>
+where
+    E: EventsJournal<OrderEvent>,
+    S: ShippingCalculator,
+    T: TaxCalculator,
+    P: PaymentProcessor,
+    I: Inventory,
+{
+    fn call(&mut self, principal: &Principal, command: OrderCommand) -> CommandResult {
+        self.handle_as(principal, command).map(|(state, events)| (state.clone(), events))
+    }
+}
+
+impl<H: CommandHandler> CommandHandler for &mut H {
+    fn call(&mut self, principal: &Principal, command: OrderCommand) -> CommandResult {
+        (**self).call(principal, command)
+    }
+}
+
+/// Wraps a `CommandHandler` into a middleware.
+pub trait Layer<H: CommandHandler> {
+    type Handler: CommandHandler;
+
+    fn layer(self, inner: H) -> Self::Handler;
+}
+
+/// A chain of middlewares around a `CommandHandler`, e.g. the `OrderService`. The last layer added is the outermost:
+/// the first to see a command, and the last to see its result.
+pub struct Pipeline<H: CommandHandler> {
+    handler: H,
+}
+
+impl<H: CommandHandler> Pipeline<H> {
+    pub fn new(handler: H) -> Self {
+        Self { handler }
+    }
+
+    /// Wrap the handlers added so far with the layer.
+    pub fn layer<L: Layer<H>>(self, layer: L) -> Pipeline<L::Handler> {
+        Pipeline { handler: layer.layer(self.handler) }
+    }
+
+    pub fn handle_as(&mut self, principal: &Principal, command: OrderCommand) -> CommandResult {
+        self.handler.call(principal, command)
+    }
+}
+
+impl<H: CommandHandler> CommandHandler for Pipeline<H> {
+    fn call(&mut self, principal: &Principal, command: OrderCommand) -> CommandResult {
+        self.handler.call(principal, command)
+    }
+}
+
+/// Traces every command once over, to the `CommandTracer`.
+pub struct TraceLayer<C: CommandTracer> {
+    tracer: C,
+}
+
+impl<C: CommandTracer> TraceLayer<C> {
+    pub fn new(tracer: C) -> Self {
+        Self { tracer }
+    }
+}
+
+impl<H: CommandHandler, C: CommandTracer> Layer<H> for TraceLayer<C> {
+    type Handler = Traced<H, C>;
+
+    fn layer(self, inner: H) -> Self::Handler {
+        Traced { inner, tracer: self.tracer }
+    }
+}
+
+pub struct Traced<H: CommandHandler, C: CommandTracer> {
+    inner: H,
+    tracer: C,
+}
+
+impl<H: CommandHandler, C: CommandTracer> CommandHandler for Traced<H, C> {
+    fn call(&mut self, principal: &Principal, command: OrderCommand) -> CommandResult {
+        let (order_id, name) = (command.order_id(), command.name());
+        let start_time = Instant::now();
+        let result = self.inner.call(principal, command);
+        self.tracer.on_command(&CommandTrace::new(order_id, name, principal, start_time.elapsed(), &result));
+        result
+    }
+}
+
+/// Records the latency of every command in the `CommandLatencies`, by kind of command.
+pub struct LatencyLayer {
+    latencies: Rc<CommandLatencies>,
+}
+
+impl LatencyLayer {
+    /// Record in the latencies, shared with their readers.
+    pub fn new(latencies: Rc<CommandLatencies>) -> Self {
+        Self { latencies }
+    }
+}
+
+impl<H: CommandHandler> Layer<H> for LatencyLayer {
+    type Handler = Timed<H>;
+
+    fn layer(self, inner: H) -> Self::Handler {
+        Timed { inner, latencies: self.latencies }
+    }
+}
+
+pub struct Timed<H: CommandHandler> {
+    inner: H,
+    latencies: Rc<CommandLatencies>,
+}
+
+impl<H: CommandHandler> CommandHandler for Timed<H> {
+    fn call(&mut self, principal: &Principal, command: OrderCommand) -> CommandResult {
+        let name = command.name();
+        let start_time = Instant::now();
+        let result = self.inner.call(principal, command);
+        self.latencies.record(name, start_time.elapsed(), result.is_err());
+        result
+    }
+}
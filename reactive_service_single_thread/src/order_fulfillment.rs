@@ -0,0 +1,163 @@
+use std::time::{Duration, Instant, SystemTime};
+use reactive_service_domain::aggregate_root::AggregateRoot;
+use reactive_service_domain::order_entity::{OrderEntity, OrderEvent};
+use crate::catch_up::{CatchUpSubscription, CheckpointStore};
+use crate::entity_host::EntityHost;
+use crate::inventory::Inventory;
+use crate::order_service::{EventsJournal, GlobalEventsJournal, OrderId, Versioned};
+use crate::payment_processor::PaymentProcessor;
+use crate::shipment_service::ShipmentService;
+use tracing::warn;
+
+pub use reactive_service_application::order_fulfillment::{
+    FulfillmentAction, FulfillmentCommand, FulfillmentConfig, FulfillmentEvent, FulfillmentOrder, FulfillmentRetries,
+    FulfillmentState, FulfillmentStatus, FulfillmentStep, OrderFulfillment, PendingStep, StepTimeouts, FULFILLMENT_CONSUMER
+};
+
+/// Follows the events of the orders, and fulfills each order once it is completed: hosts the `OrderFulfillment`
+/// process managers, persisted in a journal of their own, and performs their steps with the ports.
+/// The journal of the orders is given on each call, e.g. by the `OrderService` between two commands.
+///
+/// A fulfillment failing, e.g. on a compensation failing, is logged then retried with a backoff,
+/// while the orders completed after it are fulfilled. The checkpoint only moves past the completion of an order
+/// once its fulfillment is over: a fulfillment interrupted by a restart is resumed where it stopped
+/// when the completion is delivered again.
+pub struct FulfillmentManager<F, C, I, P, S>
+where
+    F: EventsJournal<FulfillmentEvent>,
+    C: CheckpointStore,
+    I: Inventory,
+    P: PaymentProcessor,
+    S: ShipmentService,
+{
+    checkpoints: C,
+    subscription: CatchUpSubscription<OrderEvent>,
+    fulfillments: EntityHost<OrderFulfillment, F>,
+    retries: FulfillmentRetries,
+    inventory: I,
+    payment_processor: P,
+    shipment_service: S,
+    config: FulfillmentConfig,
+}
+
+impl<F, C, I, P, S> FulfillmentManager<F, C, I, P, S>
+where
+    F: EventsJournal<FulfillmentEvent>,
+    C: CheckpointStore,
+    I: Inventory,
+    P: PaymentProcessor,
+    S: ShipmentService,
+{
+    /// Follow the orders from the checkpoint of the manager.
+    pub fn new(mut checkpoints: C, fulfillments_journal: F, inventory: I, payment_processor: P, shipment_service: S,
+               config: FulfillmentConfig) -> Result<Self, &'static str> {
+
+        let subscription = CatchUpSubscription::from_checkpoint(&mut checkpoints, FULFILLMENT_CONSUMER, config.catch_up.clone())?;
+        Ok(Self {
+            checkpoints,
+            subscription,
+            fulfillments: EntityHost::new(fulfillments_journal),
+            retries: FulfillmentRetries::default(),
+            inventory,
+            payment_processor,
+            shipment_service,
+            config,
+        })
+    }
+
+    pub fn checkpoints(&mut self) -> &mut C {
+        &mut self.checkpoints
+    }
+
+    /// Start the fulfillment of the completed order, if needed, then move it on until it is over.
+    pub fn fulfill<J: EventsJournal<OrderEvent>>(&mut self, orders: &mut J, order_id: OrderId)
+        -> Result<FulfillmentState, &'static str> {
+
+        if self.fulfillments.get_state(order_id)?.status == FulfillmentStatus::NotStarted {
+            let mut order = OrderEntity::default();
+            order.restore_from_events(orders.retrieve_events(order_id)?)?;
+            let start = FulfillmentCommand::start(order.get_state(), self.config.step_timeouts.clone(), SystemTime::now())?;
+            self.fulfillments.handle(order_id, |_| Ok(start))?;
+        }
+
+        while let Some(action) = self.fulfillments.get_state(order_id)?.next_action(SystemTime::now()) {
+            let command = action.perform(
+                order_id, self.fulfillments.get_state(order_id)?, &self.inventory, &self.payment_processor, &self.shipment_service
+            )?;
+            self.fulfillments.handle(order_id, |_| Ok(command))?;
+        }
+        Ok(self.fulfillments.get_state(order_id)?.clone())
+    }
+
+    /// The fulfillment of the order, with its sequence number.
+    pub fn get_fulfillment(&mut self, order_id: OrderId) -> Result<Versioned<FulfillmentState>, &'static str> {
+        self.fulfillments.query(order_id)
+    }
+
+    /// Fulfill the orders completed so far. Returns the number of events processed.
+    pub fn catch_up<J: GlobalEventsJournal<OrderEvent>>(&mut self, orders: &mut J) -> Result<usize, &'static str> {
+        let mut processed = 0;
+        loop {
+            match self.process(orders, Duration::ZERO)? {
+                0 => return Ok(processed),
+                count => processed += count,
+            }
+        }
+    }
+
+    /// Retry the failed fulfillments due, then fulfill the orders completed in the next batch of events,
+    /// once there is one within `timeout`, and save the checkpoint. Returns the number of events in the batch.
+    pub fn process<J: GlobalEventsJournal<OrderEvent>>(&mut self, orders: &mut J, timeout: Duration) -> Result<usize, &'static str> {
+        if self.retry_due(orders) {
+            self.save_checkpoint()?;
+        }
+        let Some(first) = self.subscription.recv_timeout(orders, timeout)? else { return Ok(0) };
+        let mut batch = vec![first];
+        while batch.len() < self.config.catch_up.batch_size {
+            match self.subscription.recv_timeout(orders, Duration::ZERO) {
+                Ok(Some(positioned)) => batch.push(positioned),
+                // The events not read yet are read on the next call
+                Ok(None) | Err(_) => break,
+            }
+        }
+
+        for positioned in &batch {
+            if let OrderEvent::Completed { .. } = positioned.event.event {
+                if let Err(err) = self.fulfill(orders, positioned.entity_id) {
+                    self.failed(positioned.position, positioned.entity_id, err);
+                }
+            }
+        }
+        self.save_checkpoint()?;
+        Ok(batch.len())
+    }
+
+    /// Number of the fulfillments failed, waiting to be retried.
+    pub fn failed_fulfillments(&self) -> usize {
+        self.retries.len()
+    }
+
+    fn failed(&mut self, position: i64, order_id: OrderId, err: &'static str) {
+        let failures = self.retries.failed(position, order_id, Instant::now(), &self.config);
+        warn!(order_id, error = err, failures, "fulfillment failed, it will be retried");
+    }
+
+    /// Retry the failed fulfillments due. Returns whether one is over.
+    fn retry_due<J: EventsJournal<OrderEvent>>(&mut self, orders: &mut J) -> bool {
+        let mut over = false;
+        for (position, order_id) in self.retries.due(Instant::now()) {
+            match self.fulfill(orders, order_id) {
+                Ok(_) => {
+                    self.retries.over(position);
+                    over = true;
+                },
+                Err(err) => self.failed(position, order_id, err),
+            }
+        }
+        over
+    }
+
+    fn save_checkpoint(&mut self) -> Result<(), &'static str> {
+        self.checkpoints.save_checkpoint(FULFILLMENT_CONSUMER, self.retries.checkpoint(self.subscription.position()))
+    }
+}
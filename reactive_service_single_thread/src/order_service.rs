@@ -1,36 +1,65 @@
-use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use reactive_service_domain::aggregate_root::{AggregateRoot, SequencedEvent};
-use reactive_service_domain::non_empty_cart::NonEmptyCart;
 use reactive_service_domain::order_entity::{OrderEntity, OrderEntityCommand, OrderEvent};
-use reactive_service_domain::order_state::{DeliveryAddress, Money, OrderState};
-use crate::payment_processor::{PaymentProcessor, PaymentToken};
+use reactive_service_domain::order_state::OrderState;
+use crate::entity_host::EntityHost;
+use crate::event_bus::EventBus;
+use crate::inventory::{Inventory, LocalInventory};
+use crate::payment_processor::PaymentProcessor;
+use tracing::{info_span, warn};
+
+pub use reactive_service_application::order_commands::{
+    CommandResult, ExpireOrder, OrderCommand, OrderId, PayOrder, UpdateCart, UpdateDeliveryAddress
+};
+pub use reactive_service_application::authorization::Principal;
+pub use reactive_service_application::metrics::{MetricsRegistry, NoMetrics, PrometheusRegistry};
+pub use reactive_service_application::order_queries::{OrderView, QueryResult, Versioned};
+pub use reactive_service_application::subscriptions::{Committed, Positioned, SubscriptionError};
+pub use reactive_service_application::shipping_calculator::ShippingCalculator;
+pub use reactive_service_application::tax_calculator::TaxCalculator;
 
 pub trait EventsJournal<Event> {
     fn persist_event(&mut self, entity_id: OrderId, evt_w_seq: &SequencedEvent<Event>) -> Result<(), &'static str>;
     fn retrieve_events(&mut self, entity_id: OrderId) -> Result<Vec<SequencedEvent<Event>>, &'static str>;
-}
 
-pub trait ShippingCalculator {
-    fn shipping_cost(&self, cart: &NonEmptyCart, delivery_address: &DeliveryAddress) -> Money;
+    /// Persist the events of several entities with one call, all or nothing:
+    /// if it fails, none of the events is persisted, so the caller may retry any of them.
+    fn persist_events(&mut self, events: &[(OrderId, SequencedEvent<Event>)]) -> Result<(), &'static str>;
 }
 
-pub trait TaxCalculator {
-    fn tax_cost(&self, cart: &NonEmptyCart, shipping_cost: &Money) -> Money;
-}
+/// A journal keeping the global order of its events, across the entities: each persisted event gets the next position.
+/// A position is only readable once the lower ones are, a reader following the positions never skips an event.
+pub trait GlobalEventsJournal<Event>: EventsJournal<Event> {
+    /// Up to `batch_size` events, by position, from `from_position` included.
+    fn read_all(&mut self, from_position: i64, batch_size: usize) -> Result<Vec<Positioned<Event>>, &'static str>;
 
-type OrderId = i64;
+    /// Position of the last persisted event, 0 when there is none.
+    fn last_position(&mut self) -> Result<i64, &'static str>;
+
+    /// Wait until an event may be readable at `position`, or for `timeout`.
+    /// It may return early: the caller reads again to know.
+    fn wait_for_position(&mut self, position: i64, timeout: Duration) -> Result<(), &'static str>;
+}
 
+/// The host of the `OrderEntity`, with the ports needed to turn an `OrderCommand` into an entity command.
+/// The stock of an order is reserved in the `Inventory` when it is paid for, without any inventory by default.
+///
+/// Each command is traced in a `command` span, with the `order_id` and the `command` name,
+/// and its latency and errors are reported to the `MetricsRegistry`, none by default.
 pub struct OrderService<
     E: EventsJournal<OrderEvent>,
     S: ShippingCalculator,
     T: TaxCalculator,
-    P: PaymentProcessor
+    P: PaymentProcessor,
+    I: Inventory = LocalInventory
 > {
-    orders: HashMap<OrderId, OrderEntity>,
-    events_journal: E,
+    orders: EntityHost<OrderEntity, E>,
     shipping_calculator: S,
     tax_calculator: T,
-    payment_processor: P
+    payment_processor: P,
+    inventory: I,
+    metrics: Arc<dyn MetricsRegistry + Send + Sync>,
 }
 
 impl <E, S, T, P> OrderService<E, S, T, P>
@@ -42,147 +71,180 @@ where
 {
 
     pub fn new(events_journal: E, shipping_calculator: S, tax_calculator: T, payment_processor: P) -> Self {
+        Self::with_inventory(events_journal, shipping_calculator, tax_calculator, payment_processor, LocalInventory{})
+    }
+}
+
+impl <E, S, T, P, I> OrderService<E, S, T, P, I>
+where
+    E: EventsJournal<OrderEvent>,
+    S: ShippingCalculator,
+    T: TaxCalculator,
+    P: PaymentProcessor,
+    I: Inventory
+{
+
+    pub fn with_inventory(events_journal: E, shipping_calculator: S, tax_calculator: T, payment_processor: P, inventory: I) -> Self {
         Self {
-            orders: HashMap::default(),
-            events_journal: events_journal,
+            orders: EntityHost::new(events_journal),
             shipping_calculator,
             tax_calculator,
-            payment_processor
+            payment_processor,
+            inventory,
+            metrics: Arc::new(NoMetrics),
         }
     }
 
-    fn create_and_process_entity_command<F>(&mut self, entity_id: OrderId, create_command: F)
-      -> Result<(&OrderState, Vec<SequencedEvent<OrderEvent>>), &'static str>
-    where
-        F: FnOnce(&mut OrderEntity, &S, &T, &P) -> Result<OrderEntityCommand, &'static str>,
-    {
-        let order: &mut OrderEntity = self.orders.entry(entity_id)
-            .or_insert({
-                let events = self.events_journal.retrieve_events(entity_id)?;
-                let mut entity = OrderEntity::default();
-                let _ = entity.restore_from_events(events)?;
-                entity
-            });
-
-        let entity_command: OrderEntityCommand = create_command(
-            order, &self.shipping_calculator, &self.tax_calculator, &self.payment_processor
-        )?;
+    /// Report the metrics of the commands, of the journal and of the orders in memory to the registry.
+    pub fn with_metrics(self, metrics: Arc<dyn MetricsRegistry + Send + Sync>) -> Self {
+        Self { orders: self.orders.with_metrics(metrics.clone()), metrics, ..self }
+    }
 
-        let (state, events) = order.handle_command(entity_command)?;
+    /// Handle any order command as an admin, for the trusted callers: the scheduler, the fulfillment, the tools.
+    pub fn handle_as_admin(&mut self, command: OrderCommand)
+      -> Result<(&OrderState, Vec<SequencedEvent<OrderEvent>>), &'static str> {
+        self.handle_as(&Principal::Admin, command)
+    }
 
-        for evt in &events {
-            self.events_journal.persist_event(entity_id, evt)?;
-        }
-        Ok((state, events))
+    /// Handle any order command on behalf of the principal, restoring the entity from the journal if needed.
+    /// At checkout, the stock is reserved first: if the order isn't completed, the reservation expires.
+    pub fn handle_as(&mut self, principal: &Principal, command: OrderCommand)
+      -> Result<(&OrderState, Vec<SequencedEvent<OrderEvent>>), &'static str> {
+
+        let name = command.name();
+        let _span = info_span!("command", order_id = command.order_id(), command = name).entered();
+        let start_time = Instant::now();
+        let (shipping_calculator, tax_calculator, payment_processor, inventory) =
+            (&self.shipping_calculator, &self.tax_calculator, &self.payment_processor, &self.inventory);
+        let result = self.orders.handle(command.order_id(), |order| {
+            to_entity_command(principal, order, command, shipping_calculator, tax_calculator, payment_processor, inventory)
+        });
+        self.metrics.observe_command(name, start_time.elapsed(), result.as_ref().err().copied());
+        result
     }
 
-    pub fn update_cart(&mut self, cmd: UpdateCart)
+    pub fn update_cart(&mut self, principal: &Principal, cmd: UpdateCart)
         -> Result<(&OrderState, Vec<SequencedEvent<OrderEvent>>), &'static str> {
-
-        let update_cart_command_builder =
-            |order_entity: &mut OrderEntity, shipping_calculator: &S, tax_calculator: &T, _: &P| -> Result<OrderEntityCommand, &'static str> {
-
-                let cart = cmd.cart;
-                match order_entity.get_state() {
-
-                    OrderState::Empty(_) | OrderState::WithCart(_) =>
-                        Ok(OrderEntityCommand::AddCart{cart}),
-
-                    OrderState::WithAddress(with_addr) => {
-                        let shipping_cost = shipping_calculator.shipping_cost(&cart, with_addr.get_delivery_address());
-                        let tax: Money = tax_calculator.tax_cost(&cart, &shipping_cost);
-                        Ok(OrderEntityCommand::UpdateCart {cart, shipping_cost, tax})
-                    },
-
-                    OrderState::Completed(_) => Err("Can't update the cart on a completed order."),
-                }
-            };
-
-        self.create_and_process_entity_command(cmd.order_id, update_cart_command_builder)
+        self.handle_as(principal, OrderCommand::UpdateCart(cmd))
     }
 
-
-
-    pub fn update_delivery_address(&mut self, cmd: UpdateDeliveryAddress)
+    pub fn update_delivery_address(&mut self, principal: &Principal, cmd: UpdateDeliveryAddress)
        -> Result<(&OrderState, Vec<SequencedEvent<OrderEvent>>), &'static str> {
+        self.handle_as(principal, OrderCommand::UpdateDeliveryAddress(cmd))
+    }
 
-        let update_addr_command_builder =
-            |order_entity: &mut OrderEntity, shipping_calculator: &S, tax_calculator: &T, _: &P|
-             -> Result<OrderEntityCommand, &'static str> {
-
-                let delivery_address = cmd.delivery_address;
-                match order_entity.get_state() {
-
-                    OrderState::Empty(_) => Err("Can't add address to an empty cart."),
-
-                    OrderState::WithCart(with_cart) => {
-                        let cart = with_cart.get_cart();
-                        let shipping_cost = shipping_calculator.shipping_cost(cart, &delivery_address);
-                        let tax: Money = tax_calculator.tax_cost(cart, &shipping_cost);
-                        Ok(OrderEntityCommand::UpdateDeliveryAddress { delivery_address, shipping_cost, tax })
-                    },
-
-                    OrderState::WithAddress(with_addr) => {
-                        let cart = with_addr.get_cart();
-                        let shipping_cost = shipping_calculator.shipping_cost(cart, &delivery_address);
-                        let tax: Money = tax_calculator.tax_cost(cart, &shipping_cost);
-                        Ok(OrderEntityCommand::UpdateDeliveryAddress { delivery_address, shipping_cost, tax })
-                    },
-
-                    OrderState::Completed(_) => Err("Can't update address on a completed order."),
-                }
-            };
-
-        self.create_and_process_entity_command(cmd.order_id, update_addr_command_builder)
+    pub fn pay_order(&mut self, principal: &Principal, cmd: PayOrder)
+       -> Result<(&OrderState, Vec<SequencedEvent<OrderEvent>>), &'static str> {
+        self.handle_as(principal, OrderCommand::PayOrder(cmd))
     }
 
+    /// Handle several commands, each on behalf of its principal, then persist all their events with a single journal call.
+    /// If persisting fails, none of the events is persisted (the journal appends a batch all or nothing):
+    /// every command of the batch which produced events fails,
+    /// and the entities are evicted, to be restored from the journal on their next command.
+    /// The payments authorized by the failed commands are released.
+    ///
+    /// The batch is traced in a `batch` span, each command in a `command` span: their latency is the one of the batch.
+    pub fn handle_batch(&mut self, commands: Vec<(Principal, OrderCommand)>)
+        -> Vec<CommandResult> {
+
+        let _span = info_span!("batch", commands = commands.len()).entered();
+        let start_time = Instant::now();
+        let names: Vec<_> = commands.iter().map(|(_, command)| command.name()).collect();
+        let payments: Vec<_> = commands.iter()
+            .map(|(_, command)| matches!(command, OrderCommand::PayOrder(_)).then(|| command.order_id()))
+            .collect();
+        let mut results = Vec::with_capacity(commands.len());
+        let mut batch_events = Vec::new();
+
+        for (principal, command) in commands {
+            let order_id = command.order_id();
+            let _span = info_span!("command", order_id, command = command.name()).entered();
+            let (shipping_calculator, tax_calculator, payment_processor, inventory) =
+                (&self.shipping_calculator, &self.tax_calculator, &self.payment_processor, &self.inventory);
+            let processed = self.orders.process(order_id, |order| {
+                to_entity_command(&principal, order, command, shipping_calculator, tax_calculator, payment_processor, inventory)
+            });
 
-    pub fn pay_order(&mut self, cmd: PayOrder)
-       -> Result<(&OrderState, Vec<SequencedEvent<OrderEvent>>), &'static str> {
+            // Capture the state right after the command, a later command of the batch may change it
+            let result = processed.and_then(|events| {
+                let state = self.orders.get_state(order_id)?.clone();
+                batch_events.extend(events.iter().map(|evt| (order_id, evt.clone())));
+                Ok((state, events))
+            });
+            results.push(result);
+        }
 
-        let pay_order_command_builder =
-            |order_entity: &mut OrderEntity, _: &S, _: &T, payment_processor: &P|
-             -> Result<OrderEntityCommand, &'static str> {
+        if let Err(err) = self.orders.persist_events(&batch_events) {
+            results = results.into_iter().zip(&payments)
+                .map(|(result, payment)| result.and_then(|(state, events)| {
+                    if events.is_empty() {
+                        return Ok((state, events));
+                    }
+                    if let Some(order_id) = payment {
+                        if let Err(error) = self.payment_processor.refund_payment(*order_id) {
+                            warn!(order_id, error, "failed to release the payment of an order which wasn't persisted");
+                        }
+                    }
+                    Err(err)
+                }))
+                .collect();
+        }
 
-                let payment_token = cmd.payment_token;
-                match order_entity.get_state() {
+        let elapsed = start_time.elapsed();
+        for (name, result) in names.into_iter().zip(&results) {
+            self.metrics.observe_command(name, elapsed, result.as_ref().err().copied());
+        }
+        results
+    }
 
-                    OrderState::Empty(_) | OrderState::WithCart(_) =>
-                        Err("Order not ready to be paid."),
+    pub fn get_state(&mut self, entity_id: OrderId) -> Result<&OrderState, &'static str> {
+        self.orders.get_state(entity_id)
+    }
 
-                    OrderState::WithAddress(_) => {
-                        let invoice = payment_processor.pay_with_token(payment_token);
-                        Ok(OrderEntityCommand::Complete{invoice})
-                    },
+    /// The current state of the order, with its sequence number.
+    pub fn get_order(&mut self, order_id: OrderId) -> QueryResult {
+        self.orders.query(order_id)
+    }
 
-                    OrderState::Completed(_) => Err("Order is already paid."),
-                }
-            };
+    /// The state of the order, once its sequence number is at least `sequence_number`.
+    /// The service has a single writer, the caller: waiting can't make the order progress, a version not reached yet is an error.
+    /// The `OrderServiceEventLoop` waits for it.
+    pub fn get_order_at_least(&mut self, order_id: OrderId, sequence_number: i64) -> QueryResult {
+        let order = self.orders.query(order_id)?;
+        if order.sequence_number < sequence_number {
+            return Err("Version not reached");
+        }
+        Ok(order)
+    }
 
-        self.create_and_process_entity_command(cmd.order_id, pay_order_command_builder)
+    /// The events of the orders, published once persisted.
+    /// Subscribe to the events of every order with `subscribe_all`, or of a single one with `subscribe`.
+    pub fn event_bus(&self) -> &EventBus<OrderEvent> {
+        self.orders.event_bus()
     }
 
-    pub fn get_state(&mut self, entity_id: OrderId) -> Result<&OrderState, &'static str> {
-        let order: &mut OrderEntity = self.orders.entry(entity_id)
-            .or_insert_with(| | {
-                let events =
-                    self.events_journal.retrieve_events(entity_id).unwrap();
-                let mut entity = OrderEntity::default();
-                let _ = entity.restore_from_events(events).unwrap();
-                println!("restored events");
-                entity
-            });
+    /// The stock reserved for the orders, e.g. to share it with their fulfillment.
+    pub fn inventory(&self) -> &I {
+        &self.inventory
+    }
 
-        let state = order.get_state();
-        Ok(state)
+    /// The journal of the orders, e.g. to follow their events from a position with a `CatchUpSubscription`.
+    pub fn events_journal(&mut self) -> &mut E {
+        self.orders.events_journal()
     }
 
 }
 
-#[derive(Debug, Clone)]
-pub struct UpdateCart{pub order_id: OrderId, pub cart: NonEmptyCart}
-#[derive(Debug)]
-pub struct UpdateDeliveryAddress{pub order_id: OrderId, pub delivery_address: DeliveryAddress}
-#[derive(Debug)]
-pub struct PayOrder{pub order_id: OrderId, pub payment_token: PaymentToken}
+/// The entity command of the order command, once the principal is authorized and the stock of a checkout is reserved.
+fn to_entity_command<S: ShippingCalculator, T: TaxCalculator, P: PaymentProcessor, I: Inventory>(
+    principal: &Principal, order: &OrderEntity, command: OrderCommand,
+    shipping_calculator: &S, tax_calculator: &T, payment_processor: &P, inventory: &I
+) -> Result<OrderEntityCommand, &'static str> {
 
+    principal.authorize_order(order)?;
+    if let Some(cart) = command.checkout_cart(order.get_state()) {
+        inventory.reserve_stock(command.order_id(), cart)?;
+    }
+    command.entity_command(principal, order, shipping_calculator, tax_calculator, payment_processor)
+}
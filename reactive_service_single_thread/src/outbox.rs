@@ -0,0 +1,65 @@
+use std::fs::{File, OpenOptions};
+use std::io::{Stdout, Write};
+use std::path::Path;
+use std::sync::mpsc;
+
+pub use reactive_service_application::outbox::{OutboxMessage, OutboxRelayConfig, ParkedMessage};
+
+/// Where the outbox relay publishes the events to other services, e.g. a message broker.
+///
+/// The delivery is at least once: a message is published again when the relay fails before recording it is.
+/// The consumers deduplicate the messages on their position.
+pub trait MessagePublisher {
+    /// Publish the message, once it is durably received: an error is retried with a backoff.
+    fn publish(&mut self, message: &OutboxMessage) -> Result<(), &'static str>;
+}
+
+/// Publishes to the consumers of the same process, through a channel.
+pub struct InProcessPublisher {
+    sender: mpsc::Sender<OutboxMessage>,
+}
+
+impl InProcessPublisher {
+    /// The publisher, and the receiver of its messages.
+    pub fn new() -> (Self, mpsc::Receiver<OutboxMessage>) {
+        let (sender, receiver) = mpsc::channel();
+        (Self { sender }, receiver)
+    }
+}
+
+impl MessagePublisher for InProcessPublisher {
+    fn publish(&mut self, message: &OutboxMessage) -> Result<(), &'static str> {
+        self.sender.send(message.clone()).map_err(|_| "Failed to publish message")
+    }
+}
+
+/// Publishes each message as a line of JSON, flushed before the message is acknowledged.
+pub struct JsonLinesPublisher<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> JsonLinesPublisher<W> {
+    pub fn new(writer: W) -> Self {
+        Self { writer }
+    }
+}
+
+impl JsonLinesPublisher<Stdout> {
+    pub fn stdout() -> Self {
+        Self::new(std::io::stdout())
+    }
+}
+
+impl JsonLinesPublisher<File> {
+    /// Append the messages to the file, created if needed.
+    pub fn file(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        Ok(Self::new(OpenOptions::new().create(true).append(true).open(path)?))
+    }
+}
+
+impl<W: Write> MessagePublisher for JsonLinesPublisher<W> {
+    fn publish(&mut self, message: &OutboxMessage) -> Result<(), &'static str> {
+        writeln!(self.writer, "{}", message.to_json_line()).map_err(|_| "Failed to publish message")?;
+        self.writer.flush().map_err(|_| "Failed to publish message")
+    }
+}
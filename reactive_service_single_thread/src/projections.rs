@@ -0,0 +1,117 @@
+use std::time::Duration;
+use reactive_service_domain::order_entity::OrderEvent;
+use crate::catch_up::{CatchUpConfig, CatchUpSubscription};
+use crate::order_service::{GlobalEventsJournal, Positioned};
+
+pub use reactive_service_application::projections::{
+    CheckpointedModel, OrderStatus, OrderSummaries, OrderSummary, ReadModel, SalesBySku, SkuSales
+};
+
+/// A view of the events of the journal, kept up to date by a `Projector`.
+///
+/// The view is stored with its checkpoint, the position of the last event applied to it:
+/// a batch of events and the checkpoint are saved together, all or nothing.
+/// The events at or before the checkpoint are skipped: an event delivered again, e.g. after a failure, is applied once.
+pub trait Projection<E> {
+    /// Position of the last event applied to the view, 0 when it is empty.
+    fn checkpoint(&mut self) -> Result<i64, &'static str>;
+
+    /// Apply the events after the checkpoint, in their order, and move the checkpoint to the last one.
+    fn apply(&mut self, events: &[Positioned<E>]) -> Result<(), &'static str>;
+
+    /// Empty the view and its checkpoint, to rebuild it from the start of the journal.
+    fn reset(&mut self) -> Result<(), &'static str>;
+}
+
+/// A `ReadModel` kept in memory, with its checkpoint.
+#[derive(Default)]
+pub struct InMemoryProjection<M> {
+    view: CheckpointedModel<M>,
+}
+
+impl<M: ReadModel> InMemoryProjection<M> {
+    pub fn new() -> Self {
+        Self { view: CheckpointedModel::default() }
+    }
+
+    /// The view, as of its checkpoint.
+    pub fn view(&self) -> &M {
+        self.view.model()
+    }
+}
+
+impl<M: ReadModel> Projection<OrderEvent> for InMemoryProjection<M> {
+    fn checkpoint(&mut self) -> Result<i64, &'static str> {
+        Ok(self.view.checkpoint())
+    }
+
+    fn apply(&mut self, events: &[Positioned<OrderEvent>]) -> Result<(), &'static str> {
+        self.view.apply(events);
+        Ok(())
+    }
+
+    fn reset(&mut self) -> Result<(), &'static str> {
+        *self = Self::new();
+        Ok(())
+    }
+}
+
+/// Applies the events of a journal to the projection it owns, by batches, from its checkpoint:
+/// the history first, then the new events as they are persisted.
+/// As the `CatchUpSubscription`, the journal is given on each call.
+pub struct Projector<E, P: Projection<E>> {
+    projection: P,
+    config: CatchUpConfig,
+    subscription: CatchUpSubscription<E>,
+}
+
+impl<E, P: Projection<E>> Projector<E, P> {
+    /// Follow the journal from the checkpoint of the projection.
+    /// The batches are up to `config.batch_size` events, each one applied at once.
+    pub fn new(mut projection: P, config: CatchUpConfig) -> Result<Self, &'static str> {
+        let subscription = CatchUpSubscription::with_config(projection.checkpoint()?, config.clone());
+        Ok(Self { projection, config, subscription })
+    }
+
+    pub fn projection(&mut self) -> &mut P {
+        &mut self.projection
+    }
+
+    /// Empty the view, then apply every event of the journal again. Returns the number of events applied.
+    pub fn rebuild<J: GlobalEventsJournal<E>>(&mut self, journal: &mut J) -> Result<usize, &'static str> {
+        self.projection.reset()?;
+        self.subscription = CatchUpSubscription::with_config(0, self.config.clone());
+        self.catch_up(journal)
+    }
+
+    /// Apply the events persisted so far. Returns their number.
+    pub fn catch_up<J: GlobalEventsJournal<E>>(&mut self, journal: &mut J) -> Result<usize, &'static str> {
+        let mut applied = 0;
+        loop {
+            match self.project(journal, Duration::ZERO)? {
+                0 => return Ok(applied),
+                count => applied += count,
+            }
+        }
+    }
+
+    /// Apply the next batch of events, once there is one within `timeout`. Returns the number of events in the batch.
+    pub fn project<J: GlobalEventsJournal<E>>(&mut self, journal: &mut J, timeout: Duration) -> Result<usize, &'static str> {
+        let Some(first) = self.subscription.recv_timeout(journal, timeout)? else { return Ok(0) };
+        let first_position = first.position;
+        let mut batch = vec![first];
+        while batch.len() < self.config.batch_size {
+            match self.subscription.recv_timeout(journal, Duration::ZERO)? {
+                Some(positioned) => batch.push(positioned),
+                None => break,
+            }
+        }
+
+        if let Err(err) = self.projection.apply(&batch) {
+            // Delivered again on the next call
+            self.subscription = CatchUpSubscription::with_config(first_position - 1, self.config.clone());
+            return Err(err);
+        }
+        Ok(batch.len())
+    }
+}
@@ -0,0 +1,156 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    use reactive_service_domain::aggregate_root::SequencedEvent;
+    use reactive_service_domain::order_entity::OrderEvent;
+    use reactive_service_single_thread::catch_up::{CatchUpConfig, CatchUpSubscription, CheckpointStore, InMemoryCheckpointStore};
+    use reactive_service_single_thread::infra::inmem_journal::InMemoryJournal;
+    use reactive_service_single_thread::infra::postgres_events_store::PostgresEventStore;
+    use reactive_service_single_thread::infra::sqlite_event_store::SqliteEventStore;
+    use reactive_service_single_thread::order_service::{EventsJournal, GlobalEventsJournal, OrderService, Principal};
+    use reactive_service_single_thread::payment_processor::LocalPaymentProcessor;
+    use reactive_service_single_thread::shipping_calculator::LocalShippingCalculator;
+    use reactive_service_single_thread::tax_calculator::LocalTaxCalculator;
+    use crate::common::update_cart;
+
+    fn service<E: EventsJournal<OrderEvent>>(journal: E)
+        -> OrderService<E, LocalShippingCalculator, LocalTaxCalculator, LocalPaymentProcessor> {
+        OrderService::new(journal, LocalShippingCalculator{}, LocalTaxCalculator{}, LocalPaymentProcessor{})
+    }
+
+    fn sequenced(sequence_number: i64) -> SequencedEvent<String> {
+        SequencedEvent { sequence_number, event: format!("event {}", sequence_number) }
+    }
+
+    /// Unique across the runs, for the shared database.
+    fn unique_id() -> i64 {
+        SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_nanos() as i64
+    }
+
+    fn small_batches() -> CatchUpConfig {
+        CatchUpConfig { batch_size: 2, ..CatchUpConfig::default() }
+    }
+
+    /// Polled between the commands, without blocking.
+    #[test]
+    fn follows_the_journal_of_the_service() {
+        let mut service = service(InMemoryJournal::new().unwrap());
+        let mut subscription = CatchUpSubscription::with_config(0, small_batches());
+        for order_id in 1..=3 {
+            service.update_cart(&Principal::Admin, update_cart(order_id)).unwrap();
+        }
+
+        for order_id in 1..=3 {
+            let positioned = subscription.recv_timeout(service.events_journal(), Duration::ZERO).unwrap().expect("Missing event");
+            assert_eq!(positioned.entity_id, order_id);
+            assert_eq!(positioned.position, subscription.position());
+        }
+        assert!(subscription.recv_timeout(service.events_journal(), Duration::ZERO).unwrap().is_none());
+
+        service.update_cart(&Principal::Admin, update_cart(1)).unwrap();
+        let positioned = subscription.recv_timeout(service.events_journal(), Duration::ZERO).unwrap().expect("Missing event");
+        assert_eq!((positioned.entity_id, positioned.event.sequence_number, positioned.position), (1, 2, 4));
+    }
+
+    /// Another connection to the database catches up, then gets the writes of the service's connection.
+    #[test]
+    fn sqlite_event_store_follows_another_connection() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("events.db");
+        let mut writer = SqliteEventStore::new(&path).unwrap();
+        let mut reader = SqliteEventStore::new(&path).unwrap();
+        for sequence_number in 1..=5 {
+            writer.persist_event(1, &sequenced(sequence_number)).unwrap();
+        }
+
+        let mut subscription = CatchUpSubscription::<String>::with_config(0, small_batches());
+        thread::scope(|scope| {
+            scope.spawn(|| {
+                thread::sleep(Duration::from_millis(50));
+                for sequence_number in 6..=10 {
+                    writer.persist_event(1, &sequenced(sequence_number)).unwrap();
+                }
+            });
+
+            for sequence_number in 1..=10 {
+                let positioned = subscription.recv_timeout(&mut reader, Duration::from_secs(5)).unwrap().expect("Missing event");
+                assert_eq!(positioned.event.sequence_number, sequence_number);
+                assert_eq!(positioned.position, sequence_number);
+            }
+        });
+        assert!(subscription.recv_timeout(&mut reader, Duration::from_millis(20)).unwrap().is_none());
+    }
+
+    /// The notification of another connection wakes up the subscription.
+    #[test]
+    fn postgres_event_store_notifies_the_new_events() {
+        let mut reader = PostgresEventStore::new().unwrap();
+        let mut service = service(PostgresEventStore::new().unwrap());
+        let order_id = unique_id();
+
+        let start = GlobalEventsJournal::<OrderEvent>::last_position(&mut reader).unwrap();
+        // A wait longer than the test: only a notification gets the event in time
+        let config = CatchUpConfig { max_wait: Duration::from_secs(30), ..CatchUpConfig::default() };
+        let mut subscription = CatchUpSubscription::<OrderEvent>::with_config(start, config);
+        thread::scope(|scope| {
+            scope.spawn(move || {
+                thread::sleep(Duration::from_millis(200));
+                service.update_cart(&Principal::Admin, update_cart(order_id)).unwrap();
+            });
+
+            let positioned = subscription.recv_timeout(&mut reader, Duration::from_secs(10)).unwrap().expect("Missing event");
+            assert_eq!(positioned.entity_id, order_id);
+            assert!(matches!(positioned.event.event, OrderEvent::UpdatedCart { .. }));
+        });
+    }
+
+    #[test]
+    fn resumes_after_the_checkpoint() {
+        let mut service = service(InMemoryJournal::new().unwrap());
+        for order_id in 1..=3 {
+            service.update_cart(&Principal::Admin, update_cart(order_id)).unwrap();
+        }
+        let mut checkpoints = InMemoryCheckpointStore::default();
+        assert_eq!(checkpoints.load_checkpoint("projection").unwrap(), 0);
+
+        let mut subscription = CatchUpSubscription::from_checkpoint(&mut checkpoints, "projection", small_batches()).unwrap();
+        assert_eq!(subscription.recv(service.events_journal()).unwrap().entity_id, 1);
+        assert_eq!(subscription.recv(service.events_journal()).unwrap().entity_id, 2);
+        subscription.save_checkpoint(&mut checkpoints, "projection").unwrap();
+        // Received but not processed: not checkpointed
+        subscription.recv(service.events_journal()).unwrap();
+
+        let mut resumed = CatchUpSubscription::from_checkpoint(&mut checkpoints, "projection", small_batches()).unwrap();
+        assert_eq!(resumed.recv(service.events_journal()).unwrap().entity_id, 3);
+        assert!(resumed.recv_timeout(service.events_journal(), Duration::ZERO).unwrap().is_none());
+    }
+
+    #[test]
+    fn sqlite_event_store_keeps_the_checkpoints() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("events.db");
+        let mut store = SqliteEventStore::new(&path).unwrap();
+        assert_eq!(store.load_checkpoint("projection").unwrap(), 0);
+        store.save_checkpoint("projection", 3).unwrap();
+        store.save_checkpoint("projection", 7).unwrap();
+        drop(store);
+
+        let mut store = SqliteEventStore::new(&path).unwrap();
+        assert_eq!(store.load_checkpoint("projection").unwrap(), 7);
+        assert_eq!(store.load_checkpoint("other").unwrap(), 0);
+    }
+
+    #[test]
+    fn postgres_event_store_keeps_the_checkpoints() {
+        let mut store = PostgresEventStore::new().unwrap();
+        let consumer = format!("projection-{}", unique_id());
+        assert_eq!(store.load_checkpoint(&consumer).unwrap(), 0);
+        store.save_checkpoint(&consumer, 3).unwrap();
+        store.save_checkpoint(&consumer, 7).unwrap();
+        assert_eq!(store.load_checkpoint(&consumer).unwrap(), 7);
+    }
+}
@@ -0,0 +1,29 @@
+//! The helpers shared by the tests of the runtime.
+#![allow(dead_code)]
+
+use std::collections::HashMap;
+
+use reactive_service_domain::non_empty_cart::{NonEmptyCart, Quantity, Sku};
+use reactive_service_domain::order_entity::OrderEvent;
+use reactive_service_single_thread::infra::inmem_journal::InMemoryJournal;
+use reactive_service_single_thread::order_service::{OrderService, UpdateCart};
+use reactive_service_single_thread::payment_processor::LocalPaymentProcessor;
+use reactive_service_single_thread::shipping_calculator::LocalShippingCalculator;
+use reactive_service_single_thread::tax_calculator::LocalTaxCalculator;
+
+pub type Service = OrderService<InMemoryJournal<OrderEvent>, LocalShippingCalculator, LocalTaxCalculator, LocalPaymentProcessor>;
+
+/// An order service over an in-memory journal, with the local ports.
+pub fn service() -> Service {
+    OrderService::new(InMemoryJournal::new().unwrap(), LocalShippingCalculator{}, LocalTaxCalculator{}, LocalPaymentProcessor{})
+}
+
+/// A cart of one apple for the order.
+pub fn update_cart(order_id: i64) -> UpdateCart {
+    update_cart_of(order_id, 1)
+}
+
+/// A cart of `quantity` apples for the order.
+pub fn update_cart_of(order_id: i64, quantity: u16) -> UpdateCart {
+    UpdateCart { order_id, cart: NonEmptyCart::new(HashMap::from([(Sku("apple".to_owned()), Quantity(quantity))])).unwrap() }
+}
@@ -0,0 +1,75 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use reactive_service_domain::customer::{CustomerCommand, CustomerEvent, Profile};
+    use reactive_service_domain::order_state::{DeliveryAddress, OrderState, Street};
+    use reactive_service_single_thread::customer_service::CustomerService;
+    use reactive_service_single_thread::event_loop::{EventLoopConfig, OrderServiceEventLoop};
+    use reactive_service_single_thread::infra::inmem_journal::InMemoryJournal;
+    use reactive_service_single_thread::order_service::{OrderCommand, PayOrder, Principal, UpdateDeliveryAddress};
+    use reactive_service_single_thread::payment_processor::PaymentToken;
+    use crate::common::{service, update_cart};
+
+    const JANE: Principal = Principal::Customer(1);
+    const JOHN: Principal = Principal::Customer(2);
+
+    fn update_delivery_address(order_id: i64) -> OrderCommand {
+        let delivery_address = DeliveryAddress { street: Street("1 Main Street".to_owned()), postal_code: "H0H 0H0".parse().unwrap() };
+        UpdateDeliveryAddress { order_id, delivery_address }.into()
+    }
+
+    fn pay_order(order_id: i64) -> OrderCommand {
+        PayOrder { order_id, payment_token: PaymentToken::new("token") }.into()
+    }
+
+    #[test]
+    fn only_the_owner_or_an_admin_changes_an_order() {
+        let mut service = service();
+        service.handle_as(&JANE, update_cart(1).into()).unwrap();
+
+        assert_eq!(service.handle_as(&JOHN, update_delivery_address(1)).err(), Some("Not the owner of the order"));
+        service.handle_as(&JANE, update_delivery_address(1)).unwrap();
+        service.handle_as(&Principal::Admin, pay_order(1)).unwrap();
+        assert!(matches!(service.get_state(1).unwrap(), OrderState::Completed(_)));
+    }
+
+    #[test]
+    fn a_customer_cant_take_over_an_order_created_by_an_admin() {
+        let mut service = service();
+        service.handle_as_admin(update_cart(1).into()).unwrap();
+
+        assert_eq!(service.handle_as(&JANE, update_cart(1).into()).err(), Some("Not the owner of the order"));
+        service.handle_as_admin(update_delivery_address(1)).unwrap();
+    }
+
+    #[test]
+    fn a_batch_authorizes_each_command_for_its_principal() {
+        let mut service = service();
+        let results = service.handle_batch(vec![(JANE, update_cart(1).into()), (JOHN, update_cart(1).into()), (Principal::Admin, update_cart(1).into())]);
+
+        assert!(results[0].is_ok());
+        assert_eq!(results[1].as_ref().err(), Some(&"Not the owner of the order"));
+        assert!(results[2].is_ok());
+    }
+
+    #[test]
+    fn the_event_loop_only_lets_the_owner_change_an_order() {
+        let event_loop = OrderServiceEventLoop::spawn(service(), EventLoopConfig::default());
+        event_loop.handle_as(&JANE, update_cart(1).into()).unwrap();
+
+        assert_eq!(event_loop.handle_as(&JOHN, update_delivery_address(1)).err(), Some("Not the owner of the order"));
+        event_loop.handle_as(&JANE, update_delivery_address(1)).unwrap();
+    }
+
+    #[test]
+    fn a_customer_only_manages_their_own_profile() {
+        let mut customers = CustomerService::new(InMemoryJournal::<CustomerEvent>::new().unwrap());
+        let profile = Profile { name: "Jane".to_owned(), email: "jane@example.com".to_owned() };
+
+        customers.handle(&JANE, 1, CustomerCommand::Register { profile: profile.clone() }).unwrap();
+        assert_eq!(customers.get_customer(&JOHN, 1).err(), Some("Not the customer"));
+        assert_eq!(customers.get_customer(&Principal::Admin, 1).unwrap().state.profile, Some(profile.clone()));
+        assert_eq!(customers.handle(&JOHN, 1, CustomerCommand::UpdateProfile { profile }).err(), Some("Not the customer"));
+    }
+}
@@ -0,0 +1,97 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use reactive_service_domain::aggregate_root::SequencedEvent;
+    use reactive_service_domain::order_entity::OrderEvent;
+    use reactive_service_single_thread::event_bus::EventBus;
+    use reactive_service_single_thread::event_loop::{EventLoopConfig, OrderServiceEventLoop};
+    use reactive_service_single_thread::infra::inmem_journal::InMemoryJournal;
+    use reactive_service_single_thread::order_service::{EventsJournal, OrderCommand, OrderService, Principal, SubscriptionError};
+    use reactive_service_single_thread::payment_processor::LocalPaymentProcessor;
+    use reactive_service_single_thread::shipping_calculator::LocalShippingCalculator;
+    use reactive_service_single_thread::tax_calculator::LocalTaxCalculator;
+    use crate::common::update_cart;
+
+    fn service<E: EventsJournal<OrderEvent>>(journal: E)
+        -> OrderService<E, LocalShippingCalculator, LocalTaxCalculator, LocalPaymentProcessor> {
+        OrderService::new(journal, LocalShippingCalculator{}, LocalTaxCalculator{}, LocalPaymentProcessor{})
+    }
+
+    /// Fails to persist anything, and has no events.
+    struct FailingJournal;
+
+    impl EventsJournal<OrderEvent> for FailingJournal {
+        fn persist_event(&mut self, _: i64, _: &SequencedEvent<OrderEvent>) -> Result<(), &'static str> {
+            Err("Failed to persist event")
+        }
+
+        fn retrieve_events(&mut self, _: i64) -> Result<Vec<SequencedEvent<OrderEvent>>, &'static str> {
+            Ok(vec![])
+        }
+
+        fn persist_events(&mut self, _: &[(i64, SequencedEvent<OrderEvent>)]) -> Result<(), &'static str> {
+            Err("Failed to persist event")
+        }
+    }
+
+    #[test]
+    fn publishes_the_committed_events() {
+        let mut service = service(InMemoryJournal::new().unwrap());
+        let all_orders = service.event_bus().subscribe_all();
+        let order_2 = service.event_bus().subscribe(2);
+
+        service.update_cart(&Principal::Admin, update_cart(1)).unwrap();
+        service.update_cart(&Principal::Admin, update_cart(2)).unwrap();
+        service.handle_batch(vec![
+            (Principal::Admin, OrderCommand::UpdateCart(update_cart(2))), (Principal::Admin, OrderCommand::UpdateCart(update_cart(1)))
+        ]);
+
+        let received: Vec<(i64, i64)> = (0..4)
+            .map(|_| all_orders.recv().unwrap())
+            .map(|committed| (committed.entity_id, committed.event.sequence_number))
+            .collect();
+        assert_eq!(received, vec![(1, 1), (2, 1), (2, 2), (1, 2)]);
+
+        assert_eq!(order_2.recv().unwrap().event.sequence_number, 1);
+        assert_eq!(order_2.recv().unwrap().event.sequence_number, 2);
+        assert!(order_2.recv_timeout(Duration::from_millis(10)).unwrap().is_none());
+    }
+
+    #[test]
+    fn only_publishes_once_persisted() {
+        let mut service = service(FailingJournal);
+        let all_orders = service.event_bus().subscribe_all();
+
+        assert!(service.update_cart(&Principal::Admin, update_cart(1)).is_err());
+        assert!(service.handle_batch(vec![(Principal::Admin, OrderCommand::UpdateCart(update_cart(1)))])[0].is_err());
+        assert!(all_orders.recv_timeout(Duration::from_millis(10)).unwrap().is_none());
+    }
+
+    #[test]
+    fn reports_the_events_missed_by_a_slow_subscriber() {
+        let event_bus = EventBus::new(2);
+        let subscription = event_bus.subscribe_all();
+
+        let events: Vec<SequencedEvent<u32>> = (1..=5).map(|n| SequencedEvent { sequence_number: n, event: 0 }).collect();
+        event_bus.publish(1, &events);
+
+        assert_eq!(subscription.recv().err(), Some(SubscriptionError::Lagged(3)));
+        assert_eq!(subscription.recv().unwrap().event.sequence_number, 4);
+        assert_eq!(subscription.recv().unwrap().event.sequence_number, 5);
+    }
+
+    #[test]
+    fn closes_the_subscriptions_with_the_event_loop() {
+        let event_loop = OrderServiceEventLoop::spawn(service(InMemoryJournal::new().unwrap()), EventLoopConfig::default());
+        let subscription = event_loop.event_bus().subscribe(1);
+
+        event_loop.update_cart(&Principal::Admin, update_cart(1)).unwrap();
+        drop(event_loop);
+
+        assert_eq!(subscription.recv().unwrap().event.sequence_number, 1);
+        assert_eq!(subscription.recv().err(), Some(SubscriptionError::Closed));
+    }
+}
@@ -0,0 +1,186 @@
+#[cfg(test)]
+mod tests {
+    use std::fs::{self, OpenOptions};
+    use std::io::Write;
+    use std::path::{Path, PathBuf};
+    use std::time::{Duration, Instant};
+
+    use reactive_service_domain::aggregate_root::SequencedEvent;
+    use reactive_service_single_thread::infra::file_journal::{FileJournal, FileJournalConfig, FsyncPolicy};
+    use reactive_service_single_thread::order_service::{EventsJournal, GlobalEventsJournal};
+
+    fn sequenced(sequence_number: i64) -> SequencedEvent<String> {
+        SequencedEvent { sequence_number, event: format!("event {}", sequence_number) }
+    }
+
+    fn sequence_numbers(journal: &mut FileJournal, entity_id: i64) -> Vec<i64> {
+        let events: Vec<SequencedEvent<String>> = journal.retrieve_events(entity_id).unwrap();
+        events.iter().map(|e| e.sequence_number).collect()
+    }
+
+    fn segments(directory: &Path) -> Vec<PathBuf> {
+        let mut segments: Vec<PathBuf> = fs::read_dir(directory).unwrap().map(|e| e.unwrap().path()).collect();
+        segments.sort();
+        segments
+    }
+
+    #[test]
+    fn retrieves_the_events_of_an_order_in_sequence_order() {
+        let directory = tempfile::tempdir().unwrap();
+        let mut journal = FileJournal::new(directory.path()).unwrap();
+
+        journal.persist_event(1, &sequenced(2)).unwrap();
+        journal.persist_event(2, &sequenced(1)).unwrap();
+        journal.persist_events(&[(1, sequenced(1)), (1, sequenced(3))]).unwrap();
+
+        let events: Vec<SequencedEvent<String>> = journal.retrieve_events(1).unwrap();
+        assert_eq!(events.iter().map(|e| e.sequence_number).collect::<Vec<_>>(), vec![1, 2, 3]);
+        assert_eq!(events[1].event, "event 2");
+        assert_eq!(sequence_numbers(&mut journal, 2), vec![1]);
+        assert!(sequence_numbers(&mut journal, 3).is_empty());
+    }
+
+    #[test]
+    fn rejects_duplicate_sequence_numbers_without_writing_the_batch() {
+        let directory = tempfile::tempdir().unwrap();
+        let mut journal = FileJournal::new(directory.path()).unwrap();
+
+        journal.persist_event(1, &sequenced(1)).unwrap();
+        assert!(journal.persist_event(1, &sequenced(1)).is_err());
+        assert!(journal.persist_events(&[(2, sequenced(1)), (1, sequenced(1))]).is_err());
+        assert!(journal.persist_events(&[(3, sequenced(1)), (3, sequenced(1))]).is_err());
+
+        assert_eq!(sequence_numbers(&mut journal, 1), vec![1]);
+        assert!(sequence_numbers(&mut journal, 2).is_empty());
+        assert!(sequence_numbers(&mut journal, 3).is_empty());
+    }
+
+    #[test]
+    fn rebuilds_the_index_when_reopened() {
+        let directory = tempfile::tempdir().unwrap();
+        let config = FileJournalConfig {
+            directory: directory.path().to_owned(),
+            segment_size: 256,
+            fsync: FsyncPolicy::Batched { max_writes: 10, max_delay: Duration::from_millis(10) },
+        };
+        {
+            let mut journal = FileJournal::with_config(config.clone()).unwrap();
+            for sequence_number in 1..=50 {
+                journal.persist_event(sequence_number % 3, &sequenced(sequence_number)).unwrap();
+            }
+        }
+        assert!(segments(directory.path()).len() > 1);
+
+        let mut journal = FileJournal::with_config(config).unwrap();
+        assert_eq!(sequence_numbers(&mut journal, 0), (1..=50).filter(|n| n % 3 == 0).collect::<Vec<_>>());
+        assert_eq!(sequence_numbers(&mut journal, 1).len(), 17);
+        // Still rejected after the restart
+        assert!(journal.persist_event(1, &sequenced(1)).is_err());
+        journal.persist_event(1, &sequenced(51)).unwrap();
+        assert_eq!(sequence_numbers(&mut journal, 1).last(), Some(&51));
+    }
+
+    #[test]
+    fn truncates_a_torn_write_when_reopened() {
+        let directory = tempfile::tempdir().unwrap();
+        {
+            let mut journal = FileJournal::new(directory.path()).unwrap();
+            journal.persist_events(&[(1, sequenced(1)), (1, sequenced(2))]).unwrap();
+        }
+        let segment = segments(directory.path()).pop().unwrap();
+        let valid_size = fs::metadata(&segment).unwrap().len();
+
+        // A crash in the middle of a record: its header and part of its payload
+        let mut file = OpenOptions::new().append(true).open(&segment).unwrap();
+        file.write_all(&[40, 0, 0, 0, 1, 2, 3, 4, 1, 0, 0]).unwrap();
+        drop(file);
+
+        let mut journal = FileJournal::new(directory.path()).unwrap();
+        assert_eq!(fs::metadata(&segment).unwrap().len(), valid_size);
+        assert_eq!(sequence_numbers(&mut journal, 1), vec![1, 2]);
+
+        journal.persist_event(1, &sequenced(3)).unwrap();
+        drop(journal);
+        let mut journal = FileJournal::new(directory.path()).unwrap();
+        assert_eq!(sequence_numbers(&mut journal, 1), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn truncates_a_last_record_with_a_bad_checksum() {
+        let directory = tempfile::tempdir().unwrap();
+        {
+            let mut journal = FileJournal::new(directory.path()).unwrap();
+            journal.persist_event(1, &sequenced(1)).unwrap();
+            journal.persist_event(1, &sequenced(2)).unwrap();
+        }
+        let segment = segments(directory.path()).pop().unwrap();
+        let mut content = fs::read(&segment).unwrap();
+        *content.last_mut().unwrap() ^= 0xFF;
+        fs::write(&segment, content).unwrap();
+
+        let mut journal = FileJournal::new(directory.path()).unwrap();
+        assert_eq!(sequence_numbers(&mut journal, 1), vec![1]);
+    }
+
+    #[test]
+    fn refuses_to_open_a_segment_corrupted_before_its_last_record() {
+        let directory = tempfile::tempdir().unwrap();
+        {
+            let mut journal = FileJournal::new(directory.path()).unwrap();
+            journal.persist_event(1, &sequenced(1)).unwrap();
+            journal.persist_event(1, &sequenced(2)).unwrap();
+        }
+        let segment = segments(directory.path()).pop().unwrap();
+        let mut content = fs::read(&segment).unwrap();
+        // In the event of the first record, after its header and key
+        content[8 + 16 + 1] ^= 0xFF;
+        fs::write(&segment, &content).unwrap();
+
+        assert!(FileJournal::new(directory.path()).is_err());
+        // The acknowledged events after the corruption are kept
+        assert_eq!(fs::read(&segment).unwrap(), content);
+    }
+
+    #[test]
+    fn refuses_to_open_a_corrupted_segment_which_is_not_the_last() {
+        let directory = tempfile::tempdir().unwrap();
+        let config = FileJournalConfig { directory: directory.path().to_owned(), segment_size: 64, ..Default::default() };
+        {
+            let mut journal = FileJournal::with_config(config.clone()).unwrap();
+            for sequence_number in 1..=3 {
+                journal.persist_event(1, &sequenced(sequence_number)).unwrap();
+            }
+        }
+        let segment = segments(directory.path()).remove(0);
+        let mut content = fs::read(&segment).unwrap();
+        *content.last_mut().unwrap() ^= 0xFF;
+        fs::write(&segment, content).unwrap();
+
+        assert!(FileJournal::with_config(config).is_err());
+    }
+
+    #[test]
+    fn only_waits_for_a_position_not_written_yet() {
+        let directory = tempfile::tempdir().unwrap();
+        let mut journal = FileJournal::new(directory.path()).unwrap();
+        journal.persist_events(&[(1, sequenced(1)), (2, sequenced(1))]).unwrap();
+
+        let start = Instant::now();
+        GlobalEventsJournal::<String>::wait_for_position(&mut journal, 2, Duration::from_secs(5)).unwrap();
+        assert!(start.elapsed() < Duration::from_secs(1));
+
+        let start = Instant::now();
+        GlobalEventsJournal::<String>::wait_for_position(&mut journal, 3, Duration::from_millis(50)).unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(50));
+    }
+
+    #[test]
+    fn reports_events_that_cant_be_deserialized() {
+        let directory = tempfile::tempdir().unwrap();
+        let mut journal = FileJournal::new(directory.path()).unwrap();
+        journal.persist_event(1, &sequenced(1)).unwrap();
+
+        let result: Result<Vec<SequencedEvent<i64>>, &'static str> = journal.retrieve_events(1);
+        assert_eq!(result.err(), Some("Failed to deserialize event"));
+    }
+}
@@ -0,0 +1,42 @@
+#[cfg(test)]
+mod tests {
+    use reactive_service_single_thread::infra::file_journal::FileJournal;
+    use reactive_service_single_thread::infra::inmem_journal::InMemoryJournal;
+    use reactive_service_single_thread::infra::postgres_events_store::PostgresEventStore;
+    use reactive_service_single_thread::infra::sqlite_event_store::SqliteEventStore;
+    use reactive_service_single_thread::journal_conformance::{check_deserialization_failure, check_global_order, check_journal};
+
+    #[test]
+    fn in_memory_journal() {
+        let mut journal = InMemoryJournal::new().unwrap();
+        check_journal(&mut journal);
+        check_global_order(&mut journal);
+    }
+
+    #[test]
+    fn file_journal() {
+        let directory = tempfile::tempdir().unwrap();
+        let mut journal = FileJournal::new(directory.path()).unwrap();
+        check_journal(&mut journal);
+        check_global_order(&mut journal);
+        check_deserialization_failure(&mut journal);
+    }
+
+    #[test]
+    fn sqlite_event_store() {
+        let directory = tempfile::tempdir().unwrap();
+        let mut journal = SqliteEventStore::new(directory.path().join("events.db")).unwrap();
+        check_journal(&mut journal);
+        check_global_order(&mut journal);
+        check_deserialization_failure(&mut journal);
+    }
+
+    #[test]
+    #[ignore = "needs a PostgreSQL server on localhost"]
+    fn postgres_event_store() {
+        let mut journal = PostgresEventStore::new().unwrap();
+        check_journal(&mut journal);
+        check_global_order(&mut journal);
+        check_deserialization_failure(&mut journal);
+    }
+}
@@ -0,0 +1,69 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use reactive_service_single_thread::middleware::{
+        CommandHandler, CommandLatencies, CommandTrace, LatencyLayer, Layer, Pipeline, TraceLayer
+    };
+    use reactive_service_single_thread::order_service::{CommandResult, OrderCommand, PayOrder, Principal};
+    use reactive_service_single_thread::payment_processor::PaymentToken;
+    use crate::common::{service, update_cart};
+
+    /// Rejects the commands of the orders above a limit, without calling the handler.
+    struct MaxOrderId(i64);
+
+    struct Validated<H> { inner: H, max: i64 }
+
+    impl<H: CommandHandler> Layer<H> for MaxOrderId {
+        type Handler = Validated<H>;
+
+        fn layer(self, inner: H) -> Self::Handler {
+            Validated { inner, max: self.0 }
+        }
+    }
+
+    impl<H: CommandHandler> CommandHandler for Validated<H> {
+        fn call(&mut self, principal: &Principal, command: OrderCommand) -> CommandResult {
+            if command.order_id() > self.max {
+                return Err("Unknown order");
+            }
+            self.inner.call(principal, command)
+        }
+    }
+
+    #[test]
+    fn traces_and_measures_the_commands() {
+        let traces = Rc::new(RefCell::new(Vec::<CommandTrace>::new()));
+        let latencies = Rc::new(CommandLatencies::default());
+        let recorded = traces.clone();
+        let mut pipeline = Pipeline::new(service())
+            .layer(LatencyLayer::new(latencies.clone()))
+            .layer(TraceLayer::new(move |trace: &CommandTrace| recorded.borrow_mut().push(trace.clone())));
+
+        pipeline.handle_as(&Principal::Customer(3), update_cart(1).into()).unwrap();
+        let pay_order = OrderCommand::PayOrder(PayOrder { order_id: 1, payment_token: PaymentToken::new("token") });
+        assert!(pipeline.handle_as(&Principal::Admin, pay_order).is_err());
+
+        let outcomes: Vec<_> = traces.borrow().iter().map(|trace| (trace.command, trace.customer_id, trace.outcome)).collect();
+        assert_eq!(outcomes, vec![("update_cart", Some(3), Ok(1)), ("pay_order", None, Err("Order not ready to be paid."))]);
+        assert_eq!(latencies.get("update_cart").map(|histogram| (histogram.count(), histogram.errors())), Some((1, 0)));
+        assert_eq!(latencies.get("pay_order").map(|histogram| (histogram.count(), histogram.errors())), Some((1, 1)));
+    }
+
+    #[test]
+    fn the_outer_layers_see_the_commands_first() {
+        let latencies = Rc::new(CommandLatencies::default());
+        let mut service = service();
+        let mut inner_validation = Pipeline::new(&mut service).layer(MaxOrderId(10)).layer(LatencyLayer::new(latencies.clone()));
+        assert_eq!(inner_validation.handle_as(&Principal::Admin, update_cart(11).into()).err(), Some("Unknown order"));
+        assert_eq!(latencies.get("update_cart").map(|histogram| histogram.errors()), Some(1));
+
+        let mut outer_validation = Pipeline::new(&mut service).layer(LatencyLayer::new(latencies.clone())).layer(MaxOrderId(10));
+        assert_eq!(outer_validation.handle_as(&Principal::Admin, update_cart(11).into()).err(), Some("Unknown order"));
+        assert!(outer_validation.handle_as(&Principal::Admin, update_cart(1).into()).is_ok());
+        assert_eq!(latencies.get("update_cart").map(|histogram| histogram.count()), Some(2));
+    }
+}
@@ -0,0 +1,161 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+    use reactive_service_domain::aggregate_root::SequencedEvent;
+    use reactive_service_domain::non_empty_cart::Sku;
+    use reactive_service_domain::order_entity::OrderEvent;
+    use reactive_service_domain::order_state::{DeliveryAddress, Invoice, OrderState, Street};
+    use reactive_service_single_thread::event_loop::{EventLoopConfig, OrderServiceEventLoop};
+    use reactive_service_single_thread::infra::inmem_journal::InMemoryJournal;
+    use reactive_service_single_thread::order_service::{
+        EventsJournal, OrderCommand, OrderId, OrderService, PayOrder, Principal, UpdateDeliveryAddress
+    };
+    use reactive_service_single_thread::payment_processor::{PaymentProcessor, PaymentToken};
+    use reactive_service_single_thread::shipping_calculator::LocalShippingCalculator;
+    use reactive_service_single_thread::tax_calculator::LocalTaxCalculator;
+    use crate::common::update_cart_of;
+
+    type Service = OrderService<ProbedJournal, LocalShippingCalculator, LocalTaxCalculator, RecordingPayments>;
+
+    /// An in-memory journal counting the restores, which fails to persist while `failing`.
+    #[derive(Clone)]
+    struct ProbedJournal {
+        journal: Arc<Mutex<InMemoryJournal<OrderEvent>>>,
+        retrievals: Arc<AtomicUsize>,
+        failing: Arc<AtomicBool>,
+    }
+
+    impl EventsJournal<OrderEvent> for ProbedJournal {
+        fn persist_event(&mut self, entity_id: OrderId, evt_w_seq: &SequencedEvent<OrderEvent>) -> Result<(), &'static str> {
+            if self.failing.load(Ordering::SeqCst) {
+                return Err("Failed to persist event");
+            }
+            self.journal.lock().unwrap().persist_event(entity_id, evt_w_seq)
+        }
+
+        fn retrieve_events(&mut self, entity_id: OrderId) -> Result<Vec<SequencedEvent<OrderEvent>>, &'static str> {
+            self.retrievals.fetch_add(1, Ordering::SeqCst);
+            self.journal.lock().unwrap().retrieve_events(entity_id)
+        }
+
+        fn persist_events(&mut self, events: &[(OrderId, SequencedEvent<OrderEvent>)]) -> Result<(), &'static str> {
+            if self.failing.load(Ordering::SeqCst) {
+                return Err("Failed to persist event");
+            }
+            self.journal.lock().unwrap().persist_events(events)
+        }
+    }
+
+    /// Records the payments released.
+    #[derive(Clone, Default)]
+    struct RecordingPayments {
+        refunds: Arc<Mutex<Vec<OrderId>>>,
+    }
+
+    impl PaymentProcessor for RecordingPayments {
+        fn pay_with_token(&self, _: PaymentToken) -> Invoice {
+            Invoice{}
+        }
+
+        fn capture_payment(&self, _: OrderId) -> Result<(), &'static str> {
+            Ok(())
+        }
+
+        fn refund_payment(&self, order_id: OrderId) -> Result<(), &'static str> {
+            self.refunds.lock().unwrap().push(order_id);
+            Ok(())
+        }
+    }
+
+    fn service() -> (Service, ProbedJournal, RecordingPayments) {
+        let journal = ProbedJournal {
+            journal: Arc::new(Mutex::new(InMemoryJournal::new().unwrap())),
+            retrievals: Arc::default(),
+            failing: Arc::default(),
+        };
+        let payments = RecordingPayments::default();
+        let service = OrderService::new(journal.clone(), LocalShippingCalculator{}, LocalTaxCalculator{}, payments.clone());
+        (service, journal, payments)
+    }
+
+    fn update_delivery_address(order_id: i64) -> OrderCommand {
+        let delivery_address = DeliveryAddress { street: Street("1 Main Street".to_owned()), postal_code: "H0H 0H0".parse().unwrap() };
+        UpdateDeliveryAddress { order_id, delivery_address }.into()
+    }
+
+    fn pay_order(order_id: i64) -> OrderCommand {
+        PayOrder { order_id, payment_token: PaymentToken::new("token") }.into()
+    }
+
+    fn quantity(state: &OrderState) -> u16 {
+        let OrderState::WithCart(order) = state else { panic!("Not with a cart: {:?}", state) };
+        order.get_cart().get_items()[&Sku("apple".to_owned())].0
+    }
+
+    #[test]
+    fn replies_to_each_command_of_a_batch() {
+        let (mut service, _, _) = service();
+
+        let results = service.handle_batch(vec![
+            (Principal::Admin, update_cart_of(1, 1).into()),
+            (Principal::Admin, update_cart_of(2, 1).into()),
+            (Principal::Admin, update_cart_of(1, 2).into()),
+            (Principal::Admin, pay_order(3)),
+        ]);
+
+        // Each reply holds the state right after its command, not the one at the end of the batch
+        let (state, events) = results[0].as_ref().unwrap();
+        assert_eq!((events[0].sequence_number, quantity(state)), (1, 1));
+        let (_, events) = results[1].as_ref().unwrap();
+        assert_eq!(events[0].sequence_number, 1);
+        let (state, events) = results[2].as_ref().unwrap();
+        assert_eq!((events[0].sequence_number, quantity(state)), (2, 2));
+        assert_eq!(results[3].as_ref().err(), Some(&"Order not ready to be paid."));
+    }
+
+    #[test]
+    fn evicts_the_orders_of_a_failed_batch_and_releases_their_payments() {
+        let (mut service, journal, payments) = service();
+        service.handle_as_admin(update_cart_of(1, 1).into()).unwrap();
+        service.handle_as_admin(update_delivery_address(1)).unwrap();
+        service.handle_as_admin(update_cart_of(2, 1).into()).unwrap();
+
+        journal.failing.store(true, Ordering::SeqCst);
+        let results = service.handle_batch(vec![
+            (Principal::Admin, pay_order(1)),
+            (Principal::Admin, update_cart_of(2, 2).into()),
+            (Principal::Admin, pay_order(3)),
+        ]);
+        journal.failing.store(false, Ordering::SeqCst);
+        let retrievals = journal.retrievals.load(Ordering::SeqCst);
+
+        let errors: Vec<_> = results.iter().map(|result| result.as_ref().err().copied()).collect();
+        assert_eq!(errors, vec![Some("Failed to persist event"), Some("Failed to persist event"), Some("Order not ready to be paid.")]);
+        assert_eq!(*payments.refunds.lock().unwrap(), vec![1]);
+
+        // Restored from the journal, without the events of the failed batch
+        assert!(matches!(service.get_state(1).unwrap(), OrderState::WithAddress(_)));
+        assert_eq!(quantity(service.get_state(2).unwrap()), 1);
+        assert_eq!(journal.retrievals.load(Ordering::SeqCst), retrievals + 2);
+        let (state, events) = service.handle_as_admin(pay_order(1)).unwrap();
+        assert_eq!((events[0].sequence_number, matches!(state, OrderState::Completed(_))), (3, true));
+    }
+
+    #[test]
+    fn the_event_loop_replies_once_the_batch_is_persisted() {
+        let (service, journal, _) = service();
+        let event_loop = OrderServiceEventLoop::spawn(service, EventLoopConfig::default());
+
+        journal.failing.store(true, Ordering::SeqCst);
+        assert_eq!(event_loop.handle_as(&Principal::Admin, update_cart_of(1, 1).into()).err(), Some("Failed to persist event"));
+        journal.failing.store(false, Ordering::SeqCst);
+
+        let (_, events) = event_loop.handle_as(&Principal::Admin, update_cart_of(1, 2).into()).unwrap();
+        assert_eq!(events[0].sequence_number, 1);
+        assert_eq!(event_loop.get_order(1).unwrap().sequence_number, 1);
+    }
+}
@@ -0,0 +1,67 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+    use std::time::Duration;
+
+    use reactive_service_domain::order_state::OrderState;
+    use reactive_service_single_thread::event_loop::{EventLoopConfig, OrderServiceEventLoop};
+    use reactive_service_single_thread::order_service::Principal;
+    use crate::common::{service, update_cart};
+
+    #[test]
+    fn returns_the_state_and_the_sequence_number() {
+        let mut service = service();
+
+        let order = service.get_order(1).unwrap();
+        assert!(matches!(order.state, OrderState::Empty(_)));
+        assert_eq!(order.sequence_number, 0);
+
+        service.update_cart(&Principal::Admin, update_cart(1)).unwrap();
+        service.update_cart(&Principal::Admin, update_cart(1)).unwrap();
+
+        let order = service.get_order(1).unwrap();
+        assert!(matches!(order.state, OrderState::WithCart(_)));
+        assert_eq!(order.sequence_number, 2);
+    }
+
+    #[test]
+    fn fails_on_a_version_not_reached() {
+        let mut service = service();
+        service.update_cart(&Principal::Admin, update_cart(1)).unwrap();
+
+        assert_eq!(service.get_order_at_least(1, 1).unwrap().sequence_number, 1);
+        assert_eq!(service.get_order_at_least(1, 2).err(), Some("Version not reached"));
+    }
+
+    #[test]
+    fn event_loop_waits_for_the_version() {
+        let event_loop = OrderServiceEventLoop::spawn(service(), EventLoopConfig::default());
+
+        thread::scope(|scope| {
+            let waiting = scope.spawn(|| event_loop.get_order_at_least(1, 2, Duration::from_secs(5)));
+
+            thread::sleep(Duration::from_millis(50));
+            event_loop.update_cart(&Principal::Admin, update_cart(1)).unwrap();
+            event_loop.update_cart(&Principal::Admin, update_cart(1)).unwrap();
+
+            assert_eq!(waiting.join().unwrap().unwrap().sequence_number, 2);
+        });
+
+        assert_eq!(event_loop.get_order(1).unwrap().sequence_number, 2);
+        assert_eq!(event_loop.get_order(2).unwrap().sequence_number, 0);
+    }
+
+    #[test]
+    fn event_loop_times_out_waiting_for_the_version() {
+        let event_loop = OrderServiceEventLoop::spawn(service(), EventLoopConfig::default());
+        event_loop.update_cart(&Principal::Admin, update_cart(1)).unwrap();
+
+        let result = event_loop.get_order_at_least(1, 2, Duration::from_millis(50));
+        assert_eq!(result.err(), Some("Timed out waiting for the version"));
+
+        // The other requests are still served
+        assert_eq!(event_loop.get_order_at_least(1, 1, Duration::from_millis(50)).unwrap().sequence_number, 1);
+    }
+}
@@ -0,0 +1,130 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    use reactive_service_domain::non_empty_cart::{NonEmptyCart, Quantity, Sku};
+    use reactive_service_domain::order_state::{DeliveryAddress, Street};
+    use reactive_service_single_thread::catch_up::CatchUpConfig;
+    use reactive_service_single_thread::infra::postgres_projections::{PostgresOrderSummaries, PostgresSalesBySku};
+    use reactive_service_single_thread::order_service::{GlobalEventsJournal, PayOrder, Principal, UpdateCart, UpdateDeliveryAddress};
+    use reactive_service_single_thread::payment_processor::PaymentToken;
+    use reactive_service_single_thread::projections::{
+        InMemoryProjection, OrderStatus, OrderSummaries, Projection, Projector, SalesBySku, SkuSales
+    };
+    use crate::common::{service, Service};
+
+    fn cart(sku: &str, quantity: u16) -> NonEmptyCart {
+        NonEmptyCart::new(HashMap::from([(Sku(sku.to_owned()), Quantity(quantity))])).unwrap()
+    }
+
+    /// An order with a cart, completed or not.
+    fn place_order(service: &mut Service, order_id: i64, cart: NonEmptyCart, completed: bool) {
+        service.update_cart(&Principal::Admin, UpdateCart { order_id, cart }).unwrap();
+        if completed {
+            let delivery_address = DeliveryAddress { street: Street("1 Main Street".to_owned()), postal_code: "H0H 0H0".parse().unwrap() };
+            service.update_delivery_address(&Principal::Admin, UpdateDeliveryAddress { order_id, delivery_address }).unwrap();
+            service.pay_order(&Principal::Admin, PayOrder { order_id, payment_token: PaymentToken::new("token") }).unwrap();
+        }
+    }
+
+    /// Unique across the runs, for the shared database.
+    fn unique_id() -> i64 {
+        SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_nanos() as i64
+    }
+
+    fn small_batches() -> CatchUpConfig {
+        CatchUpConfig { batch_size: 2, ..CatchUpConfig::default() }
+    }
+
+    #[test]
+    fn projects_the_history() {
+        let mut service = service();
+        place_order(&mut service, 1, cart("apple", 2), true);
+        place_order(&mut service, 2, cart("apple", 3), true);
+        place_order(&mut service, 3, cart("apple", 7), false);
+
+        let mut summaries = Projector::new(InMemoryProjection::<OrderSummaries>::new(), small_batches()).unwrap();
+        let mut sales = Projector::new(InMemoryProjection::<SalesBySku>::new(), small_batches()).unwrap();
+        assert_eq!(summaries.catch_up(service.events_journal()).unwrap(), 7);
+        assert_eq!(sales.catch_up(service.events_journal()).unwrap(), 7);
+
+        let summary = summaries.projection().view().get(1).unwrap();
+        assert_eq!((summary.status, summary.total_cents, summary.postal_code.as_deref()), (OrderStatus::Completed, 330, Some("H0H 0H0")));
+        assert_eq!(summaries.projection().view().get(3).map(|s| s.status), Some(OrderStatus::WithCart));
+        assert_eq!(sales.projection().view().get(&Sku("apple".to_owned())), Some(&SkuSales { quantity: 5, orders: 2 }));
+        assert_eq!(summaries.projection().checkpoint().unwrap(), 7);
+    }
+
+    #[test]
+    fn applies_the_redelivered_events_once() {
+        let mut service = service();
+        place_order(&mut service, 1, cart("apple", 2), true);
+        let events = service.events_journal().read_all(1, 100).unwrap();
+
+        let mut sales = InMemoryProjection::<SalesBySku>::new();
+        sales.apply(&events[..2]).unwrap();
+        sales.apply(&events).unwrap();
+        sales.apply(&events).unwrap();
+        assert_eq!(sales.view().get(&Sku("apple".to_owned())), Some(&SkuSales { quantity: 2, orders: 1 }));
+    }
+
+    /// Projected between the commands, without blocking.
+    #[test]
+    fn follows_the_journal_of_the_service_and_rebuilds() {
+        let mut service = service();
+        let mut summaries = Projector::new(InMemoryProjection::<OrderSummaries>::new(), small_batches()).unwrap();
+        place_order(&mut service, 1, cart("apple", 2), true);
+        assert_eq!(summaries.catch_up(service.events_journal()).unwrap(), 3);
+
+        place_order(&mut service, 2, cart("apple", 1), false);
+        assert_eq!(summaries.project(service.events_journal(), Duration::ZERO).unwrap(), 1);
+        assert_eq!(summaries.project(service.events_journal(), Duration::ZERO).unwrap(), 0);
+        assert_eq!(summaries.projection().view().len(), 2);
+
+        assert_eq!(summaries.rebuild(service.events_journal()).unwrap(), 4);
+        assert_eq!(summaries.projection().view().len(), 2);
+        assert_eq!(summaries.projection().checkpoint().unwrap(), 4);
+    }
+
+    /// The view tables are shared by the runs: the projection is rebuilt from a journal of its own.
+    #[test]
+    fn postgres_order_summaries() {
+        let mut service = service();
+        let order_id = unique_id();
+        place_order(&mut service, order_id, cart("apple", 1), true);
+        place_order(&mut service, order_id + 1, cart("apple", 1), false);
+
+        let mut summaries = Projector::new(PostgresOrderSummaries::new().unwrap(), small_batches()).unwrap();
+        assert_eq!(summaries.rebuild(service.events_journal()).unwrap(), 4);
+        assert_eq!(summaries.projection().checkpoint().unwrap(), 4);
+        let events = service.events_journal().read_all(1, 100).unwrap();
+        summaries.projection().apply(&events).unwrap();
+
+        let summary = summaries.projection().get(order_id).unwrap().unwrap();
+        assert_eq!((summary.status, summary.total_cents, summary.postal_code.as_deref()), (OrderStatus::Completed, 330, Some("H0H 0H0")));
+        assert_eq!(summary.last_updated, 3);
+        assert_eq!(summaries.projection().get(order_id + 1).unwrap().map(|s| s.status), Some(OrderStatus::WithCart));
+        assert_eq!(summaries.projection().get(order_id + 2).unwrap(), None);
+    }
+
+    #[test]
+    fn postgres_sales_by_sku() {
+        let mut service = service();
+        let sku = format!("apple-{}", unique_id());
+        place_order(&mut service, 1, cart(&sku, 2), true);
+        place_order(&mut service, 2, cart(&sku, 3), true);
+        place_order(&mut service, 3, cart(&sku, 7), false);
+
+        let mut sales = Projector::new(PostgresSalesBySku::new().unwrap(), small_batches()).unwrap();
+        assert_eq!(sales.rebuild(service.events_journal()).unwrap(), 7);
+        let events = service.events_journal().read_all(1, 100).unwrap();
+        sales.projection().apply(&events).unwrap();
+
+        assert_eq!(sales.projection().get(&Sku(sku.clone())).unwrap(), Some(SkuSales { quantity: 5, orders: 2 }));
+        assert_eq!(sales.rebuild(service.events_journal()).unwrap(), 7);
+        assert_eq!(sales.projection().get(&Sku(sku)).unwrap(), Some(SkuSales { quantity: 5, orders: 2 }));
+    }
+}
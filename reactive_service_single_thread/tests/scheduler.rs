@@ -0,0 +1,122 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    use reactive_service_domain::order_entity::OrderEvent;
+    use reactive_service_domain::order_state::{DeliveryAddress, OrderState, Street};
+    use reactive_service_single_thread::catch_up::InMemoryCheckpointStore;
+    use reactive_service_single_thread::infra::inmem_journal::InMemoryJournal;
+    use reactive_service_single_thread::infra::postgres_events_store::PostgresEventStore;
+    use reactive_service_single_thread::infra::sqlite_event_store::SqliteEventStore;
+    use reactive_service_single_thread::order_service::{EventsJournal, ExpireOrder, OrderService, PayOrder, Principal, UpdateDeliveryAddress};
+    use reactive_service_single_thread::payment_processor::{LocalPaymentProcessor, PaymentToken};
+    use reactive_service_single_thread::scheduler::{
+        Clock, CommandScheduler, InMemoryScheduleStore, ManualClock, Scheduled, ScheduledCommand, ScheduleStore, SchedulerConfig
+    };
+    use reactive_service_single_thread::shipping_calculator::LocalShippingCalculator;
+    use reactive_service_single_thread::tax_calculator::LocalTaxCalculator;
+    use crate::common::update_cart;
+
+    const TTL: Duration = Duration::from_secs(60);
+
+    fn service<E: EventsJournal<OrderEvent>>(journal: E) -> OrderService<E, LocalShippingCalculator, LocalTaxCalculator, LocalPaymentProcessor> {
+        OrderService::new(journal, LocalShippingCalculator{}, LocalTaxCalculator{}, LocalPaymentProcessor{})
+    }
+
+    fn config() -> SchedulerConfig {
+        SchedulerConfig { order_ttl: TTL, ..SchedulerConfig::default() }
+    }
+
+    fn expiry(order_id: i64, sequence_number: i64, due_at: SystemTime) -> Scheduled {
+        Scheduled { due_at, command: ScheduledCommand::ExpireOrder(ExpireOrder { order_id, sequence_number }) }
+    }
+
+    /// Unique across the runs, for the shared database.
+    fn unique_id() -> i64 {
+        SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_nanos() as i64
+    }
+
+    #[test]
+    fn expires_an_order_left_untouched_for_the_ttl() {
+        let mut service = service(InMemoryJournal::new().unwrap());
+        let clock = ManualClock::default();
+        let mut scheduler = CommandScheduler::new(InMemoryCheckpointStore::default(), InMemoryScheduleStore::default(), &clock, config()).unwrap();
+
+        service.update_cart(&Principal::Admin, update_cart(1)).unwrap();
+        scheduler.catch_up(service.events_journal()).unwrap();
+        clock.advance(TTL / 2);
+        service.update_cart(&Principal::Admin, update_cart(1)).unwrap();
+        scheduler.catch_up(service.events_journal()).unwrap();
+
+        // Scheduled again by the last change
+        clock.advance(TTL / 2);
+        assert_eq!(scheduler.deliver_due(&mut service).unwrap(), 0);
+        clock.advance(TTL / 2);
+        assert_eq!(scheduler.deliver_due(&mut service).unwrap(), 1);
+        assert!(matches!(service.get_order(1).unwrap().state, OrderState::Expired(_)));
+        assert_eq!(scheduler.deliver_due(&mut service).unwrap(), 0);
+    }
+
+    #[test]
+    fn a_paid_order_never_expires() {
+        let mut service = service(InMemoryJournal::new().unwrap());
+        let clock = ManualClock::default();
+        let mut scheduler = CommandScheduler::new(InMemoryCheckpointStore::default(), InMemoryScheduleStore::default(), &clock, config()).unwrap();
+
+        service.update_cart(&Principal::Admin, update_cart(1)).unwrap();
+        let delivery_address = DeliveryAddress { street: Street("1 Main Street".to_owned()), postal_code: "H0H 0H0".parse().unwrap() };
+        service.update_delivery_address(&Principal::Admin, UpdateDeliveryAddress { order_id: 1, delivery_address }).unwrap();
+        service.pay_order(&Principal::Admin, PayOrder { order_id: 1, payment_token: PaymentToken::new("token") }).unwrap();
+        scheduler.catch_up(service.events_journal()).unwrap();
+
+        // The stale expiry is rejected, then removed
+        clock.advance(TTL);
+        assert_eq!(scheduler.deliver_due(&mut service).unwrap(), 1);
+        assert!(matches!(service.get_order(1).unwrap().state, OrderState::Completed(_)));
+        assert!(scheduler.store().due(clock.now() + TTL, 10).unwrap().is_empty());
+    }
+
+    #[test]
+    fn the_scheduled_expiries_survive_a_restart() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("events.db");
+        let clock = ManualClock::default();
+        let scheduler = || CommandScheduler::new(
+            SqliteEventStore::new(&path).unwrap(), SqliteEventStore::new(&path).unwrap(), &clock, config()).unwrap();
+        {
+            let mut service = service(SqliteEventStore::new(&path).unwrap());
+            service.update_cart(&Principal::Admin, update_cart(1)).unwrap();
+            assert_eq!(scheduler().catch_up(service.events_journal()).unwrap(), 1);
+        }
+
+        let mut service = service(SqliteEventStore::new(&path).unwrap());
+        let mut scheduler = scheduler();
+        assert_eq!(scheduler.catch_up(service.events_journal()).unwrap(), 0);
+        clock.advance(TTL);
+        assert_eq!(scheduler.deliver_due(&mut service).unwrap(), 1);
+        assert!(matches!(service.get_order(1).unwrap().state, OrderState::Expired(_)));
+    }
+
+    #[test]
+    fn postgres_replaces_a_pending_command_scheduled_again() {
+        let mut store = PostgresEventStore::new().unwrap();
+        let order_id = unique_id();
+        let now = SystemTime::now();
+        let (first, second) = (expiry(order_id, 1, now), expiry(order_id, 2, now));
+
+        store.schedule(&first).unwrap();
+        store.schedule(&second).unwrap();
+        let pending = |store: &mut PostgresEventStore| store.due(now + TTL, 1_000_000).unwrap().into_iter()
+            .filter(|scheduled| scheduled.key() == first.key())
+            .map(|scheduled| scheduled.command)
+            .collect::<Vec<_>>();
+        assert_eq!(pending(&mut store), vec![second.command.clone()]);
+
+        store.remove(&first).unwrap();
+        assert_eq!(pending(&mut store).len(), 1);
+        store.remove(&second).unwrap();
+        assert!(pending(&mut store).is_empty());
+    }
+}
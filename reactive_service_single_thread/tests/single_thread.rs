@@ -1,17 +1,21 @@
 #[cfg(test)]
 mod tests {
     use std::collections::HashMap;
-    use std::time::Instant;
+    use std::thread;
+    use std::time::{Instant, SystemTime, UNIX_EPOCH};
 
     use reactive_service_domain::non_empty_cart::{NonEmptyCart, Quantity, Sku};
-    use reactive_service_single_thread::infra::inmem_journal::InMemoryJournal;
+    use reactive_service_domain::order_state::{DeliveryAddress, Street};
+    use reactive_service_single_thread::event_loop::{EventLoopConfig, OrderServiceEventLoop};
     use reactive_service_single_thread::infra::postgres_events_store::PostgresEventStore;
-    use reactive_service_single_thread::order_service::{OrderService, UpdateCart};
-    use reactive_service_single_thread::payment_processor::LocalPaymentProcessor;
+    use reactive_service_single_thread::inventory::InventoryService;
+    use reactive_service_single_thread::order_service::{OrderService, PayOrder, Principal, UpdateCart, UpdateDeliveryAddress};
+    use reactive_service_single_thread::payment_processor::{LocalPaymentProcessor, PaymentToken};
     use reactive_service_single_thread::shipping_calculator::LocalShippingCalculator;
     use reactive_service_single_thread::tax_calculator::LocalTaxCalculator;
 
     #[test]
+    #[ignore = "needs a PostgreSQL server on localhost"]
     fn bench_throughput() {
 
         let event_journal= PostgresEventStore::new().unwrap();
@@ -28,7 +32,7 @@ mod tests {
             let mut ring_iterator = (0i64..=1000i64).cycle();
             // warmup entities
             for _i in 0..1000 {
-                let _ = service.update_cart(UpdateCart {
+                let _ = service.update_cart(&Principal::Admin, UpdateCart {
                     order_id: ring_iterator.next().unwrap(),
                     cart: NonEmptyCart::new(HashMap::from([(Sku("apple".to_owned()), Quantity(1))])).unwrap()
                 });
@@ -44,7 +48,7 @@ mod tests {
             let start_time = Instant::now();
 
             for _i in 0..num_commands {
-                let _ = service.update_cart(UpdateCart {
+                let _ = service.update_cart(&Principal::Admin, UpdateCart {
                     order_id: ring_iterator.next().unwrap(),
                     cart: NonEmptyCart::new(HashMap::from(
                     [
@@ -78,15 +82,133 @@ mod tests {
 
     }
 
+    #[test]
+    #[ignore = "needs a PostgreSQL server on localhost"]
+    fn bench_event_loop_throughput() {
+
+        let event_journal= PostgresEventStore::new().unwrap();
+        let service = OrderService::new(
+            event_journal,
+            LocalShippingCalculator{},
+            LocalTaxCalculator{},
+            LocalPaymentProcessor{}
+        );
+
+        // The service stays single threaded, but can now be called from many threads
+        let event_loop = OrderServiceEventLoop::spawn(service, EventLoopConfig::default());
+
+        {
+            // cycle over X entities
+            let mut ring_iterator = (0i64..=1000i64).cycle();
+            // warmup entities
+            for _i in 0..1000 {
+                let _ = event_loop.update_cart(&Principal::Admin, UpdateCart {
+                    order_id: ring_iterator.next().unwrap(),
+                    cart: NonEmptyCart::new(HashMap::from([(Sku("apple".to_owned()), Quantity(1))])).unwrap()
+                });
+            }
+        }
+
+        {
+            let num_threads = 8;
+            let num_commands_per_thread = 1250;
+            let number_entities = 1000;
+
+            let start_time = Instant::now();
+
+            thread::scope(|scope| {
+                for thread_id in 0..num_threads {
+                    let event_loop = &event_loop;
+                    scope.spawn(move || {
+                        // each thread cycles over its own subset of the entities
+                        let mut ring_iterator = (0..=number_entities)
+                            .filter(|order_id| order_id % num_threads == thread_id)
+                            .cycle();
+
+                        for _i in 0..num_commands_per_thread {
+                            let _ = event_loop.update_cart(&Principal::Admin, UpdateCart {
+                                order_id: ring_iterator.next().unwrap(),
+                                cart: NonEmptyCart::new(HashMap::from(
+                                    [
+                                        (Sku("apple".to_owned()), Quantity(1)),
+                                        (Sku("chocolate".to_owned()), Quantity(2))
+                                    ]
+                                )).unwrap()
+                            });
+                        }
+                    });
+                }
+            });
+
+            let elapsed_time = start_time.elapsed();
+            let commands_per_sec = (num_threads * num_commands_per_thread) as f64 / elapsed_time.as_secs_f64();
+            println!("Commands/seq {:?}", human_readable_format(commands_per_sec));
+        }
+    }
+
+    #[test]
+    #[ignore = "needs a PostgreSQL server on localhost"]
+    fn bench_event_loop_checkout_hot_skus() {
+
+        let inventory = InventoryService::new(PostgresEventStore::new().unwrap());
+        let hot_skus = 4;
+        let cold_skus = 1000;
+        for sku in (0..hot_skus).map(|n| format!("hot-{}", n)).chain((0..cold_skus).map(|n| format!("cold-{}", n))) {
+            inventory.receive_stock(&Sku(sku), 1_000_000).unwrap();
+        }
+
+        // Every checkout reserves one of the few hot SKUs, on the thread of the event loop
+        let service = OrderService::with_inventory(
+            PostgresEventStore::new().unwrap(),
+            LocalShippingCalculator{},
+            LocalTaxCalculator{},
+            LocalPaymentProcessor{},
+            inventory
+        );
+        let event_loop = OrderServiceEventLoop::spawn(service, EventLoopConfig::default());
+
+        let num_threads = 8;
+        let num_orders_per_thread = 250;
+        // Unique orders across the runs: the journal is shared
+        let first_order_id = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_nanos() as i64;
+        let start_time = Instant::now();
+
+        thread::scope(|scope| {
+            for thread_id in 0..num_threads {
+                let event_loop = &event_loop;
+                scope.spawn(move || {
+                    for n in 0..num_orders_per_thread {
+                        let order_id = first_order_id + thread_id * num_orders_per_thread + n;
+                        let cart = NonEmptyCart::new(HashMap::from([
+                            (Sku(format!("hot-{}", order_id % hot_skus)), Quantity(1)),
+                            (Sku(format!("cold-{}", order_id % cold_skus)), Quantity(2)),
+                        ])).unwrap();
+                        let delivery_address = DeliveryAddress {
+                            street: Street("1 Main Street".to_owned()),
+                            postal_code: "H0H 0H0".parse().unwrap()
+                        };
+                        let _ = event_loop.update_cart(&Principal::Admin, UpdateCart { order_id, cart });
+                        let _ = event_loop.update_delivery_address(&Principal::Admin, UpdateDeliveryAddress { order_id, delivery_address });
+                        let _ = event_loop.pay_order(&Principal::Admin, PayOrder { order_id, payment_token: PaymentToken::new("token") });
+                    }
+                });
+            }
+        });
+
+        let elapsed_time = start_time.elapsed();
+        let checkouts_per_sec = (num_threads * num_orders_per_thread) as f64 / elapsed_time.as_secs_f64();
+        println!("Checkouts/seq {:?}", human_readable_format(checkouts_per_sec));
+    }
+
     fn human_readable_format(n: f64) -> String {
         if n < 1_000.0 {
-            return format!("{:.2}", n);
+            format!("{:.2}", n)
         } else if n < 1_000_000.0 {
-            return format!("{:.2}k", n / 1_000.0);
+            format!("{:.2}k", n / 1_000.0)
         } else if n < 1_000_000_000.0 {
-            return format!("{:.2}M", n / 1_000_000.0);
+            format!("{:.2}M", n / 1_000_000.0)
         } else {
-            return format!("{:.2}G", n / 1_000_000_000.0);
+            format!("{:.2}G", n / 1_000_000_000.0)
         }
     }
 }
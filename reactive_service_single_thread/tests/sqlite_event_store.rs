@@ -0,0 +1,65 @@
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use reactive_service_domain::aggregate_root::SequencedEvent;
+    use reactive_service_domain::non_empty_cart::{NonEmptyCart, Quantity, Sku};
+    use reactive_service_single_thread::infra::sqlite_event_store::SqliteEventStore;
+    use reactive_service_single_thread::order_service::{EventsJournal, OrderService, Principal, UpdateCart};
+    use reactive_service_single_thread::payment_processor::LocalPaymentProcessor;
+    use reactive_service_single_thread::shipping_calculator::LocalShippingCalculator;
+    use reactive_service_single_thread::tax_calculator::LocalTaxCalculator;
+
+    fn sequenced(sequence_number: i64) -> SequencedEvent<String> {
+        SequencedEvent { sequence_number, event: format!("event {}", sequence_number) }
+    }
+
+    #[test]
+    fn retrieves_the_events_of_an_order_in_sequence_order() {
+        let directory = tempfile::tempdir().unwrap();
+        let mut store = SqliteEventStore::new(directory.path().join("events.db")).unwrap();
+
+        store.persist_event(1, &sequenced(2)).unwrap();
+        store.persist_events(&[(1, sequenced(1)), (2, sequenced(1))]).unwrap();
+
+        let events: Vec<SequencedEvent<String>> = store.retrieve_events(1).unwrap();
+        assert_eq!(events.iter().map(|e| e.sequence_number).collect::<Vec<_>>(), vec![1, 2]);
+        assert_eq!(events[0].event, "event 1");
+    }
+
+    #[test]
+    fn rejects_a_batch_with_a_duplicate_sequence_number_as_a_whole() {
+        let directory = tempfile::tempdir().unwrap();
+        let mut store = SqliteEventStore::new(directory.path().join("events.db")).unwrap();
+
+        store.persist_event(1, &sequenced(1)).unwrap();
+        assert!(store.persist_events(&[(2, sequenced(1)), (1, sequenced(1))]).is_err());
+
+        let events: Vec<SequencedEvent<String>> = store.retrieve_events(2).unwrap();
+        assert!(events.is_empty());
+    }
+
+    #[test]
+    fn restores_orders_after_a_restart() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("events.db");
+        let update_cart = || UpdateCart {
+            order_id: 1,
+            cart: NonEmptyCart::new(HashMap::from([(Sku("apple".to_owned()), Quantity(1))])).unwrap()
+        };
+
+        let mut service = OrderService::new(
+            SqliteEventStore::new(&path).unwrap(),
+            LocalShippingCalculator{}, LocalTaxCalculator{}, LocalPaymentProcessor{}
+        );
+        service.update_cart(&Principal::Admin, update_cart()).unwrap();
+        drop(service);
+
+        let mut service = OrderService::new(
+            SqliteEventStore::new(&path).unwrap(),
+            LocalShippingCalculator{}, LocalTaxCalculator{}, LocalPaymentProcessor{}
+        );
+        let (_, events) = service.update_cart(&Principal::Admin, update_cart()).unwrap();
+        assert_eq!(events[0].sequence_number, 2);
+    }
+}